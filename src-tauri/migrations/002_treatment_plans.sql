@@ -0,0 +1,115 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 002: TREATMENT PLANS
+-- ============================================================================
+-- Descripción: Planes de tratamiento multi-visita (fases, alternativas,
+--              presupuesto estimado y aceptación firmada del paciente).
+--              Columna agregada desde Rust: session_items.treatment_plan_item_id
+-- ============================================================================
+
+-- ============================================================================
+-- TREATMENT PLANS (Header)
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS treatment_plans (
+  id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+  patient_id           INTEGER NOT NULL,
+  title                TEXT NOT NULL,
+  notes                TEXT,
+
+  -- 'draft' | 'presented' | 'accepted' | 'in_progress' | 'completed' | 'rejected' | 'cancelled'
+  status               TEXT NOT NULL DEFAULT 'draft',
+
+  -- Financial (calculated by app from items)
+  estimated_total      REAL NOT NULL DEFAULT 0,
+
+  -- Patient acceptance
+  selected_option_id   INTEGER,
+  accepted_at          TEXT,
+  accepted_by          TEXT,
+  signature_data       TEXT,          -- Base64 canvas image
+
+  -- Metadata
+  created_at           TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at           TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_treatment_plans_patient ON treatment_plans(patient_id);
+CREATE INDEX IF NOT EXISTS idx_treatment_plans_status ON treatment_plans(status);
+
+CREATE TRIGGER IF NOT EXISTS trg_treatment_plans_updated_at
+AFTER UPDATE ON treatment_plans
+FOR EACH ROW
+BEGIN
+  UPDATE treatment_plans SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- ============================================================================
+-- TREATMENT PLAN PHASES (e.g. "Fase 1: Urgencias", "Fase 2: Rehabilitación")
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS treatment_plan_phases (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  plan_id       INTEGER NOT NULL,
+  name          TEXT NOT NULL,
+  notes         TEXT,
+  sort_order    INTEGER NOT NULL DEFAULT 0,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (plan_id) REFERENCES treatment_plans(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_treatment_plan_phases_plan ON treatment_plan_phases(plan_id);
+
+-- ============================================================================
+-- TREATMENT PLAN OPTIONS (Alternatives, e.g. "Implante" vs "Puente fijo")
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS treatment_plan_options (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  plan_id       INTEGER NOT NULL,
+  label         TEXT NOT NULL,
+  description   TEXT,
+  sort_order    INTEGER NOT NULL DEFAULT 0,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (plan_id) REFERENCES treatment_plans(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_treatment_plan_options_plan ON treatment_plan_options(plan_id);
+
+-- ============================================================================
+-- TREATMENT PLAN ITEMS (Planned session_items per tooth)
+-- ============================================================================
+-- option_id NULL = item común a todas las alternativas
+-- Un item se considera realizado cuando existe un session_item con
+-- treatment_plan_item_id = id (el vínculo vive en la sesión, no aquí)
+CREATE TABLE IF NOT EXISTS treatment_plan_items (
+  id                      INTEGER PRIMARY KEY AUTOINCREMENT,
+  plan_id                 INTEGER NOT NULL,
+  phase_id                INTEGER,
+  option_id               INTEGER,
+
+  -- Billing (estimate)
+  name                    TEXT NOT NULL,
+  unit_price              REAL NOT NULL DEFAULT 0,
+  quantity                INTEGER NOT NULL DEFAULT 1,
+  subtotal                REAL NOT NULL DEFAULT 0,
+  is_active               INTEGER NOT NULL DEFAULT 1,
+
+  -- Clinical metadata (optional)
+  tooth_number            TEXT,
+  procedure_notes         TEXT,
+
+  -- Traceability (optional)
+  procedure_template_id   INTEGER,
+  sort_order              INTEGER NOT NULL DEFAULT 0,
+  created_at              TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (plan_id) REFERENCES treatment_plans(id) ON DELETE CASCADE,
+  FOREIGN KEY (phase_id) REFERENCES treatment_plan_phases(id) ON DELETE SET NULL,
+  FOREIGN KEY (option_id) REFERENCES treatment_plan_options(id) ON DELETE CASCADE,
+  FOREIGN KEY (procedure_template_id) REFERENCES procedure_templates(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_treatment_plan_items_plan ON treatment_plan_items(plan_id);
+CREATE INDEX IF NOT EXISTS idx_treatment_plan_items_phase ON treatment_plan_items(phase_id);
+CREATE INDEX IF NOT EXISTS idx_session_items_plan_item ON session_items(treatment_plan_item_id);
@@ -0,0 +1,35 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 003: TOOTH EVENTS (per-tooth clinical history)
+-- ============================================================================
+-- Descripción: Eventos normalizados por pieza/superficie derivados de los
+--              snapshots de sesiones (tooth_dx_json + session_items).
+--              Se reconstruyen por paciente en cada guardado; no editar a mano.
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS tooth_events (
+  id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+  patient_id           INTEGER NOT NULL,
+  session_id           INTEGER NOT NULL,
+  event_date           TEXT NOT NULL,          -- sessions.date (snapshot)
+
+  -- Location (FDI)
+  tooth                TEXT NOT NULL,
+  surface              TEXT,                   -- 'M' | 'D' | 'O' | 'I' | 'V' | 'L' | 'P' | NULL (whole tooth)
+
+  -- 'diagnosis_added' | 'diagnosis_removed' | 'procedure'
+  event_type           TEXT NOT NULL,
+  label                TEXT NOT NULL,
+  diagnosis_option_id  INTEGER,
+  session_item_id      INTEGER,
+  notes                TEXT,
+
+  created_at           TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE,
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
+  FOREIGN KEY (session_item_id) REFERENCES session_items(id) ON DELETE CASCADE,
+  FOREIGN KEY (diagnosis_option_id) REFERENCES diagnosis_options(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_tooth_events_patient_tooth ON tooth_events(patient_id, tooth, event_date);
+CREATE INDEX IF NOT EXISTS idx_tooth_events_session ON tooth_events(session_id);
@@ -0,0 +1,78 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 004: PERIODONTAL CHARTS (periodontograma)
+-- ============================================================================
+-- Descripción: Periodontograma por sesión: sondaje, recesión y sangrado por
+--              sitio (6 por pieza), movilidad y furca por pieza, e índices
+--              calculados por la app (PD promedio, % BOP, CAL promedio).
+-- ============================================================================
+
+-- ============================================================================
+-- PERIO CHARTS (Header, one per session)
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS perio_charts (
+  id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+  patient_id           INTEGER NOT NULL,
+  session_id           INTEGER NOT NULL UNIQUE,
+  exam_date            TEXT NOT NULL,          -- sessions.date (snapshot)
+  notes                TEXT,
+
+  -- Indices (calculated by app from sites)
+  mean_probing_depth   REAL,                   -- mm
+  bop_percent          REAL,                   -- % sites bleeding on probing
+  mean_cal             REAL,                   -- mm, CAL = PD + recession
+  sites_over_4mm       INTEGER NOT NULL DEFAULT 0,
+
+  -- Metadata
+  created_at           TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at           TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE,
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_perio_charts_patient ON perio_charts(patient_id, exam_date);
+
+CREATE TRIGGER IF NOT EXISTS trg_perio_charts_updated_at
+AFTER UPDATE ON perio_charts
+FOR EACH ROW
+BEGIN
+  UPDATE perio_charts SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- ============================================================================
+-- PERIO TEETH (Per tooth: mobility, furcation, missing)
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS perio_teeth (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  chart_id      INTEGER NOT NULL,
+  tooth         TEXT NOT NULL,                 -- FDI
+  is_missing    INTEGER NOT NULL DEFAULT 0,
+  mobility      INTEGER,                       -- Miller 0-3
+  furcation     INTEGER,                       -- Glickman 0-3 (multi-rooted teeth)
+  notes         TEXT,
+
+  FOREIGN KEY (chart_id) REFERENCES perio_charts(id) ON DELETE CASCADE,
+  UNIQUE (chart_id, tooth)
+);
+
+-- ============================================================================
+-- PERIO SITES (6 sites per tooth)
+-- ============================================================================
+-- site: 'MB' | 'B' | 'DB' | 'ML' | 'L' | 'DL' (vestibular = B, lingual/palatino = L)
+-- recession: margen gingival → LAC en mm (negativo = agrandamiento gingival)
+CREATE TABLE IF NOT EXISTS perio_sites (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  chart_id        INTEGER NOT NULL,
+  tooth           TEXT NOT NULL,
+  site            TEXT NOT NULL,
+  probing_depth   INTEGER,                     -- mm
+  recession       INTEGER,                     -- mm
+  bleeding        INTEGER NOT NULL DEFAULT 0,
+  suppuration     INTEGER NOT NULL DEFAULT 0,
+  plaque          INTEGER NOT NULL DEFAULT 0,
+
+  FOREIGN KEY (chart_id) REFERENCES perio_charts(id) ON DELETE CASCADE,
+  UNIQUE (chart_id, tooth, site)
+);
+
+CREATE INDEX IF NOT EXISTS idx_perio_sites_chart ON perio_sites(chart_id);
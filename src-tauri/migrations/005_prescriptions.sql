@@ -0,0 +1,101 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 005: PRESCRIPTIONS (recetas)
+-- ============================================================================
+-- Descripción: Catálogo local de medicamentos, recetas numeradas
+--              secuencialmente vinculadas a sesiones y sus líneas.
+--              La verificación de alergias se hace en la app contra
+--              patients.allergy_detail (y se guarda el resultado).
+-- ============================================================================
+
+-- ============================================================================
+-- DRUG CATALOG
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS drug_catalog (
+  id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+  name                TEXT NOT NULL,               -- "Amoxicilina"
+  active_ingredient   TEXT,
+  presentation        TEXT NOT NULL DEFAULT '',    -- "Cápsulas 500 mg"
+  default_dosage      TEXT,                        -- "1 cápsula cada 8 horas por 7 días"
+  route               TEXT,                        -- "Oral"
+  allergy_tags        TEXT,                        -- Términos separados por coma: "penicilina, betalactámicos"
+  active              INTEGER NOT NULL DEFAULT 1,
+  sort_order          INTEGER NOT NULL DEFAULT 0,
+  created_at          TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at          TEXT NOT NULL DEFAULT (datetime('now')),
+
+  UNIQUE (name, presentation)
+);
+
+CREATE INDEX IF NOT EXISTS idx_drug_catalog_active ON drug_catalog(active);
+
+CREATE TRIGGER IF NOT EXISTS trg_drug_catalog_updated_at
+AFTER UPDATE ON drug_catalog
+FOR EACH ROW
+BEGIN
+  UPDATE drug_catalog SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- ============================================================================
+-- PRESCRIPTIONS (Header)
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS prescriptions (
+  id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+  prescription_number  INTEGER NOT NULL UNIQUE,    -- Secuencial, asignado por la app
+  patient_id           INTEGER NOT NULL,
+  session_id           INTEGER,
+  issued_at            TEXT NOT NULL DEFAULT (date('now')),
+  doctor_name          TEXT,
+  diagnosis            TEXT,
+  instructions         TEXT,                       -- Indicaciones generales
+
+  -- Allergy check (snapshot at issue time)
+  allergy_warnings     TEXT,                       -- Advertencias separadas por salto de línea
+  allergy_override     INTEGER NOT NULL DEFAULT 0, -- 1 = emitida pese a advertencias
+
+  -- 'active' | 'voided'
+  status               TEXT NOT NULL DEFAULT 'active',
+
+  created_at           TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE,
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_prescriptions_patient ON prescriptions(patient_id, issued_at);
+CREATE INDEX IF NOT EXISTS idx_prescriptions_session ON prescriptions(session_id);
+
+-- ============================================================================
+-- PRESCRIPTION ITEMS (Snapshot of drug data)
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS prescription_items (
+  id                INTEGER PRIMARY KEY AUTOINCREMENT,
+  prescription_id   INTEGER NOT NULL,
+  drug_id           INTEGER,                       -- Trazabilidad al catálogo (opcional)
+  drug_name         TEXT NOT NULL,
+  presentation      TEXT,
+  dosage            TEXT NOT NULL,
+  route             TEXT,
+  quantity          TEXT,                          -- "21 cápsulas"
+  notes             TEXT,
+  sort_order        INTEGER NOT NULL DEFAULT 0,
+
+  FOREIGN KEY (prescription_id) REFERENCES prescriptions(id) ON DELETE CASCADE,
+  FOREIGN KEY (drug_id) REFERENCES drug_catalog(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_prescription_items_prescription ON prescription_items(prescription_id);
+
+-- ============================================================================
+-- SEED: Medicamentos de uso frecuente en odontología
+-- ============================================================================
+INSERT OR IGNORE INTO drug_catalog (name, active_ingredient, presentation, default_dosage, route, allergy_tags, sort_order) VALUES
+  ('Amoxicilina', 'Amoxicilina', 'Cápsulas 500 mg', '1 cápsula cada 8 horas por 7 días', 'Oral', 'penicilina, betalactámicos, amoxicilina', 0),
+  ('Amoxicilina + Ácido clavulánico', 'Amoxicilina / Ácido clavulánico', 'Tabletas 875/125 mg', '1 tableta cada 12 horas por 7 días', 'Oral', 'penicilina, betalactámicos, amoxicilina, clavulánico', 1),
+  ('Clindamicina', 'Clindamicina', 'Cápsulas 300 mg', '1 cápsula cada 8 horas por 7 días', 'Oral', 'clindamicina, lincosamidas', 2),
+  ('Azitromicina', 'Azitromicina', 'Tabletas 500 mg', '1 tableta diaria por 3 días', 'Oral', 'azitromicina, macrólidos', 3),
+  ('Metronidazol', 'Metronidazol', 'Tabletas 500 mg', '1 tableta cada 8 horas por 7 días', 'Oral', 'metronidazol, nitroimidazoles', 4),
+  ('Ibuprofeno', 'Ibuprofeno', 'Tabletas 400 mg', '1 tableta cada 8 horas por 3 días', 'Oral', 'ibuprofeno, aines, antiinflamatorios', 5),
+  ('Ketorolaco', 'Ketorolaco', 'Tabletas 10 mg', '1 tableta cada 8 horas por 3 días', 'Oral', 'ketorolaco, aines, antiinflamatorios', 6),
+  ('Paracetamol', 'Paracetamol', 'Tabletas 500 mg', '1 tableta cada 6 horas por 3 días', 'Oral', 'paracetamol, acetaminofén', 7),
+  ('Nimesulida', 'Nimesulida', 'Tabletas 100 mg', '1 tableta cada 12 horas por 3 días', 'Oral', 'nimesulida, aines, antiinflamatorios', 8),
+  ('Clorhexidina 0,12%', 'Clorhexidina', 'Colutorio 250 ml', 'Enjuagar 15 ml por 30 segundos cada 12 horas por 7 días', 'Tópica bucal', 'clorhexidina', 9);
@@ -0,0 +1,52 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 006: LAB ORDERS (trabajos de laboratorio)
+-- ============================================================================
+-- Descripción: Órdenes a laboratorios externos (coronas, puentes, prótesis)
+--              con estados, fechas de envío/entrega y costo.
+--              Columna agregada desde Rust: session_items.lab_order_id
+--              (el vínculo vive en el item porque los items se reinsertan
+--              en cada guardado de la visita)
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS lab_orders (
+  id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+  patient_id           INTEGER NOT NULL,
+  session_id           INTEGER,                -- Sesión donde se tomó la impresión
+
+  -- Work details
+  lab_name             TEXT NOT NULL,
+  work_type            TEXT NOT NULL,          -- "Corona zirconio", "Puente 3 piezas", "Prótesis parcial"
+  shade                TEXT,                   -- Color (VITA): "A2"
+  teeth                TEXT,                   -- FDI: "14, 15, 16"
+  instructions         TEXT,
+
+  -- 'draft' | 'sent' | 'received' | 'delivered' | 'cancelled'
+  status               TEXT NOT NULL DEFAULT 'draft',
+
+  -- Dates (YYYY-MM-DD)
+  sent_date            TEXT,
+  due_date             TEXT,
+  received_date        TEXT,
+  delivered_date       TEXT,                   -- Entregado/cementado en el paciente
+
+  -- Financial
+  cost                 REAL NOT NULL DEFAULT 0,
+
+  notes                TEXT,
+  created_at           TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at           TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE,
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_lab_orders_patient ON lab_orders(patient_id);
+CREATE INDEX IF NOT EXISTS idx_lab_orders_status_due ON lab_orders(status, due_date);
+CREATE INDEX IF NOT EXISTS idx_session_items_lab_order ON session_items(lab_order_id);
+
+CREATE TRIGGER IF NOT EXISTS trg_lab_orders_updated_at
+AFTER UPDATE ON lab_orders
+FOR EACH ROW
+BEGIN
+  UPDATE lab_orders SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
@@ -0,0 +1,111 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 007: SESSION SIGN-OFF & ADDENDA
+-- ============================================================================
+-- Descripción: Firma explícita de sesiones (hash de contenido, firmante,
+--              fecha). Una sesión firmada es inmutable: solo admite adendas.
+--              Columnas agregadas desde Rust: sessions.signed_at,
+--              sessions.signed_by, sessions.content_hash
+-- ============================================================================
+
+-- ============================================================================
+-- SESSION ADDENDA (append-only)
+-- ============================================================================
+CREATE TABLE IF NOT EXISTS session_addenda (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id    INTEGER NOT NULL,
+  author        TEXT NOT NULL,
+  body          TEXT NOT NULL,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_addenda_session ON session_addenda(session_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_sessions_signed ON sessions(signed_at);
+
+CREATE TRIGGER IF NOT EXISTS trg_session_addenda_no_update
+BEFORE UPDATE ON session_addenda
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: addenda are append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_session_addenda_no_delete
+BEFORE DELETE ON session_addenda
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: addenda are append-only');
+END;
+
+-- ============================================================================
+-- LOCK GUARDS (última línea de defensa; la app valida antes con error tipado)
+-- ============================================================================
+-- Solo cambios de updated_at (trigger trg_sessions_updated_at) están permitidos
+CREATE TRIGGER IF NOT EXISTS trg_sessions_signed_no_update
+BEFORE UPDATE ON sessions
+FOR EACH ROW
+WHEN OLD.signed_at IS NOT NULL AND (
+     NEW.patient_id IS NOT OLD.patient_id
+  OR NEW.date IS NOT OLD.date
+  OR NEW.reason_type IS NOT OLD.reason_type
+  OR NEW.reason_detail IS NOT OLD.reason_detail
+  OR NEW.diagnosis_text IS NOT OLD.diagnosis_text
+  OR NEW.auto_dx_text IS NOT OLD.auto_dx_text
+  OR NEW.full_dx_text IS NOT OLD.full_dx_text
+  OR NEW.tooth_dx_json IS NOT OLD.tooth_dx_json
+  OR NEW.clinical_notes IS NOT OLD.clinical_notes
+  OR NEW.signer IS NOT OLD.signer
+  OR NEW.budget IS NOT OLD.budget
+  OR NEW.discount IS NOT OLD.discount
+  OR NEW.payment IS NOT OLD.payment
+  OR NEW.balance IS NOT OLD.balance
+  OR NEW.payment_method_id IS NOT OLD.payment_method_id
+  OR NEW.payment_notes IS NOT OLD.payment_notes
+  OR NEW.is_saved IS NOT OLD.is_saved
+  OR NEW.signed_at IS NOT OLD.signed_at
+  OR NEW.signed_by IS NOT OLD.signed_by
+  OR NEW.content_hash IS NOT OLD.content_hash
+)
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: signed sessions cannot be modified');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_sessions_signed_no_delete
+BEFORE DELETE ON sessions
+FOR EACH ROW
+WHEN OLD.signed_at IS NOT NULL
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: signed sessions cannot be deleted');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_session_items_signed_no_insert
+BEFORE INSERT ON session_items
+FOR EACH ROW
+WHEN (SELECT signed_at FROM sessions WHERE id = NEW.session_id) IS NOT NULL
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: signed sessions cannot be modified');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_session_items_signed_no_delete
+BEFORE DELETE ON session_items
+FOR EACH ROW
+WHEN (SELECT signed_at FROM sessions WHERE id = OLD.session_id) IS NOT NULL
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: signed sessions cannot be modified');
+END;
+
+-- Los vínculos (treatment_plan_item_id, lab_order_id) pueden cambiar; el contenido no
+CREATE TRIGGER IF NOT EXISTS trg_session_items_signed_no_update
+BEFORE UPDATE ON session_items
+FOR EACH ROW
+WHEN (SELECT signed_at FROM sessions WHERE id = OLD.session_id) IS NOT NULL AND (
+     NEW.session_id IS NOT OLD.session_id
+  OR NEW.name IS NOT OLD.name
+  OR NEW.unit_price IS NOT OLD.unit_price
+  OR NEW.quantity IS NOT OLD.quantity
+  OR NEW.subtotal IS NOT OLD.subtotal
+  OR NEW.is_active IS NOT OLD.is_active
+  OR NEW.tooth_number IS NOT OLD.tooth_number
+  OR NEW.procedure_notes IS NOT OLD.procedure_notes
+)
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: signed sessions cannot be modified');
+END;
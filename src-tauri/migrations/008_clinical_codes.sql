@@ -0,0 +1,168 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 008: CLINICAL CODES (CIE-10 / CDT)
+-- ============================================================================
+-- Descripción: Catálogos de códigos estándar para reportes y aseguradoras.
+--              CIE-10 capítulo dental (K00–K14) y lista de procedimientos
+--              estilo CDT. Se pueden cargar más códigos desde la app.
+--              Columnas agregadas desde Rust: diagnosis_options.cie10_code,
+--              procedure_templates.procedure_code, session_items.procedure_code
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS clinical_codes (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  system        TEXT NOT NULL,             -- 'CIE10' | 'CDT'
+  code          TEXT NOT NULL,             -- "K02.1", "D2391"
+  description   TEXT NOT NULL,
+  category      TEXT,                      -- Agrupador: "K02", "Restauradora"
+  active        INTEGER NOT NULL DEFAULT 1,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  UNIQUE (system, code)
+);
+
+CREATE INDEX IF NOT EXISTS idx_clinical_codes_system ON clinical_codes(system, active);
+
+-- ============================================================================
+-- SEED: CIE-10 K00–K14 (enfermedades de la cavidad bucal, glándulas salivales y maxilares)
+-- ============================================================================
+INSERT OR IGNORE INTO clinical_codes (system, code, description, category) VALUES
+  ('CIE10', 'K00', 'Trastornos del desarrollo y de la erupción de los dientes', 'K00'),
+  ('CIE10', 'K00.0', 'Anodoncia', 'K00'),
+  ('CIE10', 'K00.1', 'Dientes supernumerarios', 'K00'),
+  ('CIE10', 'K00.4', 'Alteraciones en la formación dentaria', 'K00'),
+  ('CIE10', 'K00.6', 'Alteraciones en la erupción dentaria', 'K00'),
+  ('CIE10', 'K01', 'Dientes incluidos e impactados', 'K01'),
+  ('CIE10', 'K01.0', 'Dientes incluidos', 'K01'),
+  ('CIE10', 'K01.1', 'Dientes impactados', 'K01'),
+  ('CIE10', 'K02', 'Caries dental', 'K02'),
+  ('CIE10', 'K02.0', 'Caries limitada al esmalte', 'K02'),
+  ('CIE10', 'K02.1', 'Caries de la dentina', 'K02'),
+  ('CIE10', 'K02.2', 'Caries del cemento', 'K02'),
+  ('CIE10', 'K02.3', 'Caries dentaria detenida', 'K02'),
+  ('CIE10', 'K02.9', 'Caries dental, no especificada', 'K02'),
+  ('CIE10', 'K03', 'Otras enfermedades de los tejidos duros de los dientes', 'K03'),
+  ('CIE10', 'K03.0', 'Atrición excesiva de los dientes', 'K03'),
+  ('CIE10', 'K03.1', 'Abrasión de los dientes', 'K03'),
+  ('CIE10', 'K03.2', 'Erosión de los dientes', 'K03'),
+  ('CIE10', 'K03.6', 'Depósitos en los dientes (cálculo)', 'K03'),
+  ('CIE10', 'K03.7', 'Cambios posteruptivos del color de los tejidos dentales duros', 'K03'),
+  ('CIE10', 'K03.8', 'Otras enfermedades especificadas de los tejidos duros (hipersensibilidad dentinaria)', 'K03'),
+  ('CIE10', 'K04', 'Enfermedades de la pulpa y de los tejidos periapicales', 'K04'),
+  ('CIE10', 'K04.0', 'Pulpitis', 'K04'),
+  ('CIE10', 'K04.1', 'Necrosis de la pulpa', 'K04'),
+  ('CIE10', 'K04.4', 'Periodontitis apical aguda originada en la pulpa', 'K04'),
+  ('CIE10', 'K04.5', 'Periodontitis apical crónica', 'K04'),
+  ('CIE10', 'K04.6', 'Absceso periapical con fístula', 'K04'),
+  ('CIE10', 'K04.7', 'Absceso periapical sin fístula', 'K04'),
+  ('CIE10', 'K04.8', 'Quiste radicular', 'K04'),
+  ('CIE10', 'K05', 'Gingivitis y enfermedades periodontales', 'K05'),
+  ('CIE10', 'K05.0', 'Gingivitis aguda', 'K05'),
+  ('CIE10', 'K05.1', 'Gingivitis crónica', 'K05'),
+  ('CIE10', 'K05.2', 'Periodontitis aguda', 'K05'),
+  ('CIE10', 'K05.3', 'Periodontitis crónica', 'K05'),
+  ('CIE10', 'K05.4', 'Periodontosis', 'K05'),
+  ('CIE10', 'K06', 'Otros trastornos de la encía y de la zona edéntula', 'K06'),
+  ('CIE10', 'K06.0', 'Retracción gingival', 'K06'),
+  ('CIE10', 'K06.1', 'Hiperplasia gingival', 'K06'),
+  ('CIE10', 'K07', 'Anomalías dentofaciales (incluso la maloclusión)', 'K07'),
+  ('CIE10', 'K07.2', 'Anomalías de la relación entre los arcos dentarios', 'K07'),
+  ('CIE10', 'K07.3', 'Anomalías de la posición del diente', 'K07'),
+  ('CIE10', 'K07.4', 'Maloclusión de tipo no especificado', 'K07'),
+  ('CIE10', 'K07.6', 'Trastornos de la articulación temporomaxilar', 'K07'),
+  ('CIE10', 'K08', 'Otros trastornos de los dientes y de sus estructuras de sostén', 'K08'),
+  ('CIE10', 'K08.1', 'Pérdida de dientes debida a accidente, extracción o enfermedad periodontal local', 'K08'),
+  ('CIE10', 'K08.3', 'Raíz dental retenida', 'K08'),
+  ('CIE10', 'K08.8', 'Otras afecciones especificadas de los dientes (odontalgia)', 'K08'),
+  ('CIE10', 'K09', 'Quistes de la región bucal, no clasificados en otra parte', 'K09'),
+  ('CIE10', 'K10', 'Otras enfermedades de los maxilares', 'K10'),
+  ('CIE10', 'K10.2', 'Afecciones inflamatorias de los maxilares', 'K10'),
+  ('CIE10', 'K10.3', 'Alveolitis del maxilar', 'K10'),
+  ('CIE10', 'K11', 'Enfermedades de las glándulas salivales', 'K11'),
+  ('CIE10', 'K12', 'Estomatitis y lesiones afines', 'K12'),
+  ('CIE10', 'K12.0', 'Estomatitis aftosa recurrente', 'K12'),
+  ('CIE10', 'K12.2', 'Celulitis y absceso de boca', 'K12'),
+  ('CIE10', 'K13', 'Otras enfermedades de los labios y de la mucosa bucal', 'K13'),
+  ('CIE10', 'K13.0', 'Enfermedades de los labios', 'K13'),
+  ('CIE10', 'K13.2', 'Leucoplasia y otras alteraciones del epitelio bucal', 'K13'),
+  ('CIE10', 'K14', 'Enfermedades de la lengua', 'K14'),
+  ('CIE10', 'K14.0', 'Glositis', 'K14');
+
+-- ============================================================================
+-- SEED: Procedimientos (lista estilo CDT)
+-- ============================================================================
+INSERT OR IGNORE INTO clinical_codes (system, code, description, category) VALUES
+  ('CDT', 'D0120', 'Evaluación oral periódica', 'Diagnóstico'),
+  ('CDT', 'D0140', 'Evaluación oral limitada (urgencia)', 'Diagnóstico'),
+  ('CDT', 'D0150', 'Evaluación oral completa (paciente nuevo)', 'Diagnóstico'),
+  ('CDT', 'D0180', 'Evaluación periodontal completa', 'Diagnóstico'),
+  ('CDT', 'D0210', 'Serie radiográfica intraoral completa', 'Diagnóstico'),
+  ('CDT', 'D0220', 'Radiografía periapical', 'Diagnóstico'),
+  ('CDT', 'D0274', 'Radiografías de aleta de mordida (4)', 'Diagnóstico'),
+  ('CDT', 'D0330', 'Radiografía panorámica', 'Diagnóstico'),
+  ('CDT', 'D1110', 'Profilaxis (adulto)', 'Preventiva'),
+  ('CDT', 'D1120', 'Profilaxis (niño)', 'Preventiva'),
+  ('CDT', 'D1206', 'Aplicación de barniz de flúor', 'Preventiva'),
+  ('CDT', 'D1351', 'Sellante de fosas y fisuras (por diente)', 'Preventiva'),
+  ('CDT', 'D2140', 'Amalgama, una superficie', 'Restauradora'),
+  ('CDT', 'D2330', 'Resina, una superficie, anterior', 'Restauradora'),
+  ('CDT', 'D2331', 'Resina, dos superficies, anterior', 'Restauradora'),
+  ('CDT', 'D2391', 'Resina, una superficie, posterior', 'Restauradora'),
+  ('CDT', 'D2392', 'Resina, dos superficies, posterior', 'Restauradora'),
+  ('CDT', 'D2393', 'Resina, tres superficies, posterior', 'Restauradora'),
+  ('CDT', 'D2740', 'Corona de porcelana / cerámica', 'Restauradora'),
+  ('CDT', 'D2750', 'Corona metal-porcelana', 'Restauradora'),
+  ('CDT', 'D2920', 'Recementado de corona', 'Restauradora'),
+  ('CDT', 'D2940', 'Restauración protectora (curación)', 'Restauradora'),
+  ('CDT', 'D2950', 'Reconstrucción de muñón', 'Restauradora'),
+  ('CDT', 'D2954', 'Perno y muñón prefabricado', 'Restauradora'),
+  ('CDT', 'D3220', 'Pulpotomía', 'Endodoncia'),
+  ('CDT', 'D3310', 'Endodoncia, diente anterior', 'Endodoncia'),
+  ('CDT', 'D3320', 'Endodoncia, premolar', 'Endodoncia'),
+  ('CDT', 'D3330', 'Endodoncia, molar', 'Endodoncia'),
+  ('CDT', 'D3346', 'Retratamiento endodóntico, anterior', 'Endodoncia'),
+  ('CDT', 'D4341', 'Raspado y alisado radicular, 4 o más dientes por cuadrante', 'Periodoncia'),
+  ('CDT', 'D4342', 'Raspado y alisado radicular, 1 a 3 dientes por cuadrante', 'Periodoncia'),
+  ('CDT', 'D4355', 'Desbridamiento de boca completa', 'Periodoncia'),
+  ('CDT', 'D4910', 'Mantenimiento periodontal', 'Periodoncia'),
+  ('CDT', 'D5110', 'Prótesis total superior', 'Prótesis removible'),
+  ('CDT', 'D5120', 'Prótesis total inferior', 'Prótesis removible'),
+  ('CDT', 'D5213', 'Prótesis parcial removible superior (base metálica)', 'Prótesis removible'),
+  ('CDT', 'D5214', 'Prótesis parcial removible inferior (base metálica)', 'Prótesis removible'),
+  ('CDT', 'D6010', 'Implante endóseo (colocación quirúrgica)', 'Implantes'),
+  ('CDT', 'D6065', 'Corona sobre implante (cerámica)', 'Implantes'),
+  ('CDT', 'D6240', 'Póntico de porcelana', 'Prótesis fija'),
+  ('CDT', 'D6750', 'Corona retenedora de puente (metal-porcelana)', 'Prótesis fija'),
+  ('CDT', 'D7140', 'Extracción simple (diente erupcionado o raíz expuesta)', 'Cirugía'),
+  ('CDT', 'D7210', 'Extracción quirúrgica de diente erupcionado', 'Cirugía'),
+  ('CDT', 'D7240', 'Extracción de diente impactado (óseo completo)', 'Cirugía'),
+  ('CDT', 'D8080', 'Tratamiento ortodóntico integral', 'Ortodoncia'),
+  ('CDT', 'D8670', 'Control periódico de ortodoncia', 'Ortodoncia'),
+  ('CDT', 'D8680', 'Retención ortodóntica (retenedores)', 'Ortodoncia'),
+  ('CDT', 'D9110', 'Tratamiento paliativo del dolor dental', 'Adjuntos'),
+  ('CDT', 'D9944', 'Guarda oclusal rígida', 'Adjuntos'),
+  ('CDT', 'D9972', 'Blanqueamiento externo (por arcada)', 'Adjuntos');
+
+-- ============================================================================
+-- Códigos sugeridos para el catálogo inicial (solo si aún no tienen código)
+-- ============================================================================
+-- NULL = nunca asignado; '' = el usuario quitó el código (no se vuelve a sugerir)
+UPDATE diagnosis_options SET cie10_code = 'K02.9' WHERE label = 'Caries' AND cie10_code IS NULL;
+UPDATE diagnosis_options SET cie10_code = 'K03.8' WHERE label = 'Sensibilidad' AND cie10_code IS NULL;
+UPDATE diagnosis_options SET cie10_code = 'K08.1' WHERE label = 'Ausente' AND cie10_code IS NULL;
+
+UPDATE procedure_templates SET procedure_code = 'D2940' WHERE name = 'Curación' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D2391' WHERE name = 'Resinas simples' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D2392' WHERE name = 'Resinas compuestas' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D7140' WHERE name = 'Extracciones simples' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D7210' WHERE name = 'Extracciones complejas' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D8080' WHERE name = 'Correctivo inicial' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D8670' WHERE name = 'Control mensual' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D5110' WHERE name = 'Prótesis total' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D5213' WHERE name = 'Prótesis removible' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D6750' WHERE name = 'Prótesis fija' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D8680' WHERE name = 'Retenedor' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D3310' WHERE name = 'Endodoncia simple' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D3330' WHERE name = 'Endodoncia compleja' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D1110' WHERE name = 'Limpieza simple' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D4355' WHERE name = 'Limpieza compleja' AND procedure_code IS NULL;
+UPDATE procedure_templates SET procedure_code = 'D2920' WHERE name = 'Pegada' AND procedure_code IS NULL;
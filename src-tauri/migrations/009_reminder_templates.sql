@@ -0,0 +1,21 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 009: REMINDER TEMPLATES
+-- ============================================================================
+-- Descripción: Plantilla editable para el recordatorio de cita 24h
+--              (antes el texto estaba fijo en generate_1d_reminders).
+--              Variables: {nombre}, {procedimiento}, {fecha}, {hora}, {doctor}, {clinica}
+-- ============================================================================
+
+-- text_templates no tiene UNIQUE(kind, title): se inserta solo si no existe y solo
+-- la primera vez (marca reminderTemplatesSeeded), para no recrearla si el usuario la borra
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'reminder_1d', 'Recordatorio 24h',
+       'Hola {nombre}. Te recuerdo que tienes tu cita de {procedimiento} mañana a las {hora}. ¡Te esperamos!',
+       1, 'system', 1
+WHERE NOT EXISTS (
+  SELECT 1 FROM text_templates WHERE kind = 'reminder_1d' AND source = 'system'
+)
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'reminderTemplatesSeeded');
+
+INSERT OR IGNORE INTO user_settings (key, value, category)
+VALUES ('reminderTemplatesSeeded', '1', 'templates');
@@ -0,0 +1,82 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 010: RECALL RULES (controles periódicos)
+-- ============================================================================
+-- Descripción: Reglas de recall ("profilaxis cada 6 meses", "control de
+--              ortodoncia mensual") por procedimiento y/o paciente.
+--              Un job diario calcula los pacientes vencidos desde la última
+--              sesión guardada y crea mensajes 'recall' en message_queue.
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS recall_rules (
+  id                     INTEGER PRIMARY KEY AUTOINCREMENT,
+  name                   TEXT NOT NULL,
+  interval_months        INTEGER NOT NULL CHECK (interval_months > 0),
+
+  -- Alcance: ambos NULL = todos los pacientes activos (control general)
+  procedure_template_id  INTEGER,             -- Cuenta desde la última vez que se hizo este procedimiento
+  patient_id             INTEGER,             -- Regla solo para este paciente
+
+  active                 INTEGER NOT NULL DEFAULT 1,
+  created_at             TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at             TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (procedure_template_id) REFERENCES procedure_templates(id) ON DELETE CASCADE,
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_recall_rules_active ON recall_rules(active);
+
+CREATE TRIGGER IF NOT EXISTS trg_recall_rules_updated_at
+AFTER UPDATE ON recall_rules
+FOR EACH ROW
+BEGIN
+  UPDATE recall_rules SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- Un mensaje por (regla, paciente, vencimiento): el job diario no repite recalls
+CREATE TABLE IF NOT EXISTS recall_log (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  rule_id       INTEGER NOT NULL,
+  patient_id    INTEGER NOT NULL,
+  due_date      TEXT NOT NULL,                 -- YYYY-MM-DD
+  message_id    INTEGER,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  UNIQUE (rule_id, patient_id, due_date),
+  FOREIGN KEY (rule_id) REFERENCES recall_rules(id) ON DELETE CASCADE,
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE,
+  FOREIGN KEY (message_id) REFERENCES message_queue(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_recall_log_patient ON recall_log(patient_id);
+
+-- ============================================================================
+-- SEEDS (inactivas: al activarlas se generarían recalls para todo el historial)
+-- Solo la primera vez (marca recallSeeded), para no recrearlas si el usuario las borra
+-- ============================================================================
+INSERT INTO recall_rules (name, interval_months, procedure_template_id, active)
+SELECT 'Profilaxis semestral', 6, id, 0
+FROM procedure_templates
+WHERE name = 'Limpieza simple'
+  AND NOT EXISTS (SELECT 1 FROM recall_rules WHERE name = 'Profilaxis semestral')
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'recallSeeded');
+
+INSERT INTO recall_rules (name, interval_months, procedure_template_id, active)
+SELECT 'Control de ortodoncia', 1, id, 0
+FROM procedure_templates
+WHERE name = 'Control mensual'
+  AND NOT EXISTS (SELECT 1 FROM recall_rules WHERE name = 'Control de ortodoncia')
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'recallSeeded');
+
+-- Plantilla del mensaje (text_templates no tiene UNIQUE(kind, title))
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'recall', 'Control periódico',
+       'Hola {nombre}. Tu última atención fue el {ultima_visita} y ya es momento de tu {control}. ¿Agendamos una cita?',
+       1, 'system', 1
+WHERE NOT EXISTS (
+  SELECT 1 FROM text_templates WHERE kind = 'recall' AND source = 'system'
+)
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'recallSeeded');
+
+INSERT OR IGNORE INTO user_settings (key, value, category)
+VALUES ('recallSeeded', '1', 'recalls');
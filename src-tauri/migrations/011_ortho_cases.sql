@@ -0,0 +1,78 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 011: ORTHODONTIC CASES
+-- ============================================================================
+-- Descripción: Casos de ortodoncia (18–30 controles mensuales) con cuota
+--              mensual, registro de arcos por visita y cargos automáticos.
+--              Cada cuota se registra como una sesión guardada (así entra
+--              al saldo del paciente igual que cualquier visita).
+--              Columna agregada desde Rust: attachments.ortho_case_id
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS ortho_cases (
+  id                       INTEGER PRIMARY KEY AUTOINCREMENT,
+  patient_id               INTEGER NOT NULL,
+
+  start_date               TEXT NOT NULL,              -- YYYY-MM-DD (cuota 1 vence este día)
+  appliance_type           TEXT NOT NULL,              -- "Brackets metálicos", "Autoligado", "Alineadores"
+  planned_duration_months  INTEGER NOT NULL CHECK (planned_duration_months > 0),
+
+  -- Financial
+  total_fee                REAL NOT NULL DEFAULT 0,    -- 0 = sin tope (solo cuotas)
+  monthly_fee              REAL NOT NULL DEFAULT 0,
+
+  -- 'active' | 'retention' | 'completed' | 'cancelled'
+  status                   TEXT NOT NULL DEFAULT 'active',
+  end_date                 TEXT,
+
+  notes                    TEXT,
+  created_at               TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at               TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ortho_cases_patient ON ortho_cases(patient_id);
+CREATE INDEX IF NOT EXISTS idx_ortho_cases_status ON ortho_cases(status);
+CREATE INDEX IF NOT EXISTS idx_attachments_ortho_case ON attachments(ortho_case_id);
+
+CREATE TRIGGER IF NOT EXISTS trg_ortho_cases_updated_at
+AFTER UPDATE ON ortho_cases
+FOR EACH ROW
+BEGIN
+  UPDATE ortho_cases SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- Cuotas generadas (una por número de cuota: el job no cobra dos veces)
+CREATE TABLE IF NOT EXISTS ortho_charges (
+  id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+  case_id             INTEGER NOT NULL,
+  installment_number  INTEGER NOT NULL,               -- 1..planned_duration_months
+  due_date            TEXT NOT NULL,                  -- YYYY-MM-DD
+  amount              REAL NOT NULL,
+  session_id          INTEGER,                        -- Sesión que lleva el cargo al saldo
+  created_at          TEXT NOT NULL DEFAULT (datetime('now')),
+
+  UNIQUE (case_id, installment_number),
+  FOREIGN KEY (case_id) REFERENCES ortho_cases(id) ON DELETE CASCADE,
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_ortho_charges_case ON ortho_charges(case_id);
+
+-- Registro de arcos por visita
+CREATE TABLE IF NOT EXISTS ortho_archwire_log (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  case_id       INTEGER NOT NULL,
+  session_id    INTEGER,                              -- Visita clínica (fotos de esa sesión van al reporte)
+  visit_date    TEXT NOT NULL,                        -- YYYY-MM-DD
+  upper_wire    TEXT,                                 -- "0.014 NiTi", "0.019x0.025 SS"
+  lower_wire    TEXT,
+  elastics      TEXT,                                 -- "Clase II 3/16 bilateral"
+  notes         TEXT,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (case_id) REFERENCES ortho_cases(id) ON DELETE CASCADE,
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_ortho_archwire_case ON ortho_archwire_log(case_id, visit_date);
@@ -0,0 +1,61 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 012: SOAP NOTES
+-- ============================================================================
+-- Descripción: Nota clínica estructurada por sesión (Subjetivo, Objetivo,
+--              Evaluación, Plan). clinical_notes sigue guardando el texto
+--              aplanado para consumidores antiguos e impresión.
+--              Columnas agregadas desde Rust: sessions.soap_subjective,
+--              sessions.soap_objective, sessions.soap_assessment,
+--              sessions.soap_plan
+-- ============================================================================
+
+-- Las secciones SOAP de una sesión firmada tampoco se pueden modificar
+-- (trg_sessions_signed_no_update es anterior a estas columnas)
+CREATE TRIGGER IF NOT EXISTS trg_sessions_signed_no_soap_update
+BEFORE UPDATE ON sessions
+FOR EACH ROW
+WHEN OLD.signed_at IS NOT NULL AND (
+     NEW.soap_subjective IS NOT OLD.soap_subjective
+  OR NEW.soap_objective IS NOT OLD.soap_objective
+  OR NEW.soap_assessment IS NOT OLD.soap_assessment
+  OR NEW.soap_plan IS NOT OLD.soap_plan
+)
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: signed sessions cannot be modified');
+END;
+
+-- ============================================================================
+-- SEEDS: plantillas por sección (text_templates no tiene UNIQUE(kind, title)).
+-- Solo la primera vez: una plantilla de sistema borrada por el usuario no vuelve
+-- a aparecer en el siguiente arranque (marca soapTemplatesSeeded)
+-- ============================================================================
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'soap_subjective', 'Dolor dental',
+       'Paciente {nombre} ({edad} años) refiere dolor en pieza {pieza}. Inicio: [pendiente]. Intensidad: [pendiente]/10.',
+       1, 'system', 1
+WHERE NOT EXISTS (SELECT 1 FROM text_templates WHERE kind = 'soap_subjective' AND source = 'system')
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'soapTemplatesSeeded');
+
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'soap_objective', 'Examen clínico',
+       'Examen intraoral de pieza {pieza}. Inspección: [pendiente]. Percusión: [pendiente]. Pruebas de vitalidad: [pendiente]. Rx: [pendiente].',
+       1, 'system', 1
+WHERE NOT EXISTS (SELECT 1 FROM text_templates WHERE kind = 'soap_objective' AND source = 'system')
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'soapTemplatesSeeded');
+
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'soap_assessment', 'Diagnóstico presuntivo',
+       'Diagnóstico presuntivo en pieza {pieza}: [pendiente].',
+       1, 'system', 1
+WHERE NOT EXISTS (SELECT 1 FROM text_templates WHERE kind = 'soap_assessment' AND source = 'system')
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'soapTemplatesSeeded');
+
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'soap_plan', 'Plan de tratamiento',
+       'Tratamiento propuesto para pieza {pieza}: [pendiente]. Se entregan indicaciones. Próximo control: [pendiente].',
+       1, 'system', 1
+WHERE NOT EXISTS (SELECT 1 FROM text_templates WHERE kind = 'soap_plan' AND source = 'system')
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'soapTemplatesSeeded');
+
+INSERT OR IGNORE INTO user_settings (key, value, category)
+VALUES ('soapTemplatesSeeded', '1', 'templates');
@@ -0,0 +1,62 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 013: PROVIDERS (odontólogos / especialistas)
+-- ============================================================================
+-- Descripción: Profesionales que atienden (reemplaza la lista de nombres de
+--              `signers` y el texto libre `sessions.signer` como referencia).
+--              Columnas agregadas desde Rust: sessions.provider_id,
+--              session_items.provider_id, appointments.provider_id
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS providers (
+  id                INTEGER PRIMARY KEY AUTOINCREMENT,
+  name              TEXT NOT NULL UNIQUE,
+  license_number    TEXT,                     -- Registro profesional / matrícula
+  specialty         TEXT,                     -- "Endodoncia", "Ortodoncia"
+  color             TEXT,                     -- Color en la agenda: "#3b82f6"
+  commission_rate   REAL NOT NULL DEFAULT 0 CHECK (commission_rate >= 0 AND commission_rate <= 100), -- % sobre lo cobrado
+  active            INTEGER NOT NULL DEFAULT 1,
+  created_at        TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at        TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_providers_active ON providers(active);
+CREATE INDEX IF NOT EXISTS idx_sessions_provider ON sessions(provider_id, date);
+CREATE INDEX IF NOT EXISTS idx_session_items_provider ON session_items(provider_id);
+CREATE INDEX IF NOT EXISTS idx_appointments_provider ON appointments(provider_id, starts_at);
+
+CREATE TRIGGER IF NOT EXISTS trg_providers_updated_at
+AFTER UPDATE ON providers
+FOR EACH ROW
+BEGIN
+  UPDATE providers SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- Sesión firmada: el profesional se puede asignar una vez (backfill), no cambiar
+CREATE TRIGGER IF NOT EXISTS trg_sessions_signed_no_provider_update
+BEFORE UPDATE ON sessions
+FOR EACH ROW
+WHEN OLD.signed_at IS NOT NULL
+  AND OLD.provider_id IS NOT NULL
+  AND NEW.provider_id IS NOT OLD.provider_id
+BEGIN
+  SELECT RAISE(ABORT, 'SESSION_LOCKED: signed sessions cannot be modified');
+END;
+
+-- ============================================================================
+-- BACKFILL: los firmantes existentes pasan a ser profesionales (solo la primera vez,
+-- marca providersBackfilled: no se recrean profesionales borrados ni se reasignan sesiones)
+-- ============================================================================
+INSERT INTO providers (name, active)
+SELECT name, active FROM signers
+WHERE NOT EXISTS (SELECT 1 FROM providers)
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'providersBackfilled');
+
+UPDATE sessions
+SET provider_id = (SELECT p.id FROM providers p WHERE p.name = TRIM(sessions.signer))
+WHERE provider_id IS NULL
+  AND signer IS NOT NULL
+  AND TRIM(signer) != ''
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'providersBackfilled');
+
+INSERT OR IGNORE INTO user_settings (key, value, category)
+VALUES ('providersBackfilled', '1', 'providers');
@@ -0,0 +1,39 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 014: RESOURCES (sillones, consultorios, equipos)
+-- ============================================================================
+-- Descripción: Recursos agendables. Una cita puede usar uno o más recursos
+--              (sillón 2 + rayos X). Los choques de horario se validan por
+--              recurso y por profesional; una cita sin profesional ni
+--              recursos bloquea toda la agenda (comportamiento anterior).
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS resources (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  name          TEXT NOT NULL UNIQUE,             -- "Sillón 1", "Consultorio B", "Rayos X"
+  kind          TEXT NOT NULL DEFAULT 'chair',    -- 'chair' | 'room' | 'equipment'
+  color         TEXT,
+  active        INTEGER NOT NULL DEFAULT 1,
+  sort_order    INTEGER NOT NULL DEFAULT 0,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at    TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_resources_active ON resources(active, sort_order);
+
+CREATE TRIGGER IF NOT EXISTS trg_resources_updated_at
+AFTER UPDATE ON resources
+FOR EACH ROW
+BEGIN
+  UPDATE resources SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+CREATE TABLE IF NOT EXISTS appointment_resources (
+  appointment_id  INTEGER NOT NULL,
+  resource_id     INTEGER NOT NULL,
+
+  PRIMARY KEY (appointment_id, resource_id),
+  FOREIGN KEY (appointment_id) REFERENCES appointments(id) ON DELETE CASCADE,
+  FOREIGN KEY (resource_id) REFERENCES resources(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_appointment_resources_resource ON appointment_resources(resource_id);
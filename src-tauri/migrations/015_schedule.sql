@@ -0,0 +1,60 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 015: WORK SCHEDULE (horario semanal, pausas y feriados)
+-- ============================================================================
+-- Descripción: Horario de atención por día de la semana con pausas (almuerzo),
+--              feriados y cierres puntuales. Un profesional puede tener su
+--              propio horario semanal; si no tiene, usa el de la clínica.
+--              Lo respetan la generación de huecos, la validación de citas
+--              y los recordatorios.
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS schedule_hours (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  provider_id   INTEGER,                          -- NULL = horario de la clínica
+  weekday       INTEGER NOT NULL CHECK (weekday BETWEEN 0 AND 6),  -- 0 = domingo (como JS getDay / strftime('%w'))
+  start_time    TEXT NOT NULL,                    -- "HH:MM"
+  end_time      TEXT NOT NULL,                    -- "HH:MM"
+  kind          TEXT NOT NULL DEFAULT 'work',     -- 'work' | 'break'
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (provider_id) REFERENCES providers(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_schedule_hours_owner ON schedule_hours(provider_id, weekday);
+
+CREATE TRIGGER IF NOT EXISTS trg_schedule_hours_updated_at
+AFTER UPDATE ON schedule_hours
+FOR EACH ROW
+BEGIN
+  UPDATE schedule_hours SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+CREATE TABLE IF NOT EXISTS schedule_closures (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  provider_id     INTEGER,                        -- NULL = cierra toda la clínica
+  start_date      TEXT NOT NULL,                  -- "YYYY-MM-DD"
+  end_date        TEXT NOT NULL,                  -- "YYYY-MM-DD" (inclusive)
+  start_time      TEXT,                           -- NULL = todo el día
+  end_time        TEXT,
+  kind            TEXT NOT NULL DEFAULT 'closure',  -- 'holiday' | 'closure'
+  repeats_yearly  INTEGER NOT NULL DEFAULT 0,     -- Feriados fijos (1 de mayo, 25 de diciembre)
+  reason          TEXT,
+  created_at      TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at      TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (provider_id) REFERENCES providers(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_schedule_closures_dates ON schedule_closures(start_date, end_date);
+
+CREATE TRIGGER IF NOT EXISTS trg_schedule_closures_updated_at
+AFTER UPDATE ON schedule_closures
+FOR EACH ROW
+BEGIN
+  UPDATE schedule_closures SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- Sin horario inicial: hasta que se guarde uno (save_schedule) la agenda no restringe
+-- horas (WorkSchedule::is_configured). Un lunes a viernes por defecto bloquearía a las
+-- clínicas que atienden sábados o de noche antes de completar la configuración.
@@ -0,0 +1,30 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 016: APPOINTMENT SERIES (citas recurrentes)
+-- ============================================================================
+-- Descripción: Serie de citas generada desde una regla de recurrencia
+--              (subconjunto de RRULE: semanal, cada N semanas, mensual por día).
+--              Cada ocurrencia es una cita normal enlazada a su serie.
+--              Columnas agregadas desde Rust: appointments.series_id
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS appointment_series (
+  id            INTEGER PRIMARY KEY AUTOINCREMENT,
+  patient_id    INTEGER NOT NULL,
+  rrule         TEXT NOT NULL,                    -- "FREQ=WEEKLY;INTERVAL=2;COUNT=10"
+  dtstart       TEXT NOT NULL,                    -- Inicio de la primera ocurrencia
+  procedure     TEXT NOT NULL,
+  created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at    TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_appointment_series_patient ON appointment_series(patient_id);
+CREATE INDEX IF NOT EXISTS idx_appointments_series ON appointments(series_id, starts_at);
+
+CREATE TRIGGER IF NOT EXISTS trg_appointment_series_updated_at
+AFTER UPDATE ON appointment_series
+FOR EACH ROW
+BEGIN
+  UPDATE appointment_series SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
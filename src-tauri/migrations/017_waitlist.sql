@@ -0,0 +1,69 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 017: WAITLIST (lista de espera)
+-- ============================================================================
+-- Descripción: Pacientes esperando un turno (procedimiento, días/horas
+--              preferidas, urgencia). Cuando se cancela una cita futura, los
+--              mejores candidatos reciben un mensaje 'availability' en la cola.
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS waitlist_entries (
+  id                    INTEGER PRIMARY KEY AUTOINCREMENT,
+  patient_id            INTEGER NOT NULL,
+  procedure             TEXT NOT NULL,
+  provider_id           INTEGER,                  -- NULL = cualquier profesional
+  preferred_weekdays    TEXT NOT NULL DEFAULT '', -- "1,3,5" (0 = domingo); vacío = cualquier día
+  preferred_start_time  TEXT,                     -- "HH:MM"; NULL = cualquier hora
+  preferred_end_time    TEXT,
+  urgency               TEXT NOT NULL DEFAULT 'normal',   -- 'normal' | 'high' | 'urgent'
+  status                TEXT NOT NULL DEFAULT 'waiting',  -- 'waiting' | 'offered' | 'booked' | 'removed'
+  notes                 TEXT,
+  last_offered_at       TEXT,
+  created_at            TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at            TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (patient_id) REFERENCES patients(id) ON DELETE CASCADE,
+  FOREIGN KEY (provider_id) REFERENCES providers(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_status ON waitlist_entries(status, created_at);
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_patient ON waitlist_entries(patient_id);
+
+CREATE TRIGGER IF NOT EXISTS trg_waitlist_entries_updated_at
+AFTER UPDATE ON waitlist_entries
+FOR EACH ROW
+BEGIN
+  UPDATE waitlist_entries SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- Ofertas enviadas: un mismo turno liberado no se ofrece dos veces al mismo paciente.
+-- appointment_id sin FK: la cita cancelada puede borrarse y la oferta queda como historial.
+CREATE TABLE IF NOT EXISTS waitlist_offers (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  entry_id        INTEGER NOT NULL,
+  appointment_id  INTEGER NOT NULL,
+  slot_starts_at  TEXT NOT NULL,
+  slot_ends_at    TEXT NOT NULL,
+  score           INTEGER NOT NULL DEFAULT 0,
+  message_id      INTEGER,
+  created_at      TEXT NOT NULL DEFAULT (datetime('now')),
+
+  UNIQUE (entry_id, appointment_id),
+  FOREIGN KEY (entry_id) REFERENCES waitlist_entries(id) ON DELETE CASCADE,
+  FOREIGN KEY (message_id) REFERENCES message_queue(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_waitlist_offers_appointment ON waitlist_offers(appointment_id);
+
+-- Plantilla del mensaje (text_templates no tiene UNIQUE(kind, title)). Solo la primera
+-- vez (marca availabilityTemplatesSeeded): una plantilla borrada no vuelve al arrancar
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'availability', 'Turno disponible',
+       'Hola {nombre}. Se liberó un turno para {procedimiento} el {fecha} a las {hora}. ¿Te gustaría tomarlo? Responde este mensaje para reservarlo.',
+       1, 'system', 1
+WHERE NOT EXISTS (
+  SELECT 1 FROM text_templates WHERE kind = 'availability' AND source = 'system'
+)
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'availabilityTemplatesSeeded');
+
+INSERT OR IGNORE INTO user_settings (key, value, category)
+VALUES ('availabilityTemplatesSeeded', '1', 'templates');
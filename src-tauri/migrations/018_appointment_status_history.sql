@@ -0,0 +1,31 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 018: APPOINTMENT STATUS HISTORY
+-- ============================================================================
+-- Descripción: Historial de cambios de estado de cada cita (quién, cuándo y
+--              por qué). Base de los reportes de inasistencias y cancelaciones
+--              tardías por paciente. Las transiciones válidas se validan en Rust.
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS appointment_status_history (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  appointment_id  INTEGER NOT NULL,
+  from_status     TEXT,                             -- NULL = creación de la cita
+  to_status       TEXT NOT NULL,
+  reason          TEXT,                             -- Motivo (p. ej. de la cancelación)
+  changed_by      TEXT,                             -- 'patient' | 'clinic' | nombre de quien lo registró
+  changed_at      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),  -- UTC, mismo formato que las citas
+
+  FOREIGN KEY (appointment_id) REFERENCES appointments(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_appointment_status_history_appointment
+  ON appointment_status_history(appointment_id, changed_at);
+
+-- Citas existentes: una entrada inicial con su estado actual (idempotente)
+INSERT INTO appointment_status_history (appointment_id, from_status, to_status, reason, changed_at)
+SELECT a.id, NULL, a.status, 'Estado previo al historial',
+       COALESCE(strftime('%Y-%m-%dT%H:%M:%SZ', a.created_at), strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+FROM appointments a
+WHERE NOT EXISTS (
+  SELECT 1 FROM appointment_status_history h WHERE h.appointment_id = a.id
+);
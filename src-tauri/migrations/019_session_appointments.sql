@@ -0,0 +1,20 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 019: SESSION ↔ APPOINTMENT LINK
+-- ============================================================================
+-- Descripción: Cada sesión puede indicar la cita a la que corresponde. Al
+--              guardarla la cita pasa a 'completed'; las citas pasadas sin
+--              sesión se listan para conciliar (marcar inasistencias).
+--              Columnas agregadas desde Rust: sessions.appointment_id
+-- ============================================================================
+
+-- Una cita se atiende en una sola sesión
+CREATE UNIQUE INDEX IF NOT EXISTS idx_sessions_appointment
+  ON sessions(appointment_id) WHERE appointment_id IS NOT NULL;
+
+-- Borrar una cita no borra la sesión clínica, solo el vínculo
+CREATE TRIGGER IF NOT EXISTS trg_appointments_unlink_sessions
+AFTER DELETE ON appointments
+FOR EACH ROW
+BEGIN
+  UPDATE sessions SET appointment_id = NULL WHERE appointment_id = OLD.id;
+END;
@@ -0,0 +1,10 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 020: ICALENDAR IMPORT
+-- ============================================================================
+-- Descripción: UID del evento de origen para las citas importadas desde .ics
+--              (otras agendas). Evita importar dos veces el mismo evento.
+--              Columnas agregadas desde Rust: appointments.external_uid
+-- ============================================================================
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_appointments_external_uid
+  ON appointments(external_uid) WHERE external_uid IS NOT NULL;
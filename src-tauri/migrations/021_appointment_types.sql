@@ -0,0 +1,53 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 021: APPOINTMENT TYPES
+-- ============================================================================
+-- Descripción: Catálogo de tipos de cita ("Limpieza 45 min", "Endodoncia
+--              90 min") con duración por defecto, color, recursos que
+--              requiere, procedimientos asociados e indicaciones previas
+--              (se incluyen en el recordatorio). El tipo completa la hora
+--              de fin y los recursos de la cita y dimensiona los huecos.
+--              Columnas agregadas desde Rust: appointments.appointment_type_id
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS appointment_types (
+  id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+  name               TEXT NOT NULL UNIQUE,
+  duration_minutes   INTEGER NOT NULL CHECK (duration_minutes > 0),
+  color              TEXT,
+  preparation_notes  TEXT,                            -- "Venir en ayunas", "Traer radiografías"
+  active             INTEGER NOT NULL DEFAULT 1,
+  sort_order         INTEGER NOT NULL DEFAULT 0,
+  created_at         TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at         TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_appointment_types_active ON appointment_types(active, sort_order);
+
+CREATE TRIGGER IF NOT EXISTS trg_appointment_types_updated_at
+AFTER UPDATE ON appointment_types
+FOR EACH ROW
+BEGIN
+  UPDATE appointment_types SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- Recursos que toda cita del tipo necesita (p. ej. Rayos X)
+CREATE TABLE IF NOT EXISTS appointment_type_resources (
+  appointment_type_id  INTEGER NOT NULL,
+  resource_id          INTEGER NOT NULL,
+
+  PRIMARY KEY (appointment_type_id, resource_id),
+  FOREIGN KEY (appointment_type_id) REFERENCES appointment_types(id) ON DELETE CASCADE,
+  FOREIGN KEY (resource_id) REFERENCES resources(id) ON DELETE CASCADE
+);
+
+-- Procedimientos que se suelen hacer en este tipo de cita
+CREATE TABLE IF NOT EXISTS appointment_type_procedures (
+  appointment_type_id    INTEGER NOT NULL,
+  procedure_template_id  INTEGER NOT NULL,
+
+  PRIMARY KEY (appointment_type_id, procedure_template_id),
+  FOREIGN KEY (appointment_type_id) REFERENCES appointment_types(id) ON DELETE CASCADE,
+  FOREIGN KEY (procedure_template_id) REFERENCES procedure_templates(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_appointments_type ON appointments(appointment_type_id);
@@ -0,0 +1,108 @@
+-- ============================================================================
+-- OKLUS - MIGRATION 022: REMINDER RULES
+-- ============================================================================
+-- Descripción: Reglas de recordatorio configurables ("3 días antes", "24 horas
+--              antes", "2 horas antes") que un programador en segundo plano
+--              aplica cada pocos minutos. Cada regla se registra una sola vez
+--              por cita en reminder_log (reemplaza a appointments.reminder_1d_sent_at
+--              como control de envío). Horario de silencio en user_settings:
+--              reminderQuietStart / reminderQuietEnd ("HH:MM").
+--              Plantillas tipo 'reminder': {nombre}, {procedimiento}, {fecha},
+--              {hora}, {cuando}, {preparacion}, {doctor}, {clinica}
+-- ============================================================================
+
+CREATE TABLE IF NOT EXISTS reminder_rules (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  name            TEXT NOT NULL,
+  offset_minutes  INTEGER NOT NULL CHECK (offset_minutes > 0),  -- Antelación respecto del inicio de la cita
+  template_kind   TEXT NOT NULL DEFAULT 'reminder',             -- 'reminder' | 'reminder_1d'
+  template_id     INTEGER,                                      -- NULL = plantilla preferida de template_kind
+  active          INTEGER NOT NULL DEFAULT 1,
+  created_at      TEXT NOT NULL DEFAULT (datetime('now')),
+  updated_at      TEXT NOT NULL DEFAULT (datetime('now')),
+
+  FOREIGN KEY (template_id) REFERENCES text_templates(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_reminder_rules_active ON reminder_rules(active, offset_minutes);
+
+CREATE TRIGGER IF NOT EXISTS trg_reminder_rules_updated_at
+AFTER UPDATE ON reminder_rules
+FOR EACH ROW
+BEGIN
+  UPDATE reminder_rules SET updated_at = datetime('now') WHERE id = NEW.id;
+END;
+
+-- Una entrada por (regla, cita): 'queued' = mensaje creado; 'skipped' = la reagendaron
+-- o se creó tarde y ya correspondía un recordatorio más cercano
+CREATE TABLE IF NOT EXISTS reminder_log (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  rule_id         INTEGER NOT NULL,
+  appointment_id  INTEGER NOT NULL,
+  message_id      INTEGER,
+  status          TEXT NOT NULL DEFAULT 'queued',    -- 'queued' | 'skipped'
+  created_at      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+
+  UNIQUE (rule_id, appointment_id),
+  FOREIGN KEY (rule_id) REFERENCES reminder_rules(id) ON DELETE CASCADE,
+  FOREIGN KEY (appointment_id) REFERENCES appointments(id) ON DELETE CASCADE,
+  FOREIGN KEY (message_id) REFERENCES message_queue(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_reminder_log_appointment ON reminder_log(appointment_id);
+
+-- ============================================================================
+-- PLANTILLAS (text_templates no tiene UNIQUE(kind, title)). Solo la primera vez
+-- (marca reminderRuleTemplatesSeeded): una plantilla borrada no vuelve al arrancar
+-- ============================================================================
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'reminder', 'Recordatorio 3 días',
+       'Hola {nombre}. Te recordamos tu cita de {procedimiento} {cuando} a las {hora}. Si no puedes asistir, avísanos para reprogramarla.',
+       0, 'system', 1
+WHERE NOT EXISTS (
+  SELECT 1 FROM text_templates WHERE kind = 'reminder' AND title = 'Recordatorio 3 días' AND source = 'system'
+)
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'reminderRuleTemplatesSeeded');
+
+INSERT INTO text_templates (kind, title, body, is_favorite, source, sort_order)
+SELECT 'reminder', 'Recordatorio 2 horas',
+       'Hola {nombre}. Te esperamos {cuando} a las {hora} para tu cita de {procedimiento}.',
+       0, 'system', 2
+WHERE NOT EXISTS (
+  SELECT 1 FROM text_templates WHERE kind = 'reminder' AND title = 'Recordatorio 2 horas' AND source = 'system'
+)
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'reminderRuleTemplatesSeeded');
+
+INSERT OR IGNORE INTO user_settings (key, value, category)
+VALUES ('reminderRuleTemplatesSeeded', '1', 'templates');
+
+-- ============================================================================
+-- SEEDS (solo la primera vez, marca reminderRulesSeeded, para no recrear reglas
+-- borradas): la regla de 24 h conserva el comportamiento anterior (plantilla
+-- 'reminder_1d' preferida); las demás quedan inactivas
+-- ============================================================================
+INSERT INTO reminder_rules (name, offset_minutes, template_kind, template_id, active)
+SELECT name, offset_minutes, template_kind, template_id, active
+FROM (
+  SELECT '3 días antes' AS name, 4320 AS offset_minutes, 'reminder' AS template_kind,
+         (SELECT id FROM text_templates WHERE kind = 'reminder' AND title = 'Recordatorio 3 días' AND source = 'system') AS template_id,
+         0 AS active
+  UNION ALL
+  SELECT '24 horas antes', 1440, 'reminder_1d', NULL, 1
+  UNION ALL
+  SELECT '2 horas antes', 120, 'reminder',
+         (SELECT id FROM text_templates WHERE kind = 'reminder' AND title = 'Recordatorio 2 horas' AND source = 'system'),
+         0
+)
+WHERE NOT EXISTS (SELECT 1 FROM reminder_rules)
+  AND NOT EXISTS (SELECT 1 FROM user_settings WHERE key = 'reminderRulesSeeded');
+
+INSERT OR IGNORE INTO user_settings (key, value, category)
+VALUES ('reminderRulesSeeded', '1', 'reminders');
+
+-- Recordatorios de 24 h ya generados con el sistema anterior (idempotente)
+INSERT OR IGNORE INTO reminder_log (rule_id, appointment_id, status, created_at)
+SELECT r.id, a.id, 'queued', COALESCE(strftime('%Y-%m-%dT%H:%M:%SZ', a.reminder_1d_sent_at), strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+FROM appointments a
+JOIN reminder_rules r ON r.template_kind = 'reminder_1d' AND r.offset_minutes = 1440
+WHERE a.reminder_1d_sent_at IS NOT NULL;
@@ -42,7 +42,12 @@ pub struct Session {
     pub full_dx_text: Option<String>,
     pub tooth_dx_json: Option<String>,
     pub clinical_notes: Option<String>,  // RENAMED: from observations
+    #[serde(default)]
+    pub soap: Option<SoapNote>,          // NEW: Structured note; flattened into clinical_notes on save
     pub signer: Option<String>,
+    pub provider_id: Option<i64>,        // NEW: Attending provider (resolved from signer if empty)
+    #[serde(default)]
+    pub appointment_id: Option<i64>,     // NEW: Appointment this session attended (completed on save)
 
     // Financial
     pub budget: f64,
@@ -57,6 +62,11 @@ pub struct Session {
     pub is_saved: Option<bool>,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
+
+    // Sign-off (read-only, set by sign_session)
+    pub signed_at: Option<String>,
+    pub signed_by: Option<String>,
+    pub content_hash: Option<String>,
 }
 
 // SessionItem (antes VisitProcedure)
@@ -73,6 +83,10 @@ pub struct SessionItem {
     pub procedure_notes: Option<String>,    // NEW: Procedure-specific notes
     pub procedure_template_id: Option<i64>,
     pub sort_order: Option<i64>,
+    pub treatment_plan_item_id: Option<i64>, // NEW: Planned item performed in this session
+    pub lab_order_id: Option<i64>,           // NEW: Lab work (crown, bridge...) for this item
+    pub procedure_code: Option<String>,      // NEW: CDT code (defaults to the template's)
+    pub provider_id: Option<i64>,            // NEW: Performing provider (defaults to the session's)
     pub created_at: Option<String>,
 }
 
@@ -82,6 +96,8 @@ pub struct SessionItem {
 pub struct SessionRow {
     pub visit: Session,  // Keep "visit" for frontend compatibility
     pub items: Vec<SessionItem>,
+    #[serde(default)]
+    pub addenda: Vec<SessionAddendum>, // Read-only: appended after sign-off
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -90,6 +106,7 @@ pub struct ProcedureTemplate {
     pub name: String,
     pub default_price: f64,
     pub active: Option<bool>,
+    pub procedure_code: Option<String>, // CDT code (clinical_codes)
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
 }
@@ -101,6 +118,7 @@ pub struct DiagnosisOption {
     pub color: String,
     pub active: Option<bool>,
     pub sort_order: Option<i64>,
+    pub cie10_code: Option<String>,     // CIE-10 code (clinical_codes)
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
 }
@@ -293,7 +311,7 @@ pub async fn get_all_patients_list(
                 SELECT id
                 FROM appointments
                 WHERE patient_id = p.id
-                  AND starts_at >= datetime('now')
+                  AND starts_at >= strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
                   AND status IN ('scheduled', 'confirmed')
                 ORDER BY starts_at ASC
                 LIMIT 1
@@ -302,7 +320,7 @@ pub async fn get_all_patients_list(
                 SELECT starts_at
                 FROM appointments
                 WHERE patient_id = p.id
-                  AND starts_at >= datetime('now')
+                  AND starts_at >= strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
                   AND status IN ('scheduled', 'confirmed')
                 ORDER BY starts_at ASC
                 LIMIT 1
@@ -311,7 +329,7 @@ pub async fn get_all_patients_list(
                 SELECT procedure
                 FROM appointments
                 WHERE patient_id = p.id
-                  AND starts_at >= datetime('now')
+                  AND starts_at >= strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
                   AND status IN ('scheduled', 'confirmed')
                 ORDER BY starts_at ASC
                 LIMIT 1
@@ -320,7 +338,7 @@ pub async fn get_all_patients_list(
                 SELECT status
                 FROM appointments
                 WHERE patient_id = p.id
-                  AND starts_at >= datetime('now')
+                  AND starts_at >= strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
                   AND status IN ('scheduled', 'confirmed')
                 ORDER BY starts_at ASC
                 LIMIT 1
@@ -329,7 +347,7 @@ pub async fn get_all_patients_list(
                 SELECT COALESCE(COUNT(*), 0)
                 FROM appointments
                 WHERE patient_id = p.id
-                  AND starts_at >= datetime('now')
+                  AND starts_at >= strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
                   AND status IN ('scheduled', 'confirmed')
             ) as appointments_count
          FROM patients p
@@ -506,7 +524,9 @@ pub async fn get_visits_by_patient(
                 diagnosis_text, auto_dx_text, full_dx_text, tooth_dx_json,
                 budget, discount, payment, balance, cumulative_balance,
                 payment_method_id, payment_notes,
-                signer, clinical_notes, is_saved, created_at, updated_at
+                signer, clinical_notes, is_saved, created_at, updated_at,
+                signed_at, signed_by, content_hash, provider_id, appointment_id,
+                soap_subjective, soap_objective, soap_assessment, soap_plan
          FROM sessions
          WHERE patient_id = ?1
          ORDER BY date DESC, id DESC"
@@ -536,10 +556,16 @@ pub async fn get_visits_by_patient(
             payment_method_id: row.get("payment_method_id"),
             payment_notes: row.get("payment_notes"),
             signer: row.get("signer"),
+            provider_id: row.get("provider_id"),
+            appointment_id: row.get("appointment_id"),
             clinical_notes: row.get("clinical_notes"),
+            soap: soap_note_from_row(&row),
             is_saved: Some(row.get::<i64, _>("is_saved") != 0),
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
+            signed_at: row.get("signed_at"),
+            signed_by: row.get("signed_by"),
+            content_hash: row.get("content_hash"),
         })
         .collect();
 
@@ -588,7 +614,8 @@ pub async fn get_procedures_by_visit(
 
     let rows = sqlx::query(
         "SELECT id, session_id, name, unit_price, quantity, subtotal, is_active,
-                tooth_number, procedure_notes, procedure_template_id, sort_order, created_at
+                tooth_number, procedure_notes, procedure_template_id, sort_order,
+                treatment_plan_item_id, lab_order_id, procedure_code, provider_id, created_at
          FROM session_items
          WHERE session_id = ?1
          ORDER BY sort_order ASC, id ASC"
@@ -612,6 +639,10 @@ pub async fn get_procedures_by_visit(
             procedure_notes: row.get("procedure_notes"),
             procedure_template_id: row.get("procedure_template_id"),
             sort_order: row.get("sort_order"),
+            treatment_plan_item_id: row.get("treatment_plan_item_id"),
+            lab_order_id: row.get("lab_order_id"),
+            procedure_code: row.get("procedure_code"),
+            provider_id: row.get("provider_id"),
             created_at: row.get("created_at"),
         })
         .collect();
@@ -631,7 +662,9 @@ pub async fn get_sessions_by_patient(
                 diagnosis_text, auto_dx_text, full_dx_text, tooth_dx_json,
                 budget, discount, payment, balance, cumulative_balance,
                 payment_method_id, payment_notes,
-                signer, clinical_notes, is_saved, created_at, updated_at
+                signer, clinical_notes, is_saved, created_at, updated_at,
+                signed_at, signed_by, content_hash, provider_id, appointment_id,
+                soap_subjective, soap_objective, soap_assessment, soap_plan
          FROM sessions
          WHERE patient_id = ?1
          ORDER BY date DESC, id DESC"
@@ -664,15 +697,22 @@ pub async fn get_sessions_by_patient(
             payment_method_id: sess_row.get("payment_method_id"),
             payment_notes: sess_row.get("payment_notes"),
             signer: sess_row.get("signer"),
+            provider_id: sess_row.get("provider_id"),
+            appointment_id: sess_row.get("appointment_id"),
             clinical_notes: sess_row.get("clinical_notes"),
+            soap: soap_note_from_row(&sess_row),
             is_saved: Some(sess_row.get::<i64, _>("is_saved") != 0),
             created_at: sess_row.get("created_at"),
             updated_at: sess_row.get("updated_at"),
+            signed_at: sess_row.get("signed_at"),
+            signed_by: sess_row.get("signed_by"),
+            content_hash: sess_row.get("content_hash"),
         };
 
         let item_rows = sqlx::query(
             "SELECT id, session_id, name, unit_price, quantity, subtotal, is_active,
-                    tooth_number, procedure_notes, procedure_template_id, sort_order, created_at
+                    tooth_number, procedure_notes, procedure_template_id, sort_order,
+                    treatment_plan_item_id, lab_order_id, procedure_code, provider_id, created_at
              FROM session_items
              WHERE session_id = ?1
              ORDER BY sort_order ASC, id ASC"
@@ -696,11 +736,17 @@ pub async fn get_sessions_by_patient(
                 procedure_notes: row.get("procedure_notes"),
                 procedure_template_id: row.get("procedure_template_id"),
                 sort_order: row.get("sort_order"),
+                treatment_plan_item_id: row.get("treatment_plan_item_id"),
+                lab_order_id: row.get("lab_order_id"),
+                procedure_code: row.get("procedure_code"),
+                provider_id: row.get("provider_id"),
                 created_at: row.get("created_at"),
             })
             .collect();
 
-        sessions.push(SessionRow { visit: session, items });
+        let addenda = load_session_addenda(&pool, session_id).await?;
+
+        sessions.push(SessionRow { visit: session, items, addenda });
     }
 
     Ok(sessions)
@@ -718,7 +764,9 @@ pub async fn get_sessions_by_visit(
                 diagnosis_text, auto_dx_text, full_dx_text, tooth_dx_json,
                 budget, discount, payment, balance, cumulative_balance,
                 payment_method_id, payment_notes,
-                signer, clinical_notes, is_saved, created_at, updated_at
+                signer, clinical_notes, is_saved, created_at, updated_at,
+                signed_at, signed_by, content_hash, provider_id, appointment_id,
+                soap_subjective, soap_objective, soap_assessment, soap_plan
          FROM sessions
          WHERE id = ?1"
     )
@@ -746,15 +794,22 @@ pub async fn get_sessions_by_visit(
             payment_method_id: row.get("payment_method_id"),
             payment_notes: row.get("payment_notes"),
             signer: row.get("signer"),
+            provider_id: row.get("provider_id"),
+            appointment_id: row.get("appointment_id"),
             clinical_notes: row.get("clinical_notes"),
+            soap: soap_note_from_row(&row),
             is_saved: Some(row.get::<i64, _>("is_saved") != 0),
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
+            signed_at: row.get("signed_at"),
+            signed_by: row.get("signed_by"),
+            content_hash: row.get("content_hash"),
         };
 
         let item_rows = sqlx::query(
             "SELECT id, session_id, name, unit_price, quantity, subtotal, is_active,
-                    tooth_number, procedure_notes, procedure_template_id, sort_order, created_at
+                    tooth_number, procedure_notes, procedure_template_id, sort_order,
+                    treatment_plan_item_id, lab_order_id, procedure_code, provider_id, created_at
              FROM session_items
              WHERE session_id = ?1
              ORDER BY sort_order ASC, id ASC"
@@ -778,11 +833,17 @@ pub async fn get_sessions_by_visit(
                 procedure_notes: row.get("procedure_notes"),
                 procedure_template_id: row.get("procedure_template_id"),
                 sort_order: row.get("sort_order"),
+                treatment_plan_item_id: row.get("treatment_plan_item_id"),
+                lab_order_id: row.get("lab_order_id"),
+                procedure_code: row.get("procedure_code"),
+                provider_id: row.get("provider_id"),
                 created_at: row.get("created_at"),
             })
             .collect();
 
-        Ok(vec![SessionRow { visit: session, items }])
+        let addenda = load_session_addenda(&pool, visit_id).await?;
+
+        Ok(vec![SessionRow { visit: session, items, addenda }])
     } else {
         Ok(vec![])
     }
@@ -807,6 +868,9 @@ pub async fn save_visit_with_sessions(
     let pool = db_pool.0.lock().await;
     let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
 
+    // 0. Validate and normalize the odontogram snapshot (legacy shapes are migrated)
+    let tooth_dx_json = normalize_tooth_dx_json(&mut tx, visit.tooth_dx_json.as_deref()).await?;
+
     // 1. Upsert patient
     let patient_id = if let Some(id) = patient.id {
         sqlx::query(
@@ -852,6 +916,7 @@ pub async fn save_visit_with_sessions(
 
     // 2. Save each session
     let mut last_session_id = 0i64;
+    let mut touched_plan_ids: Vec<i64> = Vec::new();
 
     for session in sessions {
         // Calculate budget from active items (backend is source of truth)
@@ -897,8 +962,35 @@ pub async fn save_visit_with_sessions(
 
         let cumulative_balance = previous_cumulative + calculated_balance;
 
+        // SOAP: sections are stored as-is and clinical_notes keeps a flattened copy
+        // for older consumers and printing. Without SOAP the free-text note wins.
+        let soap = session.visit.soap.as_ref().map(SoapNote::normalized).filter(|n| !n.is_empty());
+        let clinical_notes = match &soap {
+            Some(note) => Some(note.flatten()),
+            None => session.visit.clinical_notes.clone(),
+        };
+        let soap = soap.unwrap_or_default();
+
+        // Provider: explicit id wins; older frontends only send the signer name
+        let provider_id: Option<i64> = match session.visit.provider_id.filter(|&i| i > 0) {
+            Some(id) => Some(id),
+            None => sqlx::query_scalar("SELECT id FROM providers WHERE name = TRIM(?1)")
+                .bind(session.visit.signer.as_deref().unwrap_or(""))
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?,
+        };
+
+        // La cita atendida en esta sesión pasa a 'completed'
+        let appointment_id = session.visit.appointment_id.filter(|&i| i > 0);
+        if let Some(appointment_id) = appointment_id {
+            attend_appointment(&mut tx, appointment_id, patient_id, session.visit.id.filter(|&i| i > 0)).await?;
+        }
+
         // Upsert session
         let session_id = if let Some(id) = session.visit.id.filter(|&i| i > 0) {
+            ensure_session_unlocked(&mut tx, id).await?;
+
             sqlx::query(
                 "UPDATE sessions
                  SET patient_id = ?1, date = ?2, reason_type = ?3, reason_detail = ?4,
@@ -906,7 +998,10 @@ pub async fn save_visit_with_sessions(
                      clinical_notes = ?9, signer = ?10,
                      budget = ?11, discount = ?12, payment = ?13, balance = ?14, cumulative_balance = ?15,
                      payment_method_id = ?16, payment_notes = ?17,
-                     is_saved = ?18, updated_at = CURRENT_TIMESTAMP
+                     is_saved = ?18,
+                     soap_subjective = ?20, soap_objective = ?21, soap_assessment = ?22, soap_plan = ?23,
+                     provider_id = ?24, appointment_id = COALESCE(?25, appointment_id),
+                     updated_at = CURRENT_TIMESTAMP
                  WHERE id = ?19"
             )
             .bind(patient_id)
@@ -916,8 +1011,8 @@ pub async fn save_visit_with_sessions(
             .bind(&visit.diagnosis_text)
             .bind(&visit.auto_dx_text)
             .bind(&visit.full_dx_text)
-            .bind(&visit.tooth_dx_json)
-            .bind(&session.visit.clinical_notes)
+            .bind(&tooth_dx_json)
+            .bind(&clinical_notes)
             .bind(&session.visit.signer)
             .bind(calculated_budget)
             .bind(session.visit.discount)
@@ -928,6 +1023,12 @@ pub async fn save_visit_with_sessions(
             .bind(&session.visit.payment_notes)
             .bind(1_i64)  // is_saved = 1
             .bind(id)
+            .bind(&soap.subjective)
+            .bind(&soap.objective)
+            .bind(&soap.assessment)
+            .bind(&soap.plan)
+            .bind(provider_id)
+            .bind(appointment_id)
             .execute(&mut *tx)
             .await
             .map_err(|e| e.to_string())?;
@@ -938,8 +1039,11 @@ pub async fn save_visit_with_sessions(
                                       diagnosis_text, auto_dx_text, full_dx_text, tooth_dx_json,
                                       clinical_notes, signer,
                                       budget, discount, payment, balance, cumulative_balance,
-                                      payment_method_id, payment_notes, is_saved)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)"
+                                      payment_method_id, payment_notes, is_saved,
+                                      soap_subjective, soap_objective, soap_assessment, soap_plan, provider_id,
+                                      appointment_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                         ?19, ?20, ?21, ?22, ?23, ?24)"
             )
             .bind(patient_id)
             .bind(&session.visit.date)
@@ -948,8 +1052,8 @@ pub async fn save_visit_with_sessions(
             .bind(&visit.diagnosis_text)
             .bind(&visit.auto_dx_text)
             .bind(&visit.full_dx_text)
-            .bind(&visit.tooth_dx_json)
-            .bind(&session.visit.clinical_notes)
+            .bind(&tooth_dx_json)
+            .bind(&clinical_notes)
             .bind(&session.visit.signer)
             .bind(calculated_budget)
             .bind(session.visit.discount)
@@ -959,6 +1063,12 @@ pub async fn save_visit_with_sessions(
             .bind(session.visit.payment_method_id)
             .bind(&session.visit.payment_notes)
             .bind(1_i64)  // is_saved = 1
+            .bind(&soap.subjective)
+            .bind(&soap.objective)
+            .bind(&soap.assessment)
+            .bind(&soap.plan)
+            .bind(provider_id)
+            .bind(appointment_id)
             .execute(&mut *tx)
             .await
             .map_err(|e| e.to_string())?;
@@ -967,6 +1077,19 @@ pub async fn save_visit_with_sessions(
 
         last_session_id = session_id;
 
+        // Treatment plans linked before this save (items may have been removed)
+        let previous_plan_ids: Vec<i64> = sqlx::query_scalar(
+            "SELECT DISTINCT tpi.plan_id
+             FROM session_items si
+             JOIN treatment_plan_items tpi ON tpi.id = si.treatment_plan_item_id
+             WHERE si.session_id = ?1"
+        )
+        .bind(session_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+        touched_plan_ids.extend(previous_plan_ids);
+
         // Delete existing items for this session
         sqlx::query("DELETE FROM session_items WHERE session_id = ?1")
             .bind(session_id)
@@ -979,10 +1102,34 @@ pub async fn save_visit_with_sessions(
             if item.quantity > 0 || !item.name.trim().is_empty() {
                 let is_active = item.is_active.unwrap_or(item.quantity > 0) as i64;
 
+                // Only items of this patient's own plans can be linked
+                let plan_id = match item.treatment_plan_item_id {
+                    Some(plan_item_id) => Some(
+                        sqlx::query_scalar::<_, i64>(
+                            "SELECT tpi.plan_id
+                             FROM treatment_plan_items tpi
+                             JOIN treatment_plans tp ON tp.id = tpi.plan_id
+                             WHERE tpi.id = ?1 AND tp.patient_id = ?2"
+                        )
+                        .bind(plan_item_id)
+                        .bind(patient_id)
+                        .fetch_optional(&mut *tx)
+                        .await
+                        .map_err(|e| e.to_string())?
+                        .ok_or_else(|| {
+                            format!("Treatment plan item {} does not belong to the patient", plan_item_id)
+                        })?,
+                    ),
+                    None => None,
+                };
+
                 sqlx::query(
                     "INSERT INTO session_items (session_id, name, unit_price, quantity, subtotal, is_active,
-                                               tooth_number, procedure_notes, procedure_template_id, sort_order)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"
+                                               tooth_number, procedure_notes, procedure_template_id, sort_order,
+                                               treatment_plan_item_id, lab_order_id, procedure_code, provider_id)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12,
+                             COALESCE(?13, (SELECT NULLIF(procedure_code, '') FROM procedure_templates WHERE id = ?9)),
+                             ?14)"
                 )
                 .bind(session_id)
                 .bind(&item.name)
@@ -994,17 +1141,52 @@ pub async fn save_visit_with_sessions(
                 .bind(&item.procedure_notes)
                 .bind(item.procedure_template_id)
                 .bind(index as i64)
+                .bind(item.treatment_plan_item_id)
+                .bind(item.lab_order_id)
+                .bind(item.procedure_code.as_deref().map(str::trim).filter(|c| !c.is_empty()))
+                .bind(item.provider_id.filter(|&i| i > 0).or(provider_id))
                 .execute(&mut *tx)
                 .await
                 .map_err(|e| e.to_string())?;
+
+                touched_plan_ids.extend(plan_id);
             }
         }
     }
 
-    // ============================================================================
+    // Update completion/status of treatment plans whose items were performed or removed
+    touched_plan_ids.sort_unstable();
+    touched_plan_ids.dedup();
+    for plan_id in touched_plan_ids {
+        refresh_treatment_plan_progress(&mut tx, plan_id).await?;
+    }
+
+    // Per-tooth history is derived from the saved snapshots
+    rebuild_tooth_events(&mut tx, patient_id).await?;
+
     // TRIADA: Apply debt opening/closing logic
-    // ============================================================================
+    apply_debt_triada(&mut tx, patient_id, last_session_id).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    let mut result = HashMap::new();
+    result.insert("patient_id".to_string(), patient_id);
+    result.insert("visit_id".to_string(), last_session_id);
+
+    Ok(result)
+}
 
+// ============================================================================
+// TRIADA: Apply debt opening/closing logic
+// ============================================================================
+
+/// Opens, closes or unarchives the patient's debt after `last_session_id` was saved.
+/// Shared by save_visit_with_sessions and every backend flow that posts charges.
+async fn apply_debt_triada(
+    conn: &mut sqlx::SqliteConnection,
+    patient_id: i64,
+    last_session_id: i64,
+) -> Result<(), String> {
     // Get previous cumulative balance (before this save)
     let previous_cumulative: f64 = sqlx::query_scalar(
         "SELECT COALESCE(cumulative_balance, 0.0)
@@ -1015,7 +1197,7 @@ pub async fn save_visit_with_sessions(
     )
     .bind(patient_id)
     .bind(last_session_id)
-    .fetch_optional(&mut *tx)
+    .fetch_optional(&mut *conn)
     .await
     .map_err(|e| e.to_string())?
     .unwrap_or(0.0);
@@ -1029,7 +1211,7 @@ pub async fn save_visit_with_sessions(
          LIMIT 1"
     )
     .bind(patient_id)
-    .fetch_optional(&mut *tx)
+    .fetch_optional(&mut *conn)
     .await
     .map_err(|e| e.to_string())?
     .unwrap_or(0.0);
@@ -1041,7 +1223,7 @@ pub async fn save_visit_with_sessions(
         "SELECT debt_opened_at, debt_archived FROM patients WHERE id = ?1"
     )
     .bind(patient_id)
-    .fetch_one(&mut *tx)
+    .fetch_one(&mut *conn)
     .await
     .map_err(|e| e.to_string())?;
 
@@ -1058,7 +1240,7 @@ pub async fn save_visit_with_sessions(
         )
         .bind(last_session_id)
         .bind(patient_id)
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await
         .map_err(|e| e.to_string())?;
     } else if previous_cumulative > 0.0 && new_cumulative <= 0.0 {
@@ -1072,7 +1254,7 @@ pub async fn save_visit_with_sessions(
              WHERE id = ?1"
         )
         .bind(patient_id)
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await
         .map_err(|e| e.to_string())?;
     } else if new_cumulative > 0.0 && debt_archived == 1 {
@@ -1085,7 +1267,7 @@ pub async fn save_visit_with_sessions(
              WHERE id = ?1"
         )
         .bind(patient_id)
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await
         .map_err(|e| e.to_string())?;
     } else if new_cumulative > 0.0 && debt_opened_at.is_none() {
@@ -1099,18 +1281,12 @@ pub async fn save_visit_with_sessions(
         )
         .bind(last_session_id)
         .bind(patient_id)
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await
         .map_err(|e| e.to_string())?;
     }
 
-    tx.commit().await.map_err(|e| e.to_string())?;
-
-    let mut result = HashMap::new();
-    result.insert("patient_id".to_string(), patient_id);
-    result.insert("visit_id".to_string(), last_session_id);
-
-    Ok(result)
+    Ok(())
 }
 
 // =========================
@@ -1124,7 +1300,7 @@ pub async fn get_procedure_templates(
     let pool = db_pool.0.lock().await;
 
     let rows = sqlx::query(
-        "SELECT id, name, default_price, active, created_at, updated_at
+        "SELECT id, name, default_price, active, procedure_code, created_at, updated_at
          FROM procedure_templates
          WHERE active = 1
          ORDER BY name ASC"
@@ -1140,6 +1316,7 @@ pub async fn get_procedure_templates(
             name: row.get("name"),
             default_price: row.get("default_price"),
             active: Some(row.get::<i64, _>("active") != 0),
+            procedure_code: row.get::<Option<String>, _>("procedure_code").filter(|c| !c.is_empty()),
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
         })
@@ -1162,20 +1339,25 @@ pub async fn save_procedure_templates(
         .map_err(|e| e.to_string())?;
 
     for template in templates {
+        // None = keep the current code, "" = remove it
+        let procedure_code = normalize_clinical_code(&mut tx, "CDT", template.procedure_code.as_deref()).await?;
+
         if let Some(id) = template.id {
             sqlx::query(
-                "INSERT INTO procedure_templates (id, name, default_price, active)
-                 VALUES (?1, ?2, ?3, ?4)
+                "INSERT INTO procedure_templates (id, name, default_price, active, procedure_code)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
                  ON CONFLICT(id) DO UPDATE SET
                    name = excluded.name,
                    default_price = excluded.default_price,
                    active = excluded.active,
+                   procedure_code = COALESCE(excluded.procedure_code, procedure_templates.procedure_code),
                    updated_at = CURRENT_TIMESTAMP"
             )
             .bind(id)
             .bind(&template.name)
             .bind(template.default_price)
             .bind(template.active.unwrap_or(true) as i64)
+            .bind(&procedure_code)
             .execute(&mut *tx)
             .await
             .map_err(|e| e.to_string())?;
@@ -1191,23 +1373,26 @@ pub async fn save_procedure_templates(
             if let Some(existing_id) = existing {
                 sqlx::query(
                     "UPDATE procedure_templates
-                     SET default_price = ?1, active = ?2, updated_at = CURRENT_TIMESTAMP
-                     WHERE id = ?3"
+                     SET default_price = ?1, active = ?2, procedure_code = COALESCE(?3, procedure_code),
+                         updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ?4"
                 )
                 .bind(template.default_price)
                 .bind(template.active.unwrap_or(true) as i64)
+                .bind(&procedure_code)
                 .bind(existing_id)
                 .execute(&mut *tx)
                 .await
                 .map_err(|e| e.to_string())?;
             } else {
                 sqlx::query(
-                    "INSERT INTO procedure_templates (name, default_price, active)
-                     VALUES (?1, ?2, ?3)"
+                    "INSERT INTO procedure_templates (name, default_price, active, procedure_code)
+                     VALUES (?1, ?2, ?3, ?4)"
                 )
                 .bind(&template.name)
                 .bind(template.default_price)
                 .bind(template.active.unwrap_or(true) as i64)
+                .bind(&procedure_code)
                 .execute(&mut *tx)
                 .await
                 .map_err(|e| e.to_string())?;
@@ -1239,7 +1424,7 @@ pub async fn get_diagnosis_options(
     let pool = db_pool.0.lock().await;
 
     let rows = sqlx::query(
-        "SELECT id, label, color, active, sort_order, created_at, updated_at
+        "SELECT id, label, color, active, sort_order, cie10_code, created_at, updated_at
          FROM diagnosis_options
          WHERE active = 1
          ORDER BY sort_order ASC, label ASC"
@@ -1256,6 +1441,7 @@ pub async fn get_diagnosis_options(
             color: row.get("color"),
             active: Some(row.get::<i64, _>("active") != 0),
             sort_order: row.get("sort_order"),
+            cie10_code: row.get::<Option<String>, _>("cie10_code").filter(|c| !c.is_empty()),
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
         })
@@ -1271,6 +1457,16 @@ pub async fn save_diagnosis_options(
 ) -> Result<(), String> {
     let pool = db_pool.0.lock().await;
 
+    // Validar los códigos CIE-10 antes de tocar nada.
+    // None = conservar el código actual, "" = quitarlo
+    let mut cie10_codes = Vec::with_capacity(options.len());
+    {
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+        for option in &options {
+            cie10_codes.push(normalize_clinical_code(&mut conn, "CIE10", option.cie10_code.as_deref()).await?);
+        }
+    }
+
     // Obtener IDs actuales en la base de datos
     let current_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM diagnosis_options")
         .fetch_all(&*pool)
@@ -1295,34 +1491,37 @@ pub async fn save_diagnosis_options(
     }
 
     // Actualizar o insertar las opciones restantes
-    for option in options {
+    for (option, cie10_code) in options.iter().zip(cie10_codes) {
         if let Some(id) = option.id {
             sqlx::query(
                 "UPDATE diagnosis_options
-                 SET label = ?1, color = ?2, active = ?3, sort_order = ?4
-                 WHERE id = ?5"
+                 SET label = ?1, color = ?2, active = ?3, sort_order = ?4, cie10_code = COALESCE(?5, cie10_code)
+                 WHERE id = ?6"
             )
             .bind(&option.label)
             .bind(&option.color)
             .bind(option.active.unwrap_or(true) as i64)
             .bind(option.sort_order.unwrap_or(0))
+            .bind(&cie10_code)
             .bind(id)
             .execute(&*pool)
             .await
             .map_err(|e| e.to_string())?;
         } else {
             sqlx::query(
-                "INSERT INTO diagnosis_options (label, color, active, sort_order)
-                 VALUES (?1, ?2, ?3, ?4)
+                "INSERT INTO diagnosis_options (label, color, active, sort_order, cie10_code)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
                  ON CONFLICT(label) DO UPDATE SET
                    color = excluded.color,
                    active = excluded.active,
-                   sort_order = excluded.sort_order"
+                   sort_order = excluded.sort_order,
+                   cie10_code = COALESCE(excluded.cie10_code, diagnosis_options.cie10_code)"
             )
             .bind(&option.label)
             .bind(&option.color)
             .bind(option.active.unwrap_or(true) as i64)
             .bind(option.sort_order.unwrap_or(0))
+            .bind(&cie10_code)
             .execute(&*pool)
             .await
             .map_err(|e| e.to_string())?;
@@ -1381,6 +1580,13 @@ pub async fn create_signer(
     .await
     .map_err(|e| e.to_string())?;
 
+    // Los firmantes también son profesionales (sessions.provider_id se resuelve por nombre)
+    sqlx::query("INSERT OR IGNORE INTO providers (name) VALUES (TRIM(?1))")
+        .bind(&name)
+        .execute(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
     Ok(result.last_insert_rowid())
 }
 
@@ -2200,6 +2406,10 @@ pub async fn create_diagnostic_update_session(
     full_dx_text: Option<String>,
 ) -> Result<CreateDiagnosticUpdateSessionResponse, String> {
     let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    // Validate and normalize the odontogram snapshot (legacy shapes are migrated)
+    let tooth_dx_json = normalize_tooth_dx_json(&mut tx, tooth_dx_json.as_deref()).await?;
 
     // Get today's date in ISO format
     let today = chrono::Local::now().format("%Y-%m-%d").to_string();
@@ -2218,13 +2428,17 @@ pub async fn create_diagnostic_update_session(
     .bind(&tooth_dx_json)
     .bind(&auto_dx_text)
     .bind(&full_dx_text)
-    .execute(&*pool)
+    .execute(&mut *tx)
     .await
     .map_err(|e| format!("Failed to create diagnostic update session: {}", e))?;
 
-    Ok(CreateDiagnosticUpdateSessionResponse {
-        session_id: result.last_insert_rowid(),
-    })
+    let session_id = result.last_insert_rowid();
+
+    rebuild_tooth_events(&mut tx, patient_id).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(CreateDiagnosticUpdateSessionResponse { session_id })
 }
 
 // =========================
@@ -2253,6 +2467,20 @@ pub async fn generate_pdf_with_dialog(
     html_content: String,
     default_filename: String,
 ) -> Result<GeneratePdfResponse, String> {
+    let file_path = ask_pdf_save_path(&app_handle, &default_filename)?;
+    let pdf_data = print_html_to_pdf(&html_content)?;
+
+    // Write PDF to file
+    fs::write(&file_path, pdf_data)
+        .map_err(|e| format!("Failed to write PDF: {}", e))?;
+
+    Ok(GeneratePdfResponse {
+        file_path: file_path.to_string_lossy().to_string(),
+    })
+}
+
+/// Shows the native "Save As" dialog and returns the chosen path (always .pdf)
+fn ask_pdf_save_path(app_handle: &tauri::AppHandle, default_filename: &str) -> Result<PathBuf, String> {
     use tauri_plugin_dialog::DialogExt;
 
     // Show "Save As" dialog
@@ -2260,7 +2488,7 @@ pub async fn generate_pdf_with_dialog(
         .dialog()
         .file()
         .add_filter("PDF", &["pdf"])
-        .set_file_name(&default_filename)
+        .set_file_name(default_filename)
         .blocking_save_file();
 
     // User cancelled the dialog
@@ -2277,6 +2505,11 @@ pub async fn generate_pdf_with_dialog(
         file_path = file_path.with_extension("pdf");
     }
 
+    Ok(file_path)
+}
+
+/// Renders HTML to an A4 PDF using headless Chrome (print media emulation)
+fn print_html_to_pdf(html_content: &str) -> Result<Vec<u8>, String> {
     // Generate PDF using headless Chrome
     let browser = Browser::default()
         .map_err(|e| format!("Failed to launch browser: {}", e))?;
@@ -2285,7 +2518,7 @@ pub async fn generate_pdf_with_dialog(
         .map_err(|e| format!("Failed to create tab: {}", e))?;
 
     // Navigate to data URL with HTML content
-    let data_url = format!("data:text/html;charset=utf-8,{}", urlencoding::encode(&html_content));
+    let data_url = format!("data:text/html;charset=utf-8,{}", urlencoding::encode(html_content));
     tab.navigate_to(&data_url)
         .map_err(|e| format!("Failed to navigate: {}", e))?;
 
@@ -2315,16 +2548,8 @@ pub async fn generate_pdf_with_dialog(
         generate_tagged_pdf: Some(false),
     };
 
-    let pdf_data = tab.print_to_pdf(Some(pdf_options))
-        .map_err(|e| format!("Failed to generate PDF: {}", e))?;
-
-    // Write PDF to file
-    fs::write(&file_path, pdf_data)
-        .map_err(|e| format!("Failed to write PDF: {}", e))?;
-
-    Ok(GeneratePdfResponse {
-        file_path: file_path.to_string_lossy().to_string(),
-    })
+    tab.print_to_pdf(Some(pdf_options))
+        .map_err(|e| format!("Failed to generate PDF: {}", e))
 }
 
 // ============================================================================
@@ -2392,6 +2617,14 @@ pub async fn update_text_template(
 ) -> Result<(), String> {
     let pool = db_pool.0.lock().await;
 
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM text_templates WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    let kind = kind.ok_or_else(|| format!("Template {} not found", id))?;
+    validate_template_body(&kind, &body)?;
+
     sqlx::query(
         "UPDATE text_templates
          SET body = ?
@@ -2435,6 +2668,8 @@ pub async fn create_text_template(
     title: String,
     body: String,
 ) -> Result<i64, String> {
+    validate_template_body(&kind, &body)?;
+
     let pool = db_pool.0.lock().await;
 
     let result = sqlx::query(
@@ -2470,6 +2705,193 @@ pub async fn delete_text_template(
     Ok(())
 }
 
+// =========================
+// TEMPLATE ENGINE
+// =========================
+
+/// Variables permitidas por tipo de plantilla (español, una sola llave: `{nombre}`)
+const TEMPLATE_VARIABLES: &[(&str, &[&str])] = &[
+    ("whatsapp_message", &["nombre", "saldo", "dias", "clinica"]),
+    ("diagnosis", &["nombre", "edad", "pieza", "fecha", "doctor"]),
+    ("clinical_notes", &["nombre", "edad", "pieza", "fecha", "doctor"]),
+    ("reason_detail", &["nombre", "edad", "pieza", "fecha", "doctor"]),
+    ("procedure_notes", &["nombre", "procedimiento", "pieza", "fecha", "doctor"]),
+    ("payment_notes", &["monto", "nombre", "fecha"]),
+    ("reminder_1d", &["nombre", "procedimiento", "fecha", "hora", "doctor", "clinica", "preparacion"]),
+    ("reminder", &["nombre", "procedimiento", "fecha", "hora", "cuando", "doctor", "clinica", "preparacion"]),
+    ("consent", &["paciente", "cedula", "procedimiento", "fecha", "doctor"]),
+    ("recall", &["nombre", "control", "ultima_visita", "fecha_control", "fecha", "doctor", "clinica"]),
+    ("availability", &["nombre", "procedimiento", "fecha", "hora", "doctor", "clinica"]),
+    ("soap_subjective", &["nombre", "edad", "pieza", "fecha", "doctor"]),
+    ("soap_objective", &["nombre", "edad", "pieza", "fecha", "doctor"]),
+    ("soap_assessment", &["nombre", "edad", "pieza", "fecha", "doctor"]),
+    ("soap_plan", &["nombre", "edad", "pieza", "fecha", "doctor"]),
+];
+
+/// Texto de respaldo si el usuario eliminó todas las plantillas de recordatorio
+const DEFAULT_REMINDER_1D_TEMPLATE: &str =
+    "Hola {nombre}. Te recuerdo que tienes tu cita de {procedimiento} mañana a las {hora}. ¡Te esperamos!";
+
+#[derive(Debug, Serialize)]
+pub struct RenderedTemplate {
+    pub template_id: Option<i64>,
+    pub kind: String,
+    pub text: String,
+    pub missing_variables: Vec<String>, // Quedan sin reemplazar en el texto
+}
+
+fn template_variables_for(kind: &str) -> Option<&'static [&'static str]> {
+    TEMPLATE_VARIABLES
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, vars)| *vars)
+}
+
+/// Encuentra los `{...}` del texto (sin saltos de línea adentro): (rango en bytes, nombre)
+fn scan_template_placeholders(body: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut placeholders = Vec::new();
+    let mut search_from = 0;
+
+    while let Some(offset) = body[search_from..].find('{') {
+        let start = search_from + offset;
+        let rest = &body[start + 1..];
+        match rest.find(['}', '{', '\n']) {
+            Some(end) if rest[end..].starts_with('}') => {
+                placeholders.push((start..start + end + 2, &rest[..end]));
+                search_from = start + end + 2;
+            }
+            _ => search_from = start + 1,
+        }
+    }
+
+    placeholders
+}
+
+fn validate_template_body(kind: &str, body: &str) -> Result<(), String> {
+    let allowed = template_variables_for(kind)
+        .ok_or_else(|| format!("Unknown template kind: {}", kind))?;
+
+    let mut unknown: Vec<&str> = Vec::new();
+    for (_, name) in scan_template_placeholders(body) {
+        if !allowed.contains(&name) && !unknown.contains(&name) {
+            unknown.push(name);
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown template variables: {}. Allowed for {}: {}",
+            unknown.iter().map(|v| format!("{{{}}}", v)).collect::<Vec<_>>().join(", "),
+            kind,
+            allowed.iter().map(|v| format!("{{{}}}", v)).collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+/// Reemplaza las variables en una sola pasada (los valores no se vuelven a interpretar).
+/// Las variables sin valor quedan tal cual en el texto y se reportan en `missing_variables`.
+fn render_template_body(kind: &str, body: &str, context: &HashMap<String, String>) -> RenderedTemplate {
+    let mut text = String::with_capacity(body.len());
+    let mut missing_variables: Vec<String> = Vec::new();
+    let mut last = 0;
+
+    for (range, name) in scan_template_placeholders(body) {
+        text.push_str(&body[last..range.start]);
+        match context.get(name) {
+            Some(value) => text.push_str(value),
+            None => {
+                text.push_str(&body[range.clone()]);
+                if !missing_variables.iter().any(|m| m == name) {
+                    missing_variables.push(name.to_string());
+                }
+            }
+        }
+        last = range.end;
+    }
+    text.push_str(&body[last..]);
+
+    RenderedTemplate {
+        template_id: None,
+        kind: kind.to_string(),
+        text,
+        missing_variables,
+    }
+}
+
+/// Valores que el backend conoce sin ayuda del llamador: fecha de hoy, doctor y clínica
+async fn default_template_context(
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<HashMap<String, String>, String> {
+    let mut context = HashMap::new();
+    let today = load_clinic_timezone(conn).await?.now_local().date();
+    context.insert("fecha".to_string(), format_display_date(today));
+
+    let profile = sqlx::query("SELECT name, clinic_name FROM doctor_profile ORDER BY id LIMIT 1")
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    if let Some(profile) = profile {
+        context.insert("doctor".to_string(), profile.get("name"));
+        context.insert("clinica".to_string(), profile.get("clinic_name"));
+    }
+
+    Ok(context)
+}
+
+/// Carga la plantilla activa preferida de un tipo (favorita primero, luego por orden)
+async fn load_preferred_template_body(
+    conn: &mut sqlx::SqliteConnection,
+    kind: &str,
+) -> Result<Option<String>, String> {
+    sqlx::query_scalar(
+        "SELECT body FROM text_templates
+         WHERE kind = ?1 AND active = 1
+         ORDER BY is_favorite DESC, sort_order ASC, id ASC
+         LIMIT 1"
+    )
+    .bind(kind)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_template_variables(kind: String) -> Result<Vec<String>, String> {
+    template_variables_for(&kind)
+        .map(|vars| vars.iter().map(|v| v.to_string()).collect())
+        .ok_or_else(|| format!("Unknown template kind: {}", kind))
+}
+
+/// Renderiza una plantilla guardada. El contexto del llamador tiene prioridad sobre
+/// los valores por defecto (fecha de hoy, doctor y clínica del perfil).
+#[tauri::command]
+pub async fn render_template(
+    db_pool: State<'_, DbPool>,
+    template_id: i64,
+    context: HashMap<String, String>,
+) -> Result<RenderedTemplate, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let row = sqlx::query("SELECT kind, body FROM text_templates WHERE id = ?")
+        .bind(template_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Template {} not found", template_id))?;
+    let kind: String = row.get("kind");
+    let body: String = row.get("body");
+
+    let mut full_context = default_template_context(&mut conn).await?;
+    full_context.extend(context);
+
+    let mut rendered = render_template_body(&kind, &body, &full_context);
+    rendered.template_id = Some(template_id);
+    Ok(rendered)
+}
+
 // ============================================================================
 // APPOINTMENTS MODULE
 // ============================================================================
@@ -2479,10 +2901,18 @@ pub struct Appointment {
     pub id: Option<i64>,
     pub patient_id: i64,
     pub starts_at: String,  // ISO 8601 datetime
-    pub ends_at: String,    // ISO 8601 datetime
+    #[serde(default)]
+    pub ends_at: String,    // ISO 8601 datetime (empty = start + appointment type duration)
     pub procedure: String,
     pub notes: Option<String>,
     pub status: String,     // 'scheduled' | 'confirmed' | 'cancelled' | 'no_show' | 'completed'
+    pub provider_id: Option<i64>,  // NEW: Overlaps are checked per provider (None on update = keep, <= 0 = clear)
+    #[serde(default)]
+    pub resource_ids: Option<Vec<i64>>,  // NEW: Chairs/rooms/equipment used (overlaps checked per resource; None on update = keep)
+    #[serde(default)]
+    pub series_id: Option<i64>,    // NEW: Recurring series this occurrence belongs to
+    #[serde(default)]
+    pub appointment_type_id: Option<i64>,  // NEW: Catalog type (default duration, resources, preparation notes; None on update = keep, <= 0 = clear)
     pub confirmed_at: Option<String>,
     pub reminder_1d_sent_at: Option<String>,
     pub created_at: Option<String>,
@@ -2505,141 +2935,609 @@ pub struct MessageQueueItem {
 pub struct AvailableSlot {
     pub starts_at: String,
     pub ends_at: String,
+    pub resource_id: Option<i64>,      // Chair/room the slot belongs to (None = no resources configured)
+    pub resource_name: Option<String>,
 }
 
 // =========================
-// APPOINTMENT CRUD COMMANDS
+// CLINIC TIMEZONE & CANONICAL APPOINTMENT TIMES
 // =========================
+// Las citas se guardan como instante UTC canónico ("2025-01-15T13:00:00Z"), así el orden
+// lexicográfico en SQL coincide con el cronológico. La zona de la clínica
+// (user_settings 'clinicTimezone', nombre IANA) interpreta horas sin offset, el horario
+// de atención y las fechas/horas de los mensajes. Sin configurar: zona del sistema.
 
-#[tauri::command]
-pub async fn create_appointment(
-    db_pool: State<'_, DbPool>,
-    appointment: Appointment,
-) -> Result<i64, String> {
-    let pool = db_pool.0.lock().await;
+const CANONICAL_UTC_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
 
-    // Validate: status must be valid
-    if !matches!(
-        appointment.status.as_str(),
-        "scheduled" | "confirmed" | "cancelled" | "no_show" | "completed"
-    ) {
-        return Err(format!("Invalid status: {}", appointment.status));
+/// GLOB de SQLite que reconoce el formato canónico (para la migración de datos)
+const CANONICAL_UTC_GLOB: &str = "[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]T[0-9][0-9]:[0-9][0-9]:[0-9][0-9]Z";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClinicTimezone {
+    Named(chrono_tz::Tz),
+    System,
+}
+
+impl ClinicTimezone {
+    fn parse(name: &str) -> Result<Self, String> {
+        name.trim()
+            .parse::<chrono_tz::Tz>()
+            .map(ClinicTimezone::Named)
+            .map_err(|_| format!("Unknown timezone: {}", name))
     }
 
-    // Validate: starts_at must be before ends_at
-    if appointment.starts_at >= appointment.ends_at {
-        return Err("starts_at must be before ends_at".to_string());
+    fn name(self) -> String {
+        match self {
+            ClinicTimezone::Named(tz) => tz.name().to_string(),
+            ClinicTimezone::System => "system".to_string(),
+        }
     }
 
-    // Check for overlaps (exclude cancelled appointments)
-    let overlap_count: i64 = sqlx::query_scalar(
-        "SELECT COUNT(*)
-         FROM appointments
-         WHERE status NOT IN ('cancelled', 'no_show', 'completed')
-           AND (
-             (starts_at < ?2 AND ends_at > ?1)  -- Overlap condition
-           )"
-    )
-    .bind(&appointment.starts_at)
-    .bind(&appointment.ends_at)
-    .fetch_one(&*pool)
-    .await
-    .map_err(|e| format!("Failed to check overlap: {}", e))?;
+    /// Hora local de pared → instante UTC. Las horas que no existen (salto de DST) son error;
+    /// las repetidas (retroceso de DST) toman la primera ocurrencia.
+    fn to_utc(self, local: chrono::NaiveDateTime) -> Result<chrono::DateTime<chrono::Utc>, String> {
+        use chrono::TimeZone;
+        let resolved = match self {
+            ClinicTimezone::Named(tz) => tz.from_local_datetime(&local).earliest().map(|dt| dt.with_timezone(&chrono::Utc)),
+            ClinicTimezone::System => chrono::Local.from_local_datetime(&local).earliest().map(|dt| dt.with_timezone(&chrono::Utc)),
+        };
+        resolved.ok_or_else(|| {
+            format!("{} does not exist in the clinic timezone (DST change)", local.format("%d/%m/%Y %H:%M"))
+        })
+    }
 
-    if overlap_count > 0 {
-        return Err("Appointment overlaps with an existing appointment".to_string());
+    fn to_local(self, instant: chrono::DateTime<chrono::Utc>) -> chrono::NaiveDateTime {
+        match self {
+            ClinicTimezone::Named(tz) => instant.with_timezone(&tz).naive_local(),
+            ClinicTimezone::System => instant.with_timezone(&chrono::Local).naive_local(),
+        }
     }
 
-    // Insert appointment
-    let result = sqlx::query(
-        "INSERT INTO appointments (patient_id, starts_at, ends_at, procedure, notes, status, confirmed_at, reminder_1d_sent_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
-    )
-    .bind(appointment.patient_id)
-    .bind(&appointment.starts_at)
-    .bind(&appointment.ends_at)
-    .bind(&appointment.procedure)
-    .bind(&appointment.notes)
-    .bind(&appointment.status)
-    .bind(&appointment.confirmed_at)
-    .bind(&appointment.reminder_1d_sent_at)
-    .execute(&*pool)
-    .await
-    .map_err(|e| format!("Failed to create appointment: {}", e))?;
+    fn now_local(self) -> chrono::NaiveDateTime {
+        self.to_local(chrono::Utc::now())
+    }
 
-    Ok(result.last_insert_rowid())
+    /// Inicio del día local en UTC. Si la medianoche no existe (DST a las 00:00, p. ej. Chile)
+    /// el día empieza en la primera hora válida.
+    fn day_start_utc(self, date: chrono::NaiveDate) -> chrono::DateTime<chrono::Utc> {
+        (0..=180)
+            .step_by(15)
+            .find_map(|minutes| self.to_utc(date.and_time(chrono::NaiveTime::MIN) + chrono::Duration::minutes(minutes)).ok())
+            .unwrap_or_else(|| date.and_time(chrono::NaiveTime::MIN).and_utc())
+    }
 }
 
-#[tauri::command]
-pub async fn update_appointment(
-    db_pool: State<'_, DbPool>,
-    id: i64,
-    appointment: Appointment,
-) -> Result<(), String> {
-    let pool = db_pool.0.lock().await;
+async fn load_clinic_timezone(conn: &mut sqlx::SqliteConnection) -> Result<ClinicTimezone, String> {
+    let name: Option<String> = sqlx::query_scalar("SELECT value FROM user_settings WHERE key = 'clinicTimezone'")
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
 
-    // Validate: status must be valid
-    if !matches!(
-        appointment.status.as_str(),
-        "scheduled" | "confirmed" | "cancelled" | "no_show" | "completed"
-    ) {
-        return Err(format!("Invalid status: {}", appointment.status));
-    }
+    Ok(match name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        Some(name) => ClinicTimezone::parse(name).unwrap_or_else(|e| {
+            println!("⚠️ {}; using system timezone", e);
+            ClinicTimezone::System
+        }),
+        None => ClinicTimezone::System,
+    })
+}
 
-    // Validate: starts_at must be before ends_at
-    if appointment.starts_at >= appointment.ends_at {
-        return Err("starts_at must be before ends_at".to_string());
+/// Interpreta una fecha/hora de cita. Con offset ("...-03:00", "...Z") es un instante exacto;
+/// sin offset ("2025-01-15T10:00:00") o solo fecha ("2025-01-15", = 00:00) es hora local de la clínica.
+fn parse_appointment_instant(value: &str, tz: ClinicTimezone) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    let value = value.trim();
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.with_timezone(&chrono::Utc));
     }
 
-    // Check for overlaps (exclude self and cancelled appointments)
-    let overlap_count: i64 = sqlx::query_scalar(
-        "SELECT COUNT(*)
-         FROM appointments
-         WHERE id != ?1
-           AND status NOT IN ('cancelled', 'no_show', 'completed')
-           AND (
-             (starts_at < ?3 AND ends_at > ?2)  -- Overlap condition
-           )"
-    )
-    .bind(id)
-    .bind(&appointment.starts_at)
-    .bind(&appointment.ends_at)
-    .fetch_one(&*pool)
-    .await
-    .map_err(|e| format!("Failed to check overlap: {}", e))?;
+    let local = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(value, format).ok());
 
-    if overlap_count > 0 {
-        return Err("Appointment overlaps with an existing appointment".to_string());
+    match local {
+        Some(local) => tz.to_utc(local),
+        None => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(|date| tz.day_start_utc(date))
+            .map_err(|_| format!("Invalid datetime: '{}'", value)),
     }
+}
 
-    // Update appointment
-    sqlx::query(
-        "UPDATE appointments
-         SET patient_id = ?1, starts_at = ?2, ends_at = ?3, procedure = ?4,
-             notes = ?5, status = ?6, confirmed_at = ?7, reminder_1d_sent_at = ?8
-         WHERE id = ?9"
-    )
-    .bind(appointment.patient_id)
-    .bind(&appointment.starts_at)
-    .bind(&appointment.ends_at)
-    .bind(&appointment.procedure)
-    .bind(&appointment.notes)
-    .bind(&appointment.status)
-    .bind(&appointment.confirmed_at)
-    .bind(&appointment.reminder_1d_sent_at)
-    .bind(id)
-    .execute(&*pool)
-    .await
-    .map_err(|e| format!("Failed to update appointment: {}", e))?;
+fn format_canonical_utc(instant: chrono::DateTime<chrono::Utc>) -> String {
+    instant.format(CANONICAL_UTC_FORMAT).to_string()
+}
 
-    Ok(())
+fn canonical_appointment_time(value: &str, tz: ClinicTimezone) -> Result<String, String> {
+    parse_appointment_instant(value, tz).map(format_canonical_utc)
 }
 
-#[tauri::command]
-pub async fn delete_appointment(
-    db_pool: State<'_, DbPool>,
-    id: i64,
-) -> Result<(), String> {
+/// Hora local de pared de una cita guardada (para horario de atención y mensajes)
+fn appointment_local_time(value: &str, tz: ClinicTimezone) -> Option<chrono::NaiveDateTime> {
+    parse_appointment_instant(value, tz).ok().map(|instant| tz.to_local(instant))
+}
+
+/// Copia de la cita con inicio/fin en UTC canónico; valida que el inicio sea anterior al fin
+fn normalize_appointment_times(appointment: &Appointment, tz: ClinicTimezone) -> Result<Appointment, String> {
+    let starts_at = parse_appointment_instant(&appointment.starts_at, tz)?;
+    let ends_at = parse_appointment_instant(&appointment.ends_at, tz)?;
+    if starts_at >= ends_at {
+        return Err("starts_at must be before ends_at".to_string());
+    }
+
+    let mut normalized = appointment.clone();
+    normalized.starts_at = format_canonical_utc(starts_at);
+    normalized.ends_at = format_canonical_utc(ends_at);
+    Ok(normalized)
+}
+
+/// Migración de datos: reescribe en UTC canónico las horas guardadas en formatos anteriores
+/// (con offset local o sin zona). Idempotente: solo toca filas que no están en formato canónico.
+pub async fn normalize_appointment_timestamps(pool: &sqlx::SqlitePool) -> Result<(), String> {
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let tz = load_clinic_timezone(&mut conn).await?;
+
+    let targets: &[(&str, &[&str])] = &[
+        ("appointments", &["starts_at", "ends_at"]),
+        ("appointment_series", &["dtstart"]),
+        ("waitlist_offers", &["slot_starts_at", "slot_ends_at"]),
+    ];
+
+    let mut updated = 0;
+    for (table, columns) in targets {
+        for column in columns.iter() {
+            let rows: Vec<(i64, String)> = sqlx::query_as(&format!(
+                "SELECT id, {column} FROM {table} WHERE {column} NOT GLOB '{glob}'",
+                column = column,
+                table = table,
+                glob = CANONICAL_UTC_GLOB
+            ))
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| format!("{}.{}: {}", table, column, e))?;
+
+            for (id, value) in rows {
+                match canonical_appointment_time(&value, tz) {
+                    Ok(canonical) => {
+                        sqlx::query(&format!("UPDATE {} SET {} = ?1 WHERE id = ?2", table, column))
+                            .bind(&canonical)
+                            .bind(id)
+                            .execute(&mut *conn)
+                            .await
+                            .map_err(|e| format!("{}.{}: {}", table, column, e))?;
+                        updated += 1;
+                    }
+                    Err(e) => println!("⚠️ {} {}: could not normalize {} ({})", table, id, column, e),
+                }
+            }
+        }
+    }
+
+    if updated > 0 {
+        println!("🕒 Normalized {} appointment timestamps to UTC", updated);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClinicTimezoneInfo {
+    pub timezone: String,                // Nombre IANA o "system"
+    pub utc_offset: String,              // Offset actual, p. ej. "-03:00"
+    pub configured: bool,
+}
+
+#[tauri::command]
+pub async fn get_clinic_timezone(
+    db_pool: State<'_, DbPool>,
+) -> Result<ClinicTimezoneInfo, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let tz = load_clinic_timezone(&mut conn).await?;
+
+    let now = chrono::Utc::now();
+    let offset_seconds = (tz.to_local(now) - now.naive_utc()).num_seconds();
+    let sign = if offset_seconds < 0 { '-' } else { '+' };
+    let offset_seconds = offset_seconds.abs();
+
+    Ok(ClinicTimezoneInfo {
+        timezone: tz.name(),
+        utc_offset: format!("{}{:02}:{:02}", sign, offset_seconds / 3600, (offset_seconds % 3600) / 60),
+        configured: tz != ClinicTimezone::System,
+    })
+}
+
+/// Guarda la zona horaria de la clínica (None = usar la del sistema).
+/// Las citas ya guardadas son instantes UTC y no cambian.
+#[tauri::command]
+pub async fn set_clinic_timezone(
+    db_pool: State<'_, DbPool>,
+    timezone: Option<String>,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    match timezone.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        Some(name) => {
+            let tz = ClinicTimezone::parse(name)?;
+            sqlx::query(
+                "INSERT INTO user_settings (key, value, category)
+                 VALUES ('clinicTimezone', ?1, 'schedule')
+                 ON CONFLICT(key) DO UPDATE SET value = ?1, category = 'schedule'"
+            )
+            .bind(tz.name())
+            .execute(&*pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        None => {
+            sqlx::query("DELETE FROM user_settings WHERE key = 'clinicTimezone'")
+                .execute(&*pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Subconsulta con los recursos de cada cita, como "1,3" (GROUP_CONCAT)
+const APPOINTMENT_RESOURCE_IDS_SQL: &str =
+    "(SELECT GROUP_CONCAT(resource_id) FROM appointment_resources WHERE appointment_id = appointments.id) AS resource_ids";
+
+fn parse_resource_ids(value: Option<String>) -> Vec<i64> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|part| part.trim().parse::<i64>().ok())
+        .collect()
+}
+
+/// Deduplica ids de recursos y verifica que existan y estén activos
+async fn normalize_appointment_resources(
+    conn: &mut sqlx::SqliteConnection,
+    resource_ids: &[i64],
+) -> Result<Vec<i64>, String> {
+    let mut ids: Vec<i64> = resource_ids.iter().copied().filter(|&i| i > 0).collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    for resource_id in &ids {
+        let active: Option<i64> = sqlx::query_scalar("SELECT active FROM resources WHERE id = ?1")
+            .bind(resource_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        match active {
+            None => return Err(format!("Resource {} not found", resource_id)),
+            Some(0) => return Err(format!("Resource {} is inactive", resource_id)),
+            Some(_) => {}
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Cuenta citas activas que chocan con el rango dado.
+/// Chocan si comparten profesional o algún recurso; una cita sin profesional
+/// ni recursos (agenda de un solo sillón) choca con todo.
+async fn count_appointment_conflicts(
+    conn: &mut sqlx::SqliteConnection,
+    exclude_id: Option<i64>,
+    starts_at: &str,
+    ends_at: &str,
+    provider_id: Option<i64>,
+    resource_ids: &[i64],
+) -> Result<i64, String> {
+    let resources_json = serde_json::to_string(resource_ids).map_err(|e| e.to_string())?;
+
+    sqlx::query_scalar(
+        "SELECT COUNT(*)
+         FROM appointments a
+         WHERE (?1 IS NULL OR a.id != ?1)
+           AND a.status NOT IN ('cancelled', 'no_show', 'completed')
+           AND a.starts_at < ?3 AND a.ends_at > ?2  -- Overlap condition
+           AND (
+             (?4 IS NULL AND ?5 = '[]')
+             OR (a.provider_id IS NULL
+                 AND NOT EXISTS (SELECT 1 FROM appointment_resources ar WHERE ar.appointment_id = a.id))
+             OR a.provider_id = ?4
+             OR EXISTS (
+               SELECT 1 FROM appointment_resources ar
+               WHERE ar.appointment_id = a.id
+                 AND ar.resource_id IN (SELECT value FROM json_each(?5))
+             )
+           )"
+    )
+    .bind(exclude_id)
+    .bind(starts_at)
+    .bind(ends_at)
+    .bind(provider_id)
+    .bind(&resources_json)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to check overlap: {}", e))
+}
+
+async fn replace_appointment_resources(
+    conn: &mut sqlx::SqliteConnection,
+    appointment_id: i64,
+    resource_ids: &[i64],
+) -> Result<(), String> {
+    sqlx::query("DELETE FROM appointment_resources WHERE appointment_id = ?1")
+        .bind(appointment_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for resource_id in resource_ids {
+        sqlx::query("INSERT INTO appointment_resources (appointment_id, resource_id) VALUES (?1, ?2)")
+            .bind(appointment_id)
+            .bind(resource_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+// =========================
+// APPOINTMENT CRUD COMMANDS
+// =========================
+
+#[tauri::command]
+pub async fn create_appointment(
+    db_pool: State<'_, DbPool>,
+    appointment: Appointment,
+) -> Result<i64, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let appointment_id = insert_appointment(&mut tx, &appointment).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(appointment_id)
+}
+
+fn validate_appointment_fields(appointment: &Appointment) -> Result<(), String> {
+    // Validate: status must be valid
+    if !APPOINTMENT_STATUSES.contains(&appointment.status.as_str()) {
+        return Err(format!("Invalid status: {}", appointment.status));
+    }
+
+    Ok(())
+}
+
+/// Valida (estado, horario, choques) e inserta una cita con sus recursos.
+/// Nada se escribe si la validación falla.
+async fn insert_appointment(
+    conn: &mut sqlx::SqliteConnection,
+    appointment: &Appointment,
+) -> Result<i64, String> {
+    validate_appointment_fields(appointment)?;
+    if !matches!(appointment.status.as_str(), "scheduled" | "confirmed") {
+        return Err(format!("New appointments must be scheduled or confirmed (got {})", appointment.status));
+    }
+
+    // Times are stored as canonical UTC (starts_at < ends_at checked chronologically)
+    let tz = load_clinic_timezone(conn).await?;
+    let appointment = &Appointment {
+        resource_ids: Some(appointment.resource_ids.clone().unwrap_or_default()),
+        ..appointment.clone()
+    };
+    let appointment = &apply_appointment_type(conn, appointment, tz).await?;
+    let appointment = &normalize_appointment_times(appointment, tz)?;
+
+    // Check for overlaps (exclude cancelled appointments)
+    // Per provider and per resource: appointments without either block the whole agenda (single-chair legacy)
+    let provider_id = appointment.provider_id.filter(|&i| i > 0);
+    let resource_ids = normalize_appointment_resources(conn, appointment.resource_ids.as_deref().unwrap_or_default()).await?;
+    ensure_within_schedule(conn, appointment, provider_id, tz).await?;
+
+    let overlap_count = count_appointment_conflicts(
+        conn,
+        None,
+        &appointment.starts_at,
+        &appointment.ends_at,
+        provider_id,
+        &resource_ids,
+    )
+    .await?;
+
+    if overlap_count > 0 {
+        return Err("Appointment overlaps with an existing appointment".to_string());
+    }
+
+    // Insert appointment
+    let result = sqlx::query(
+        "INSERT INTO appointments (patient_id, starts_at, ends_at, procedure, notes, status, confirmed_at, reminder_1d_sent_at,
+                                   provider_id, series_id, appointment_type_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"
+    )
+    .bind(appointment.patient_id)
+    .bind(&appointment.starts_at)
+    .bind(&appointment.ends_at)
+    .bind(&appointment.procedure)
+    .bind(&appointment.notes)
+    .bind(&appointment.status)
+    .bind(&appointment.confirmed_at)
+    .bind(&appointment.reminder_1d_sent_at)
+    .bind(provider_id)
+    .bind(appointment.series_id.filter(|&i| i > 0))
+    .bind(appointment.appointment_type_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to create appointment: {}", e))?;
+
+    let appointment_id = result.last_insert_rowid();
+    replace_appointment_resources(conn, appointment_id, &resource_ids).await?;
+    record_status_change(conn, appointment_id, None, &appointment.status, None, None).await?;
+
+    Ok(appointment_id)
+}
+
+/// Actualiza una cita. Los cambios de estado siguen las transiciones válidas y quedan
+/// en el historial con el motivo y quién lo registró (opcionales).
+#[tauri::command]
+pub async fn update_appointment(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+    appointment: Appointment,
+    status_reason: Option<String>,
+    changed_by: Option<String>,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let previous_status: Option<String> = sqlx::query_scalar("SELECT status FROM appointments WHERE id = ?1")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let change = StatusChange { reason: status_reason.as_deref(), changed_by: changed_by.as_deref() };
+    apply_appointment_update(&mut tx, id, &appointment, change).await?;
+
+    // Turno liberado: ofrecerlo a la lista de espera
+    if matches!(previous_status.as_deref(), Some("scheduled" | "confirmed")) && appointment.status == "cancelled" {
+        offer_freed_slot(&mut tx, id, None, true).await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Valida y actualiza una cita (la serie a la que pertenece no cambia).
+/// Sin resource_ids, provider_id o appointment_type_id se conservan los valores guardados;
+/// un id <= 0 quita el profesional o el tipo.
+async fn apply_appointment_update(
+    conn: &mut sqlx::SqliteConnection,
+    id: i64,
+    appointment: &Appointment,
+    change: StatusChange<'_>,
+) -> Result<(), String> {
+    validate_appointment_fields(appointment)?;
+
+    let mut appointment = appointment.clone();
+    if appointment.resource_ids.is_none() {
+        let stored: Option<String> = sqlx::query_scalar(
+            "SELECT GROUP_CONCAT(resource_id) FROM appointment_resources WHERE appointment_id = ?1"
+        )
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+        appointment.resource_ids = Some(parse_resource_ids(stored));
+    }
+    let stored: Option<(Option<i64>, Option<i64>)> = sqlx::query_as(
+        "SELECT provider_id, appointment_type_id FROM appointments WHERE id = ?1"
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    if let Some((stored_provider, stored_type)) = stored {
+        appointment.provider_id = appointment.provider_id.or(stored_provider);
+        appointment.appointment_type_id = appointment.appointment_type_id.or(stored_type);
+    }
+
+    let tz = load_clinic_timezone(conn).await?;
+    let appointment = &apply_appointment_type(conn, &appointment, tz).await?;
+    let appointment = &normalize_appointment_times(appointment, tz)?;
+
+    // Check for overlaps (exclude self and cancelled appointments), per provider and per resource
+    let provider_id = appointment.provider_id.filter(|&i| i > 0);
+    let resource_ids = normalize_appointment_resources(conn, appointment.resource_ids.as_deref().unwrap_or_default()).await?;
+
+    // Working hours: only re-checked when the slot, provider or status changes
+    // (editing notes of an old appointment must not fail after a schedule change)
+    let previous: Option<(String, String, Option<i64>, String)> = sqlx::query_as(
+        "SELECT starts_at, ends_at, provider_id, status FROM appointments WHERE id = ?1"
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    let previous_status = previous
+        .as_ref()
+        .map(|(_, _, _, status)| status.clone())
+        .ok_or_else(|| format!("Appointment {} not found", id))?;
+    ensure_status_transition(&previous_status, &appointment.status)?;
+    // Cambio de hora: los recordatorios se recalculan para el nuevo inicio
+    let rescheduled = previous.as_ref().is_some_and(|(starts_at, _, _, _)| *starts_at != appointment.starts_at);
+
+    let unchanged = previous.is_some_and(|(starts_at, ends_at, previous_provider, status)| {
+        starts_at == appointment.starts_at
+            && ends_at == appointment.ends_at
+            && previous_provider == provider_id
+            && status == appointment.status
+    });
+    if !unchanged {
+        ensure_within_schedule(conn, appointment, provider_id, tz).await?;
+    }
+
+    let overlap_count = count_appointment_conflicts(
+        conn,
+        Some(id),
+        &appointment.starts_at,
+        &appointment.ends_at,
+        provider_id,
+        &resource_ids,
+    )
+    .await?;
+
+    if overlap_count > 0 {
+        return Err("Appointment overlaps with an existing appointment".to_string());
+    }
+
+    // Update appointment
+    sqlx::query(
+        "UPDATE appointments
+         SET patient_id = ?1, starts_at = ?2, ends_at = ?3, procedure = ?4,
+             notes = ?5, status = ?6, confirmed_at = ?7, reminder_1d_sent_at = ?8, provider_id = ?10,
+             appointment_type_id = ?11
+         WHERE id = ?9"
+    )
+    .bind(appointment.patient_id)
+    .bind(&appointment.starts_at)
+    .bind(&appointment.ends_at)
+    .bind(&appointment.procedure)
+    .bind(&appointment.notes)
+    .bind(&appointment.status)
+    .bind(&appointment.confirmed_at)
+    .bind(appointment.reminder_1d_sent_at.as_ref().filter(|_| !rescheduled))
+    .bind(id)
+    .bind(provider_id)
+    .bind(appointment.appointment_type_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to update appointment: {}", e))?;
+
+    if rescheduled {
+        // La próxima pasada del programador envía la regla vencida más cercana al nuevo
+        // inicio y marca 'skipped' las anteriores (run_reminder_rules)
+        sqlx::query("DELETE FROM reminder_log WHERE appointment_id = ?1")
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    replace_appointment_resources(conn, id, &resource_ids).await?;
+
+    if previous_status != appointment.status {
+        record_status_change(conn, id, Some(&previous_status), &appointment.status, change.reason, change.changed_by).await?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_appointment(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
     let pool = db_pool.0.lock().await;
 
     sqlx::query("DELETE FROM appointments WHERE id = ?1")
@@ -2656,19 +3554,49 @@ pub async fn list_appointments(
     db_pool: State<'_, DbPool>,
     range_start: String,
     range_end: String,
+    provider_id: Option<i64>,
+    resource_id: Option<i64>,
 ) -> Result<Vec<Appointment>, String> {
     let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    // Range bounds may come with offset, as local time or as a plain date (clinic midnight)
+    let tz = load_clinic_timezone(&mut conn).await?;
+    let range_start = canonical_appointment_time(&range_start, tz)?;
+    let range_end = canonical_appointment_time(&range_end, tz)?;
 
+    query_appointments(&mut conn, &range_start, &range_end, provider_id, resource_id).await
+}
+
+/// Citas que se superponen con [range_start, range_end) (UTC canónico), opcionalmente
+/// de un profesional o recurso. Base de la agenda, la exportación .ics y el feed.
+async fn query_appointments(
+    conn: &mut sqlx::SqliteConnection,
+    range_start: &str,
+    range_end: &str,
+    provider_id: Option<i64>,
+    resource_id: Option<i64>,
+) -> Result<Vec<Appointment>, String> {
     let rows = sqlx::query(
-        "SELECT id, patient_id, starts_at, ends_at, procedure, notes, status,
-                confirmed_at, reminder_1d_sent_at, created_at, updated_at
-         FROM appointments
-         WHERE starts_at < ?2 AND ends_at > ?1
-         ORDER BY starts_at ASC"
+        &format!(
+            "SELECT id, patient_id, starts_at, ends_at, procedure, notes, status, provider_id,
+                    confirmed_at, reminder_1d_sent_at, series_id, appointment_type_id, created_at, updated_at, {}
+             FROM appointments
+             WHERE starts_at < ?2 AND ends_at > ?1
+               AND (?3 IS NULL OR provider_id = ?3)
+               AND (?4 IS NULL OR EXISTS (
+                 SELECT 1 FROM appointment_resources ar
+                 WHERE ar.appointment_id = appointments.id AND ar.resource_id = ?4
+               ))
+             ORDER BY starts_at ASC",
+            APPOINTMENT_RESOURCE_IDS_SQL
+        )
     )
-    .bind(&range_start)
-    .bind(&range_end)
-    .fetch_all(&*pool)
+    .bind(range_start)
+    .bind(range_end)
+    .bind(provider_id)
+    .bind(resource_id)
+    .fetch_all(&mut *conn)
     .await
     .map_err(|e| format!("Failed to list appointments: {}", e))?;
 
@@ -2682,6 +3610,10 @@ pub async fn list_appointments(
             procedure: row.get("procedure"),
             notes: row.get("notes"),
             status: row.get("status"),
+            provider_id: row.get("provider_id"),
+            resource_ids: Some(parse_resource_ids(row.get("resource_ids"))),
+            series_id: row.get("series_id"),
+            appointment_type_id: row.get("appointment_type_id"),
             confirmed_at: row.get("confirmed_at"),
             reminder_1d_sent_at: row.get("reminder_1d_sent_at"),
             created_at: row.get("created_at"),
@@ -2700,16 +3632,21 @@ pub async fn list_upcoming_appointments(
     let pool = db_pool.0.lock().await;
 
     // Calculate date range for next N days
+    let now = chrono::Utc::now();
     let rows = sqlx::query(
-        "SELECT id, patient_id, starts_at, ends_at, procedure, notes, status,
-                confirmed_at, reminder_1d_sent_at, created_at, updated_at
-         FROM appointments
-         WHERE starts_at >= datetime('now')
-           AND starts_at < datetime('now', '+' || ?1 || ' days')
-           AND status NOT IN ('cancelled', 'completed')
-         ORDER BY starts_at ASC"
+        &format!(
+            "SELECT id, patient_id, starts_at, ends_at, procedure, notes, status, provider_id,
+                    confirmed_at, reminder_1d_sent_at, series_id, appointment_type_id, created_at, updated_at, {}
+             FROM appointments
+             WHERE starts_at >= ?1
+               AND starts_at < ?2
+               AND status NOT IN ('cancelled', 'completed')
+             ORDER BY starts_at ASC",
+            APPOINTMENT_RESOURCE_IDS_SQL
+        )
     )
-    .bind(days)
+    .bind(format_canonical_utc(now))
+    .bind(format_canonical_utc(now + chrono::Duration::days(days)))
     .fetch_all(&*pool)
     .await
     .map_err(|e| format!("Failed to list upcoming appointments: {}", e))?;
@@ -2724,6 +3661,10 @@ pub async fn list_upcoming_appointments(
             procedure: row.get("procedure"),
             notes: row.get("notes"),
             status: row.get("status"),
+            provider_id: row.get("provider_id"),
+            resource_ids: Some(parse_resource_ids(row.get("resource_ids"))),
+            series_id: row.get("series_id"),
+            appointment_type_id: row.get("appointment_type_id"),
             confirmed_at: row.get("confirmed_at"),
             reminder_1d_sent_at: row.get("reminder_1d_sent_at"),
             created_at: row.get("created_at"),
@@ -2738,13 +3679,26 @@ pub async fn list_upcoming_appointments(
 // AVAILABLE SLOTS GENERATION
 // =========================
 
+/// Largo permitido de un hueco (también acota la duración de los tipos de cita)
+const MIN_SLOT_MINUTES: i64 = 15;
+const MAX_SLOT_MINUTES: i64 = 240;
+
+/// Huecos libres según el horario semanal (pausas, feriados y cierres incluidos).
+/// `work_start_hour`/`work_end_hour` solo se usan si no hay horario semanal cargado.
+/// Con tipo de cita, el hueco dura lo que el tipo y sus recursos requeridos deben estar libres.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn generate_available_slots(
     db_pool: State<'_, DbPool>,
     days: i64,
     slot_minutes: i64,
-    work_start_hour: i64,  // e.g. 9 for 9am
-    work_end_hour: i64,    // e.g. 18 for 6pm
+    work_start_hour: Option<i64>,  // e.g. 9 for 9am (fallback)
+    work_end_hour: Option<i64>,    // e.g. 18 for 6pm (fallback)
+    provider_id: Option<i64>, // Only this provider's agenda (plus unassigned appointments)
+    resource_ids: Option<Vec<i64>>, // Resources to compute slots for (default: active chairs)
+    limit_per_resource: Option<i64>, // Default: setting 'slotsPerResourceLimit' or 8
+    limit_total: Option<i64>,        // Default: setting 'slotsTotalLimit' or no limit
+    appointment_type_id: Option<i64>, // Slot length and required resources from the type
 ) -> Result<Vec<AvailableSlot>, String> {
     let pool = db_pool.0.lock().await;
 
@@ -2752,92 +3706,204 @@ pub async fn generate_available_slots(
     if days < 1 || days > 14 {
         return Err("days must be between 1 and 14".to_string());
     }
-    if slot_minutes < 15 || slot_minutes > 240 {
-        return Err("slot_minutes must be between 15 and 240".to_string());
-    }
+    let work_start_hour = work_start_hour.unwrap_or(9);
+    let work_end_hour = work_end_hour.unwrap_or(18);
     if work_start_hour < 0 || work_start_hour > 23 || work_end_hour < 0 || work_end_hour > 23 {
         return Err("work hours must be between 0 and 23".to_string());
     }
     if work_start_hour >= work_end_hour {
         return Err("work_start_hour must be before work_end_hour".to_string());
     }
+    if limit_per_resource.is_some_and(|n| n < 1) || limit_total.is_some_and(|n| n < 1) {
+        return Err("slot limits must be at least 1".to_string());
+    }
 
-    // Get all appointments in range
-    let rows = sqlx::query(
-        "SELECT starts_at, ends_at
-         FROM appointments
-         WHERE starts_at >= datetime('now')
-           AND starts_at < datetime('now', '+' || ?1 || ' days')
-           AND status NOT IN ('cancelled', 'completed', 'no_show')
-         ORDER BY starts_at ASC"
+    let provider_id = provider_id.filter(|&i| i > 0);
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let limit_per_resource = match limit_per_resource {
+        Some(n) => n,
+        None => load_limit_setting(&mut conn, "slotsPerResourceLimit")
+            .await?
+            .unwrap_or(DEFAULT_SLOTS_PER_RESOURCE),
+    };
+    let limit_total = match limit_total {
+        Some(n) => Some(n),
+        None => load_limit_setting(&mut conn, "slotsTotalLimit").await?,
+    };
+
+    // Appointment type: its duration replaces slot_minutes; its chairs narrow the lanes
+    // and its other resources (rooms, equipment) must be free in every lane
+    let (slot_minutes, type_resources) = match appointment_type_id.filter(|&i| i > 0) {
+        Some(type_id) => {
+            let appointment_type = load_appointment_type(&mut conn, type_id).await?;
+            (appointment_type.duration_minutes, appointment_type.resource_ids)
+        }
+        None => (slot_minutes, Vec::new()),
+    };
+    // Después del tipo: su duración reemplaza a slot_minutes y debe cumplir el mismo límite
+    if !(MIN_SLOT_MINUTES..=MAX_SLOT_MINUTES).contains(&slot_minutes) {
+        return Err(format!(
+            "slot_minutes must be between {} and {}",
+            MIN_SLOT_MINUTES, MAX_SLOT_MINUTES
+        ));
+    }
+    let type_chairs: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM resources WHERE kind = 'chair' AND id IN (SELECT value FROM json_each(?1))"
     )
-    .bind(days)
-    .fetch_all(&*pool)
+    .bind(serde_json::to_string(&type_resources).map_err(|e| e.to_string())?)
+    .fetch_all(&mut *conn)
     .await
-    .map_err(|e| format!("Failed to fetch appointments: {}", e))?;
+    .map_err(|e| e.to_string())?;
 
-    let booked_appointments: Vec<(String, String)> = rows
-        .into_iter()
-        .map(|row| {
-            let start: String = row.get("starts_at");
-            let end: String = row.get("ends_at");
-            (start, end)
-        })
-        .collect();
+    let tz = load_clinic_timezone(&mut conn).await?;
+    let schedule = load_work_schedule(&mut conn, provider_id).await?;
+    let fallback_hours = (
+        chrono::NaiveTime::from_hms_opt(work_start_hour as u32, 0, 0).unwrap(),
+        chrono::NaiveTime::from_hms_opt(work_end_hour as u32, 0, 0).unwrap(),
+    );
+
+    // Resources to schedule: the requested ones, or every active chair.
+    // Without resources configured we keep a single lane (legacy behavior).
+    let mut requested: Vec<i64> = resource_ids.unwrap_or_default().into_iter().filter(|&i| i > 0).collect();
+    if requested.is_empty() {
+        requested = type_chairs;
+    }
+    let resource_rows = sqlx::query(
+        "SELECT id, name
+         FROM resources
+         WHERE active = 1
+           AND ((?1 = '[]' AND kind = 'chair') OR id IN (SELECT value FROM json_each(?1)))
+         ORDER BY sort_order ASC, name ASC"
+    )
+    .bind(serde_json::to_string(&requested).map_err(|e| e.to_string())?)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to fetch resources: {}", e))?;
 
-    // Generate candidate slots
-    let mut available_slots = Vec::new();
+    if !requested.is_empty() && resource_rows.is_empty() {
+        return Err("None of the requested resources is active".to_string());
+    }
 
-    use chrono::{Local, Duration, Timelike};
+    let lanes: Vec<(Option<i64>, Option<String>)> = if resource_rows.is_empty() {
+        vec![(None, None)]
+    } else {
+        resource_rows
+            .into_iter()
+            .map(|row| (Some(row.get("id")), Some(row.get("name"))))
+            .collect()
+    };
 
-    for day_offset in 0..days {
-        let mut current_date = Local::now() + Duration::days(day_offset);
+    use chrono::{Duration, Utc};
 
-        // Set to work start hour
-        current_date = current_date
-            .with_hour(work_start_hour as u32)
-            .unwrap()
-            .with_minute(0)
-            .unwrap()
-            .with_second(0)
-            .unwrap()
-            .with_nanosecond(0)
-            .unwrap();
+    // Days are clinic-local; stored appointments are canonical UTC
+    let now = Utc::now();
+    let today = tz.to_local(now).date();
+    let range_start = tz.day_start_utc(today);
+    let range_end = tz.day_start_utc(today + Duration::days(days));
 
-        let end_of_day = current_date
-            .with_hour(work_end_hour as u32)
-            .unwrap();
+    // Get all appointments in range
+    let rows = sqlx::query(
+        &format!(
+            "SELECT starts_at, ends_at, provider_id, {}
+             FROM appointments
+             WHERE starts_at < ?2 AND ends_at > ?1
+               AND status NOT IN ('cancelled', 'completed', 'no_show')
+             ORDER BY starts_at ASC",
+            APPOINTMENT_RESOURCE_IDS_SQL
+        )
+    )
+    .bind(format_canonical_utc(range_start))
+    .bind(format_canonical_utc(range_end))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to fetch appointments: {}", e))?;
 
-        while current_date < end_of_day {
-            let slot_end = current_date + Duration::minutes(slot_minutes);
+    // (start, end, provider_id, resource_ids) as UTC instants
+    let booked_appointments: Vec<_> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let start: String = row.get("starts_at");
+            let end: String = row.get("ends_at");
+            Some((
+                parse_appointment_instant(&start, tz).ok()?,
+                parse_appointment_instant(&end, tz).ok()?,
+                row.get::<Option<i64>, _>("provider_id"),
+                parse_resource_ids(row.get("resource_ids")),
+            ))
+        })
+        .collect();
 
-            if slot_end > end_of_day {
-                break;
-            }
+    let shared_resources: Vec<i64> = type_resources
+        .into_iter()
+        .filter(|resource_id| !lanes.iter().any(|(lane_id, _)| *lane_id == Some(*resource_id)))
+        .collect();
 
-            // Check if slot overlaps with any booked appointment
-            let slot_start_str = current_date.to_rfc3339();
-            let slot_end_str = slot_end.to_rfc3339();
+    // Generate candidate slots
+    let mut available_slots = Vec::new();
 
-            let is_available = !booked_appointments.iter().any(|(booked_start, booked_end)| {
-                // Check overlap: (start1 < end2) AND (end1 > start2)
-                slot_start_str < *booked_end && slot_end_str > *booked_start
-            });
+    for (lane_resource_id, lane_resource_name) in lanes {
+        // Appointments that block this lane: unscoped ones, the same resource, or the same provider
+        let blocking: Vec<(chrono::DateTime<Utc>, chrono::DateTime<Utc>)> = booked_appointments
+            .iter()
+            .filter(|(_, _, booked_provider, booked_resources)| {
+                let unscoped = booked_provider.is_none() && booked_resources.is_empty();
+                let same_provider = provider_id.is_some() && *booked_provider == provider_id;
+                let uses_shared = booked_resources.iter().any(|r| shared_resources.contains(r));
+                uses_shared || match lane_resource_id {
+                    Some(resource_id) => unscoped || same_provider || booked_resources.contains(&resource_id),
+                    None => provider_id.is_none() || booked_provider.is_none() || same_provider,
+                }
+            })
+            .map(|(start, end, _, _)| (*start, *end))
+            .collect();
 
-            if is_available {
-                available_slots.push(AvailableSlot {
-                    starts_at: slot_start_str,
-                    ends_at: slot_end_str,
-                });
+        let mut lane_slots = Vec::new();
+
+        for day_offset in 0..days {
+            let date = today + Duration::days(day_offset);
+
+            for (open_start, open_end) in schedule.open_intervals(date, Some(fallback_hours)) {
+                let mut current = date.and_time(open_start);
+                let interval_end = date.and_time(open_end);
+
+                while current + Duration::minutes(slot_minutes) <= interval_end {
+                    let slot_end = current + Duration::minutes(slot_minutes);
+
+                    // Skip wall-clock times that don't exist in the clinic timezone (DST gap)
+                    if let (Ok(start_utc), Ok(end_utc)) = (tz.to_utc(current), tz.to_utc(slot_end)) {
+                        // Check overlap: (start1 < end2) AND (end1 > start2)
+                        let is_available = start_utc > now
+                            && start_utc < end_utc
+                            && !blocking
+                                .iter()
+                                .any(|(booked_start, booked_end)| start_utc < *booked_end && end_utc > *booked_start);
+
+                        if is_available {
+                            lane_slots.push(AvailableSlot {
+                                starts_at: format_canonical_utc(start_utc),
+                                ends_at: format_canonical_utc(end_utc),
+                                resource_id: lane_resource_id,
+                                resource_name: lane_resource_name.clone(),
+                            });
+                        }
+                    }
+
+                    // Move to next slot
+                    current = slot_end;
+                }
             }
-
-            // Move to next slot
-            current_date = current_date + Duration::minutes(slot_minutes);
         }
+
+        // Limit slots per resource
+        lane_slots.truncate(limit_per_resource as usize);
+        available_slots.extend(lane_slots);
     }
 
-    // Limit to first 8 slots
-    available_slots.truncate(8);
+    if let Some(limit) = limit_total {
+        available_slots.sort_by(|a, b| a.starts_at.cmp(&b.starts_at));
+        available_slots.truncate(limit as usize);
+    }
 
     Ok(available_slots)
 }
@@ -2924,83 +3990,6 @@ pub async fn create_message(
     Ok(result.last_insert_rowid())
 }
 
-// =========================
-// REMINDER SCHEDULER
-// =========================
-
-/// Scans for appointments 24-48h from now that haven't received a reminder yet.
-/// Creates pending messages in message_queue and marks reminder_1d_sent_at.
-#[tauri::command]
-pub async fn generate_1d_reminders(
-    db_pool: State<'_, DbPool>,
-) -> Result<i64, String> {
-    let pool = db_pool.0.lock().await;
-
-    // Find appointments tomorrow (24-48h window) without reminder
-    let rows = sqlx::query(
-        "SELECT a.id as appointment_id, a.patient_id, a.starts_at, a.procedure, p.full_name
-         FROM appointments a
-         JOIN patients p ON a.patient_id = p.id
-         WHERE a.starts_at >= datetime('now', '+24 hours')
-           AND a.starts_at < datetime('now', '+48 hours')
-           AND a.status IN ('scheduled', 'confirmed')
-           AND a.reminder_1d_sent_at IS NULL"
-    )
-    .fetch_all(&*pool)
-    .await
-    .map_err(|e| format!("Failed to fetch appointments for reminders: {}", e))?;
-
-    let mut count = 0i64;
-
-    for row in rows {
-        let appointment_id: i64 = row.get("appointment_id");
-        let patient_id: i64 = row.get("patient_id");
-        let starts_at: String = row.get("starts_at");
-        let procedure: String = row.get("procedure");
-        let full_name: String = row.get("full_name");
-
-        // Parse datetime for display
-        let formatted_time = starts_at.split('T').nth(1)
-            .map(|t| t.split(':').take(2).collect::<Vec<_>>().join(":"))
-            .unwrap_or_else(|| "hora programada".to_string());
-
-        // Generate message
-        let message_text = format!(
-            "Hola {}. Te recuerdo que tienes tu cita de {} mañana a las {}. ¡Te esperamos!",
-            full_name, procedure, formatted_time
-        );
-
-        // Create message in queue
-        sqlx::query(
-            "INSERT INTO message_queue (patient_id, appointment_id, type, message_text, status)
-             VALUES (?1, ?2, 'reminder_1d', ?3, 'pending')"
-        )
-        .bind(patient_id)
-        .bind(appointment_id)
-        .bind(&message_text)
-        .execute(&*pool)
-        .await
-        .map_err(|e| format!("Failed to create reminder message: {}", e))?;
-
-        // Mark reminder as sent
-        sqlx::query(
-            "UPDATE appointments
-             SET reminder_1d_sent_at = datetime('now')
-             WHERE id = ?1"
-        )
-        .bind(appointment_id)
-        .execute(&*pool)
-        .await
-        .map_err(|e| format!("Failed to update reminder timestamp: {}", e))?;
-
-        count += 1;
-    }
-
-    println!("✅ Generated {} reminder messages", count);
-
-    Ok(count)
-}
-
 // ============================================================================
 // UTILITY COMMANDS
 // ============================================================================
@@ -3327,3 +4316,9129 @@ pub async fn get_consent_by_id(
         updated_at: row.get("updated_at"),
     })
 }
+
+// ============================================================================
+// TREATMENT PLANS MODULE
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TreatmentPlan {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub title: String,
+    pub notes: Option<String>,
+    pub status: Option<String>,          // 'draft' | 'presented' | 'accepted' | 'in_progress' | 'completed' | 'rejected' | 'cancelled'
+    pub estimated_total: Option<f64>,    // Calculated by backend
+    pub selected_option_id: Option<i64>,
+    pub accepted_at: Option<String>,
+    pub accepted_by: Option<String>,
+    pub signature_data: Option<String>,  // Base64 canvas image
+    pub completion_percent: Option<f64>, // Calculated: performed items / planned items
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TreatmentPlanPhase {
+    pub id: Option<i64>,                 // Negative = temporary id from frontend
+    pub plan_id: Option<i64>,
+    pub name: String,
+    pub notes: Option<String>,
+    pub sort_order: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TreatmentPlanOption {
+    pub id: Option<i64>,                 // Negative = temporary id from frontend
+    pub plan_id: Option<i64>,
+    pub label: String,
+    pub description: Option<String>,
+    pub sort_order: Option<i64>,
+    pub estimated_total: Option<f64>,    // Common items + this option's items
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TreatmentPlanItem {
+    pub id: Option<i64>,
+    pub plan_id: Option<i64>,
+    pub phase_id: Option<i64>,           // References TreatmentPlanPhase.id (may be temporary)
+    pub option_id: Option<i64>,          // None = common to all options
+    pub name: String,
+    pub unit_price: f64,
+    pub quantity: i64,
+    pub subtotal: f64,
+    pub is_active: Option<bool>,
+    pub tooth_number: Option<String>,
+    pub procedure_notes: Option<String>,
+    pub procedure_template_id: Option<i64>,
+    pub sort_order: Option<i64>,
+    pub performed_session_id: Option<i64>, // Read-only: session where it was performed
+    pub performed_at: Option<String>,      // Read-only: date of that session
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TreatmentPlanDetail {
+    pub plan: TreatmentPlan,
+    pub phases: Vec<TreatmentPlanPhase>,
+    pub options: Vec<TreatmentPlanOption>,
+    pub items: Vec<TreatmentPlanItem>,
+}
+
+// Items in scope: active, and either common or belonging to the selected option
+// (first option by sort_order while none has been selected)
+const TREATMENT_PLAN_SCOPE_FILTER: &str =
+    "i.is_active = 1
+     AND (i.option_id IS NULL OR i.option_id = COALESCE(
+            p.selected_option_id,
+            (SELECT o.id FROM treatment_plan_options o WHERE o.plan_id = p.id ORDER BY o.sort_order ASC, o.id ASC LIMIT 1)
+         ))";
+
+/// Returns (estimated_total, completion_percent) for a plan
+async fn compute_treatment_plan_progress(
+    conn: &mut sqlx::SqliteConnection,
+    plan_id: i64,
+) -> Result<(f64, f64), String> {
+    let row = sqlx::query(&format!(
+        "SELECT
+            COALESCE(CAST(SUM(i.subtotal) AS REAL), 0.0) as estimated_total,
+            COUNT(i.id) as planned_count,
+            COALESCE(SUM(CASE WHEN EXISTS (
+                SELECT 1 FROM session_items si WHERE si.treatment_plan_item_id = i.id
+            ) THEN 1 ELSE 0 END), 0) as performed_count
+         FROM treatment_plans p
+         JOIN treatment_plan_items i ON i.plan_id = p.id
+         WHERE p.id = ?1 AND {}",
+        TREATMENT_PLAN_SCOPE_FILTER
+    ))
+    .bind(plan_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let estimated_total: f64 = row.get("estimated_total");
+    let planned_count: i64 = row.get("planned_count");
+    let performed_count: i64 = row.get("performed_count");
+
+    let completion_percent = if planned_count > 0 {
+        ((performed_count as f64 / planned_count as f64) * 1000.0).round() / 10.0
+    } else {
+        0.0
+    };
+
+    Ok((estimated_total, completion_percent))
+}
+
+/// Recalculates estimated_total and moves accepted plans to in_progress/completed
+async fn refresh_treatment_plan_progress(
+    conn: &mut sqlx::SqliteConnection,
+    plan_id: i64,
+) -> Result<f64, String> {
+    let (estimated_total, completion_percent) =
+        compute_treatment_plan_progress(&mut *conn, plan_id).await?;
+
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM treatment_plans WHERE id = ?1")
+        .bind(plan_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let Some(status) = status else {
+        return Ok(completion_percent);
+    };
+
+    // Only plans accepted by the patient advance automatically
+    let new_status = match status.as_str() {
+        "accepted" | "in_progress" | "completed" if completion_percent >= 100.0 => "completed",
+        "accepted" | "in_progress" | "completed" if completion_percent > 0.0 => "in_progress",
+        "in_progress" | "completed" => "accepted",
+        other => other,
+    };
+
+    sqlx::query("UPDATE treatment_plans SET estimated_total = ?1, status = ?2 WHERE id = ?3")
+        .bind(estimated_total)
+        .bind(new_status)
+        .bind(plan_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(completion_percent)
+}
+
+#[tauri::command]
+pub async fn get_treatment_plans_by_patient(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+) -> Result<Vec<TreatmentPlan>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let rows = sqlx::query(
+        "SELECT id, patient_id, title, notes, status, estimated_total, selected_option_id,
+                accepted_at, accepted_by, signature_data, created_at, updated_at
+         FROM treatment_plans
+         WHERE patient_id = ?1
+         ORDER BY created_at DESC, id DESC"
+    )
+    .bind(patient_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get treatment plans: {}", e))?;
+
+    let mut plans = Vec::new();
+
+    for row in rows {
+        let plan_id: i64 = row.get("id");
+        let (_, completion_percent) = compute_treatment_plan_progress(&mut conn, plan_id).await?;
+
+        plans.push(TreatmentPlan {
+            id: Some(plan_id),
+            patient_id: row.get("patient_id"),
+            title: row.get("title"),
+            notes: row.get("notes"),
+            status: row.get("status"),
+            estimated_total: row.get("estimated_total"),
+            selected_option_id: row.get("selected_option_id"),
+            accepted_at: row.get("accepted_at"),
+            accepted_by: row.get("accepted_by"),
+            signature_data: row.get("signature_data"),
+            completion_percent: Some(completion_percent),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        });
+    }
+
+    Ok(plans)
+}
+
+#[tauri::command]
+pub async fn get_treatment_plan(
+    db_pool: State<'_, DbPool>,
+    plan_id: i64,
+) -> Result<Option<TreatmentPlanDetail>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let row = sqlx::query(
+        "SELECT id, patient_id, title, notes, status, estimated_total, selected_option_id,
+                accepted_at, accepted_by, signature_data, created_at, updated_at
+         FROM treatment_plans
+         WHERE id = ?1"
+    )
+    .bind(plan_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get treatment plan: {}", e))?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let (_, completion_percent) = compute_treatment_plan_progress(&mut conn, plan_id).await?;
+
+    let plan = TreatmentPlan {
+        id: row.get("id"),
+        patient_id: row.get("patient_id"),
+        title: row.get("title"),
+        notes: row.get("notes"),
+        status: row.get("status"),
+        estimated_total: row.get("estimated_total"),
+        selected_option_id: row.get("selected_option_id"),
+        accepted_at: row.get("accepted_at"),
+        accepted_by: row.get("accepted_by"),
+        signature_data: row.get("signature_data"),
+        completion_percent: Some(completion_percent),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    };
+
+    let phases = sqlx::query(
+        "SELECT id, plan_id, name, notes, sort_order
+         FROM treatment_plan_phases
+         WHERE plan_id = ?1
+         ORDER BY sort_order ASC, id ASC"
+    )
+    .bind(plan_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get treatment plan phases: {}", e))?
+    .into_iter()
+    .map(|row| TreatmentPlanPhase {
+        id: row.get("id"),
+        plan_id: row.get("plan_id"),
+        name: row.get("name"),
+        notes: row.get("notes"),
+        sort_order: row.get("sort_order"),
+    })
+    .collect();
+
+    let options = sqlx::query(
+        "SELECT o.id, o.plan_id, o.label, o.description, o.sort_order,
+                COALESCE((
+                    SELECT CAST(SUM(i.subtotal) AS REAL)
+                    FROM treatment_plan_items i
+                    WHERE i.plan_id = o.plan_id
+                      AND i.is_active = 1
+                      AND (i.option_id IS NULL OR i.option_id = o.id)
+                ), 0.0) as estimated_total
+         FROM treatment_plan_options o
+         WHERE o.plan_id = ?1
+         ORDER BY o.sort_order ASC, o.id ASC"
+    )
+    .bind(plan_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get treatment plan options: {}", e))?
+    .into_iter()
+    .map(|row| TreatmentPlanOption {
+        id: row.get("id"),
+        plan_id: row.get("plan_id"),
+        label: row.get("label"),
+        description: row.get("description"),
+        sort_order: row.get("sort_order"),
+        estimated_total: row.get("estimated_total"),
+    })
+    .collect();
+
+    let items = sqlx::query(
+        "SELECT i.id, i.plan_id, i.phase_id, i.option_id, i.name, i.unit_price, i.quantity, i.subtotal,
+                i.is_active, i.tooth_number, i.procedure_notes, i.procedure_template_id, i.sort_order,
+                i.created_at, s.id as performed_session_id, s.date as performed_at
+         FROM treatment_plan_items i
+         LEFT JOIN session_items si ON si.id = (
+             SELECT MIN(id) FROM session_items WHERE treatment_plan_item_id = i.id
+         )
+         LEFT JOIN sessions s ON s.id = si.session_id
+         WHERE i.plan_id = ?1
+         ORDER BY i.sort_order ASC, i.id ASC"
+    )
+    .bind(plan_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get treatment plan items: {}", e))?
+    .into_iter()
+    .map(|row| TreatmentPlanItem {
+        id: row.get("id"),
+        plan_id: row.get("plan_id"),
+        phase_id: row.get("phase_id"),
+        option_id: row.get("option_id"),
+        name: row.get("name"),
+        unit_price: row.get("unit_price"),
+        quantity: row.get("quantity"),
+        subtotal: row.get("subtotal"),
+        is_active: Some(row.get::<i64, _>("is_active") != 0),
+        tooth_number: row.get("tooth_number"),
+        procedure_notes: row.get("procedure_notes"),
+        procedure_template_id: row.get("procedure_template_id"),
+        sort_order: row.get("sort_order"),
+        performed_session_id: row.get("performed_session_id"),
+        performed_at: row.get("performed_at"),
+        created_at: row.get("created_at"),
+    })
+    .collect();
+
+    Ok(Some(TreatmentPlanDetail { plan, phases, options, items }))
+}
+
+/// Creates or replaces a treatment plan with its phases, options and items.
+/// Only plans still in 'draft' or 'presented' can be edited; once the patient
+/// accepts, the plan becomes a frozen estimate and only progresses via sessions.
+#[tauri::command]
+pub async fn save_treatment_plan(
+    db_pool: State<'_, DbPool>,
+    detail: TreatmentPlanDetail,
+) -> Result<i64, String> {
+    let status = detail.plan.status.as_deref().unwrap_or("draft");
+    if !matches!(status, "draft" | "presented") {
+        return Err(format!("Invalid status for editing a plan: {}", status));
+    }
+    if detail.plan.title.trim().is_empty() {
+        return Err("Plan title is required".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    // 1. Upsert header
+    let plan_id = if let Some(id) = detail.plan.id.filter(|&i| i > 0) {
+        let current_status: Option<String> = sqlx::query_scalar(
+            "SELECT status FROM treatment_plans WHERE id = ?1"
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        match current_status.as_deref() {
+            None => return Err(format!("Treatment plan {} not found", id)),
+            Some("draft") | Some("presented") | Some("rejected") => {}
+            Some(other) => {
+                return Err(format!("Treatment plan cannot be edited in status '{}'", other));
+            }
+        }
+
+        sqlx::query(
+            "UPDATE treatment_plans
+             SET patient_id = ?1, title = ?2, notes = ?3, status = ?4, selected_option_id = NULL
+             WHERE id = ?5"
+        )
+        .bind(detail.plan.patient_id)
+        .bind(&detail.plan.title)
+        .bind(&detail.plan.notes)
+        .bind(status)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+        id
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO treatment_plans (patient_id, title, notes, status)
+             VALUES (?1, ?2, ?3, ?4)"
+        )
+        .bind(detail.plan.patient_id)
+        .bind(&detail.plan.title)
+        .bind(&detail.plan.notes)
+        .bind(status)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+        result.last_insert_rowid()
+    };
+
+    // 2. Replace children (items first because they reference phases/options)
+    for table in ["treatment_plan_items", "treatment_plan_phases", "treatment_plan_options"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE plan_id = ?1", table))
+            .bind(plan_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    // Map frontend ids (existing or temporary) to the newly inserted ids
+    let mut phase_ids: HashMap<i64, i64> = HashMap::new();
+    for (index, phase) in detail.phases.iter().enumerate() {
+        let result = sqlx::query(
+            "INSERT INTO treatment_plan_phases (plan_id, name, notes, sort_order)
+             VALUES (?1, ?2, ?3, ?4)"
+        )
+        .bind(plan_id)
+        .bind(&phase.name)
+        .bind(&phase.notes)
+        .bind(phase.sort_order.unwrap_or(index as i64))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        if let Some(old_id) = phase.id {
+            phase_ids.insert(old_id, result.last_insert_rowid());
+        }
+    }
+
+    let mut option_ids: HashMap<i64, i64> = HashMap::new();
+    for (index, option) in detail.options.iter().enumerate() {
+        let result = sqlx::query(
+            "INSERT INTO treatment_plan_options (plan_id, label, description, sort_order)
+             VALUES (?1, ?2, ?3, ?4)"
+        )
+        .bind(plan_id)
+        .bind(&option.label)
+        .bind(&option.description)
+        .bind(option.sort_order.unwrap_or(index as i64))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        if let Some(old_id) = option.id {
+            option_ids.insert(old_id, result.last_insert_rowid());
+        }
+    }
+
+    for (index, item) in detail.items.iter().enumerate() {
+        if item.name.trim().is_empty() {
+            continue;
+        }
+
+        let phase_id = match item.phase_id {
+            Some(old_id) => Some(
+                *phase_ids
+                    .get(&old_id)
+                    .ok_or_else(|| format!("Item '{}' references unknown phase {}", item.name, old_id))?,
+            ),
+            None => None,
+        };
+        let option_id = match item.option_id {
+            Some(old_id) => Some(
+                *option_ids
+                    .get(&old_id)
+                    .ok_or_else(|| format!("Item '{}' references unknown option {}", item.name, old_id))?,
+            ),
+            None => None,
+        };
+
+        sqlx::query(
+            "INSERT INTO treatment_plan_items (plan_id, phase_id, option_id, name, unit_price, quantity, subtotal,
+                                              is_active, tooth_number, procedure_notes, procedure_template_id, sort_order)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
+        )
+        .bind(plan_id)
+        .bind(phase_id)
+        .bind(option_id)
+        .bind(&item.name)
+        .bind(item.unit_price)
+        .bind(item.quantity)
+        .bind(item.unit_price * item.quantity as f64) // backend is source of truth
+        .bind(item.is_active.unwrap_or(true) as i64)
+        .bind(&item.tooth_number)
+        .bind(&item.procedure_notes)
+        .bind(item.procedure_template_id)
+        .bind(item.sort_order.unwrap_or(index as i64))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    refresh_treatment_plan_progress(&mut tx, plan_id).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(plan_id)
+}
+
+/// Records the patient's acceptance (chosen option, signature and date)
+#[tauri::command]
+pub async fn accept_treatment_plan(
+    db_pool: State<'_, DbPool>,
+    plan_id: i64,
+    selected_option_id: Option<i64>,
+    accepted_by: String,
+    signature_data: String,
+    accepted_at: Option<String>,
+) -> Result<TreatmentPlan, String> {
+    if accepted_by.trim().is_empty() {
+        return Err("accepted_by is required".to_string());
+    }
+    if signature_data.trim().is_empty() {
+        return Err("Patient signature is required".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM treatment_plans WHERE id = ?1")
+        .bind(plan_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match status.as_deref() {
+        None => return Err(format!("Treatment plan {} not found", plan_id)),
+        Some("draft") | Some("presented") => {}
+        Some(other) => return Err(format!("Treatment plan cannot be accepted in status '{}'", other)),
+    }
+
+    let options_count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM treatment_plan_options WHERE plan_id = ?1"
+    )
+    .bind(plan_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    match selected_option_id {
+        Some(option_id) => {
+            let belongs: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM treatment_plan_options WHERE id = ?1 AND plan_id = ?2"
+            )
+            .bind(option_id)
+            .bind(plan_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+            if belongs == 0 {
+                return Err(format!("Option {} does not belong to plan {}", option_id, plan_id));
+            }
+        }
+        None if options_count > 1 => {
+            return Err("An option must be selected when the plan has alternatives".to_string());
+        }
+        None => {}
+    }
+
+    let accepted_at = accepted_at.unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+
+    sqlx::query(
+        "UPDATE treatment_plans
+         SET status = 'accepted', selected_option_id = ?1, accepted_by = ?2,
+             signature_data = ?3, accepted_at = ?4
+         WHERE id = ?5"
+    )
+    .bind(selected_option_id)
+    .bind(&accepted_by)
+    .bind(&signature_data)
+    .bind(&accepted_at)
+    .bind(plan_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to accept treatment plan: {}", e))?;
+
+    let completion_percent = refresh_treatment_plan_progress(&mut tx, plan_id).await?;
+
+    let row = sqlx::query(
+        "SELECT id, patient_id, title, notes, status, estimated_total, selected_option_id,
+                accepted_at, accepted_by, signature_data, created_at, updated_at
+         FROM treatment_plans
+         WHERE id = ?1"
+    )
+    .bind(plan_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(TreatmentPlan {
+        id: row.get("id"),
+        patient_id: row.get("patient_id"),
+        title: row.get("title"),
+        notes: row.get("notes"),
+        status: row.get("status"),
+        estimated_total: row.get("estimated_total"),
+        selected_option_id: row.get("selected_option_id"),
+        accepted_at: row.get("accepted_at"),
+        accepted_by: row.get("accepted_by"),
+        signature_data: row.get("signature_data"),
+        completion_percent: Some(completion_percent),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+/// Manual status changes: present, reject, cancel or send back to draft.
+/// 'accepted' goes through accept_treatment_plan; 'in_progress'/'completed' are automatic.
+#[tauri::command]
+pub async fn update_treatment_plan_status(
+    db_pool: State<'_, DbPool>,
+    plan_id: i64,
+    status: String,
+) -> Result<(), String> {
+    if !matches!(status.as_str(), "draft" | "presented" | "rejected" | "cancelled") {
+        return Err(format!("Invalid status: {}", status));
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    let current: Option<String> = sqlx::query_scalar("SELECT status FROM treatment_plans WHERE id = ?1")
+        .bind(plan_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let Some(current) = current else {
+        return Err(format!("Treatment plan {} not found", plan_id));
+    };
+
+    let allowed = match status.as_str() {
+        "draft" => matches!(current.as_str(), "presented" | "rejected"),
+        "presented" => current == "draft",
+        "rejected" => matches!(current.as_str(), "draft" | "presented"),
+        "cancelled" => current != "completed",
+        _ => false,
+    };
+
+    if !allowed {
+        return Err(format!("Cannot change treatment plan status from '{}' to '{}'", current, status));
+    }
+
+    sqlx::query("UPDATE treatment_plans SET status = ?1 WHERE id = ?2")
+        .bind(&status)
+        .bind(plan_id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update treatment plan status: {}", e))?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_treatment_plan(
+    db_pool: State<'_, DbPool>,
+    plan_id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    let performed: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*)
+         FROM session_items si
+         JOIN treatment_plan_items i ON i.id = si.treatment_plan_item_id
+         WHERE i.plan_id = ?1"
+    )
+    .bind(plan_id)
+    .fetch_one(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if performed > 0 {
+        return Err("Cannot delete a treatment plan with performed items".to_string());
+    }
+
+    sqlx::query("DELETE FROM treatment_plans WHERE id = ?1")
+        .bind(plan_id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to delete treatment plan: {}", e))?;
+
+    Ok(())
+}
+
+/// Converts planned items into session items ready to be added to the current session.
+/// The link (treatment_plan_item_id) is persisted by save_visit_with_sessions, which
+/// also updates the plan completion.
+#[tauri::command]
+pub async fn convert_plan_items_to_session_items(
+    db_pool: State<'_, DbPool>,
+    plan_id: i64,
+    item_ids: Vec<i64>,
+) -> Result<Vec<SessionItem>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM treatment_plans WHERE id = ?1")
+        .bind(plan_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match status.as_deref() {
+        None => return Err(format!("Treatment plan {} not found", plan_id)),
+        Some("accepted") | Some("in_progress") => {}
+        Some(other) => {
+            return Err(format!("Items can only be performed from an accepted plan (status '{}')", other));
+        }
+    }
+
+    let mut session_items = Vec::new();
+
+    for item_id in item_ids {
+        let row = sqlx::query(&format!(
+            "SELECT i.id, i.name, i.unit_price, i.quantity, i.subtotal, i.tooth_number,
+                    i.procedure_notes, i.procedure_template_id,
+                    EXISTS (SELECT 1 FROM session_items si WHERE si.treatment_plan_item_id = i.id) as performed,
+                    ({}) as in_scope
+             FROM treatment_plan_items i
+             JOIN treatment_plans p ON p.id = i.plan_id
+             WHERE i.id = ?1 AND i.plan_id = ?2",
+            TREATMENT_PLAN_SCOPE_FILTER
+        ))
+        .bind(item_id)
+        .bind(plan_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Item {} does not belong to plan {}", item_id, plan_id))?;
+
+        let name: String = row.get("name");
+
+        if row.get::<i64, _>("performed") != 0 {
+            return Err(format!("Item '{}' was already performed", name));
+        }
+        if row.get::<i64, _>("in_scope") == 0 {
+            return Err(format!("Item '{}' is not part of the accepted option", name));
+        }
+
+        session_items.push(SessionItem {
+            id: None,
+            session_id: None,
+            name,
+            unit_price: row.get("unit_price"),
+            quantity: row.get("quantity"),
+            subtotal: row.get("subtotal"),
+            is_active: Some(true),
+            tooth_number: row.get("tooth_number"),
+            procedure_notes: row.get("procedure_notes"),
+            procedure_template_id: row.get("procedure_template_id"),
+            sort_order: None,
+            treatment_plan_item_id: Some(item_id),
+            lab_order_id: None,
+            procedure_code: None,
+            provider_id: None,
+            created_at: None,
+        });
+    }
+
+    Ok(session_items)
+}
+
+// ============================================================================
+// ODONTOGRAM MODEL (typed tooth_dx_json)
+// ============================================================================
+//
+// Versions of tooth_dx_json:
+//   v0 (legacy, no version): { "11": ["Caries", "Obturación"], ... }
+//   v1: { "schema_version": 1, "teeth": { "11": { "conditions": [
+//           { "diagnosis": "Caries", "diagnosis_option_id": 2, "surfaces": ["O", "M"] }
+//         ] } } }
+
+pub const ODONTOGRAM_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ToothSurface {
+    #[serde(rename = "M")]
+    Mesial,
+    #[serde(rename = "D")]
+    Distal,
+    #[serde(rename = "O")]
+    Occlusal,   // Posterior teeth
+    #[serde(rename = "I")]
+    Incisal,    // Anterior teeth
+    #[serde(rename = "V")]
+    Vestibular,
+    #[serde(rename = "L")]
+    Lingual,
+    #[serde(rename = "P")]
+    Palatal,
+}
+
+impl ToothSurface {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ToothSurface::Mesial => "M",
+            ToothSurface::Distal => "D",
+            ToothSurface::Occlusal => "O",
+            ToothSurface::Incisal => "I",
+            ToothSurface::Vestibular => "V",
+            ToothSurface::Lingual => "L",
+            ToothSurface::Palatal => "P",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToothCondition {
+    pub diagnosis: String,                   // diagnosis_options.label
+    #[serde(default)]
+    pub diagnosis_option_id: Option<i64>,    // Filled by backend on write
+    #[serde(default)]
+    pub surfaces: Vec<ToothSurface>,         // Empty = whole tooth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ToothState {
+    #[serde(default)]
+    pub conditions: Vec<ToothCondition>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Odontogram {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub teeth: std::collections::BTreeMap<String, ToothState>, // FDI tooth number -> state
+}
+
+impl Default for Odontogram {
+    fn default() -> Self {
+        Odontogram {
+            schema_version: ODONTOGRAM_SCHEMA_VERSION,
+            teeth: std::collections::BTreeMap::new(),
+        }
+    }
+}
+
+/// FDI two-digit notation: permanent 11-48, deciduous 51-85
+pub fn is_valid_fdi_tooth(tooth: &str) -> bool {
+    let Ok(number) = tooth.trim().parse::<u8>() else {
+        return false;
+    };
+    let (quadrant, position) = (number / 10, number % 10);
+    match quadrant {
+        1..=4 => (1..=8).contains(&position),
+        5..=8 => (1..=5).contains(&position),
+        _ => false,
+    }
+}
+
+/// Incisors and canines (positions 1-3) have an incisal edge instead of an occlusal face
+fn is_anterior_tooth(tooth: &str) -> bool {
+    tooth.trim().parse::<u8>().map(|n| (1..=3).contains(&(n % 10))).unwrap_or(false)
+}
+
+/// Parses any known shape of tooth_dx_json (legacy or versioned) into the current model.
+/// Does not check diagnoses against the catalog (see validate_odontogram).
+pub fn parse_odontogram(json: &str) -> Result<Odontogram, String> {
+    if json.trim().is_empty() {
+        return Ok(Odontogram::default());
+    }
+
+    let value: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| format!("Invalid odontogram JSON: {}", e))?;
+
+    let serde_json::Value::Object(map) = value else {
+        return Err("Invalid odontogram JSON: expected an object".to_string());
+    };
+
+    match map.get("schema_version") {
+        Some(version) => {
+            let version = version
+                .as_u64()
+                .ok_or_else(|| "Invalid odontogram JSON: schema_version must be a number".to_string())?;
+
+            if version != ODONTOGRAM_SCHEMA_VERSION as u64 {
+                return Err(format!("Unsupported odontogram schema version: {}", version));
+            }
+
+            serde_json::from_value(serde_json::Value::Object(map))
+                .map_err(|e| format!("Invalid odontogram JSON: {}", e))
+        }
+        None => migrate_legacy_odontogram(map),
+    }
+}
+
+/// v0 -> v1: { "11": ["Caries"] } becomes whole-tooth conditions
+fn migrate_legacy_odontogram(
+    map: serde_json::Map<String, serde_json::Value>,
+) -> Result<Odontogram, String> {
+    let mut odontogram = Odontogram::default();
+
+    for (tooth, diagnoses) in map {
+        let serde_json::Value::Array(diagnoses) = diagnoses else {
+            return Err(format!("Invalid legacy odontogram: tooth {} is not a list", tooth));
+        };
+
+        let mut state = ToothState::default();
+        for diagnosis in diagnoses {
+            let label = diagnosis
+                .as_str()
+                .ok_or_else(|| format!("Invalid legacy odontogram: tooth {} has a non-text diagnosis", tooth))?;
+
+            if !label.trim().is_empty() {
+                state.conditions.push(ToothCondition {
+                    diagnosis: label.trim().to_string(),
+                    diagnosis_option_id: None,
+                    surfaces: Vec::new(),
+                    notes: None,
+                });
+            }
+        }
+
+        if !state.conditions.is_empty() {
+            odontogram.teeth.insert(tooth.trim().to_string(), state);
+        }
+    }
+
+    Ok(odontogram)
+}
+
+/// Validates tooth numbers and surfaces, and links diagnoses to diagnosis_options (label -> id).
+/// Normalizes labels to the catalog spelling, fills diagnosis_option_id and removes duplicates.
+/// Labels no longer in the catalog (renamed or deleted options) are kept with no id,
+/// so historic conditions never block saving a later visit.
+pub fn validate_odontogram(
+    odontogram: &mut Odontogram,
+    diagnosis_catalog: &HashMap<String, (i64, String)>, // lowercase label -> (id, label)
+) -> Result<(), String> {
+    let mut errors = Vec::new();
+
+    for (tooth, state) in odontogram.teeth.iter_mut() {
+        if !is_valid_fdi_tooth(tooth) {
+            errors.push(format!("Invalid FDI tooth number: {}", tooth));
+            continue;
+        }
+
+        for condition in state.conditions.iter_mut() {
+            match diagnosis_catalog.get(&condition.diagnosis.trim().to_lowercase()) {
+                Some((id, label)) => {
+                    condition.diagnosis_option_id = Some(*id);
+                    condition.diagnosis = label.clone();
+                }
+                None => {
+                    condition.diagnosis_option_id = None;
+                    condition.diagnosis = condition.diagnosis.trim().to_string();
+                    eprintln!(
+                        "⚠️ Tooth {}: diagnosis '{}' is not in the catalog; kept without option id",
+                        tooth, condition.diagnosis
+                    );
+                }
+            }
+
+            condition.surfaces.sort();
+            condition.surfaces.dedup();
+
+            let anterior = is_anterior_tooth(tooth);
+            for surface in &condition.surfaces {
+                let wrong_face = match surface {
+                    ToothSurface::Occlusal => anterior,
+                    ToothSurface::Incisal => !anterior,
+                    _ => false,
+                };
+                if wrong_face {
+                    errors.push(format!(
+                        "Tooth {}: surface '{}' does not exist on this tooth",
+                        tooth,
+                        surface.code()
+                    ));
+                }
+            }
+        }
+
+        // Duplicates may be anywhere in the list; keep the first occurrence
+        let mut seen = std::collections::HashSet::new();
+        state
+            .conditions
+            .retain(|c| seen.insert((c.diagnosis.to_lowercase(), c.surfaces.clone())));
+    }
+
+    odontogram.teeth.retain(|_, state| !state.conditions.is_empty());
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid odontogram: {}", errors.join("; ")))
+    }
+}
+
+async fn load_diagnosis_catalog(
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<HashMap<String, (i64, String)>, String> {
+    let rows = sqlx::query("SELECT id, label FROM diagnosis_options")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let label: String = row.get("label");
+            (label.trim().to_lowercase(), (row.get("id"), label))
+        })
+        .collect())
+}
+
+/// Parses, migrates and validates tooth_dx_json before it is written.
+/// Returns the normalized JSON (always the current schema version).
+async fn normalize_tooth_dx_json(
+    conn: &mut sqlx::SqliteConnection,
+    tooth_dx_json: Option<&str>,
+) -> Result<Option<String>, String> {
+    let Some(json) = tooth_dx_json.filter(|j| !j.trim().is_empty()) else {
+        return Ok(None);
+    };
+
+    let mut odontogram = parse_odontogram(json)?;
+    let catalog = load_diagnosis_catalog(&mut *conn).await?;
+    validate_odontogram(&mut odontogram, &catalog)?;
+
+    serde_json::to_string(&odontogram)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the parsed odontogram of a session (legacy snapshots are migrated on the fly)
+#[tauri::command]
+pub async fn get_odontogram(
+    db_pool: State<'_, DbPool>,
+    session_id: i64,
+) -> Result<Option<Odontogram>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let tooth_dx_json: Option<Option<String>> = sqlx::query_scalar(
+        "SELECT tooth_dx_json FROM sessions WHERE id = ?1"
+    )
+    .bind(session_id)
+    .fetch_optional(&*pool)
+    .await
+    .map_err(|e| format!("Failed to get odontogram: {}", e))?;
+
+    match tooth_dx_json {
+        None => Ok(None),
+        Some(json) => parse_odontogram(json.as_deref().unwrap_or("")).map(Some),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OdontogramMigrationReport {
+    pub migrated: i64,
+    pub already_current: i64,
+    pub invalid_session_ids: Vec<i64>, // Corrupt JSON, left untouched for manual review
+}
+
+/// One-time data migration: rewrites legacy tooth_dx_json snapshots in the current schema.
+/// Diagnoses are not checked against the catalog (historic labels may have been deleted).
+#[tauri::command]
+pub async fn migrate_tooth_dx_json(
+    db_pool: State<'_, DbPool>,
+) -> Result<OdontogramMigrationReport, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let rows = sqlx::query(
+        "SELECT id, tooth_dx_json FROM sessions
+         WHERE tooth_dx_json IS NOT NULL AND TRIM(tooth_dx_json) != ''
+           AND signed_at IS NULL" // Signed sessions are immutable; readers accept the legacy shape
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut report = OdontogramMigrationReport {
+        migrated: 0,
+        already_current: 0,
+        invalid_session_ids: Vec::new(),
+    };
+
+    for row in rows {
+        let session_id: i64 = row.get("id");
+        let json: String = row.get("tooth_dx_json");
+
+        let is_current = serde_json::from_str::<serde_json::Value>(&json)
+            .ok()
+            .and_then(|v| v.get("schema_version").and_then(|v| v.as_u64()))
+            == Some(ODONTOGRAM_SCHEMA_VERSION as u64);
+
+        if is_current {
+            report.already_current += 1;
+            continue;
+        }
+
+        match parse_odontogram(&json).and_then(|o| serde_json::to_string(&o).map_err(|e| e.to_string())) {
+            Ok(normalized) => {
+                sqlx::query("UPDATE sessions SET tooth_dx_json = ?1 WHERE id = ?2")
+                    .bind(&normalized)
+                    .bind(session_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                report.migrated += 1;
+            }
+            Err(e) => {
+                eprintln!("⚠️ Session {} has an invalid odontogram: {}", session_id, e);
+                report.invalid_session_ids.push(session_id);
+            }
+        }
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!(
+        "✅ Odontogram migration: {} migrated, {} already current, {} invalid",
+        report.migrated,
+        report.already_current,
+        report.invalid_session_ids.len()
+    );
+
+    Ok(report)
+}
+
+// ============================================================================
+// TOOTH HISTORY (normalized per-tooth events)
+// ============================================================================
+
+/// One condition of the mouth: (tooth, surface, diagnosis). surface None = whole tooth
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ToothConditionKey {
+    pub tooth: String,
+    pub surface: Option<ToothSurface>,
+    pub diagnosis: String,
+}
+
+/// Flattens an odontogram into one entry per tooth/surface/diagnosis
+pub fn odontogram_conditions(
+    odontogram: &Odontogram,
+) -> std::collections::BTreeMap<ToothConditionKey, Option<i64>> {
+    let mut conditions = std::collections::BTreeMap::new();
+
+    for (tooth, state) in &odontogram.teeth {
+        for condition in &state.conditions {
+            let surfaces: Vec<Option<ToothSurface>> = if condition.surfaces.is_empty() {
+                vec![None]
+            } else {
+                condition.surfaces.iter().copied().map(Some).collect()
+            };
+
+            for surface in surfaces {
+                conditions.insert(
+                    ToothConditionKey {
+                        tooth: tooth.trim().to_string(),
+                        surface,
+                        diagnosis: condition.diagnosis.clone(),
+                    },
+                    condition.diagnosis_option_id,
+                );
+            }
+        }
+    }
+
+    conditions
+}
+
+/// Extracts FDI tooth numbers from the free-text session_items.tooth_number ("36", "36, 37", "36 y 46")
+pub fn parse_tooth_numbers(text: &str) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    text.split(|c: char| !c.is_ascii_digit())
+        .filter(|token| is_valid_fdi_tooth(token) && seen.insert(*token))
+        .map(|token| token.to_string())
+        .collect()
+}
+
+/// Rebuilds all tooth_events of a patient by walking saved sessions chronologically:
+/// odontogram snapshots are diffed against the previous one (added/removed diagnoses)
+/// and session items with a tooth number become procedure events.
+/// Events already recorded for signed sessions are locked and kept as they are.
+async fn rebuild_tooth_events(
+    conn: &mut sqlx::SqliteConnection,
+    patient_id: i64,
+) -> Result<i64, String> {
+    sqlx::query(
+        "DELETE FROM tooth_events
+         WHERE patient_id = ?1
+           AND (session_id IS NULL
+                OR session_id NOT IN (SELECT id FROM sessions WHERE signed_at IS NOT NULL))"
+    )
+    .bind(patient_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let catalog = load_diagnosis_catalog(&mut *conn).await?;
+
+    let sessions = sqlx::query(
+        "SELECT s.id, s.date, s.tooth_dx_json,
+                (s.signed_at IS NOT NULL
+                 AND EXISTS (SELECT 1 FROM tooth_events te WHERE te.session_id = s.id)) as locked
+         FROM sessions s
+         WHERE s.patient_id = ?1 AND s.is_saved = 1
+         ORDER BY s.date ASC, s.id ASC"
+    )
+    .bind(patient_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut previous = std::collections::BTreeMap::new();
+    let mut count = 0i64;
+
+    for session in sessions {
+        let session_id: i64 = session.get("id");
+        let date: String = session.get("date");
+        let tooth_dx_json: Option<String> = session.get("tooth_dx_json");
+        let locked: bool = session.get("locked");
+
+        // Sessions without odontogram carry no information (not "everything healed")
+        let current = match tooth_dx_json.as_deref().filter(|j| !j.trim().is_empty()).map(parse_odontogram) {
+            Some(Ok(odontogram)) => Some(odontogram_conditions(&odontogram)),
+            Some(Err(e)) => {
+                eprintln!("⚠️ Skipping odontogram of session {}: {}", session_id, e);
+                None
+            }
+            None => None,
+        };
+
+        if let Some(current) = current {
+            let added = current
+                .iter()
+                .filter(|(key, _)| !previous.contains_key(*key))
+                .map(|(key, id)| ("diagnosis_added", key, *id));
+            let removed = previous
+                .iter()
+                .filter(|(key, _)| !current.contains_key(*key))
+                .map(|(key, id)| ("diagnosis_removed", key, *id));
+
+            // Signed sessions keep their events but remain the baseline for the next diff
+            for (event_type, key, option_id) in added.chain(removed).filter(|_| !locked) {
+                // Legacy snapshots have no ids: resolve by label when possible
+                let option_id = option_id.or_else(|| {
+                    catalog.get(&key.diagnosis.trim().to_lowercase()).map(|(id, _)| *id)
+                });
+
+                sqlx::query(
+                    "INSERT INTO tooth_events (patient_id, session_id, event_date, tooth, surface,
+                                              event_type, label, diagnosis_option_id)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+                )
+                .bind(patient_id)
+                .bind(session_id)
+                .bind(&date)
+                .bind(&key.tooth)
+                .bind(key.surface.map(|s| s.code()))
+                .bind(event_type)
+                .bind(&key.diagnosis)
+                .bind(option_id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+                count += 1;
+            }
+
+            previous = current;
+        }
+
+        if locked {
+            continue;
+        }
+
+        let items = sqlx::query(
+            "SELECT id, name, tooth_number, procedure_notes
+             FROM session_items
+             WHERE session_id = ?1 AND is_active = 1
+               AND tooth_number IS NOT NULL AND TRIM(tooth_number) != ''
+             ORDER BY sort_order ASC, id ASC"
+        )
+        .bind(session_id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for item in items {
+            let tooth_number: String = item.get("tooth_number");
+            let session_item_id: i64 = item.get("id");
+            let name: String = item.get("name");
+            let procedure_notes: Option<String> = item.get("procedure_notes");
+
+            for tooth in parse_tooth_numbers(&tooth_number) {
+                sqlx::query(
+                    "INSERT INTO tooth_events (patient_id, session_id, event_date, tooth, event_type,
+                                              label, session_item_id, notes)
+                     VALUES (?1, ?2, ?3, ?4, 'procedure', ?5, ?6, ?7)"
+                )
+                .bind(patient_id)
+                .bind(session_id)
+                .bind(&date)
+                .bind(&tooth)
+                .bind(&name)
+                .bind(session_item_id)
+                .bind(&procedure_notes)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToothHistoryEntry {
+    pub date: String,
+    pub session_id: i64,
+    pub event_type: String,          // 'diagnosis_added' | 'diagnosis_removed' | 'procedure' | 'attachment' | 'note'
+    pub surface: Option<String>,
+    pub label: String,
+    pub notes: Option<String>,
+    pub diagnosis_option_id: Option<i64>,
+    pub session_item_id: Option<i64>,
+    pub attachment_id: Option<i64>,
+    pub signer: Option<String>,
+}
+
+/// Chronological history of one tooth: diagnoses, procedures, and the attachments
+/// and clinical notes of the sessions where that tooth was involved
+#[tauri::command]
+pub async fn get_tooth_history(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+    tooth: String,
+) -> Result<Vec<ToothHistoryEntry>, String> {
+    let tooth = tooth.trim().to_string();
+    if !is_valid_fdi_tooth(&tooth) {
+        return Err(format!("Invalid FDI tooth number: {}", tooth));
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    let event_rows = sqlx::query(
+        "SELECT e.event_date, e.session_id, e.event_type, e.surface, e.label, e.notes,
+                e.diagnosis_option_id, e.session_item_id, s.signer
+         FROM tooth_events e
+         JOIN sessions s ON s.id = e.session_id
+         WHERE e.patient_id = ?1 AND e.tooth = ?2
+         ORDER BY e.event_date ASC, e.session_id ASC, e.id ASC"
+    )
+    .bind(patient_id)
+    .bind(&tooth)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to get tooth history: {}", e))?;
+
+    let mut entries: Vec<ToothHistoryEntry> = event_rows
+        .into_iter()
+        .map(|row| ToothHistoryEntry {
+            date: row.get("event_date"),
+            session_id: row.get("session_id"),
+            event_type: row.get("event_type"),
+            surface: row.get("surface"),
+            label: row.get("label"),
+            notes: row.get("notes"),
+            diagnosis_option_id: row.get("diagnosis_option_id"),
+            session_item_id: row.get("session_item_id"),
+            attachment_id: None,
+            signer: row.get("signer"),
+        })
+        .collect();
+
+    // Notes and attachments of the sessions involved
+    let note_rows = sqlx::query(
+        "SELECT s.id, s.date, s.clinical_notes, s.signer
+         FROM sessions s
+         WHERE s.id IN (SELECT DISTINCT session_id FROM tooth_events WHERE patient_id = ?1 AND tooth = ?2)
+           AND s.clinical_notes IS NOT NULL AND TRIM(s.clinical_notes) != ''"
+    )
+    .bind(patient_id)
+    .bind(&tooth)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to get tooth notes: {}", e))?;
+
+    entries.extend(note_rows.into_iter().map(|row| ToothHistoryEntry {
+        date: row.get("date"),
+        session_id: row.get("id"),
+        event_type: "note".to_string(),
+        surface: None,
+        label: "Notas clínicas".to_string(),
+        notes: row.get("clinical_notes"),
+        diagnosis_option_id: None,
+        session_item_id: None,
+        attachment_id: None,
+        signer: row.get("signer"),
+    }));
+
+    let attachment_rows = sqlx::query(
+        "SELECT a.id, a.session_id, a.filename, a.note, s.date, s.signer
+         FROM attachments a
+         JOIN sessions s ON s.id = a.session_id
+         WHERE a.session_id IN (SELECT DISTINCT session_id FROM tooth_events WHERE patient_id = ?1 AND tooth = ?2)"
+    )
+    .bind(patient_id)
+    .bind(&tooth)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to get tooth attachments: {}", e))?;
+
+    entries.extend(attachment_rows.into_iter().map(|row| ToothHistoryEntry {
+        date: row.get("date"),
+        session_id: row.get("session_id"),
+        event_type: "attachment".to_string(),
+        surface: None,
+        label: row.get("filename"),
+        notes: row.get("note"),
+        diagnosis_option_id: None,
+        session_item_id: None,
+        attachment_id: row.get("id"),
+        signer: row.get("signer"),
+    }));
+
+    // Stable sort keeps diagnoses before procedures, then notes and attachments, within a session
+    entries.sort_by(|a, b| a.date.cmp(&b.date).then(a.session_id.cmp(&b.session_id)));
+
+    Ok(entries)
+}
+
+/// Backfill: rebuilds tooth_events for every patient from existing sessions
+#[tauri::command]
+pub async fn rebuild_tooth_history(
+    db_pool: State<'_, DbPool>,
+) -> Result<i64, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let patient_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM patients")
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut count = 0i64;
+    for patient_id in patient_ids {
+        count += rebuild_tooth_events(&mut tx, patient_id).await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("✅ Tooth history rebuilt: {} events", count);
+
+    Ok(count)
+}
+
+// ============================================================================
+// ODONTOGRAM DIFF
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OdontogramLocationChange {
+    pub tooth: String,
+    pub surface: Option<String>, // None = whole tooth
+    pub before: Vec<String>,     // Diagnoses at this location in session A
+    pub after: Vec<String>,      // Diagnoses at this location in session B
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OdontogramDiff {
+    pub session_a: Option<i64>,
+    pub session_b: i64,
+    pub added: Vec<OdontogramLocationChange>,   // Nothing before, something now
+    pub removed: Vec<OdontogramLocationChange>, // Something before, nothing now
+    pub changed: Vec<OdontogramLocationChange>, // Different diagnoses at the same location
+    pub summary_text: String,
+}
+
+/// Compares two odontograms location by location (tooth + surface)
+pub fn diff_odontograms(
+    before: &Odontogram,
+    after: &Odontogram,
+) -> (
+    Vec<OdontogramLocationChange>,
+    Vec<OdontogramLocationChange>,
+    Vec<OdontogramLocationChange>,
+) {
+    type Location = (String, Option<ToothSurface>);
+    let mut locations: std::collections::BTreeMap<Location, (Vec<String>, Vec<String>)> =
+        std::collections::BTreeMap::new();
+
+    for key in odontogram_conditions(before).into_keys() {
+        locations.entry((key.tooth, key.surface)).or_default().0.push(key.diagnosis);
+    }
+    for key in odontogram_conditions(after).into_keys() {
+        locations.entry((key.tooth, key.surface)).or_default().1.push(key.diagnosis);
+    }
+
+    let (mut added, mut removed, mut changed) = (Vec::new(), Vec::new(), Vec::new());
+
+    for ((tooth, surface), (before, after)) in locations {
+        if before == after {
+            continue;
+        }
+
+        let change = OdontogramLocationChange {
+            tooth,
+            surface: surface.map(|s| s.code().to_string()),
+            before,
+            after,
+        };
+
+        if change.before.is_empty() {
+            added.push(change);
+        } else if change.after.is_empty() {
+            removed.push(change);
+        } else {
+            changed.push(change);
+        }
+    }
+
+    (added, removed, changed)
+}
+
+/// Human readable summary (Spanish, same "Diente N: ..." style as auto_dx_text)
+pub fn odontogram_diff_summary(
+    added: &[OdontogramLocationChange],
+    removed: &[OdontogramLocationChange],
+    changed: &[OdontogramLocationChange],
+) -> String {
+    let mut lines: Vec<(String, Option<String>, String)> = Vec::new();
+
+    for change in added {
+        lines.push((change.tooth.clone(), change.surface.clone(), format!("nuevo {}", change.after.join(", "))));
+    }
+    for change in changed {
+        lines.push((
+            change.tooth.clone(),
+            change.surface.clone(),
+            format!("{} → {}", change.before.join(", "), change.after.join(", ")),
+        ));
+    }
+    for change in removed {
+        lines.push((change.tooth.clone(), change.surface.clone(), format!("resuelto {}", change.before.join(", "))));
+    }
+
+    lines.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    lines
+        .into_iter()
+        .map(|(tooth, surface, text)| match surface {
+            Some(surface) => format!("Diente {} ({}): {}", tooth, surface, text),
+            None => format!("Diente {}: {}", tooth, text),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Diff between two session snapshots. When session_a is None, the previous saved
+/// session of the same patient with an odontogram is used ("last visit vs today").
+/// With apply_summary = true the summary is written to session_b.auto_dx_text.
+#[tauri::command]
+pub async fn diff_odontogram(
+    db_pool: State<'_, DbPool>,
+    session_a: Option<i64>,
+    session_b: i64,
+    apply_summary: Option<bool>,
+) -> Result<OdontogramDiff, String> {
+    let pool = db_pool.0.lock().await;
+
+    let row_b = sqlx::query("SELECT id, patient_id, date, tooth_dx_json FROM sessions WHERE id = ?1")
+        .bind(session_b)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Session {} not found", session_b))?;
+
+    let patient_id: i64 = row_b.get("patient_id");
+    let date_b: String = row_b.get("date");
+    let json_b: Option<String> = row_b.get("tooth_dx_json");
+
+    let session_a = match session_a {
+        Some(id) => Some(id),
+        None => sqlx::query_scalar(
+            "SELECT id FROM sessions
+             WHERE patient_id = ?1 AND is_saved = 1 AND id != ?2
+               AND tooth_dx_json IS NOT NULL AND TRIM(tooth_dx_json) != ''
+               AND (date < ?3 OR (date = ?3 AND id < ?2))
+             ORDER BY date DESC, id DESC
+             LIMIT 1"
+        )
+        .bind(patient_id)
+        .bind(session_b)
+        .bind(&date_b)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?,
+    };
+
+    let json_a: Option<String> = match session_a {
+        Some(id) => {
+            let row_a = sqlx::query("SELECT patient_id, tooth_dx_json FROM sessions WHERE id = ?1")
+                .bind(id)
+                .fetch_optional(&*pool)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Session {} not found", id))?;
+
+            if row_a.get::<i64, _>("patient_id") != patient_id {
+                return Err("Both sessions must belong to the same patient".to_string());
+            }
+            row_a.get("tooth_dx_json")
+        }
+        None => None,
+    };
+
+    let before = parse_odontogram(json_a.as_deref().unwrap_or(""))?;
+    let after = parse_odontogram(json_b.as_deref().unwrap_or(""))?;
+
+    let (added, removed, changed) = diff_odontograms(&before, &after);
+    let summary_text = odontogram_diff_summary(&added, &removed, &changed);
+
+    if apply_summary.unwrap_or(false) {
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+        ensure_session_unlocked(&mut conn, session_b).await?;
+
+        sqlx::query("UPDATE sessions SET auto_dx_text = ?1 WHERE id = ?2")
+            .bind(&summary_text)
+            .bind(session_b)
+            .execute(&*pool)
+            .await
+            .map_err(|e| format!("Failed to update auto_dx_text: {}", e))?;
+    }
+
+    Ok(OdontogramDiff {
+        session_a,
+        session_b,
+        added,
+        removed,
+        changed,
+        summary_text,
+    })
+}
+
+// ============================================================================
+// PERIODONTAL CHARTS MODULE
+// ============================================================================
+
+pub const PERIO_SITES: [&str; 6] = ["MB", "B", "DB", "ML", "L", "DL"];
+
+// Cambio de sondaje considerado clínicamente significativo al comparar exámenes
+const PERIO_SIGNIFICANT_CHANGE_MM: i64 = 2;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PerioChart {
+    pub id: Option<i64>,
+    pub patient_id: Option<i64>,              // Taken from the session
+    pub session_id: i64,
+    pub exam_date: Option<String>,            // Taken from the session
+    pub notes: Option<String>,
+    pub mean_probing_depth: Option<f64>,      // Calculated by backend
+    pub bop_percent: Option<f64>,             // Calculated by backend
+    pub mean_cal: Option<f64>,                // Calculated by backend
+    pub sites_over_4mm: Option<i64>,          // Calculated by backend
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PerioTooth {
+    pub tooth: String,
+    pub is_missing: Option<bool>,
+    pub mobility: Option<i64>,                // Miller 0-3
+    pub furcation: Option<i64>,               // Glickman 0-3
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PerioSite {
+    pub tooth: String,
+    pub site: String,                         // 'MB' | 'B' | 'DB' | 'ML' | 'L' | 'DL'
+    pub probing_depth: Option<i64>,           // mm
+    pub recession: Option<i64>,               // mm (negative = gingival overgrowth)
+    pub bleeding: Option<bool>,
+    pub suppuration: Option<bool>,
+    pub plaque: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PerioChartDetail {
+    pub chart: PerioChart,
+    pub teeth: Vec<PerioTooth>,
+    pub sites: Vec<PerioSite>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct PerioIndices {
+    pub mean_probing_depth: Option<f64>,
+    pub bop_percent: Option<f64>,
+    pub mean_cal: Option<f64>,
+    pub sites_over_4mm: i64,
+    pub sites_recorded: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PerioSiteChange {
+    pub tooth: String,
+    pub site: String,
+    pub probing_depth_before: Option<i64>,
+    pub probing_depth_after: Option<i64>,
+    pub cal_before: Option<i64>,
+    pub cal_after: Option<i64>,
+    pub bleeding_before: bool,
+    pub bleeding_after: bool,
+    pub change: String,                       // 'improved' | 'worsened'
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PerioComparison {
+    pub chart_a: PerioChart,
+    pub chart_b: PerioChart,
+    pub delta_mean_probing_depth: Option<f64>, // b - a (negative = improvement)
+    pub delta_bop_percent: Option<f64>,
+    pub delta_mean_cal: Option<f64>,
+    pub improved_sites: i64,
+    pub worsened_sites: i64,
+    pub site_changes: Vec<PerioSiteChange>,   // Only changes >= 2mm in PD or CAL
+}
+
+fn round_one_decimal(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+/// Clinical attachment level of a site (PD + recession), when both are recorded
+fn perio_site_cal(site: &PerioSite) -> Option<i64> {
+    Some(site.probing_depth? + site.recession?)
+}
+
+/// Mean PD and % BOP over probed sites (sites without PD are ignored);
+/// mean CAL only over sites that also have recession recorded
+pub fn compute_perio_indices(sites: &[PerioSite]) -> PerioIndices {
+    let probed: Vec<&PerioSite> = sites.iter().filter(|s| s.probing_depth.is_some()).collect();
+    if probed.is_empty() {
+        return PerioIndices::default();
+    }
+
+    let count = probed.len() as f64;
+    let pd_sum: i64 = probed.iter().filter_map(|s| s.probing_depth).sum();
+    let cal_values: Vec<i64> = probed.iter().filter_map(|s| perio_site_cal(s)).collect();
+    let bleeding = probed.iter().filter(|s| s.bleeding.unwrap_or(false)).count() as f64;
+
+    PerioIndices {
+        mean_probing_depth: Some(round_one_decimal(pd_sum as f64 / count)),
+        bop_percent: Some(round_one_decimal(bleeding * 100.0 / count)),
+        mean_cal: (!cal_values.is_empty()).then(|| {
+            round_one_decimal(cal_values.iter().sum::<i64>() as f64 / cal_values.len() as f64)
+        }),
+        sites_over_4mm: probed.iter().filter(|s| s.probing_depth.unwrap_or(0) > 4).count() as i64,
+        sites_recorded: probed.len() as i64,
+    }
+}
+
+/// Site-by-site comparison; only clinically significant changes are returned
+pub fn compare_perio_sites(before: &[PerioSite], after: &[PerioSite]) -> Vec<PerioSiteChange> {
+    let before_map: HashMap<(&str, &str), &PerioSite> = before
+        .iter()
+        .map(|s| ((s.tooth.as_str(), s.site.as_str()), s))
+        .collect();
+
+    let mut changes: Vec<PerioSiteChange> = after
+        .iter()
+        .filter_map(|after_site| {
+            let before_site = before_map.get(&(after_site.tooth.as_str(), after_site.site.as_str()))?;
+            let (pd_before, pd_after) = (before_site.probing_depth?, after_site.probing_depth?);
+            let (cal_before, cal_after) = (perio_site_cal(before_site), perio_site_cal(after_site));
+
+            // CAL is the reference for attachment loss; PD covers sites without recession data
+            let delta = match (cal_before, cal_after) {
+                (Some(b), Some(a)) if (a - b).abs() >= (pd_after - pd_before).abs() => a - b,
+                _ => pd_after - pd_before,
+            };
+            if delta.abs() < PERIO_SIGNIFICANT_CHANGE_MM {
+                return None;
+            }
+
+            Some(PerioSiteChange {
+                tooth: after_site.tooth.clone(),
+                site: after_site.site.clone(),
+                probing_depth_before: Some(pd_before),
+                probing_depth_after: Some(pd_after),
+                cal_before,
+                cal_after,
+                bleeding_before: before_site.bleeding.unwrap_or(false),
+                bleeding_after: after_site.bleeding.unwrap_or(false),
+                change: if delta < 0 { "improved" } else { "worsened" }.to_string(),
+            })
+        })
+        .collect();
+
+    changes.sort_by(|a, b| {
+        a.tooth
+            .cmp(&b.tooth)
+            .then(perio_site_index(&a.site).cmp(&perio_site_index(&b.site)))
+    });
+    changes
+}
+
+fn perio_site_index(site: &str) -> usize {
+    PERIO_SITES.iter().position(|s| *s == site).unwrap_or(PERIO_SITES.len())
+}
+
+fn validate_perio_chart(detail: &PerioChartDetail) -> Result<(), String> {
+    for tooth in &detail.teeth {
+        if !is_valid_fdi_tooth(&tooth.tooth) {
+            return Err(format!("Invalid tooth number: {}", tooth.tooth));
+        }
+        if tooth.mobility.is_some_and(|m| !(0..=3).contains(&m)) {
+            return Err(format!("Tooth {}: mobility must be between 0 and 3", tooth.tooth));
+        }
+        if tooth.furcation.is_some_and(|f| !(0..=3).contains(&f)) {
+            return Err(format!("Tooth {}: furcation must be between 0 and 3", tooth.tooth));
+        }
+    }
+
+    for site in &detail.sites {
+        if !is_valid_fdi_tooth(&site.tooth) {
+            return Err(format!("Invalid tooth number: {}", site.tooth));
+        }
+        if !PERIO_SITES.contains(&site.site.as_str()) {
+            return Err(format!("Tooth {}: invalid site '{}'", site.tooth, site.site));
+        }
+        if site.probing_depth.is_some_and(|pd| !(0..=20).contains(&pd)) {
+            return Err(format!("Tooth {} {}: probing depth out of range", site.tooth, site.site));
+        }
+        if site.recession.is_some_and(|r| !(-10..=20).contains(&r)) {
+            return Err(format!("Tooth {} {}: recession out of range", site.tooth, site.site));
+        }
+    }
+
+    Ok(())
+}
+
+fn perio_chart_from_row(row: &sqlx::sqlite::SqliteRow) -> PerioChart {
+    PerioChart {
+        id: row.get("id"),
+        patient_id: row.get("patient_id"),
+        session_id: row.get("session_id"),
+        exam_date: row.get("exam_date"),
+        notes: row.get("notes"),
+        mean_probing_depth: row.get("mean_probing_depth"),
+        bop_percent: row.get("bop_percent"),
+        mean_cal: row.get("mean_cal"),
+        sites_over_4mm: row.get("sites_over_4mm"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+const PERIO_CHART_COLUMNS: &str =
+    "id, patient_id, session_id, exam_date, notes, mean_probing_depth, bop_percent,
+     mean_cal, sites_over_4mm, created_at, updated_at";
+
+async fn load_perio_chart_detail(
+    conn: &mut sqlx::SqliteConnection,
+    chart_id: i64,
+) -> Result<Option<PerioChartDetail>, String> {
+    let row = sqlx::query(&format!("SELECT {} FROM perio_charts WHERE id = ?1", PERIO_CHART_COLUMNS))
+        .bind(chart_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to get periodontal chart: {}", e))?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let teeth = sqlx::query(
+        "SELECT tooth, is_missing, mobility, furcation, notes
+         FROM perio_teeth
+         WHERE chart_id = ?1
+         ORDER BY CAST(tooth AS INTEGER) ASC"
+    )
+    .bind(chart_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get periodontal teeth: {}", e))?
+    .into_iter()
+    .map(|row| PerioTooth {
+        tooth: row.get("tooth"),
+        is_missing: Some(row.get::<i64, _>("is_missing") != 0),
+        mobility: row.get("mobility"),
+        furcation: row.get("furcation"),
+        notes: row.get("notes"),
+    })
+    .collect();
+
+    let mut sites: Vec<PerioSite> = sqlx::query(
+        "SELECT tooth, site, probing_depth, recession, bleeding, suppuration, plaque
+         FROM perio_sites
+         WHERE chart_id = ?1"
+    )
+    .bind(chart_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get periodontal sites: {}", e))?
+    .into_iter()
+    .map(|row| PerioSite {
+        tooth: row.get("tooth"),
+        site: row.get("site"),
+        probing_depth: row.get("probing_depth"),
+        recession: row.get("recession"),
+        bleeding: Some(row.get::<i64, _>("bleeding") != 0),
+        suppuration: Some(row.get::<i64, _>("suppuration") != 0),
+        plaque: Some(row.get::<i64, _>("plaque") != 0),
+    })
+    .collect();
+
+    sites.sort_by(|a, b| {
+        a.tooth
+            .parse::<u8>()
+            .unwrap_or(0)
+            .cmp(&b.tooth.parse::<u8>().unwrap_or(0))
+            .then(perio_site_index(&a.site).cmp(&perio_site_index(&b.site)))
+    });
+
+    Ok(Some(PerioChartDetail {
+        chart: perio_chart_from_row(&row),
+        teeth,
+        sites,
+    }))
+}
+
+#[tauri::command]
+pub async fn get_perio_chart(
+    db_pool: State<'_, DbPool>,
+    session_id: i64,
+) -> Result<Option<PerioChartDetail>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let chart_id: Option<i64> = sqlx::query_scalar("SELECT id FROM perio_charts WHERE session_id = ?1")
+        .bind(session_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match chart_id {
+        Some(id) => load_perio_chart_detail(&mut conn, id).await,
+        None => Ok(None),
+    }
+}
+
+/// Headers only (with indices), oldest first, for evolution charts
+#[tauri::command]
+pub async fn get_perio_charts_by_patient(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+) -> Result<Vec<PerioChart>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM perio_charts WHERE patient_id = ?1 ORDER BY exam_date ASC, id ASC",
+        PERIO_CHART_COLUMNS
+    ))
+    .bind(patient_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to get periodontal charts: {}", e))?;
+
+    Ok(rows.iter().map(perio_chart_from_row).collect())
+}
+
+/// Creates or replaces the periodontal chart of a session (one chart per session).
+/// Sites of missing teeth are discarded and indices are recalculated here.
+#[tauri::command]
+pub async fn save_perio_chart(
+    db_pool: State<'_, DbPool>,
+    detail: PerioChartDetail,
+) -> Result<i64, String> {
+    validate_perio_chart(&detail)?;
+
+    let missing: std::collections::HashSet<&str> = detail
+        .teeth
+        .iter()
+        .filter(|t| t.is_missing.unwrap_or(false))
+        .map(|t| t.tooth.as_str())
+        .collect();
+    let sites: Vec<PerioSite> = detail
+        .sites
+        .iter()
+        .filter(|s| !missing.contains(s.tooth.as_str()))
+        .cloned()
+        .collect();
+    let indices = compute_perio_indices(&sites);
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let session = sqlx::query("SELECT patient_id, date FROM sessions WHERE id = ?1")
+        .bind(detail.chart.session_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Session {} not found", detail.chart.session_id))?;
+    let patient_id: i64 = session.get("patient_id");
+    let exam_date: String = session.get("date");
+
+    ensure_session_unlocked(&mut tx, detail.chart.session_id).await?;
+
+    let existing_id: Option<i64> = sqlx::query_scalar("SELECT id FROM perio_charts WHERE session_id = ?1")
+        .bind(detail.chart.session_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let chart_id = if let Some(id) = existing_id {
+        sqlx::query(
+            "UPDATE perio_charts
+             SET patient_id = ?1, exam_date = ?2, notes = ?3, mean_probing_depth = ?4,
+                 bop_percent = ?5, mean_cal = ?6, sites_over_4mm = ?7
+             WHERE id = ?8"
+        )
+        .bind(patient_id)
+        .bind(&exam_date)
+        .bind(&detail.chart.notes)
+        .bind(indices.mean_probing_depth)
+        .bind(indices.bop_percent)
+        .bind(indices.mean_cal)
+        .bind(indices.sites_over_4mm)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for table in ["perio_sites", "perio_teeth"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE chart_id = ?1", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        id
+    } else {
+        sqlx::query(
+            "INSERT INTO perio_charts (patient_id, session_id, exam_date, notes, mean_probing_depth,
+                                       bop_percent, mean_cal, sites_over_4mm)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+        )
+        .bind(patient_id)
+        .bind(detail.chart.session_id)
+        .bind(&exam_date)
+        .bind(&detail.chart.notes)
+        .bind(indices.mean_probing_depth)
+        .bind(indices.bop_percent)
+        .bind(indices.mean_cal)
+        .bind(indices.sites_over_4mm)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .last_insert_rowid()
+    };
+
+    for tooth in &detail.teeth {
+        sqlx::query(
+            "INSERT INTO perio_teeth (chart_id, tooth, is_missing, mobility, furcation, notes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(chart_id, tooth) DO UPDATE SET
+                is_missing = excluded.is_missing, mobility = excluded.mobility,
+                furcation = excluded.furcation, notes = excluded.notes"
+        )
+        .bind(chart_id)
+        .bind(tooth.tooth.trim())
+        .bind(tooth.is_missing.unwrap_or(false) as i64)
+        .bind(tooth.mobility)
+        .bind(tooth.furcation)
+        .bind(&tooth.notes)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    for site in &sites {
+        sqlx::query(
+            "INSERT INTO perio_sites (chart_id, tooth, site, probing_depth, recession, bleeding, suppuration, plaque)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(chart_id, tooth, site) DO UPDATE SET
+                probing_depth = excluded.probing_depth, recession = excluded.recession,
+                bleeding = excluded.bleeding, suppuration = excluded.suppuration, plaque = excluded.plaque"
+        )
+        .bind(chart_id)
+        .bind(site.tooth.trim())
+        .bind(&site.site)
+        .bind(site.probing_depth)
+        .bind(site.recession)
+        .bind(site.bleeding.unwrap_or(false) as i64)
+        .bind(site.suppuration.unwrap_or(false) as i64)
+        .bind(site.plaque.unwrap_or(false) as i64)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("🦷 Periodontograma guardado (sesión {}, chart {})", detail.chart.session_id, chart_id);
+    Ok(chart_id)
+}
+
+#[tauri::command]
+pub async fn delete_perio_chart(
+    db_pool: State<'_, DbPool>,
+    chart_id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let session_id: Option<i64> = sqlx::query_scalar("SELECT session_id FROM perio_charts WHERE id = ?1")
+        .bind(chart_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(session_id) = session_id {
+        ensure_session_unlocked(&mut conn, session_id).await?;
+    }
+
+    sqlx::query("DELETE FROM perio_charts WHERE id = ?1")
+        .bind(chart_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to delete periodontal chart: {}", e))?;
+
+    Ok(())
+}
+
+/// Compares two exams of the same patient (chart_b is taken as the later one)
+#[tauri::command]
+pub async fn compare_perio_charts(
+    db_pool: State<'_, DbPool>,
+    chart_a: i64,
+    chart_b: i64,
+) -> Result<PerioComparison, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let a = load_perio_chart_detail(&mut conn, chart_a)
+        .await?
+        .ok_or_else(|| format!("Periodontal chart {} not found", chart_a))?;
+    let b = load_perio_chart_detail(&mut conn, chart_b)
+        .await?
+        .ok_or_else(|| format!("Periodontal chart {} not found", chart_b))?;
+
+    if a.chart.patient_id != b.chart.patient_id {
+        return Err("Both periodontal charts must belong to the same patient".to_string());
+    }
+
+    let delta = |before: Option<f64>, after: Option<f64>| Some(round_one_decimal(after? - before?));
+    let site_changes = compare_perio_sites(&a.sites, &b.sites);
+
+    Ok(PerioComparison {
+        delta_mean_probing_depth: delta(a.chart.mean_probing_depth, b.chart.mean_probing_depth),
+        delta_bop_percent: delta(a.chart.bop_percent, b.chart.bop_percent),
+        delta_mean_cal: delta(a.chart.mean_cal, b.chart.mean_cal),
+        improved_sites: site_changes.iter().filter(|c| c.change == "improved").count() as i64,
+        worsened_sites: site_changes.iter().filter(|c| c.change == "worsened").count() as i64,
+        site_changes,
+        chart_a: a.chart,
+        chart_b: b.chart,
+    })
+}
+
+// ============================================================================
+// PRESCRIPTIONS MODULE
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Drug {
+    pub id: Option<i64>,
+    pub name: String,
+    pub active_ingredient: Option<String>,
+    pub presentation: Option<String>,
+    pub default_dosage: Option<String>,
+    pub route: Option<String>,
+    pub allergy_tags: Option<String>,     // Comma separated: "penicilina, betalactámicos"
+    pub active: Option<bool>,
+    pub sort_order: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Prescription {
+    pub id: Option<i64>,
+    pub prescription_number: Option<i64>, // Assigned by backend (sequential)
+    pub patient_id: i64,
+    pub session_id: Option<i64>,
+    pub issued_at: Option<String>,
+    pub doctor_name: Option<String>,
+    pub diagnosis: Option<String>,
+    pub instructions: Option<String>,
+    pub allergy_warnings: Option<String>, // Snapshot of the check at issue time
+    pub allergy_override: Option<bool>,
+    pub status: Option<String>,           // 'active' | 'voided'
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrescriptionItem {
+    pub id: Option<i64>,
+    pub prescription_id: Option<i64>,
+    pub drug_id: Option<i64>,
+    pub drug_name: String,
+    pub presentation: Option<String>,
+    pub dosage: String,
+    pub route: Option<String>,
+    pub quantity: Option<String>,
+    pub notes: Option<String>,
+    pub sort_order: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrescriptionDetail {
+    pub prescription: Prescription,
+    pub items: Vec<PrescriptionItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AllergyWarning {
+    pub drug_name: String,
+    pub allergen: String,                 // Catalog term found in the patient's allergies
+}
+
+/// Lowercase without Spanish accents, for tolerant text matching
+fn normalize_search_text(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'ä' => 'a',
+            'é' | 'è' | 'ë' => 'e',
+            'í' | 'ì' | 'ï' => 'i',
+            'ó' | 'ò' | 'ö' => 'o',
+            'ú' | 'ù' | 'ü' => 'u',
+            other => other,
+        })
+        .collect()
+}
+
+/// Cross-checks drugs against the patient's free-text allergies.
+/// `terms` are (drug_name, comma separated terms: name, active ingredient, allergy tags).
+pub fn find_allergy_conflicts(allergy_detail: &str, drugs: &[(String, String)]) -> Vec<AllergyWarning> {
+    let allergies = normalize_search_text(allergy_detail);
+    if allergies.trim().is_empty() {
+        return Vec::new();
+    }
+
+    let mut warnings: Vec<AllergyWarning> = Vec::new();
+    for (drug_name, terms) in drugs {
+        let matched = terms
+            .split([',', '/', '+'])
+            .map(|t| t.trim())
+            .filter(|t| t.chars().count() >= 4)
+            .find(|t| allergies.contains(&normalize_search_text(t)));
+
+        if let Some(allergen) = matched {
+            let warning = AllergyWarning {
+                drug_name: drug_name.clone(),
+                allergen: allergen.to_string(),
+            };
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+    }
+    warnings
+}
+
+async fn check_allergies_for_items(
+    conn: &mut sqlx::SqliteConnection,
+    patient_id: i64,
+    items: &[PrescriptionItem],
+) -> Result<Vec<AllergyWarning>, String> {
+    let allergy_detail: Option<Option<String>> =
+        sqlx::query_scalar("SELECT allergy_detail FROM patients WHERE id = ?1")
+            .bind(patient_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    let allergy_detail = allergy_detail
+        .ok_or_else(|| format!("Patient {} not found", patient_id))?
+        .unwrap_or_default();
+
+    let mut drugs: Vec<(String, String)> = Vec::new();
+    for item in items {
+        let mut terms = vec![item.drug_name.clone()];
+        if let Some(drug_id) = item.drug_id {
+            let row = sqlx::query("SELECT name, active_ingredient, allergy_tags FROM drug_catalog WHERE id = ?1")
+                .bind(drug_id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+            if let Some(row) = row {
+                terms.push(row.get("name"));
+                terms.extend(row.get::<Option<String>, _>("active_ingredient"));
+                terms.extend(row.get::<Option<String>, _>("allergy_tags"));
+            }
+        }
+        drugs.push((item.drug_name.clone(), terms.join(",")));
+    }
+
+    Ok(find_allergy_conflicts(&allergy_detail, &drugs))
+}
+
+#[tauri::command]
+pub async fn get_drug_catalog(
+    db_pool: State<'_, DbPool>,
+) -> Result<Vec<Drug>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT id, name, active_ingredient, presentation, default_dosage, route, allergy_tags, active, sort_order
+         FROM drug_catalog
+         WHERE active = 1
+         ORDER BY sort_order ASC, name ASC"
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to get drug catalog: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Drug {
+            id: row.get("id"),
+            name: row.get("name"),
+            active_ingredient: row.get("active_ingredient"),
+            presentation: row.get("presentation"),
+            default_dosage: row.get("default_dosage"),
+            route: row.get("route"),
+            allergy_tags: row.get("allergy_tags"),
+            active: Some(row.get::<i64, _>("active") != 0),
+            sort_order: row.get("sort_order"),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn save_drug(
+    db_pool: State<'_, DbPool>,
+    drug: Drug,
+) -> Result<i64, String> {
+    if drug.name.trim().is_empty() {
+        return Err("Drug name is required".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    if let Some(id) = drug.id.filter(|&i| i > 0) {
+        sqlx::query(
+            "UPDATE drug_catalog
+             SET name = ?1, active_ingredient = ?2, presentation = ?3, default_dosage = ?4,
+                 route = ?5, allergy_tags = ?6, active = ?7, sort_order = ?8
+             WHERE id = ?9"
+        )
+        .bind(drug.name.trim())
+        .bind(&drug.active_ingredient)
+        .bind(drug.presentation.as_deref().unwrap_or(""))
+        .bind(&drug.default_dosage)
+        .bind(&drug.route)
+        .bind(&drug.allergy_tags)
+        .bind(drug.active.unwrap_or(true) as i64)
+        .bind(drug.sort_order.unwrap_or(0))
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update drug: {}", e))?;
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO drug_catalog (name, active_ingredient, presentation, default_dosage, route, allergy_tags, active, sort_order)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(name, presentation) DO UPDATE SET
+               active_ingredient = excluded.active_ingredient,
+               default_dosage = excluded.default_dosage,
+               route = excluded.route,
+               allergy_tags = excluded.allergy_tags,
+               active = excluded.active"
+        )
+        .bind(drug.name.trim())
+        .bind(&drug.active_ingredient)
+        .bind(drug.presentation.as_deref().unwrap_or(""))
+        .bind(&drug.default_dosage)
+        .bind(&drug.route)
+        .bind(&drug.allergy_tags)
+        .bind(drug.active.unwrap_or(true) as i64)
+        .bind(drug.sort_order.unwrap_or(0))
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create drug: {}", e))?;
+
+        // ON CONFLICT UPDATE does not report the existing rowid reliably
+        let existing_id: Option<i64> =
+            sqlx::query_scalar("SELECT id FROM drug_catalog WHERE name = ?1 AND presentation = ?2")
+                .bind(drug.name.trim())
+                .bind(drug.presentation.as_deref().unwrap_or(""))
+                .fetch_optional(&*pool)
+                .await
+                .map_err(|e| e.to_string())?;
+
+        Ok(existing_id.unwrap_or(result.last_insert_rowid()))
+    }
+}
+
+/// Soft delete: issued prescriptions keep their snapshot and drug_id
+#[tauri::command]
+pub async fn delete_drug(
+    db_pool: State<'_, DbPool>,
+    drug_id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("UPDATE drug_catalog SET active = 0 WHERE id = ?1")
+        .bind(drug_id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to delete drug: {}", e))?;
+
+    Ok(())
+}
+
+/// Allergy check before issuing (the frontend shows the warnings and may override)
+#[tauri::command]
+pub async fn check_prescription_allergies(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+    items: Vec<PrescriptionItem>,
+) -> Result<Vec<AllergyWarning>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    check_allergies_for_items(&mut conn, patient_id, &items).await
+}
+
+const PRESCRIPTION_COLUMNS: &str =
+    "id, prescription_number, patient_id, session_id, issued_at, doctor_name, diagnosis, instructions,
+     allergy_warnings, allergy_override, status, created_at";
+
+async fn load_prescription_detail(
+    conn: &mut sqlx::SqliteConnection,
+    prescription_id: i64,
+) -> Result<Option<PrescriptionDetail>, String> {
+    let row = sqlx::query(&format!("SELECT {} FROM prescriptions WHERE id = ?1", PRESCRIPTION_COLUMNS))
+        .bind(prescription_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to get prescription: {}", e))?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let prescription = Prescription {
+        id: row.get("id"),
+        prescription_number: row.get("prescription_number"),
+        patient_id: row.get("patient_id"),
+        session_id: row.get("session_id"),
+        issued_at: row.get("issued_at"),
+        doctor_name: row.get("doctor_name"),
+        diagnosis: row.get("diagnosis"),
+        instructions: row.get("instructions"),
+        allergy_warnings: row.get("allergy_warnings"),
+        allergy_override: Some(row.get::<i64, _>("allergy_override") != 0),
+        status: row.get("status"),
+        created_at: row.get("created_at"),
+    };
+
+    let items = sqlx::query(
+        "SELECT id, prescription_id, drug_id, drug_name, presentation, dosage, route, quantity, notes, sort_order
+         FROM prescription_items
+         WHERE prescription_id = ?1
+         ORDER BY sort_order ASC, id ASC"
+    )
+    .bind(prescription_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get prescription items: {}", e))?
+    .into_iter()
+    .map(|row| PrescriptionItem {
+        id: row.get("id"),
+        prescription_id: row.get("prescription_id"),
+        drug_id: row.get("drug_id"),
+        drug_name: row.get("drug_name"),
+        presentation: row.get("presentation"),
+        dosage: row.get("dosage"),
+        route: row.get("route"),
+        quantity: row.get("quantity"),
+        notes: row.get("notes"),
+        sort_order: row.get("sort_order"),
+    })
+    .collect();
+
+    Ok(Some(PrescriptionDetail { prescription, items }))
+}
+
+/// Issues a prescription with the next sequential number.
+/// Prescriptions are immutable once issued: corrections are done by voiding and re-issuing.
+/// If the allergy check finds conflicts, `override_allergies` must be true.
+#[tauri::command]
+pub async fn create_prescription(
+    db_pool: State<'_, DbPool>,
+    detail: PrescriptionDetail,
+    override_allergies: Option<bool>,
+) -> Result<PrescriptionDetail, String> {
+    let items: Vec<&PrescriptionItem> = detail
+        .items
+        .iter()
+        .filter(|i| !i.drug_name.trim().is_empty())
+        .collect();
+    if items.is_empty() {
+        return Err("A prescription needs at least one drug".to_string());
+    }
+    if let Some(item) = items.iter().find(|i| i.dosage.trim().is_empty()) {
+        return Err(format!("Dosage is required for '{}'", item.drug_name));
+    }
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let owned_items: Vec<PrescriptionItem> = items.iter().map(|i| (*i).clone()).collect();
+    let warnings = check_allergies_for_items(&mut tx, detail.prescription.patient_id, &owned_items).await?;
+    let override_allergies = override_allergies.unwrap_or(false);
+    let warnings_text = warnings
+        .iter()
+        .map(|w| format!("{}: paciente alérgico a {}", w.drug_name, w.allergen))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if !warnings.is_empty() && !override_allergies {
+        return Err(format!("Allergy conflict:\n{}", warnings_text));
+    }
+
+    if let Some(session_id) = detail.prescription.session_id {
+        let session_patient: Option<i64> = sqlx::query_scalar("SELECT patient_id FROM sessions WHERE id = ?1")
+            .bind(session_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        if session_patient != Some(detail.prescription.patient_id) {
+            return Err(format!("Session {} does not belong to the patient", session_id));
+        }
+        ensure_session_unlocked(&mut tx, session_id).await?;
+    }
+
+    let next_number: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(prescription_number), 0) + 1 FROM prescriptions")
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let issued_at = detail
+        .prescription
+        .issued_at
+        .clone()
+        .unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d").to_string());
+
+    let prescription_id = sqlx::query(
+        "INSERT INTO prescriptions (prescription_number, patient_id, session_id, issued_at, doctor_name,
+                                    diagnosis, instructions, allergy_warnings, allergy_override)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+    )
+    .bind(next_number)
+    .bind(detail.prescription.patient_id)
+    .bind(detail.prescription.session_id)
+    .bind(&issued_at)
+    .bind(&detail.prescription.doctor_name)
+    .bind(&detail.prescription.diagnosis)
+    .bind(&detail.prescription.instructions)
+    .bind(if warnings_text.is_empty() { None } else { Some(&warnings_text) })
+    .bind((!warnings.is_empty() && override_allergies) as i64)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to create prescription: {}", e))?
+    .last_insert_rowid();
+
+    for (index, item) in items.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO prescription_items (prescription_id, drug_id, drug_name, presentation, dosage,
+                                             route, quantity, notes, sort_order)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+        )
+        .bind(prescription_id)
+        .bind(item.drug_id)
+        .bind(item.drug_name.trim())
+        .bind(&item.presentation)
+        .bind(item.dosage.trim())
+        .bind(&item.route)
+        .bind(&item.quantity)
+        .bind(&item.notes)
+        .bind(item.sort_order.unwrap_or(index as i64))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to create prescription item: {}", e))?;
+    }
+
+    let created = load_prescription_detail(&mut tx, prescription_id)
+        .await?
+        .ok_or_else(|| "Prescription not found after insert".to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("💊 Receta N° {} emitida (paciente {})", next_number, detail.prescription.patient_id);
+    Ok(created)
+}
+
+#[tauri::command]
+pub async fn get_prescriptions_by_patient(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+) -> Result<Vec<PrescriptionDetail>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM prescriptions WHERE patient_id = ?1 ORDER BY issued_at DESC, prescription_number DESC"
+    )
+    .bind(patient_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get prescriptions: {}", e))?;
+
+    let mut prescriptions = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(detail) = load_prescription_detail(&mut conn, id).await? {
+            prescriptions.push(detail);
+        }
+    }
+
+    Ok(prescriptions)
+}
+
+#[tauri::command]
+pub async fn get_prescription(
+    db_pool: State<'_, DbPool>,
+    prescription_id: i64,
+) -> Result<Option<PrescriptionDetail>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    load_prescription_detail(&mut conn, prescription_id).await
+}
+
+/// Voids a prescription (the number is never reused)
+#[tauri::command]
+pub async fn void_prescription(
+    db_pool: State<'_, DbPool>,
+    prescription_id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let session_id: Option<Option<i64>> = sqlx::query_scalar("SELECT session_id FROM prescriptions WHERE id = ?1")
+        .bind(prescription_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(Some(session_id)) = session_id {
+        ensure_session_unlocked(&mut conn, session_id).await?;
+    }
+
+    let result = sqlx::query("UPDATE prescriptions SET status = 'voided' WHERE id = ?1")
+        .bind(prescription_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to void prescription: {}", e))?;
+
+    if result.rows_affected() == 0 {
+        return Err(format!("Prescription {} not found", prescription_id));
+    }
+
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Whole years between a birth date and a reference date (both YYYY-MM-DD)
+fn age_in_years(date_of_birth: &str, at: &str) -> Option<i64> {
+    use chrono::Datelike;
+
+    let birth = chrono::NaiveDate::parse_from_str(date_of_birth.get(..10)?, "%Y-%m-%d").ok()?;
+    let at = chrono::NaiveDate::parse_from_str(at.get(..10)?, "%Y-%m-%d").ok()?;
+    let mut years = (at.year() - birth.year()) as i64;
+    if (at.month(), at.day()) < (birth.month(), birth.day()) {
+        years -= 1;
+    }
+    Some(years)
+}
+
+fn render_prescription_html(
+    detail: &PrescriptionDetail,
+    patient: &Patient,
+    profile: Option<&DoctorProfile>,
+) -> String {
+    let rx = &detail.prescription;
+    let issued_at = rx.issued_at.clone().unwrap_or_default();
+    let opt = |value: Option<&String>| escape_html(value.map(|s| s.as_str()).unwrap_or(""));
+
+    let clinic_name = opt(profile.and_then(|p| p.clinic_name.as_ref()));
+    let clinic_contact = [
+        profile.and_then(|p| p.location.clone()),
+        profile.and_then(|p| p.phone.clone()),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|s| escape_html(&s))
+    .collect::<Vec<_>>()
+    .join(" · ");
+    let doctor_name = escape_html(
+        rx.doctor_name
+            .as_deref()
+            .or(profile.map(|p| p.name.as_str()))
+            .unwrap_or(""),
+    );
+    let age = age_in_years(&patient.date_of_birth, &issued_at)
+        .map(|years| format!("{} años", years))
+        .unwrap_or_default();
+
+    let items_html: String = detail
+        .items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let mut line = format!(
+                "<li><strong>{}. {}</strong>{}",
+                index + 1,
+                escape_html(&item.drug_name),
+                item.presentation
+                    .as_deref()
+                    .filter(|p| !p.is_empty())
+                    .map(|p| format!(" — {}", escape_html(p)))
+                    .unwrap_or_default()
+            );
+            if let Some(quantity) = item.quantity.as_deref().filter(|q| !q.is_empty()) {
+                line.push_str(&format!(" <span class=\"qty\">({})</span>", escape_html(quantity)));
+            }
+            line.push_str(&format!("<div class=\"dosage\">{}", escape_html(&item.dosage)));
+            if let Some(route) = item.route.as_deref().filter(|r| !r.is_empty()) {
+                line.push_str(&format!(" · Vía {}", escape_html(route)));
+            }
+            line.push_str("</div>");
+            if let Some(notes) = item.notes.as_deref().filter(|n| !n.is_empty()) {
+                line.push_str(&format!("<div class=\"notes\">{}</div>", escape_html(notes)));
+            }
+            line.push_str("</li>");
+            line
+        })
+        .collect();
+
+    let voided = if rx.status.as_deref() == Some("voided") {
+        "<div class=\"voided\">ANULADA</div>"
+    } else {
+        ""
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="es">
+<head>
+<meta charset="utf-8">
+<title>Receta N° {number}</title>
+<style>
+  @page {{ size: A4; }}
+  body {{ font-family: Arial, Helvetica, sans-serif; font-size: 12pt; color: #111; }}
+  header {{ border-bottom: 2px solid #333; padding-bottom: 8px; margin-bottom: 12px; }}
+  header h1 {{ font-size: 16pt; margin: 0; }}
+  header .contact {{ font-size: 9pt; color: #555; }}
+  .meta {{ display: flex; justify-content: space-between; font-size: 10pt; margin-bottom: 8px; }}
+  .patient {{ font-size: 11pt; margin-bottom: 12px; }}
+  .rx {{ font-size: 22pt; font-weight: bold; }}
+  ol {{ list-style: none; padding: 0; }}
+  li {{ margin-bottom: 10px; }}
+  .dosage {{ margin-left: 18px; }}
+  .notes {{ margin-left: 18px; font-size: 10pt; color: #555; }}
+  .instructions {{ margin-top: 16px; white-space: pre-wrap; }}
+  .signature {{ margin-top: 60px; text-align: center; }}
+  .signature .line {{ border-top: 1px solid #333; width: 240px; margin: 0 auto 4px; }}
+  .voided {{ color: #b00; font-size: 20pt; font-weight: bold; text-align: center; border: 3px solid #b00; margin-bottom: 12px; }}
+</style>
+</head>
+<body>
+<header>
+  <h1>{clinic_name}</h1>
+  <div class="contact">{clinic_contact}</div>
+</header>
+{voided}
+<div class="meta"><span>Receta N° {number}</span><span>Fecha: {issued_at}</span></div>
+<div class="patient">
+  <div><strong>Paciente:</strong> {patient_name} &nbsp; <strong>CI:</strong> {doc_id} &nbsp; <strong>Edad:</strong> {age}</div>
+  <div><strong>Alergias:</strong> {allergies}</div>
+  <div><strong>Diagnóstico:</strong> {diagnosis}</div>
+</div>
+<div class="rx">Rp.</div>
+<ol>{items_html}</ol>
+<div class="instructions"><strong>Indicaciones:</strong> {instructions}</div>
+<div class="signature"><div class="line"></div>{doctor_name}</div>
+</body>
+</html>"#,
+        number = rx.prescription_number.unwrap_or(0),
+        clinic_name = clinic_name,
+        clinic_contact = clinic_contact,
+        voided = voided,
+        issued_at = escape_html(&issued_at),
+        patient_name = escape_html(&patient.full_name),
+        doc_id = escape_html(&patient.doc_id),
+        age = age,
+        allergies = opt(patient.allergy_detail.as_ref().filter(|a| !a.trim().is_empty())),
+        diagnosis = opt(rx.diagnosis.as_ref()),
+        items_html = items_html,
+        instructions = opt(rx.instructions.as_ref()),
+        doctor_name = doctor_name,
+    )
+}
+
+/// Renders a prescription to PDF through the same headless Chrome path as other documents
+#[tauri::command]
+pub async fn generate_prescription_pdf(
+    app_handle: tauri::AppHandle,
+    db_pool: State<'_, DbPool>,
+    prescription_id: i64,
+) -> Result<GeneratePdfResponse, String> {
+    // Load everything first so the DB lock is not held while the dialog is open
+    let (html_content, number) = {
+        let pool = db_pool.0.lock().await;
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+        let detail = load_prescription_detail(&mut conn, prescription_id)
+            .await?
+            .ok_or_else(|| format!("Prescription {} not found", prescription_id))?;
+
+        let row = sqlx::query(
+            "SELECT id, full_name, doc_id, email, phone, emergency_phone, date_of_birth, anamnesis, allergy_detail, status, created_at, updated_at
+             FROM patients
+             WHERE id = ?1"
+        )
+        .bind(detail.prescription.patient_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to get patient: {}", e))?;
+
+        let patient = Patient {
+            id: row.get("id"),
+            full_name: row.get("full_name"),
+            doc_id: row.get("doc_id"),
+            email: row.get("email"),
+            phone: row.get("phone"),
+            emergency_phone: row.get("emergency_phone"),
+            date_of_birth: row.get("date_of_birth"),
+            anamnesis: row.get("anamnesis"),
+            allergy_detail: row.get("allergy_detail"),
+            status: row.get("status"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        };
+
+        let profile = sqlx::query(
+            "SELECT name, clinic_name, phone, location FROM doctor_profile ORDER BY id DESC LIMIT 1"
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|row| DoctorProfile {
+            id: None,
+            doctor_id: String::new(),
+            name: row.get("name"),
+            email: None,
+            clinic_name: row.get("clinic_name"),
+            clinic_hours: None,
+            clinic_slogan: None,
+            phone: row.get("phone"),
+            location: row.get("location"),
+            app_version: None,
+            agreed_to_terms: None,
+            last_sync: None,
+            created_at: None,
+            updated_at: None,
+        });
+
+        (
+            render_prescription_html(&detail, &patient, profile.as_ref()),
+            detail.prescription.prescription_number.unwrap_or(prescription_id),
+        )
+    };
+
+    let file_path = ask_pdf_save_path(&app_handle, &format!("receta_{:06}.pdf", number))?;
+    let pdf_data = print_html_to_pdf(&html_content)?;
+
+    fs::write(&file_path, pdf_data)
+        .map_err(|e| format!("Failed to write PDF: {}", e))?;
+
+    Ok(GeneratePdfResponse {
+        file_path: file_path.to_string_lossy().to_string(),
+    })
+}
+
+// ============================================================================
+// LAB ORDERS MODULE
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LabOrder {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub session_id: Option<i64>,
+    pub lab_name: String,
+    pub work_type: String,
+    pub shade: Option<String>,
+    pub teeth: Option<String>,
+    pub instructions: Option<String>,
+    pub status: Option<String>,           // 'draft' | 'sent' | 'received' | 'delivered' | 'cancelled'
+    pub sent_date: Option<String>,
+    pub due_date: Option<String>,
+    pub received_date: Option<String>,
+    pub delivered_date: Option<String>,
+    pub cost: Option<f64>,
+    pub notes: Option<String>,
+    pub is_overdue: Option<bool>,         // Calculated: open and past due_date
+    pub linked_item_count: Option<i64>,   // Calculated: session_items pointing to this order
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LabOrderAgendaAlert {
+    pub appointment_id: i64,
+    pub patient_id: i64,
+    pub starts_at: String,
+    pub lab_order_id: i64,
+    pub lab_name: String,
+    pub work_type: String,
+    pub status: String,
+    pub due_date: Option<String>,
+    pub reason: String,                   // 'overdue' | 'due_after_appointment' | 'not_sent'
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProcedureProfitability {
+    pub name: String,
+    pub times_performed: i64,
+    pub revenue: f64,
+    pub lab_cost: f64,
+    pub margin: f64,
+    pub margin_percent: Option<f64>,
+}
+
+// Orders still waiting on the lab
+const LAB_ORDER_OPEN_STATUSES: &str = "('draft', 'sent')";
+
+/// Allowed status transitions (received → sent = returned to the lab for adjustments)
+fn is_valid_lab_order_transition(from: &str, to: &str) -> bool {
+    matches!(
+        (from, to),
+        ("draft", "sent")
+            | ("draft", "cancelled")
+            | ("sent", "received")
+            | ("sent", "cancelled")
+            | ("received", "sent")
+            | ("received", "delivered")
+    )
+}
+
+const LAB_ORDER_SELECT: &str =
+    "SELECT lo.id, lo.patient_id, lo.session_id, lo.lab_name, lo.work_type, lo.shade, lo.teeth,
+            lo.instructions, lo.status, lo.sent_date, lo.due_date, lo.received_date, lo.delivered_date,
+            lo.cost, lo.notes, lo.created_at, lo.updated_at,
+            (SELECT COUNT(*) FROM session_items si WHERE si.lab_order_id = lo.id) as linked_item_count
+     FROM lab_orders lo";
+
+fn lab_order_from_row(row: &sqlx::sqlite::SqliteRow, today: &str) -> LabOrder {
+    let status: String = row.get("status");
+    let due_date: Option<String> = row.get("due_date");
+    let is_overdue = status == "sent" && due_date.as_deref().is_some_and(|due| due < today);
+
+    LabOrder {
+        id: row.get("id"),
+        patient_id: row.get("patient_id"),
+        session_id: row.get("session_id"),
+        lab_name: row.get("lab_name"),
+        work_type: row.get("work_type"),
+        shade: row.get("shade"),
+        teeth: row.get("teeth"),
+        instructions: row.get("instructions"),
+        status: Some(status),
+        sent_date: row.get("sent_date"),
+        due_date,
+        received_date: row.get("received_date"),
+        delivered_date: row.get("delivered_date"),
+        cost: row.get("cost"),
+        notes: row.get("notes"),
+        is_overdue: Some(is_overdue),
+        linked_item_count: row.get("linked_item_count"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+/// Today's date in the clinic timezone (due/overdue checks)
+async fn today_string(conn: &mut sqlx::SqliteConnection) -> Result<String, String> {
+    Ok(load_clinic_timezone(conn).await?.now_local().date().format("%Y-%m-%d").to_string())
+}
+
+#[tauri::command]
+pub async fn get_lab_orders_by_patient(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+) -> Result<Vec<LabOrder>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let today = today_string(&mut conn).await?;
+
+    let rows = sqlx::query(&format!(
+        "{} WHERE lo.patient_id = ?1 ORDER BY lo.created_at DESC, lo.id DESC",
+        LAB_ORDER_SELECT
+    ))
+    .bind(patient_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to get lab orders: {}", e))?;
+
+    Ok(rows.iter().map(|row| lab_order_from_row(row, &today)).collect())
+}
+
+#[tauri::command]
+pub async fn get_lab_order(
+    db_pool: State<'_, DbPool>,
+    lab_order_id: i64,
+) -> Result<Option<LabOrder>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let today = today_string(&mut conn).await?;
+
+    let row = sqlx::query(&format!("{} WHERE lo.id = ?1", LAB_ORDER_SELECT))
+        .bind(lab_order_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to get lab order: {}", e))?;
+
+    Ok(row.map(|row| lab_order_from_row(&row, &today)))
+}
+
+/// Creates or updates the order details. Status changes go through update_lab_order_status.
+#[tauri::command]
+pub async fn save_lab_order(
+    db_pool: State<'_, DbPool>,
+    order: LabOrder,
+) -> Result<i64, String> {
+    if order.lab_name.trim().is_empty() {
+        return Err("Lab name is required".to_string());
+    }
+    if order.work_type.trim().is_empty() {
+        return Err("Work type is required".to_string());
+    }
+    if order.cost.is_some_and(|c| c < 0.0) {
+        return Err("Lab cost cannot be negative".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    if let Some(session_id) = order.session_id {
+        let session_patient: Option<i64> = sqlx::query_scalar("SELECT patient_id FROM sessions WHERE id = ?1")
+            .bind(session_id)
+            .fetch_optional(&*pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        if session_patient != Some(order.patient_id) {
+            return Err(format!("Session {} does not belong to the patient", session_id));
+        }
+    }
+
+    if let Some(id) = order.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE lab_orders
+             SET session_id = ?1, lab_name = ?2, work_type = ?3, shade = ?4, teeth = ?5, instructions = ?6,
+                 due_date = ?7, cost = ?8, notes = ?9
+             WHERE id = ?10 AND patient_id = ?11"
+        )
+        .bind(order.session_id)
+        .bind(order.lab_name.trim())
+        .bind(order.work_type.trim())
+        .bind(&order.shade)
+        .bind(&order.teeth)
+        .bind(&order.instructions)
+        .bind(&order.due_date)
+        .bind(order.cost.unwrap_or(0.0))
+        .bind(&order.notes)
+        .bind(id)
+        .bind(order.patient_id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update lab order: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Lab order {} not found", id));
+        }
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO lab_orders (patient_id, session_id, lab_name, work_type, shade, teeth, instructions,
+                                     due_date, cost, notes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"
+        )
+        .bind(order.patient_id)
+        .bind(order.session_id)
+        .bind(order.lab_name.trim())
+        .bind(order.work_type.trim())
+        .bind(&order.shade)
+        .bind(&order.teeth)
+        .bind(&order.instructions)
+        .bind(&order.due_date)
+        .bind(order.cost.unwrap_or(0.0))
+        .bind(&order.notes)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create lab order: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+/// Moves an order through its lifecycle and stamps the matching date
+/// (defaults to today when `date` is not given)
+#[tauri::command]
+pub async fn update_lab_order_status(
+    db_pool: State<'_, DbPool>,
+    lab_order_id: i64,
+    status: String,
+    date: Option<String>,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let current: Option<String> = sqlx::query_scalar("SELECT status FROM lab_orders WHERE id = ?1")
+        .bind(lab_order_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    let current = current.ok_or_else(|| format!("Lab order {} not found", lab_order_id))?;
+
+    if !is_valid_lab_order_transition(&current, &status) {
+        return Err(format!("Invalid lab order transition: {} → {}", current, status));
+    }
+
+    let date = match date {
+        Some(date) => date,
+        None => today_string(&mut conn).await?,
+    };
+    let date_column = match status.as_str() {
+        "sent" => Some("sent_date"),
+        "received" => Some("received_date"),
+        "delivered" => Some("delivered_date"),
+        _ => None,
+    };
+
+    let mut sql = "UPDATE lab_orders SET status = ?1".to_string();
+    if let Some(column) = date_column {
+        sql.push_str(&format!(", {} = ?2", column));
+    }
+    if status == "sent" {
+        // Re-sending after an adjustment clears the previous reception
+        sql.push_str(", received_date = NULL");
+    }
+    sql.push_str(" WHERE id = ?3");
+
+    sqlx::query(&sql)
+        .bind(&status)
+        .bind(&date)
+        .bind(lab_order_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to update lab order status: {}", e))?;
+
+    Ok(())
+}
+
+/// Deletes an order that never reached the patient; session items are unlinked
+#[tauri::command]
+pub async fn delete_lab_order(
+    db_pool: State<'_, DbPool>,
+    lab_order_id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM lab_orders WHERE id = ?1")
+        .bind(lab_order_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if status.as_deref() == Some("delivered") {
+        return Err("Delivered lab orders cannot be deleted".to_string());
+    }
+
+    sqlx::query("UPDATE session_items SET lab_order_id = NULL WHERE lab_order_id = ?1")
+        .bind(lab_order_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM lab_orders WHERE id = ?1")
+        .bind(lab_order_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to delete lab order: {}", e))?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Orders sent to the lab and not yet received after their due date
+#[tauri::command]
+pub async fn list_overdue_lab_orders(
+    db_pool: State<'_, DbPool>,
+) -> Result<Vec<LabOrder>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let today = today_string(&mut conn).await?;
+
+    let rows = sqlx::query(&format!(
+        "{} WHERE lo.status = 'sent' AND lo.due_date IS NOT NULL AND lo.due_date < ?1
+         ORDER BY lo.due_date ASC",
+        LAB_ORDER_SELECT
+    ))
+    .bind(&today)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to list overdue lab orders: {}", e))?;
+
+    Ok(rows.iter().map(|row| lab_order_from_row(row, &today)).collect())
+}
+
+/// Appointments in range whose patient has lab work that will not be ready:
+/// overdue, due after the appointment day, or never sent.
+#[tauri::command]
+pub async fn list_lab_order_agenda_alerts(
+    db_pool: State<'_, DbPool>,
+    range_start: String,
+    range_end: String,
+) -> Result<Vec<LabOrderAgendaAlert>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let tz = load_clinic_timezone(&mut conn).await?;
+    let today = tz.now_local().date().format("%Y-%m-%d").to_string();
+    let range_start = canonical_appointment_time(&range_start, tz)?;
+    let range_end = canonical_appointment_time(&range_end, tz)?;
+
+    let rows = sqlx::query(&format!(
+        "SELECT a.id as appointment_id, a.patient_id, a.starts_at,
+                lo.id as lab_order_id, lo.lab_name, lo.work_type, lo.status, lo.due_date
+         FROM appointments a
+         JOIN lab_orders lo ON lo.patient_id = a.patient_id
+         WHERE a.starts_at < ?2 AND a.ends_at > ?1
+           AND a.status NOT IN ('cancelled', 'completed')
+           AND lo.status IN {}
+         ORDER BY a.starts_at ASC, lo.id ASC",
+        LAB_ORDER_OPEN_STATUSES
+    ))
+    .bind(&range_start)
+    .bind(&range_end)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to list lab order alerts: {}", e))?;
+
+    let alerts = rows
+        .into_iter()
+        .filter_map(|row| {
+            let status: String = row.get("status");
+            let starts_at: String = row.get("starts_at");
+            let due_date: Option<String> = row.get("due_date");
+            let appointment_day = appointment_local_time(&starts_at, tz)
+                .map(|local| local.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| starts_at.get(..10).unwrap_or(&starts_at).to_string());
+
+            let reason = match (status.as_str(), due_date.as_deref()) {
+                ("draft", _) => "not_sent",
+                (_, Some(due)) if due < today.as_str() => "overdue",
+                (_, Some(due)) if due > appointment_day.as_str() => "due_after_appointment",
+                _ => return None,
+            };
+
+            Some(LabOrderAgendaAlert {
+                appointment_id: row.get("appointment_id"),
+                patient_id: row.get("patient_id"),
+                starts_at,
+                lab_order_id: row.get("lab_order_id"),
+                lab_name: row.get("lab_name"),
+                work_type: row.get("work_type"),
+                status,
+                due_date,
+                reason: reason.to_string(),
+            })
+        })
+        .collect();
+
+    Ok(alerts)
+}
+
+/// Revenue vs lab cost per procedure name over saved sessions in a date range.
+/// An order linked to several items (e.g. a bridge) splits its cost evenly among them.
+#[tauri::command]
+pub async fn get_procedure_profitability(
+    db_pool: State<'_, DbPool>,
+    date_from: String,
+    date_to: String,
+) -> Result<Vec<ProcedureProfitability>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT si.name,
+                CAST(SUM(si.quantity) AS INTEGER) as times_performed,
+                COALESCE(CAST(SUM(si.subtotal) AS REAL), 0.0) as revenue,
+                COALESCE(CAST(SUM(
+                    CASE WHEN lo.id IS NOT NULL AND lo.status != 'cancelled'
+                         THEN lo.cost / (SELECT COUNT(*) FROM session_items x WHERE x.lab_order_id = lo.id)
+                         ELSE 0 END
+                ) AS REAL), 0.0) as lab_cost
+         FROM session_items si
+         JOIN sessions s ON s.id = si.session_id
+         LEFT JOIN lab_orders lo ON lo.id = si.lab_order_id
+         WHERE s.is_saved = 1
+           AND si.is_active = 1
+           AND s.date >= ?1 AND s.date <= ?2
+         GROUP BY si.name
+         ORDER BY revenue DESC"
+    )
+    .bind(&date_from)
+    .bind(&date_to)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to get procedure profitability: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let revenue: f64 = row.get("revenue");
+            let lab_cost: f64 = row.get("lab_cost");
+            let margin = revenue - lab_cost;
+            ProcedureProfitability {
+                name: row.get("name"),
+                times_performed: row.get("times_performed"),
+                revenue,
+                lab_cost,
+                margin,
+                margin_percent: (revenue > 0.0).then(|| round_one_decimal(margin * 100.0 / revenue)),
+            }
+        })
+        .collect())
+}
+
+// ============================================================================
+// SESSION SIGN-OFF & ADDENDA
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionAddendum {
+    pub id: Option<i64>,
+    pub session_id: i64,
+    pub author: String,
+    pub body: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionSignature {
+    pub session_id: i64,
+    pub signed_at: String,
+    pub signed_by: String,
+    pub content_hash: String,                 // SHA-256 (hex) of the clinical + financial snapshot
+    pub is_valid: bool,                       // Stored hash matches the current content
+}
+
+/// Error returned when trying to modify a signed session.
+/// Serialized to the frontend as a string starting with "SESSION_LOCKED:".
+#[derive(Debug)]
+pub enum SessionLockError {
+    Locked {
+        session_id: i64,
+        signed_at: String,
+        signed_by: String,
+    },
+    Database(String),
+}
+
+impl std::fmt::Display for SessionLockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionLockError::Locked { session_id, signed_at, signed_by } => write!(
+                f,
+                "SESSION_LOCKED: session {} was signed by {} on {} and cannot be modified (add an addendum instead)",
+                session_id, signed_by, signed_at
+            ),
+            SessionLockError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<SessionLockError> for String {
+    fn from(error: SessionLockError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Fails with SessionLockError::Locked if the session has been signed
+async fn ensure_session_unlocked(
+    conn: &mut sqlx::SqliteConnection,
+    session_id: i64,
+) -> Result<(), SessionLockError> {
+    let row = sqlx::query("SELECT signed_at, signed_by FROM sessions WHERE id = ?1")
+        .bind(session_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| SessionLockError::Database(e.to_string()))?;
+
+    match row.and_then(|row| {
+        let signed_at: Option<String> = row.get("signed_at");
+        signed_at.map(|signed_at| (signed_at, row.get::<Option<String>, _>("signed_by")))
+    }) {
+        Some((signed_at, signed_by)) => Err(SessionLockError::Locked {
+            session_id,
+            signed_at,
+            signed_by: signed_by.unwrap_or_default(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// SHA-256 of the session snapshot (header + items in display order).
+/// cumulative_balance is excluded: it depends on other sessions.
+async fn compute_session_content_hash(
+    conn: &mut sqlx::SqliteConnection,
+    session_id: i64,
+) -> Result<String, String> {
+    use sha2::{Digest, Sha256};
+
+    let row = sqlx::query(
+        "SELECT patient_id, date, reason_type, reason_detail, diagnosis_text, auto_dx_text, full_dx_text,
+                tooth_dx_json, clinical_notes, signer, budget, discount, payment, balance,
+                payment_method_id, payment_notes
+         FROM sessions
+         WHERE id = ?1"
+    )
+    .bind(session_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Session {} not found", session_id))?;
+
+    let items: Vec<serde_json::Value> = sqlx::query(
+        "SELECT name, unit_price, quantity, subtotal, is_active, tooth_number, procedure_notes, procedure_template_id
+         FROM session_items
+         WHERE session_id = ?1
+         ORDER BY sort_order ASC, id ASC"
+    )
+    .bind(session_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .into_iter()
+    .map(|item| {
+        serde_json::json!({
+            "name": item.get::<String, _>("name"),
+            "unit_price": item.get::<f64, _>("unit_price"),
+            "quantity": item.get::<i64, _>("quantity"),
+            "subtotal": item.get::<f64, _>("subtotal"),
+            "is_active": item.get::<i64, _>("is_active"),
+            "tooth_number": item.get::<Option<String>, _>("tooth_number"),
+            "procedure_notes": item.get::<Option<String>, _>("procedure_notes"),
+            "procedure_template_id": item.get::<Option<i64>, _>("procedure_template_id"),
+        })
+    })
+    .collect();
+
+    // Fixed key order, so the serialization is deterministic for the same content
+    let snapshot = serde_json::json!({
+        "session_id": session_id,
+        "patient_id": row.get::<i64, _>("patient_id"),
+        "date": row.get::<String, _>("date"),
+        "reason_type": row.get::<Option<String>, _>("reason_type"),
+        "reason_detail": row.get::<Option<String>, _>("reason_detail"),
+        "diagnosis_text": row.get::<Option<String>, _>("diagnosis_text"),
+        "auto_dx_text": row.get::<Option<String>, _>("auto_dx_text"),
+        "full_dx_text": row.get::<Option<String>, _>("full_dx_text"),
+        "tooth_dx_json": row.get::<Option<String>, _>("tooth_dx_json"),
+        "clinical_notes": row.get::<Option<String>, _>("clinical_notes"),
+        "signer": row.get::<Option<String>, _>("signer"),
+        "budget": row.get::<f64, _>("budget"),
+        "discount": row.get::<f64, _>("discount"),
+        "payment": row.get::<f64, _>("payment"),
+        "balance": row.get::<f64, _>("balance"),
+        "payment_method_id": row.get::<Option<i64>, _>("payment_method_id"),
+        "payment_notes": row.get::<Option<String>, _>("payment_notes"),
+        "items": items,
+    });
+
+    let digest = Sha256::digest(snapshot.to_string().as_bytes());
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+async fn load_session_addenda(
+    pool: &sqlx::SqlitePool,
+    session_id: i64,
+) -> Result<Vec<SessionAddendum>, String> {
+    let rows = sqlx::query(
+        "SELECT id, session_id, author, body, created_at
+         FROM session_addenda
+         WHERE session_id = ?1
+         ORDER BY created_at ASC, id ASC"
+    )
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to get session addenda: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| SessionAddendum {
+            id: row.get("id"),
+            session_id: row.get("session_id"),
+            author: row.get("author"),
+            body: row.get("body"),
+            created_at: row.get("created_at"),
+        })
+        .collect())
+}
+
+/// Signs off a saved session: stores the content hash, signer and timestamp.
+/// From here on the session (and its items) can only be complemented with addenda.
+#[tauri::command]
+pub async fn sign_session(
+    db_pool: State<'_, DbPool>,
+    session_id: i64,
+    signed_by: String,
+) -> Result<SessionSignature, String> {
+    if signed_by.trim().is_empty() {
+        return Err("Signer is required".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let is_saved: Option<i64> = sqlx::query_scalar("SELECT is_saved FROM sessions WHERE id = ?1")
+        .bind(session_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match is_saved {
+        None => return Err(format!("Session {} not found", session_id)),
+        Some(0) => return Err("Only saved sessions can be signed".to_string()),
+        Some(_) => {}
+    }
+    ensure_session_unlocked(&mut tx, session_id).await?;
+
+    let content_hash = compute_session_content_hash(&mut tx, session_id).await?;
+    let signed_at = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    sqlx::query("UPDATE sessions SET signed_at = ?1, signed_by = ?2, content_hash = ?3 WHERE id = ?4")
+        .bind(&signed_at)
+        .bind(signed_by.trim())
+        .bind(&content_hash)
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to sign session: {}", e))?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("🔏 Sesión {} firmada por {}", session_id, signed_by.trim());
+    Ok(SessionSignature {
+        session_id,
+        signed_at,
+        signed_by: signed_by.trim().to_string(),
+        content_hash,
+        is_valid: true,
+    })
+}
+
+/// Returns the signature of a session (None if unsigned) and whether its content still matches
+#[tauri::command]
+pub async fn verify_session_signature(
+    db_pool: State<'_, DbPool>,
+    session_id: i64,
+) -> Result<Option<SessionSignature>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let row = sqlx::query("SELECT signed_at, signed_by, content_hash FROM sessions WHERE id = ?1")
+        .bind(session_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Session {} not found", session_id))?;
+
+    let Some(signed_at) = row.get::<Option<String>, _>("signed_at") else {
+        return Ok(None);
+    };
+    let stored_hash: String = row.get::<Option<String>, _>("content_hash").unwrap_or_default();
+    let current_hash = compute_session_content_hash(&mut conn, session_id).await?;
+
+    Ok(Some(SessionSignature {
+        session_id,
+        signed_at,
+        signed_by: row.get::<Option<String>, _>("signed_by").unwrap_or_default(),
+        is_valid: stored_hash == current_hash,
+        content_hash: stored_hash,
+    }))
+}
+
+/// Appends an addendum to a signed session (unsigned sessions are edited directly)
+#[tauri::command]
+pub async fn add_session_addendum(
+    db_pool: State<'_, DbPool>,
+    session_id: i64,
+    author: String,
+    body: String,
+) -> Result<SessionAddendum, String> {
+    if author.trim().is_empty() || body.trim().is_empty() {
+        return Err("Addendum author and text are required".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    let signed_at: Option<Option<String>> = sqlx::query_scalar("SELECT signed_at FROM sessions WHERE id = ?1")
+        .bind(session_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match signed_at {
+        None => return Err(format!("Session {} not found", session_id)),
+        Some(None) => return Err("Addenda can only be added to signed sessions".to_string()),
+        Some(Some(_)) => {}
+    }
+
+    let result = sqlx::query("INSERT INTO session_addenda (session_id, author, body) VALUES (?1, ?2, ?3)")
+        .bind(session_id)
+        .bind(author.trim())
+        .bind(body.trim())
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to add addendum: {}", e))?;
+
+    let row = sqlx::query("SELECT created_at FROM session_addenda WHERE id = ?1")
+        .bind(result.last_insert_rowid())
+        .fetch_one(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(SessionAddendum {
+        id: Some(result.last_insert_rowid()),
+        session_id,
+        author: author.trim().to_string(),
+        body: body.trim().to_string(),
+        created_at: row.get("created_at"),
+    })
+}
+
+#[tauri::command]
+pub async fn get_session_addenda(
+    db_pool: State<'_, DbPool>,
+    session_id: i64,
+) -> Result<Vec<SessionAddendum>, String> {
+    let pool = db_pool.0.lock().await;
+
+    load_session_addenda(&pool, session_id).await
+}
+
+// ============================================================================
+// CLINICAL CODES MODULE (CIE-10 / CDT)
+// ============================================================================
+
+const CLINICAL_CODE_SYSTEMS: &[&str] = &["CIE10", "CDT"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClinicalCode {
+    pub id: Option<i64>,
+    pub system: String,
+    pub code: String,
+    pub description: String,
+    pub category: Option<String>,
+    pub active: Option<bool>,
+}
+
+fn row_to_clinical_code(row: &sqlx::sqlite::SqliteRow) -> ClinicalCode {
+    ClinicalCode {
+        id: Some(row.get("id")),
+        system: row.get("system"),
+        code: row.get("code"),
+        description: row.get("description"),
+        category: row.get("category"),
+        active: Some(row.get::<i64, _>("active") != 0),
+    }
+}
+
+fn normalize_code_system(system: &str) -> Result<String, String> {
+    let normalized = system.trim().to_uppercase().replace(['-', ' '], "");
+    if CLINICAL_CODE_SYSTEMS.contains(&normalized.as_str()) {
+        Ok(normalized)
+    } else {
+        Err(format!("Unknown code system '{}': expected CIE10 or CDT", system))
+    }
+}
+
+/// Normaliza un código opcional de un catálogo y verifica que exista.
+/// `None` se conserva (no tocar), un texto vacío se devuelve como `""` (quitar el código).
+async fn normalize_clinical_code(
+    conn: &mut sqlx::SqliteConnection,
+    system: &str,
+    code: Option<&str>,
+) -> Result<Option<String>, String> {
+    let Some(code) = code else {
+        return Ok(None);
+    };
+
+    let code = code.trim().to_uppercase();
+    if code.is_empty() {
+        return Ok(Some(String::new()));
+    }
+
+    let exists: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM clinical_codes WHERE system = ?1 AND code = ?2 AND active = 1"
+    )
+    .bind(system)
+    .bind(&code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if exists.is_none() {
+        return Err(format!("Unknown {} code: {}", system, code));
+    }
+
+    Ok(Some(code))
+}
+
+#[tauri::command]
+pub async fn search_clinical_codes(
+    db_pool: State<'_, DbPool>,
+    system: Option<String>,
+    query: String,
+    limit: Option<i64>,
+) -> Result<Vec<ClinicalCode>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let system = system.as_deref().map(normalize_code_system).transpose()?;
+    let query = query.trim().to_string();
+    let limit = limit.unwrap_or(50).clamp(1, 500);
+
+    // Las coincidencias por prefijo de código van primero, luego por descripción
+    let rows = sqlx::query(
+        "SELECT id, system, code, description, category, active
+         FROM clinical_codes
+         WHERE active = 1
+           AND (?1 IS NULL OR system = ?1)
+           AND (?2 = '' OR code LIKE ?2 || '%' OR description LIKE '%' || ?2 || '%')
+         ORDER BY
+           CASE WHEN upper(code) = upper(?2) THEN 0
+                WHEN code LIKE ?2 || '%' THEN 1
+                ELSE 2 END,
+           system ASC, code ASC
+         LIMIT ?3"
+    )
+    .bind(&system)
+    .bind(&query)
+    .bind(limit)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows.iter().map(row_to_clinical_code).collect())
+}
+
+#[tauri::command]
+pub async fn import_clinical_codes(
+    db_pool: State<'_, DbPool>,
+    codes: Vec<ClinicalCode>,
+) -> Result<i64, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let mut imported = 0i64;
+    for entry in codes {
+        let system = normalize_code_system(&entry.system)?;
+        let code = entry.code.trim().to_uppercase();
+        let description = entry.description.trim();
+        if code.is_empty() || description.is_empty() {
+            return Err(format!("Invalid clinical code entry: '{}' requires code and description", entry.code));
+        }
+
+        sqlx::query(
+            "INSERT INTO clinical_codes (system, code, description, category, active)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(system, code) DO UPDATE SET
+               description = excluded.description,
+               category = COALESCE(excluded.category, clinical_codes.category),
+               active = excluded.active"
+        )
+        .bind(&system)
+        .bind(&code)
+        .bind(description)
+        .bind(entry.category.as_deref().map(str::trim).filter(|c| !c.is_empty()))
+        .bind(entry.active.unwrap_or(true) as i64)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to import code {} {}: {}", system, code, e))?;
+
+        imported += 1;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("📚 {} clinical codes imported", imported);
+    Ok(imported)
+}
+
+// ============================================================================
+// RECALL MODULE (controles periódicos)
+// ============================================================================
+
+/// Texto de respaldo si el usuario eliminó todas las plantillas de recall
+const DEFAULT_RECALL_TEMPLATE: &str =
+    "Hola {nombre}. Tu última atención fue el {ultima_visita} y ya es momento de tu {control}. ¿Agendamos una cita?";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecallRule {
+    pub id: Option<i64>,
+    pub name: String,
+    pub interval_months: i64,
+    pub procedure_template_id: Option<i64>, // None = cualquier sesión guardada
+    pub patient_id: Option<i64>,            // None = todos los pacientes activos
+    pub active: Option<bool>,
+    pub procedure_name: Option<String>,     // Solo lectura (JOIN)
+    pub patient_name: Option<String>,       // Solo lectura (JOIN)
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DueRecall {
+    pub rule_id: i64,
+    pub rule_name: String,
+    pub patient_id: i64,
+    pub patient_name: String,
+    pub phone: String,
+    pub last_visit_date: String, // YYYY-MM-DD (última sesión que cuenta para la regla)
+    pub due_date: String,        // YYYY-MM-DD
+    pub days_overdue: i64,       // Negativo = vence en los próximos días
+    pub already_notified: bool,
+}
+
+fn format_display_date(date: chrono::NaiveDate) -> String {
+    date.format("%d/%m/%Y").to_string()
+}
+
+/// Pacientes con recall vencido (o por vencer en `days_ahead` días) según las reglas activas.
+/// Se excluyen los pacientes que ya tienen una cita futura agendada o confirmada.
+async fn find_due_recalls(
+    conn: &mut sqlx::SqliteConnection,
+    today: chrono::NaiveDate,
+    days_ahead: i64,
+) -> Result<Vec<DueRecall>, String> {
+    let rules = sqlx::query(
+        "SELECT id, name, interval_months, procedure_template_id, patient_id
+         FROM recall_rules
+         WHERE active = 1
+         ORDER BY id ASC"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let horizon = today + chrono::Duration::days(days_ahead.max(0));
+    // Upcoming appointments are compared against the clinic-local start of today
+    let tz = load_clinic_timezone(&mut *conn).await?;
+    let today_start = format_canonical_utc(tz.day_start_utc(today));
+    let mut due = Vec::new();
+
+    for rule in rules {
+        let rule_id: i64 = rule.get("id");
+        let rule_name: String = rule.get("name");
+        let interval_months: i64 = rule.get("interval_months");
+        let procedure_template_id: Option<i64> = rule.get("procedure_template_id");
+        let patient_id: Option<i64> = rule.get("patient_id");
+
+        // Con procedimiento: solo cuentan las sesiones donde se realizó (por id o por nombre)
+        let candidates = sqlx::query(
+            "SELECT p.id AS patient_id, p.full_name, p.phone, MAX(substr(s.date, 1, 10)) AS last_date
+             FROM patients p
+             JOIN sessions s ON s.patient_id = p.id AND s.is_saved = 1
+             WHERE COALESCE(p.status, 'active') = 'active'
+               AND (?1 IS NULL OR p.id = ?1)
+               AND (?2 IS NULL OR EXISTS (
+                     SELECT 1 FROM session_items si
+                     WHERE si.session_id = s.id
+                       AND (si.procedure_template_id = ?2
+                            OR si.name = (SELECT name FROM procedure_templates WHERE id = ?2))))
+               AND NOT EXISTS (
+                     SELECT 1 FROM appointments a
+                     WHERE a.patient_id = p.id
+                       AND a.status IN ('scheduled', 'confirmed')
+                       AND a.starts_at >= ?3)
+             GROUP BY p.id, p.full_name, p.phone"
+        )
+        .bind(patient_id)
+        .bind(procedure_template_id)
+        .bind(&today_start)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to find recall candidates: {}", e))?;
+
+        let notified: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT patient_id, due_date FROM recall_log WHERE rule_id = ?1"
+        )
+        .bind(rule_id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for candidate in candidates {
+            let last_date: String = candidate.get("last_date");
+            let Ok(last_visit) = chrono::NaiveDate::parse_from_str(&last_date, "%Y-%m-%d") else {
+                continue;
+            };
+            // checked_add_months ajusta al último día del mes (31/08 + 6 meses = 28/02)
+            let Some(due_date) = last_visit.checked_add_months(chrono::Months::new(interval_months as u32)) else {
+                continue;
+            };
+            if due_date > horizon {
+                continue;
+            }
+
+            let patient_id: i64 = candidate.get("patient_id");
+            let due_date_str = due_date.format("%Y-%m-%d").to_string();
+            due.push(DueRecall {
+                rule_id,
+                rule_name: rule_name.clone(),
+                patient_id,
+                patient_name: candidate.get("full_name"),
+                phone: candidate.get("phone"),
+                last_visit_date: last_date,
+                already_notified: notified.iter().any(|(p, d)| *p == patient_id && *d == due_date_str),
+                due_date: due_date_str,
+                days_overdue: (today - due_date).num_days(),
+            });
+        }
+    }
+
+    due.sort_by(|a, b| b.days_overdue.cmp(&a.days_overdue).then(a.patient_name.cmp(&b.patient_name)));
+    Ok(due)
+}
+
+#[tauri::command]
+pub async fn get_recall_rules(
+    db_pool: State<'_, DbPool>,
+) -> Result<Vec<RecallRule>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT r.id, r.name, r.interval_months, r.procedure_template_id, r.patient_id, r.active,
+                pt.name AS procedure_name, p.full_name AS patient_name, r.created_at, r.updated_at
+         FROM recall_rules r
+         LEFT JOIN procedure_templates pt ON pt.id = r.procedure_template_id
+         LEFT JOIN patients p ON p.id = r.patient_id
+         ORDER BY r.active DESC, r.name ASC"
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .iter()
+        .map(|row| RecallRule {
+            id: Some(row.get("id")),
+            name: row.get("name"),
+            interval_months: row.get("interval_months"),
+            procedure_template_id: row.get("procedure_template_id"),
+            patient_id: row.get("patient_id"),
+            active: Some(row.get::<i64, _>("active") != 0),
+            procedure_name: row.get("procedure_name"),
+            patient_name: row.get("patient_name"),
+            created_at: Some(row.get("created_at")),
+            updated_at: Some(row.get("updated_at")),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn save_recall_rule(
+    db_pool: State<'_, DbPool>,
+    rule: RecallRule,
+) -> Result<i64, String> {
+    if rule.name.trim().is_empty() {
+        return Err("Recall rule name is required".to_string());
+    }
+    if !(1..=60).contains(&rule.interval_months) {
+        return Err("Recall interval must be between 1 and 60 months".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+    let procedure_template_id = rule.procedure_template_id.filter(|&i| i > 0);
+    let patient_id = rule.patient_id.filter(|&i| i > 0);
+
+    if let Some(id) = rule.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE recall_rules
+             SET name = ?1, interval_months = ?2, procedure_template_id = ?3, patient_id = ?4, active = ?5
+             WHERE id = ?6"
+        )
+        .bind(rule.name.trim())
+        .bind(rule.interval_months)
+        .bind(procedure_template_id)
+        .bind(patient_id)
+        .bind(rule.active.unwrap_or(true) as i64)
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update recall rule: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Recall rule {} not found", id));
+        }
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO recall_rules (name, interval_months, procedure_template_id, patient_id, active)
+             VALUES (?1, ?2, ?3, ?4, ?5)"
+        )
+        .bind(rule.name.trim())
+        .bind(rule.interval_months)
+        .bind(procedure_template_id)
+        .bind(patient_id)
+        .bind(rule.active.unwrap_or(true) as i64)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create recall rule: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+#[tauri::command]
+pub async fn delete_recall_rule(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("DELETE FROM recall_rules WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to delete recall rule: {}", e))?;
+
+    Ok(())
+}
+
+/// Vista previa: pacientes con recall vencido, sin crear mensajes
+#[tauri::command]
+pub async fn list_due_recalls(
+    db_pool: State<'_, DbPool>,
+    days_ahead: Option<i64>,
+) -> Result<Vec<DueRecall>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let today = load_clinic_timezone(&mut conn).await?.now_local().date();
+    find_due_recalls(&mut conn, today, days_ahead.unwrap_or(0)).await
+}
+
+/// Crea un mensaje 'recall' en message_queue por cada paciente vencido que aún no fue
+/// notificado para ese vencimiento. Seguro de ejecutar varias veces al día.
+async fn queue_recall_messages(
+    conn: &mut sqlx::SqliteConnection,
+    days_ahead: i64,
+) -> Result<i64, String> {
+    let today = load_clinic_timezone(conn).await?.now_local().date();
+    let due = find_due_recalls(conn, today, days_ahead).await?;
+
+    let template_body = load_preferred_template_body(conn, "recall")
+        .await?
+        .unwrap_or_else(|| DEFAULT_RECALL_TEMPLATE.to_string());
+    let base_context = default_template_context(conn).await?;
+
+    let mut count = 0i64;
+    for recall in due.into_iter().filter(|r| !r.already_notified) {
+        let mut context = base_context.clone();
+        context.insert("nombre".to_string(), recall.patient_name.clone());
+        context.insert("control".to_string(), recall.rule_name.to_lowercase());
+        for (key, date) in [("ultima_visita", &recall.last_visit_date), ("fecha_control", &recall.due_date)] {
+            if let Ok(parsed) = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                context.insert(key.to_string(), format_display_date(parsed));
+            }
+        }
+        let message_text = render_template_body("recall", &template_body, &context).text;
+
+        let message_id = sqlx::query(
+            "INSERT INTO message_queue (patient_id, appointment_id, type, message_text, status)
+             VALUES (?1, NULL, 'recall', ?2, 'pending')"
+        )
+        .bind(recall.patient_id)
+        .bind(&message_text)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to create recall message: {}", e))?
+        .last_insert_rowid();
+
+        sqlx::query(
+            "INSERT INTO recall_log (rule_id, patient_id, due_date, message_id)
+             VALUES (?1, ?2, ?3, ?4)"
+        )
+        .bind(recall.rule_id)
+        .bind(recall.patient_id)
+        .bind(&recall.due_date)
+        .bind(message_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to log recall: {}", e))?;
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Pasada manual del job diario de recalls (también lo corre el programador en segundo plano)
+#[tauri::command]
+pub async fn generate_recall_messages(
+    db_pool: State<'_, DbPool>,
+    days_ahead: Option<i64>,
+) -> Result<i64, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let count = queue_recall_messages(&mut tx, days_ahead.unwrap_or(0)).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("🔁 Generated {} recall messages", count);
+    Ok(count)
+}
+
+// ============================================================================
+// ORTHODONTIC CASES MODULE
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrthoCase {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub start_date: String,               // YYYY-MM-DD
+    pub appliance_type: String,
+    pub planned_duration_months: i64,
+    pub total_fee: Option<f64>,           // 0/None = sin tope
+    pub monthly_fee: f64,
+    pub status: Option<String>,           // 'active' | 'retention' | 'completed' | 'cancelled'
+    pub end_date: Option<String>,
+    pub notes: Option<String>,
+    pub installments_charged: Option<i64>, // Solo lectura
+    pub charged_total: Option<f64>,        // Solo lectura
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrthoArchwireEntry {
+    pub id: Option<i64>,
+    pub case_id: i64,
+    pub session_id: Option<i64>,
+    pub visit_date: String,               // YYYY-MM-DD
+    pub upper_wire: Option<String>,
+    pub lower_wire: Option<String>,
+    pub elastics: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrthoCharge {
+    pub id: i64,
+    pub case_id: i64,
+    pub installment_number: i64,
+    pub due_date: String,
+    pub amount: f64,
+    pub session_id: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrthoProgressReport {
+    pub case: OrthoCase,
+    pub months_elapsed: i64,
+    pub progress_percent: f64,            // Meses transcurridos / duración planificada
+    pub charged_total: f64,
+    pub remaining_fee: Option<f64>,       // None si el caso no tiene tope
+    pub archwire_log: Vec<OrthoArchwireEntry>,
+    pub charges: Vec<OrthoCharge>,
+    pub photos: Vec<Attachment>,          // Vinculadas al caso o a sesiones del registro de arcos
+}
+
+const ORTHO_CASE_SELECT: &str =
+    "SELECT c.id, c.patient_id, c.start_date, c.appliance_type, c.planned_duration_months,
+            c.total_fee, c.monthly_fee, c.status, c.end_date, c.notes, c.created_at, c.updated_at,
+            (SELECT COUNT(*) FROM ortho_charges oc WHERE oc.case_id = c.id) AS installments_charged,
+            (SELECT COALESCE(SUM(oc.amount), 0.0) FROM ortho_charges oc WHERE oc.case_id = c.id) AS charged_total
+     FROM ortho_cases c";
+
+fn row_to_ortho_case(row: &sqlx::sqlite::SqliteRow) -> OrthoCase {
+    OrthoCase {
+        id: Some(row.get("id")),
+        patient_id: row.get("patient_id"),
+        start_date: row.get("start_date"),
+        appliance_type: row.get("appliance_type"),
+        planned_duration_months: row.get("planned_duration_months"),
+        total_fee: Some(row.get("total_fee")),
+        monthly_fee: row.get("monthly_fee"),
+        status: Some(row.get("status")),
+        end_date: row.get("end_date"),
+        notes: row.get("notes"),
+        installments_charged: Some(row.get("installments_charged")),
+        charged_total: Some(row.get("charged_total")),
+        created_at: Some(row.get("created_at")),
+        updated_at: Some(row.get("updated_at")),
+    }
+}
+
+fn row_to_archwire_entry(row: &sqlx::sqlite::SqliteRow) -> OrthoArchwireEntry {
+    OrthoArchwireEntry {
+        id: Some(row.get("id")),
+        case_id: row.get("case_id"),
+        session_id: row.get("session_id"),
+        visit_date: row.get("visit_date"),
+        upper_wire: row.get("upper_wire"),
+        lower_wire: row.get("lower_wire"),
+        elastics: row.get("elastics"),
+        notes: row.get("notes"),
+        created_at: Some(row.get("created_at")),
+    }
+}
+
+fn parse_iso_date(value: &str, field: &str) -> Result<chrono::NaiveDate, String> {
+    chrono::NaiveDate::parse_from_str(value.get(..10).unwrap_or(value), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid {}: expected YYYY-MM-DD, got '{}'", field, value))
+}
+
+/// Fecha de vencimiento de la cuota `installment` (la cuota 1 vence el día de inicio)
+fn ortho_installment_due_date(start: chrono::NaiveDate, installment: i64) -> Option<chrono::NaiveDate> {
+    start.checked_add_months(chrono::Months::new((installment - 1).max(0) as u32))
+}
+
+/// Meses completos entre dos fechas (15/01 → 14/03 = 1, 15/01 → 15/03 = 2)
+fn full_months_between(from: chrono::NaiveDate, to: chrono::NaiveDate) -> i64 {
+    use chrono::Datelike;
+    if to <= from {
+        return 0;
+    }
+    let mut months = (to.year() - from.year()) as i64 * 12 + to.month() as i64 - from.month() as i64;
+    if from.checked_add_months(chrono::Months::new(months as u32)).is_some_and(|d| d > to) {
+        months -= 1;
+    }
+    months.max(0)
+}
+
+/// Registra una cuota como sesión guardada (sin datos clínicos) para que entre al saldo
+async fn post_ortho_charge_session(
+    conn: &mut sqlx::SqliteConnection,
+    patient_id: i64,
+    date: &str,
+    description: &str,
+    amount: f64,
+) -> Result<i64, String> {
+    let previous_cumulative: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(balance), 0.0) FROM sessions
+         WHERE patient_id = ?1 AND is_saved = 1"
+    )
+    .bind(patient_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let session_id = sqlx::query(
+        "INSERT INTO sessions (patient_id, date, reason_type, reason_detail,
+                              budget, discount, payment, balance, cumulative_balance, is_saved)
+         VALUES (?1, ?2, 'Ortodoncia', ?3, ?4, 0, 0, ?4, ?5, 1)"
+    )
+    .bind(patient_id)
+    .bind(date)
+    .bind(format!("Sistema: {}", description))
+    .bind(amount)
+    .bind(previous_cumulative + amount)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to create ortho charge session: {}", e))?
+    .last_insert_rowid();
+
+    sqlx::query(
+        "INSERT INTO session_items (session_id, name, unit_price, quantity, subtotal, is_active, sort_order)
+         VALUES (?1, ?2, ?3, 1, ?3, 1, 0)"
+    )
+    .bind(session_id)
+    .bind(description)
+    .bind(amount)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to create ortho charge item: {}", e))?;
+
+    apply_debt_triada(&mut *conn, patient_id, session_id).await?;
+
+    Ok(session_id)
+}
+
+#[tauri::command]
+pub async fn get_ortho_cases_by_patient(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+) -> Result<Vec<OrthoCase>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(&format!(
+        "{} WHERE c.patient_id = ?1 ORDER BY c.start_date DESC, c.id DESC",
+        ORTHO_CASE_SELECT
+    ))
+    .bind(patient_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows.iter().map(row_to_ortho_case).collect())
+}
+
+#[tauri::command]
+pub async fn save_ortho_case(
+    db_pool: State<'_, DbPool>,
+    case: OrthoCase,
+) -> Result<i64, String> {
+    parse_iso_date(&case.start_date, "start date")?;
+    if let Some(end_date) = case.end_date.as_deref().filter(|d| !d.is_empty()) {
+        parse_iso_date(end_date, "end date")?;
+    }
+    if case.appliance_type.trim().is_empty() {
+        return Err("Appliance type is required".to_string());
+    }
+    if !(1..=120).contains(&case.planned_duration_months) {
+        return Err("Planned duration must be between 1 and 120 months".to_string());
+    }
+    if case.monthly_fee < 0.0 || case.total_fee.is_some_and(|f| f < 0.0) {
+        return Err("Ortho fees cannot be negative".to_string());
+    }
+    let status = case.status.as_deref().unwrap_or("active");
+    if !matches!(status, "active" | "retention" | "completed" | "cancelled") {
+        return Err(format!("Invalid ortho case status: {}", status));
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    if let Some(id) = case.id.filter(|&i| i > 0) {
+        // Con cuotas generadas, la fecha de inicio ya no se puede mover
+        let (current_start, charges): (String, i64) = sqlx::query_as(
+            "SELECT start_date, (SELECT COUNT(*) FROM ortho_charges WHERE case_id = ?1)
+             FROM ortho_cases WHERE id = ?1 AND patient_id = ?2"
+        )
+        .bind(id)
+        .bind(case.patient_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Ortho case {} not found", id))?;
+
+        if charges > 0 && current_start != case.start_date {
+            return Err("Cannot change the start date of a case with generated charges".to_string());
+        }
+
+        sqlx::query(
+            "UPDATE ortho_cases
+             SET start_date = ?1, appliance_type = ?2, planned_duration_months = ?3,
+                 total_fee = ?4, monthly_fee = ?5, status = ?6, end_date = ?7, notes = ?8
+             WHERE id = ?9"
+        )
+        .bind(&case.start_date)
+        .bind(case.appliance_type.trim())
+        .bind(case.planned_duration_months)
+        .bind(case.total_fee.unwrap_or(0.0))
+        .bind(case.monthly_fee)
+        .bind(status)
+        .bind(case.end_date.as_deref().filter(|d| !d.is_empty()))
+        .bind(&case.notes)
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update ortho case: {}", e))?;
+
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO ortho_cases (patient_id, start_date, appliance_type, planned_duration_months,
+                                      total_fee, monthly_fee, status, end_date, notes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+        )
+        .bind(case.patient_id)
+        .bind(&case.start_date)
+        .bind(case.appliance_type.trim())
+        .bind(case.planned_duration_months)
+        .bind(case.total_fee.unwrap_or(0.0))
+        .bind(case.monthly_fee)
+        .bind(status)
+        .bind(case.end_date.as_deref().filter(|d| !d.is_empty()))
+        .bind(&case.notes)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create ortho case: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+/// Solo se pueden borrar casos sin cuotas generadas (si no, cancelar el caso)
+#[tauri::command]
+pub async fn delete_ortho_case(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    let charges: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ortho_charges WHERE case_id = ?1")
+        .bind(id)
+        .fetch_one(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if charges > 0 {
+        return Err("Cannot delete an ortho case with generated charges; cancel it instead".to_string());
+    }
+
+    sqlx::query("UPDATE attachments SET ortho_case_id = NULL WHERE ortho_case_id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM ortho_cases WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to delete ortho case: {}", e))?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn save_ortho_archwire_entry(
+    db_pool: State<'_, DbPool>,
+    entry: OrthoArchwireEntry,
+) -> Result<i64, String> {
+    parse_iso_date(&entry.visit_date, "visit date")?;
+
+    let pool = db_pool.0.lock().await;
+
+    let case_patient: Option<i64> = sqlx::query_scalar("SELECT patient_id FROM ortho_cases WHERE id = ?1")
+        .bind(entry.case_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let case_patient = case_patient.ok_or_else(|| format!("Ortho case {} not found", entry.case_id))?;
+
+    let session_id = entry.session_id.filter(|&i| i > 0);
+    if let Some(session_id) = session_id {
+        let session_patient: Option<i64> = sqlx::query_scalar("SELECT patient_id FROM sessions WHERE id = ?1")
+            .bind(session_id)
+            .fetch_optional(&*pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        if session_patient != Some(case_patient) {
+            return Err(format!("Session {} does not belong to the patient", session_id));
+        }
+    }
+
+    if let Some(id) = entry.id.filter(|&i| i > 0) {
+        sqlx::query(
+            "UPDATE ortho_archwire_log
+             SET session_id = ?1, visit_date = ?2, upper_wire = ?3, lower_wire = ?4, elastics = ?5, notes = ?6
+             WHERE id = ?7 AND case_id = ?8"
+        )
+        .bind(session_id)
+        .bind(&entry.visit_date)
+        .bind(&entry.upper_wire)
+        .bind(&entry.lower_wire)
+        .bind(&entry.elastics)
+        .bind(&entry.notes)
+        .bind(id)
+        .bind(entry.case_id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update archwire entry: {}", e))?;
+
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO ortho_archwire_log (case_id, session_id, visit_date, upper_wire, lower_wire, elastics, notes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+        )
+        .bind(entry.case_id)
+        .bind(session_id)
+        .bind(&entry.visit_date)
+        .bind(&entry.upper_wire)
+        .bind(&entry.lower_wire)
+        .bind(&entry.elastics)
+        .bind(&entry.notes)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create archwire entry: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+#[tauri::command]
+pub async fn get_ortho_archwire_log(
+    db_pool: State<'_, DbPool>,
+    case_id: i64,
+) -> Result<Vec<OrthoArchwireEntry>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT id, case_id, session_id, visit_date, upper_wire, lower_wire, elastics, notes, created_at
+         FROM ortho_archwire_log
+         WHERE case_id = ?1
+         ORDER BY visit_date ASC, id ASC"
+    )
+    .bind(case_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows.iter().map(row_to_archwire_entry).collect())
+}
+
+#[tauri::command]
+pub async fn delete_ortho_archwire_entry(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("DELETE FROM ortho_archwire_log WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to delete archwire entry: {}", e))?;
+
+    Ok(())
+}
+
+/// Vincula (o desvincula con `case_id = None`) una foto existente al caso
+#[tauri::command]
+pub async fn link_ortho_photo(
+    db_pool: State<'_, DbPool>,
+    attachment_id: i64,
+    case_id: Option<i64>,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    if let Some(case_id) = case_id {
+        let same_patient: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM attachments a
+             JOIN ortho_cases c ON c.patient_id = a.patient_id
+             WHERE a.id = ?1 AND c.id = ?2"
+        )
+        .bind(attachment_id)
+        .bind(case_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        if same_patient.is_none() {
+            return Err("Attachment and ortho case must belong to the same patient".to_string());
+        }
+    }
+
+    sqlx::query("UPDATE attachments SET ortho_case_id = ?1 WHERE id = ?2")
+        .bind(case_id)
+        .bind(attachment_id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to link photo: {}", e))?;
+
+    Ok(())
+}
+
+/// Job de cuotas: genera las cuotas vencidas (hasta hoy, en la zona de la clínica) de los
+/// casos activos. Cada cuota crea una sesión guardada con el cargo, que actualiza el saldo
+/// del paciente. Seguro de ejecutar varias veces: ortho_charges tiene UNIQUE(case_id, installment_number).
+async fn create_due_ortho_charges(
+    conn: &mut sqlx::SqliteConnection,
+    case_id: Option<i64>,
+) -> Result<i64, String> {
+    let today = load_clinic_timezone(conn).await?.now_local().date();
+    let today_str = today.format("%Y-%m-%d").to_string();
+
+    let cases = sqlx::query(
+        "SELECT c.id, c.patient_id, c.start_date, c.planned_duration_months, c.total_fee, c.monthly_fee,
+                COALESCE(MAX(oc.installment_number), 0) AS last_installment,
+                COALESCE(SUM(oc.amount), 0.0) AS charged_total
+         FROM ortho_cases c
+         LEFT JOIN ortho_charges oc ON oc.case_id = c.id
+         WHERE c.status = 'active' AND (?1 IS NULL OR c.id = ?1)
+         GROUP BY c.id"
+    )
+    .bind(case_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut count = 0i64;
+    for case in cases {
+        let id: i64 = case.get("id");
+        let patient_id: i64 = case.get("patient_id");
+        let planned: i64 = case.get("planned_duration_months");
+        let total_fee: f64 = case.get("total_fee");
+        let monthly_fee: f64 = case.get("monthly_fee");
+        let mut charged_total: f64 = case.get("charged_total");
+        let Ok(start) = parse_iso_date(&case.get::<String, _>("start_date"), "start date") else {
+            continue;
+        };
+
+        for installment in (case.get::<i64, _>("last_installment") + 1)..=planned {
+            let Some(due_date) = ortho_installment_due_date(start, installment) else {
+                break;
+            };
+            if due_date > today {
+                break;
+            }
+
+            // Con tope: la última cuota se ajusta para no superar el total del tratamiento
+            let amount = if total_fee > 0.0 {
+                monthly_fee.min(total_fee - charged_total)
+            } else {
+                monthly_fee
+            };
+            if amount <= 0.0 {
+                break;
+            }
+
+            let description = format!("Cuota ortodoncia {}/{}", installment, planned);
+            let session_id = post_ortho_charge_session(conn, patient_id, &today_str, &description, amount).await?;
+
+            sqlx::query(
+                "INSERT INTO ortho_charges (case_id, installment_number, due_date, amount, session_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5)"
+            )
+            .bind(id)
+            .bind(installment)
+            .bind(due_date.format("%Y-%m-%d").to_string())
+            .bind(amount)
+            .bind(session_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to record ortho charge: {}", e))?;
+
+            charged_total += amount;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Pasada manual del job de cuotas (también lo corre el programador una vez por día)
+#[tauri::command]
+pub async fn generate_ortho_charges(
+    db_pool: State<'_, DbPool>,
+    case_id: Option<i64>,
+) -> Result<i64, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let count = create_due_ortho_charges(&mut tx, case_id).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("🦷 Generated {} ortho charges", count);
+    Ok(count)
+}
+
+#[tauri::command]
+pub async fn get_ortho_progress_report(
+    db_pool: State<'_, DbPool>,
+    case_id: i64,
+) -> Result<OrthoProgressReport, String> {
+    let pool = db_pool.0.lock().await;
+
+    let row = sqlx::query(&format!("{} WHERE c.id = ?1", ORTHO_CASE_SELECT))
+        .bind(case_id)
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Ortho case {} not found", case_id))?;
+    let case = row_to_ortho_case(&row);
+
+    let archwire_log: Vec<OrthoArchwireEntry> = sqlx::query(
+        "SELECT id, case_id, session_id, visit_date, upper_wire, lower_wire, elastics, notes, created_at
+         FROM ortho_archwire_log
+         WHERE case_id = ?1
+         ORDER BY visit_date ASC, id ASC"
+    )
+    .bind(case_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(row_to_archwire_entry)
+    .collect();
+
+    let charges: Vec<OrthoCharge> = sqlx::query(
+        "SELECT id, case_id, installment_number, due_date, amount, session_id, created_at
+         FROM ortho_charges
+         WHERE case_id = ?1
+         ORDER BY installment_number ASC"
+    )
+    .bind(case_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .into_iter()
+    .map(|row| OrthoCharge {
+        id: row.get("id"),
+        case_id: row.get("case_id"),
+        installment_number: row.get("installment_number"),
+        due_date: row.get("due_date"),
+        amount: row.get("amount"),
+        session_id: row.get("session_id"),
+        created_at: row.get("created_at"),
+    })
+    .collect();
+
+    let photos: Vec<Attachment> = sqlx::query(
+        "SELECT id, patient_id, session_id, kind, filename, mime_type, size_bytes, storage_key, note, created_at
+         FROM attachments
+         WHERE (ortho_case_id = ?1
+                OR session_id IN (SELECT session_id FROM ortho_archwire_log WHERE case_id = ?1))
+           AND (kind = 'photo' OR mime_type LIKE 'image/%')
+         ORDER BY created_at ASC"
+    )
+    .bind(case_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .into_iter()
+    .map(|row| Attachment {
+        id: row.get("id"),
+        patient_id: row.get("patient_id"),
+        session_id: row.get("session_id"),
+        kind: row.get("kind"),
+        filename: row.get("filename"),
+        mime_type: row.get("mime_type"),
+        size_bytes: row.get("size_bytes"),
+        storage_key: row.get("storage_key"),
+        note: row.get("note"),
+        created_at: row.get("created_at"),
+    })
+    .collect();
+
+    // Casos cerrados: el avance se mide hasta la fecha de fin
+    let start = parse_iso_date(&case.start_date, "start date")?;
+    let until = case
+        .end_date
+        .as_deref()
+        .and_then(|d| parse_iso_date(d, "end date").ok())
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
+    let months_elapsed = full_months_between(start, until);
+    let progress_percent = round_one_decimal(
+        (months_elapsed as f64 / case.planned_duration_months as f64 * 100.0).min(100.0)
+    );
+
+    let charged_total = case.charged_total.unwrap_or(0.0);
+    let remaining_fee = case
+        .total_fee
+        .filter(|&f| f > 0.0)
+        .map(|f| (f - charged_total).max(0.0));
+
+    Ok(OrthoProgressReport {
+        case,
+        months_elapsed,
+        progress_percent,
+        charged_total,
+        remaining_fee,
+        archwire_log,
+        charges,
+        photos,
+    })
+}
+
+// ============================================================================
+// SOAP NOTES MODULE
+// ============================================================================
+
+/// Nota clínica estructurada (Subjetivo, Objetivo, Evaluación, Plan).
+/// Se guarda en columnas de `sessions`; `clinical_notes` conserva la versión aplanada.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SoapNote {
+    pub subjective: Option<String>,
+    pub objective: Option<String>,
+    pub assessment: Option<String>,
+    pub plan: Option<String>,
+}
+
+/// (kind de text_templates, etiqueta al aplanar) en orden S-O-A-P
+const SOAP_SECTIONS: [(&str, &str); 4] = [
+    ("soap_subjective", "Subjetivo"),
+    ("soap_objective", "Objetivo"),
+    ("soap_assessment", "Evaluación"),
+    ("soap_plan", "Plan"),
+];
+
+impl SoapNote {
+    fn sections(&self) -> [&Option<String>; 4] {
+        [&self.subjective, &self.objective, &self.assessment, &self.plan]
+    }
+
+    /// Recorta espacios y convierte secciones vacías en None
+    fn normalized(&self) -> SoapNote {
+        let clean = |section: &Option<String>| {
+            section.as_deref().map(str::trim).filter(|t| !t.is_empty()).map(str::to_string)
+        };
+        SoapNote {
+            subjective: clean(&self.subjective),
+            objective: clean(&self.objective),
+            assessment: clean(&self.assessment),
+            plan: clean(&self.plan),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sections().iter().all(|section| section.is_none())
+    }
+
+    /// Texto plano para clinical_notes: "Subjetivo: ...\nObjetivo: ..." (omite secciones vacías)
+    fn flatten(&self) -> String {
+        SOAP_SECTIONS
+            .iter()
+            .zip(self.sections())
+            .filter_map(|((_, label), section)| section.as_ref().map(|text| format!("{}: {}", label, text)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn soap_note_from_row(row: &sqlx::sqlite::SqliteRow) -> Option<SoapNote> {
+    let note = SoapNote {
+        subjective: row.get("soap_subjective"),
+        objective: row.get("soap_objective"),
+        assessment: row.get("soap_assessment"),
+        plan: row.get("soap_plan"),
+    };
+    (!note.is_empty()).then_some(note)
+}
+
+/// Plantilla elegida por sección: None = la plantilla preferida de ese tipo (si existe)
+#[derive(Debug, Deserialize, Default)]
+pub struct SoapTemplateSelection {
+    pub subjective: Option<i64>,
+    pub objective: Option<i64>,
+    pub assessment: Option<i64>,
+    pub plan: Option<i64>,
+}
+
+/// Prellena una nota SOAP renderizando una plantilla por sección con el contexto dado
+#[tauri::command]
+pub async fn prefill_soap_note(
+    db_pool: State<'_, DbPool>,
+    templates: Option<SoapTemplateSelection>,
+    context: HashMap<String, String>,
+) -> Result<SoapNote, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let templates = templates.unwrap_or_default();
+    let mut full_context = default_template_context(&mut conn).await?;
+    full_context.extend(context);
+
+    let mut rendered: Vec<Option<String>> = Vec::with_capacity(SOAP_SECTIONS.len());
+    let selections = [templates.subjective, templates.objective, templates.assessment, templates.plan];
+    for ((kind, _), template_id) in SOAP_SECTIONS.iter().zip(selections) {
+        let body = match template_id {
+            Some(id) => {
+                let row = sqlx::query("SELECT kind, body FROM text_templates WHERE id = ?1")
+                    .bind(id)
+                    .fetch_optional(&mut *conn)
+                    .await
+                    .map_err(|e| format!("Database error: {}", e))?
+                    .ok_or_else(|| format!("Template {} not found", id))?;
+                if row.get::<String, _>("kind") != *kind {
+                    return Err(format!("Template {} is not a {} template", id, kind));
+                }
+                Some(row.get::<String, _>("body"))
+            }
+            None => load_preferred_template_body(&mut conn, kind).await?,
+        };
+        rendered.push(body.map(|b| render_template_body(kind, &b, &full_context).text));
+    }
+
+    let [subjective, objective, assessment, plan]: [Option<String>; 4] = rendered
+        .try_into()
+        .map_err(|_| "SOAP sections mismatch".to_string())?;
+
+    Ok(SoapNote { subjective, objective, assessment, plan })
+}
+
+// ============================================================================
+// PROVIDERS MODULE (odontólogos / especialistas)
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Provider {
+    pub id: Option<i64>,
+    pub name: String,
+    pub license_number: Option<String>,
+    pub specialty: Option<String>,
+    pub color: Option<String>,           // "#3b82f6"
+    pub commission_rate: Option<f64>,    // % sobre lo cobrado (0–100)
+    pub active: Option<bool>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProviderProduction {
+    pub provider_id: Option<i64>,        // None = sesiones sin profesional asignado
+    pub provider_name: String,
+    pub session_count: i64,
+    pub production: f64,                 // Procedimientos realizados (items activos)
+    pub collections: f64,                // Pagos registrados en sus sesiones
+    pub commission_rate: f64,
+    pub commission: f64,                 // collections * commission_rate / 100
+}
+
+#[tauri::command]
+pub async fn get_providers(
+    db_pool: State<'_, DbPool>,
+    include_inactive: Option<bool>,
+) -> Result<Vec<Provider>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT id, name, license_number, specialty, color, commission_rate, active, created_at, updated_at
+         FROM providers
+         WHERE active = 1 OR ?1 = 1
+         ORDER BY active DESC, name ASC"
+    )
+    .bind(include_inactive.unwrap_or(false) as i64)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Provider {
+            id: row.get("id"),
+            name: row.get("name"),
+            license_number: row.get("license_number"),
+            specialty: row.get("specialty"),
+            color: row.get("color"),
+            commission_rate: Some(row.get("commission_rate")),
+            active: Some(row.get::<i64, _>("active") != 0),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn save_provider(
+    db_pool: State<'_, DbPool>,
+    provider: Provider,
+) -> Result<i64, String> {
+    let name = provider.name.trim();
+    if name.is_empty() {
+        return Err("Provider name is required".to_string());
+    }
+    let commission_rate = provider.commission_rate.unwrap_or(0.0);
+    if !(0.0..=100.0).contains(&commission_rate) {
+        return Err("Commission rate must be between 0 and 100".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    if let Some(id) = provider.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE providers
+             SET name = ?1, license_number = ?2, specialty = ?3, color = ?4, commission_rate = ?5, active = ?6
+             WHERE id = ?7"
+        )
+        .bind(name)
+        .bind(&provider.license_number)
+        .bind(&provider.specialty)
+        .bind(&provider.color)
+        .bind(commission_rate)
+        .bind(provider.active.unwrap_or(true) as i64)
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update provider: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Provider {} not found", id));
+        }
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO providers (name, license_number, specialty, color, commission_rate, active)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(name)
+        .bind(&provider.license_number)
+        .bind(&provider.specialty)
+        .bind(&provider.color)
+        .bind(commission_rate)
+        .bind(provider.active.unwrap_or(true) as i64)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create provider: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+/// Soft delete: las sesiones y citas históricas conservan la referencia
+#[tauri::command]
+pub async fn delete_provider(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("UPDATE providers SET active = 0 WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Producción (procedimientos realizados) y cobranza (pagos de sus sesiones) por profesional.
+/// La producción se atribuye al profesional del item; si no tiene, al de la sesión.
+#[tauri::command]
+pub async fn get_provider_production_report(
+    db_pool: State<'_, DbPool>,
+    start_date: String,
+    end_date: String,
+) -> Result<Vec<ProviderProduction>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let providers: Vec<(i64, String, f64)> = sqlx::query_as(
+        "SELECT id, name, commission_rate FROM providers ORDER BY name ASC"
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let production: Vec<(Option<i64>, f64)> = sqlx::query_as(
+        "SELECT COALESCE(si.provider_id, s.provider_id) AS provider_id,
+                COALESCE(SUM(si.subtotal), 0.0) AS production
+         FROM session_items si
+         JOIN sessions s ON s.id = si.session_id
+         WHERE s.is_saved = 1
+           AND si.is_active = 1
+           AND substr(s.date, 1, 10) BETWEEN ?1 AND ?2
+         GROUP BY COALESCE(si.provider_id, s.provider_id)"
+    )
+    .bind(&start_date)
+    .bind(&end_date)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to compute production: {}", e))?;
+
+    let collections: Vec<(Option<i64>, i64, f64)> = sqlx::query_as(
+        "SELECT provider_id, COUNT(*) AS session_count, COALESCE(SUM(payment), 0.0) AS collections
+         FROM sessions
+         WHERE is_saved = 1
+           AND substr(date, 1, 10) BETWEEN ?1 AND ?2
+         GROUP BY provider_id"
+    )
+    .bind(&start_date)
+    .bind(&end_date)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to compute collections: {}", e))?;
+
+    let mut keys: Vec<Option<i64>> = production
+        .iter()
+        .map(|(id, _)| *id)
+        .chain(collections.iter().map(|(id, _, _)| *id))
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let mut report: Vec<ProviderProduction> = keys
+        .into_iter()
+        .map(|provider_id| {
+            let provider = provider_id.and_then(|id| providers.iter().find(|(pid, _, _)| *pid == id));
+            let (session_count, collected) = collections
+                .iter()
+                .find(|(id, _, _)| *id == provider_id)
+                .map(|(_, count, amount)| (*count, *amount))
+                .unwrap_or((0, 0.0));
+            let produced = production
+                .iter()
+                .find(|(id, _)| *id == provider_id)
+                .map(|(_, amount)| *amount)
+                .unwrap_or(0.0);
+            let commission_rate = provider.map(|(_, _, rate)| *rate).unwrap_or(0.0);
+
+            ProviderProduction {
+                provider_id,
+                provider_name: provider
+                    .map(|(_, name, _)| name.clone())
+                    .unwrap_or_else(|| "Sin asignar".to_string()),
+                session_count,
+                production: produced,
+                collections: collected,
+                commission_rate,
+                commission: (collected * commission_rate / 100.0 * 100.0).round() / 100.0,
+            }
+        })
+        .collect();
+
+    report.sort_by(|a, b| b.production.partial_cmp(&a.production).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(report)
+}
+
+// ============================================================================
+// RESOURCES MODULE (sillones, consultorios, equipos)
+// ============================================================================
+
+const RESOURCE_KINDS: &[&str] = &["chair", "room", "equipment"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resource {
+    pub id: Option<i64>,
+    pub name: String,
+    pub kind: String,                    // 'chair' | 'room' | 'equipment'
+    pub color: Option<String>,
+    pub active: Option<bool>,
+    pub sort_order: Option<i64>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[tauri::command]
+pub async fn get_resources(
+    db_pool: State<'_, DbPool>,
+    include_inactive: Option<bool>,
+) -> Result<Vec<Resource>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT id, name, kind, color, active, sort_order, created_at, updated_at
+         FROM resources
+         WHERE active = 1 OR ?1 = 1
+         ORDER BY active DESC, sort_order ASC, name ASC"
+    )
+    .bind(include_inactive.unwrap_or(false) as i64)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Resource {
+            id: row.get("id"),
+            name: row.get("name"),
+            kind: row.get("kind"),
+            color: row.get("color"),
+            active: Some(row.get::<i64, _>("active") != 0),
+            sort_order: row.get("sort_order"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn save_resource(
+    db_pool: State<'_, DbPool>,
+    resource: Resource,
+) -> Result<i64, String> {
+    let name = resource.name.trim();
+    if name.is_empty() {
+        return Err("Resource name is required".to_string());
+    }
+    if !RESOURCE_KINDS.contains(&resource.kind.as_str()) {
+        return Err(format!("Invalid resource kind: {}", resource.kind));
+    }
+
+    let pool = db_pool.0.lock().await;
+
+    if let Some(id) = resource.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE resources
+             SET name = ?1, kind = ?2, color = ?3, active = ?4, sort_order = ?5
+             WHERE id = ?6"
+        )
+        .bind(name)
+        .bind(&resource.kind)
+        .bind(&resource.color)
+        .bind(resource.active.unwrap_or(true) as i64)
+        .bind(resource.sort_order.unwrap_or(0))
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update resource: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Resource {} not found", id));
+        }
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO resources (name, kind, color, active, sort_order)
+             VALUES (?1, ?2, ?3, ?4, ?5)"
+        )
+        .bind(name)
+        .bind(&resource.kind)
+        .bind(&resource.color)
+        .bind(resource.active.unwrap_or(true) as i64)
+        .bind(resource.sort_order.unwrap_or(0))
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create resource: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+/// Soft delete: las citas existentes conservan sus recursos asignados
+#[tauri::command]
+pub async fn delete_resource(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("UPDATE resources SET active = 0 WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// ============================================================================
+// APPOINTMENT TYPES MODULE (duración, color, recursos e indicaciones por tipo)
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppointmentType {
+    pub id: Option<i64>,
+    pub name: String,
+    pub duration_minutes: i64,
+    pub color: Option<String>,
+    pub preparation_notes: Option<String>,  // Se agregan al recordatorio de la cita
+    #[serde(default)]
+    pub resource_ids: Vec<i64>,             // Recursos requeridos (se suman a los de la cita)
+    #[serde(default)]
+    pub procedure_template_ids: Vec<i64>,   // Procedimientos asociados
+    pub active: Option<bool>,
+    pub sort_order: Option<i64>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+fn appointment_type_from_row(row: &sqlx::sqlite::SqliteRow) -> AppointmentType {
+    AppointmentType {
+        id: row.get("id"),
+        name: row.get("name"),
+        duration_minutes: row.get("duration_minutes"),
+        color: row.get("color"),
+        preparation_notes: row.get("preparation_notes"),
+        resource_ids: parse_resource_ids(row.get("resource_ids")),
+        procedure_template_ids: parse_resource_ids(row.get("procedure_template_ids")),
+        active: Some(row.get::<i64, _>("active") != 0),
+        sort_order: row.get("sort_order"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+const APPOINTMENT_TYPE_SELECT_SQL: &str =
+    "SELECT t.id, t.name, t.duration_minutes, t.color, t.preparation_notes, t.active, t.sort_order,
+            t.created_at, t.updated_at,
+            (SELECT GROUP_CONCAT(resource_id) FROM appointment_type_resources WHERE appointment_type_id = t.id) AS resource_ids,
+            (SELECT GROUP_CONCAT(procedure_template_id) FROM appointment_type_procedures WHERE appointment_type_id = t.id) AS procedure_template_ids
+     FROM appointment_types t";
+
+async fn load_appointment_type(
+    conn: &mut sqlx::SqliteConnection,
+    id: i64,
+) -> Result<AppointmentType, String> {
+    sqlx::query(&format!("{} WHERE t.id = ?1", APPOINTMENT_TYPE_SELECT_SQL))
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|row| appointment_type_from_row(&row))
+        .ok_or_else(|| format!("Appointment type {} not found", id))
+}
+
+/// Completa una cita con su tipo: fin = inicio + duración (si no vino), procedimiento
+/// (si está vacío) y recursos requeridos (se suman a los elegidos). Sin tipo, la copia queda igual.
+async fn apply_appointment_type(
+    conn: &mut sqlx::SqliteConnection,
+    appointment: &Appointment,
+    tz: ClinicTimezone,
+) -> Result<Appointment, String> {
+    let mut typed = appointment.clone();
+    typed.appointment_type_id = appointment.appointment_type_id.filter(|&i| i > 0);
+    let Some(type_id) = typed.appointment_type_id else {
+        return Ok(typed);
+    };
+    let appointment_type = load_appointment_type(conn, type_id).await?;
+
+    if typed.ends_at.trim().is_empty() {
+        let starts_at = parse_appointment_instant(&typed.starts_at, tz)?;
+        typed.ends_at = format_canonical_utc(starts_at + chrono::Duration::minutes(appointment_type.duration_minutes));
+    }
+    if typed.procedure.trim().is_empty() {
+        typed.procedure = appointment_type.name;
+    }
+    // None (edición que conserva los recursos) lo resuelve apply_appointment_update antes de llamar
+    if let Some(resource_ids) = typed.resource_ids.as_mut() {
+        for resource_id in appointment_type.resource_ids {
+            if !resource_ids.contains(&resource_id) {
+                resource_ids.push(resource_id);
+            }
+        }
+    }
+
+    Ok(typed)
+}
+
+#[tauri::command]
+pub async fn get_appointment_types(
+    db_pool: State<'_, DbPool>,
+    include_inactive: Option<bool>,
+) -> Result<Vec<AppointmentType>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(&format!(
+        "{} WHERE t.active = 1 OR ?1 = 1 ORDER BY t.active DESC, t.sort_order ASC, t.name ASC",
+        APPOINTMENT_TYPE_SELECT_SQL
+    ))
+    .bind(include_inactive.unwrap_or(false) as i64)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows.iter().map(appointment_type_from_row).collect())
+}
+
+#[tauri::command]
+pub async fn save_appointment_type(
+    db_pool: State<'_, DbPool>,
+    appointment_type: AppointmentType,
+) -> Result<i64, String> {
+    let name = appointment_type.name.trim();
+    if name.is_empty() {
+        return Err("Appointment type name is required".to_string());
+    }
+    // Mismo rango que los huecos: un tipo debe poder usarse en generate_available_slots
+    if !(MIN_SLOT_MINUTES..=MAX_SLOT_MINUTES).contains(&appointment_type.duration_minutes) {
+        return Err(format!(
+            "duration_minutes must be between {} and {}",
+            MIN_SLOT_MINUTES, MAX_SLOT_MINUTES
+        ));
+    }
+    let preparation_notes = appointment_type
+        .preparation_notes
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty());
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let resource_ids = normalize_appointment_resources(&mut tx, &appointment_type.resource_ids).await?;
+    let mut procedure_template_ids: Vec<i64> =
+        appointment_type.procedure_template_ids.iter().copied().filter(|&i| i > 0).collect();
+    procedure_template_ids.sort_unstable();
+    procedure_template_ids.dedup();
+
+    let id = if let Some(id) = appointment_type.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE appointment_types
+             SET name = ?1, duration_minutes = ?2, color = ?3, preparation_notes = ?4, active = ?5, sort_order = ?6
+             WHERE id = ?7"
+        )
+        .bind(name)
+        .bind(appointment_type.duration_minutes)
+        .bind(&appointment_type.color)
+        .bind(preparation_notes)
+        .bind(appointment_type.active.unwrap_or(true) as i64)
+        .bind(appointment_type.sort_order.unwrap_or(0))
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to update appointment type: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Appointment type {} not found", id));
+        }
+        id
+    } else {
+        sqlx::query(
+            "INSERT INTO appointment_types (name, duration_minutes, color, preparation_notes, active, sort_order)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(name)
+        .bind(appointment_type.duration_minutes)
+        .bind(&appointment_type.color)
+        .bind(preparation_notes)
+        .bind(appointment_type.active.unwrap_or(true) as i64)
+        .bind(appointment_type.sort_order.unwrap_or(0))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to create appointment type: {}", e))?
+        .last_insert_rowid()
+    };
+
+    sqlx::query("DELETE FROM appointment_type_resources WHERE appointment_type_id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    for resource_id in &resource_ids {
+        sqlx::query("INSERT INTO appointment_type_resources (appointment_type_id, resource_id) VALUES (?1, ?2)")
+            .bind(id)
+            .bind(resource_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    sqlx::query("DELETE FROM appointment_type_procedures WHERE appointment_type_id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    for procedure_template_id in &procedure_template_ids {
+        let result = sqlx::query(
+            "INSERT INTO appointment_type_procedures (appointment_type_id, procedure_template_id)
+             SELECT ?1, id FROM procedure_templates WHERE id = ?2"
+        )
+        .bind(id)
+        .bind(procedure_template_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Procedure template {} not found", procedure_template_id));
+        }
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
+/// Soft delete: las citas existentes conservan su tipo
+#[tauri::command]
+pub async fn delete_appointment_type(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("UPDATE appointment_types SET active = 0 WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// ============================================================================
+// SCHEDULE MODULE (horario semanal, pausas, feriados y cierres)
+// ============================================================================
+
+const DEFAULT_SLOTS_PER_RESOURCE: i64 = 8;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScheduleRange {
+    pub id: Option<i64>,
+    pub provider_id: Option<i64>,        // None = horario de la clínica
+    pub weekday: i64,                    // 0 = domingo … 6 = sábado
+    pub start_time: String,              // "HH:MM"
+    pub end_time: String,                // "HH:MM"
+    pub kind: String,                    // 'work' | 'break'
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScheduleClosure {
+    pub id: Option<i64>,
+    pub provider_id: Option<i64>,        // None = cierra toda la clínica
+    pub start_date: String,              // "YYYY-MM-DD"
+    pub end_date: Option<String>,        // inclusive; None = solo start_date
+    pub start_time: Option<String>,      // None = todo el día
+    pub end_time: Option<String>,
+    pub kind: String,                    // 'holiday' | 'closure'
+    pub repeats_yearly: Option<bool>,
+    pub reason: Option<String>,
+}
+
+/// Acepta "HH:MM" y, por compatibilidad con perfiles antiguos, solo la hora ("9")
+fn parse_schedule_time(value: &str) -> Option<chrono::NaiveTime> {
+    let value = value.trim();
+    chrono::NaiveTime::parse_from_str(value, "%H:%M")
+        .ok()
+        .or_else(|| value.parse::<u32>().ok().and_then(|h| chrono::NaiveTime::from_hms_opt(h, 0, 0)))
+}
+
+/// Resta `cut` de cada intervalo abierto
+fn subtract_interval(
+    intervals: Vec<(chrono::NaiveTime, chrono::NaiveTime)>,
+    cut: (chrono::NaiveTime, chrono::NaiveTime),
+) -> Vec<(chrono::NaiveTime, chrono::NaiveTime)> {
+    let mut result = Vec::new();
+    for (start, end) in intervals {
+        if cut.1 <= start || cut.0 >= end {
+            result.push((start, end));
+            continue;
+        }
+        if cut.0 > start {
+            result.push((start, cut.0));
+        }
+        if cut.1 < end {
+            result.push((cut.1, end));
+        }
+    }
+    result
+}
+
+struct ParsedClosure {
+    start_date: chrono::NaiveDate,
+    end_date: chrono::NaiveDate,
+    hours: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+    repeats_yearly: bool,
+}
+
+impl ParsedClosure {
+    fn covers(&self, date: chrono::NaiveDate) -> bool {
+        use chrono::Datelike;
+        if !self.repeats_yearly {
+            return self.start_date <= date && date <= self.end_date;
+        }
+        let md = (date.month(), date.day());
+        let from = (self.start_date.month(), self.start_date.day());
+        let to = (self.end_date.month(), self.end_date.day());
+        if from <= to {
+            from <= md && md <= to
+        } else {
+            md >= from || md <= to  // Cruza fin de año (24/12 → 02/01)
+        }
+    }
+}
+
+/// Horario efectivo de la clínica o de un profesional
+struct WorkSchedule {
+    work: Vec<(u32, chrono::NaiveTime, chrono::NaiveTime)>,
+    breaks: Vec<(u32, chrono::NaiveTime, chrono::NaiveTime)>,
+    closures: Vec<ParsedClosure>,
+}
+
+impl WorkSchedule {
+    /// Sin horario semanal cargado no se restringe la agenda (comportamiento anterior)
+    fn is_configured(&self) -> bool {
+        !self.work.is_empty()
+    }
+
+    /// Intervalos abiertos de un día. `fallback` se usa cuando no hay horario semanal.
+    fn open_intervals(
+        &self,
+        date: chrono::NaiveDate,
+        fallback: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+    ) -> Vec<(chrono::NaiveTime, chrono::NaiveTime)> {
+        use chrono::Datelike;
+        let weekday = date.weekday().num_days_from_sunday();
+
+        let mut intervals: Vec<(chrono::NaiveTime, chrono::NaiveTime)> = if self.is_configured() {
+            self.work
+                .iter()
+                .filter(|(day, _, _)| *day == weekday)
+                .map(|(_, start, end)| (*start, *end))
+                .collect()
+        } else {
+            vec![fallback.unwrap_or((
+                chrono::NaiveTime::MIN,
+                chrono::NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+            ))]
+        };
+
+        for (_, start, end) in self.breaks.iter().filter(|(day, _, _)| *day == weekday) {
+            intervals = subtract_interval(intervals, (*start, *end));
+        }
+
+        for closure in self.closures.iter().filter(|c| c.covers(date)) {
+            match closure.hours {
+                Some(hours) => intervals = subtract_interval(intervals, hours),
+                None => return Vec::new(),
+            }
+        }
+
+        intervals.sort();
+        intervals
+    }
+
+    fn allows(&self, starts_at: chrono::NaiveDateTime, ends_at: chrono::NaiveDateTime) -> bool {
+        if starts_at.date() != ends_at.date() {
+            return !self.is_configured() && self.closures.iter().all(|c| !c.covers(starts_at.date()));
+        }
+        self.open_intervals(starts_at.date(), None)
+            .iter()
+            .any(|(start, end)| *start <= starts_at.time() && ends_at.time() <= *end)
+    }
+}
+
+/// Carga el horario semanal del profesional (si tiene uno propio) o el de la clínica,
+/// más los cierres de la clínica y los del profesional.
+async fn load_work_schedule(
+    conn: &mut sqlx::SqliteConnection,
+    provider_id: Option<i64>,
+) -> Result<WorkSchedule, String> {
+    let provider_id = provider_id.filter(|&i| i > 0);
+
+    let has_override: bool = match provider_id {
+        Some(id) => sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM schedule_hours WHERE provider_id = ?1")
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())? > 0,
+        None => false,
+    };
+
+    let hour_rows: Vec<(i64, String, String, String)> = sqlx::query_as(
+        "SELECT weekday, start_time, end_time, kind
+         FROM schedule_hours
+         WHERE (?1 IS NULL AND provider_id IS NULL) OR provider_id = ?1"
+    )
+    .bind(if has_override { provider_id } else { None })
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to load schedule: {}", e))?;
+
+    let mut schedule = WorkSchedule { work: Vec::new(), breaks: Vec::new(), closures: Vec::new() };
+    for (weekday, start_time, end_time, kind) in hour_rows {
+        let (Some(start), Some(end)) = (parse_schedule_time(&start_time), parse_schedule_time(&end_time)) else {
+            continue;
+        };
+        if start >= end {
+            continue;
+        }
+        let range = (weekday as u32, start, end);
+        if kind == "break" {
+            schedule.breaks.push(range);
+        } else {
+            schedule.work.push(range);
+        }
+    }
+
+    let closure_rows = sqlx::query(
+        "SELECT start_date, end_date, start_time, end_time, repeats_yearly
+         FROM schedule_closures
+         WHERE provider_id IS NULL OR provider_id = ?1"
+    )
+    .bind(provider_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to load closures: {}", e))?;
+
+    for row in closure_rows {
+        let start_time: Option<String> = row.get("start_time");
+        let end_time: Option<String> = row.get("end_time");
+        let repeats_yearly: i64 = row.get("repeats_yearly");
+        let (Ok(start_date), Ok(end_date)) = (
+            chrono::NaiveDate::parse_from_str(row.get("start_date"), "%Y-%m-%d"),
+            chrono::NaiveDate::parse_from_str(row.get("end_date"), "%Y-%m-%d"),
+        ) else {
+            continue;
+        };
+        let hours = match (start_time.as_deref().and_then(parse_schedule_time), end_time.as_deref().and_then(parse_schedule_time)) {
+            (Some(start), Some(end)) if start < end => Some((start, end)),
+            _ => None,
+        };
+        schedule.closures.push(ParsedClosure { start_date, end_date, hours, repeats_yearly: repeats_yearly != 0 });
+    }
+
+    Ok(schedule)
+}
+
+/// Rechaza citas activas fuera del horario de atención (incluye pausas, feriados y cierres)
+async fn ensure_within_schedule(
+    conn: &mut sqlx::SqliteConnection,
+    appointment: &Appointment,
+    provider_id: Option<i64>,
+    tz: ClinicTimezone,
+) -> Result<(), String> {
+    if !matches!(appointment.status.as_str(), "scheduled" | "confirmed") {
+        return Ok(());
+    }
+
+    let (Some(starts_at), Some(ends_at)) = (
+        appointment_local_time(&appointment.starts_at, tz),
+        appointment_local_time(&appointment.ends_at, tz),
+    ) else {
+        return Err("Invalid appointment datetime".to_string());
+    };
+
+    let schedule = load_work_schedule(conn, provider_id).await?;
+    if !schedule.allows(starts_at, ends_at) {
+        return Err("Appointment is outside working hours".to_string());
+    }
+
+    Ok(())
+}
+
+/// Lee un límite numérico positivo de user_settings
+async fn load_limit_setting(
+    conn: &mut sqlx::SqliteConnection,
+    key: &str,
+) -> Result<Option<i64>, String> {
+    let value: Option<String> = sqlx::query_scalar("SELECT value FROM user_settings WHERE key = ?1")
+        .bind(key)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(value.and_then(|v| v.trim().parse::<i64>().ok()).filter(|&n| n > 0))
+}
+
+fn validate_schedule_range(range: &ScheduleRange) -> Result<(), String> {
+    if !(0..=6).contains(&range.weekday) {
+        return Err(format!("Invalid weekday: {}", range.weekday));
+    }
+    if !matches!(range.kind.as_str(), "work" | "break") {
+        return Err(format!("Invalid schedule kind: {}", range.kind));
+    }
+    let start = chrono::NaiveTime::parse_from_str(&range.start_time, "%H:%M")
+        .map_err(|_| format!("Invalid start_time: {}", range.start_time))?;
+    let end = chrono::NaiveTime::parse_from_str(&range.end_time, "%H:%M")
+        .map_err(|_| format!("Invalid end_time: {}", range.end_time))?;
+    if start >= end {
+        return Err("start_time must be before end_time".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_schedule(
+    db_pool: State<'_, DbPool>,
+    provider_id: Option<i64>,
+) -> Result<Vec<ScheduleRange>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT id, provider_id, weekday, start_time, end_time, kind
+         FROM schedule_hours
+         WHERE (?1 IS NULL AND provider_id IS NULL) OR provider_id = ?1
+         ORDER BY weekday ASC, start_time ASC"
+    )
+    .bind(provider_id.filter(|&i| i > 0))
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ScheduleRange {
+            id: row.get("id"),
+            provider_id: row.get("provider_id"),
+            weekday: row.get("weekday"),
+            start_time: row.get("start_time"),
+            end_time: row.get("end_time"),
+            kind: row.get("kind"),
+        })
+        .collect())
+}
+
+/// Reemplaza el horario semanal completo de la clínica o de un profesional.
+/// Una lista vacía para un profesional elimina su horario propio (vuelve al de la clínica).
+#[tauri::command]
+pub async fn save_schedule(
+    db_pool: State<'_, DbPool>,
+    provider_id: Option<i64>,
+    ranges: Vec<ScheduleRange>,
+) -> Result<(), String> {
+    for range in &ranges {
+        validate_schedule_range(range)?;
+    }
+
+    let provider_id = provider_id.filter(|&i| i > 0);
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM schedule_hours WHERE (?1 IS NULL AND provider_id IS NULL) OR provider_id = ?1")
+        .bind(provider_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for range in &ranges {
+        sqlx::query(
+            "INSERT INTO schedule_hours (provider_id, weekday, start_time, end_time, kind)
+             VALUES (?1, ?2, ?3, ?4, ?5)"
+        )
+        .bind(provider_id)
+        .bind(range.weekday)
+        .bind(&range.start_time)
+        .bind(&range.end_time)
+        .bind(&range.kind)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to save schedule: {}", e))?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_schedule_closures(
+    db_pool: State<'_, DbPool>,
+    from_date: Option<String>,
+) -> Result<Vec<ScheduleClosure>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT id, provider_id, start_date, end_date, start_time, end_time, kind, repeats_yearly, reason
+         FROM schedule_closures
+         WHERE ?1 IS NULL OR end_date >= ?1 OR repeats_yearly = 1
+         ORDER BY start_date ASC"
+    )
+    .bind(&from_date)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ScheduleClosure {
+            id: row.get("id"),
+            provider_id: row.get("provider_id"),
+            start_date: row.get("start_date"),
+            end_date: Some(row.get("end_date")),
+            start_time: row.get("start_time"),
+            end_time: row.get("end_time"),
+            kind: row.get("kind"),
+            repeats_yearly: Some(row.get::<i64, _>("repeats_yearly") != 0),
+            reason: row.get("reason"),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn save_schedule_closure(
+    db_pool: State<'_, DbPool>,
+    closure: ScheduleClosure,
+) -> Result<i64, String> {
+    if !matches!(closure.kind.as_str(), "holiday" | "closure") {
+        return Err(format!("Invalid closure kind: {}", closure.kind));
+    }
+    let start_date = parse_iso_date(&closure.start_date, "start_date")?;
+    let end_date = match closure.end_date.as_deref().filter(|d| !d.is_empty()) {
+        Some(value) => parse_iso_date(value, "end_date")?,
+        None => start_date,
+    };
+    if end_date < start_date {
+        return Err("end_date must not be before start_date".to_string());
+    }
+    match (closure.start_time.as_deref(), closure.end_time.as_deref()) {
+        (None, None) => {}
+        (Some(start), Some(end)) => {
+            let start = chrono::NaiveTime::parse_from_str(start, "%H:%M")
+                .map_err(|_| format!("Invalid start_time: {}", start))?;
+            let end = chrono::NaiveTime::parse_from_str(end, "%H:%M")
+                .map_err(|_| format!("Invalid end_time: {}", end))?;
+            if start >= end {
+                return Err("start_time must be before end_time".to_string());
+            }
+        }
+        _ => return Err("start_time and end_time must be set together".to_string()),
+    }
+
+    let provider_id = closure.provider_id.filter(|&i| i > 0);
+    let start_date = start_date.format("%Y-%m-%d").to_string();
+    let end_date = end_date.format("%Y-%m-%d").to_string();
+    let repeats_yearly = closure.repeats_yearly.unwrap_or(false) as i64;
+
+    let pool = db_pool.0.lock().await;
+
+    if let Some(id) = closure.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE schedule_closures
+             SET provider_id = ?1, start_date = ?2, end_date = ?3, start_time = ?4, end_time = ?5,
+                 kind = ?6, repeats_yearly = ?7, reason = ?8
+             WHERE id = ?9"
+        )
+        .bind(provider_id)
+        .bind(&start_date)
+        .bind(&end_date)
+        .bind(&closure.start_time)
+        .bind(&closure.end_time)
+        .bind(&closure.kind)
+        .bind(repeats_yearly)
+        .bind(&closure.reason)
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update closure: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Closure {} not found", id));
+        }
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO schedule_closures (provider_id, start_date, end_date, start_time, end_time, kind, repeats_yearly, reason)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+        )
+        .bind(provider_id)
+        .bind(&start_date)
+        .bind(&end_date)
+        .bind(&closure.start_time)
+        .bind(&closure.end_time)
+        .bind(&closure.kind)
+        .bind(repeats_yearly)
+        .bind(&closure.reason)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create closure: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+#[tauri::command]
+pub async fn delete_schedule_closure(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("DELETE FROM schedule_closures WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// ============================================================================
+// RECURRING APPOINTMENTS MODULE (series con RRULE)
+// ============================================================================
+// Subconjunto soportado de RRULE (RFC 5545):
+//   FREQ=WEEKLY;INTERVAL=N;BYDAY=MO,TH;COUNT=k | UNTIL=YYYYMMDD
+//   FREQ=MONTHLY;INTERVAL=N;BYMONTHDAY=15;COUNT=k | UNTIL=YYYYMMDD
+
+const MAX_SERIES_OCCURRENCES: usize = 104;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RecurrenceFreq {
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug, Clone)]
+struct RecurrenceRule {
+    freq: RecurrenceFreq,
+    interval: u32,
+    count: Option<usize>,
+    until: Option<chrono::NaiveDate>,
+    by_day: Vec<chrono::Weekday>,
+    by_month_day: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeriesOccurrence {
+    pub starts_at: String,
+    pub ends_at: String,
+    pub appointment_id: Option<i64>,     // None = no se creó (ver error)
+    pub error: Option<String>,           // Choque de horario, fuera de horario, etc.
+}
+
+#[derive(Debug, Serialize)]
+pub struct AppointmentSeriesResult {
+    pub series_id: Option<i64>,          // None en dry_run o si no se creó ninguna ocurrencia
+    pub created: i64,
+    pub skipped: i64,
+    pub occurrences: Vec<SeriesOccurrence>,
+}
+
+fn parse_rrule(value: &str) -> Result<RecurrenceRule, String> {
+    let value = value.trim();
+    let value = value.strip_prefix("RRULE:").unwrap_or(value);
+
+    let mut freq = None;
+    let mut rule = RecurrenceRule {
+        freq: RecurrenceFreq::Weekly,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+        by_month_day: None,
+    };
+
+    for part in value.split(';').filter(|p| !p.trim().is_empty()) {
+        let (key, val) = part
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid RRULE part: {}", part))?;
+        let val = val.trim().to_uppercase();
+
+        match key.trim().to_uppercase().as_str() {
+            "FREQ" => {
+                freq = Some(match val.as_str() {
+                    "WEEKLY" => RecurrenceFreq::Weekly,
+                    "MONTHLY" => RecurrenceFreq::Monthly,
+                    _ => return Err(format!("Unsupported FREQ: {} (use WEEKLY or MONTHLY)", val)),
+                });
+            }
+            "INTERVAL" => {
+                rule.interval = val
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|n| (1..=52).contains(n))
+                    .ok_or_else(|| format!("INTERVAL must be between 1 and 52, got {}", val))?;
+            }
+            "COUNT" => {
+                rule.count = Some(
+                    val.parse::<usize>()
+                        .ok()
+                        .filter(|n| (1..=MAX_SERIES_OCCURRENCES).contains(n))
+                        .ok_or_else(|| format!("COUNT must be between 1 and {}", MAX_SERIES_OCCURRENCES))?,
+                );
+            }
+            "UNTIL" => {
+                rule.until = Some(
+                    chrono::NaiveDate::parse_from_str(val.get(..8).unwrap_or(&val), "%Y%m%d")
+                        .map_err(|_| format!("Invalid UNTIL: {}", val))?,
+                );
+            }
+            "BYDAY" => {
+                for day in val.split(',') {
+                    rule.by_day.push(match day.trim() {
+                        "MO" => chrono::Weekday::Mon,
+                        "TU" => chrono::Weekday::Tue,
+                        "WE" => chrono::Weekday::Wed,
+                        "TH" => chrono::Weekday::Thu,
+                        "FR" => chrono::Weekday::Fri,
+                        "SA" => chrono::Weekday::Sat,
+                        "SU" => chrono::Weekday::Sun,
+                        other => return Err(format!("Invalid BYDAY: {}", other)),
+                    });
+                }
+            }
+            "BYMONTHDAY" => {
+                rule.by_month_day = Some(
+                    val.parse::<u32>()
+                        .ok()
+                        .filter(|n| (1..=31).contains(n))
+                        .ok_or_else(|| format!("Invalid BYMONTHDAY: {}", val))?,
+                );
+            }
+            other => return Err(format!("Unsupported RRULE part: {}", other)),
+        }
+    }
+
+    rule.freq = freq.ok_or("RRULE requires FREQ")?;
+    if rule.count.is_none() && rule.until.is_none() {
+        return Err("RRULE requires COUNT or UNTIL".to_string());
+    }
+    if rule.freq == RecurrenceFreq::Weekly && rule.by_month_day.is_some() {
+        return Err("BYMONTHDAY is only supported with FREQ=MONTHLY".to_string());
+    }
+    if rule.freq == RecurrenceFreq::Monthly && !rule.by_day.is_empty() {
+        return Err("BYDAY is only supported with FREQ=WEEKLY".to_string());
+    }
+
+    Ok(rule)
+}
+
+/// Fechas de las ocurrencias a partir de `start` (incluida si cumple la regla).
+/// Los meses sin el día pedido (31 en abril) se saltan, como en RFC 5545.
+fn expand_rrule(rule: &RecurrenceRule, start: chrono::NaiveDate) -> Result<Vec<chrono::NaiveDate>, String> {
+    use chrono::Datelike;
+
+    let mut dates = Vec::new();
+
+    // Cota de iteraciones: semanas/meses recorridos aunque no generen fechas
+    for step in 0..(MAX_SERIES_OCCURRENCES as u32 * 12) {
+        let candidates: Vec<chrono::NaiveDate> = match rule.freq {
+            RecurrenceFreq::Weekly => {
+                let week_start = start - chrono::Duration::days(start.weekday().num_days_from_monday() as i64)
+                    + chrono::Duration::weeks((step * rule.interval) as i64);
+                let mut days: Vec<chrono::Weekday> = if rule.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    rule.by_day.clone()
+                };
+                days.sort_by_key(|d| d.num_days_from_monday());
+                days.dedup();
+                days.into_iter()
+                    .map(|d| week_start + chrono::Duration::days(d.num_days_from_monday() as i64))
+                    .collect()
+            }
+            RecurrenceFreq::Monthly => {
+                let month_start = start
+                    .with_day(1)
+                    .and_then(|d| d.checked_add_months(chrono::Months::new(step * rule.interval)));
+                month_start
+                    .and_then(|m| m.with_day(rule.by_month_day.unwrap_or(start.day())))
+                    .into_iter()
+                    .collect()
+            }
+        };
+
+        for date in candidates {
+            if date < start {
+                continue;
+            }
+            if rule.until.is_some_and(|until| date > until) {
+                return Ok(dates);
+            }
+            // Solo alcanzable con UNTIL: COUNT ya está acotado a MAX_SERIES_OCCURRENCES
+            if dates.len() >= MAX_SERIES_OCCURRENCES {
+                return Err(format!("Series exceeds {} occurrences", MAX_SERIES_OCCURRENCES));
+            }
+            dates.push(date);
+            if rule.count.is_some_and(|count| dates.len() >= count) {
+                return Ok(dates);
+            }
+        }
+    }
+
+    Ok(dates)
+}
+
+/// Ocurrencia en hora local de pared → inicio/fin en UTC canónico. La duración es real
+/// (instantes), así que una cita de 1 h sigue durando 1 h aunque cruce un cambio de DST.
+fn series_occurrence_times(
+    local_start: chrono::NaiveDateTime,
+    duration: chrono::Duration,
+    tz: ClinicTimezone,
+) -> Result<(String, String), String> {
+    let starts_at = tz.to_utc(local_start)?;
+    Ok((format_canonical_utc(starts_at), format_canonical_utc(starts_at + duration)))
+}
+
+fn validate_series_scope(scope: &str) -> Result<(), String> {
+    if !matches!(scope, "this" | "following" | "all") {
+        return Err(format!("Invalid scope: {} (use this, following or all)", scope));
+    }
+    Ok(())
+}
+
+/// Crea una serie: cada ocurrencia pasa por las mismas validaciones que `create_appointment`.
+/// Las que chocan se informan y no se crean; con `dry_run` solo se devuelve el informe.
+#[tauri::command]
+pub async fn create_appointment_series(
+    db_pool: State<'_, DbPool>,
+    appointment: Appointment,
+    rrule: String,
+    dry_run: Option<bool>,
+) -> Result<AppointmentSeriesResult, String> {
+    validate_appointment_fields(&appointment)?;
+    let rule = parse_rrule(&rrule)?;
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    // La regla se expande en hora local de la clínica (10:00 sigue siendo 10:00 después del cambio de DST)
+    let tz = load_clinic_timezone(&mut tx).await?;
+    let appointment = apply_appointment_type(&mut tx, &appointment, tz).await?;
+    let appointment = normalize_appointment_times(&appointment, tz)?;
+    let first_start = appointment_local_time(&appointment.starts_at, tz).ok_or("Invalid starts_at")?;
+    let duration = parse_appointment_instant(&appointment.ends_at, tz)? - parse_appointment_instant(&appointment.starts_at, tz)?;
+    let dates = expand_rrule(&rule, first_start.date())?;
+
+    let series_id = sqlx::query(
+        "INSERT INTO appointment_series (patient_id, rrule, dtstart, procedure)
+         VALUES (?1, ?2, ?3, ?4)"
+    )
+    .bind(appointment.patient_id)
+    .bind(rrule.trim())
+    .bind(&appointment.starts_at)
+    .bind(&appointment.procedure)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to create series: {}", e))?
+    .last_insert_rowid();
+
+    let mut result = AppointmentSeriesResult { series_id: None, created: 0, skipped: 0, occurrences: Vec::new() };
+
+    for date in dates {
+        let local_start = date.and_time(first_start.time());
+        let mut occurrence = appointment.clone();
+        occurrence.id = None;
+        occurrence.series_id = Some(series_id);
+
+        let inserted = match series_occurrence_times(local_start, duration, tz) {
+            Ok((starts_at, ends_at)) => {
+                occurrence.starts_at = starts_at;
+                occurrence.ends_at = ends_at;
+                insert_appointment(&mut tx, &occurrence).await
+            }
+            Err(e) => {
+                occurrence.starts_at = local_start.format("%Y-%m-%dT%H:%M:%S").to_string();
+                occurrence.ends_at = (local_start + duration).format("%Y-%m-%dT%H:%M:%S").to_string();
+                Err(e)
+            }
+        };
+
+        match inserted {
+            Ok(id) => {
+                result.created += 1;
+                result.occurrences.push(SeriesOccurrence {
+                    starts_at: occurrence.starts_at,
+                    ends_at: occurrence.ends_at,
+                    appointment_id: Some(id),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                result.skipped += 1;
+                result.occurrences.push(SeriesOccurrence {
+                    starts_at: occurrence.starts_at,
+                    ends_at: occurrence.ends_at,
+                    appointment_id: None,
+                    error: Some(e),
+                });
+            }
+        }
+    }
+
+    // Dry run (o ninguna ocurrencia válida): se descarta todo al soltar la transacción
+    if dry_run.unwrap_or(false) || result.created == 0 {
+        return Ok(result);
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+    result.series_id = Some(series_id);
+
+    println!("🔁 Series {} created: {} occurrences, {} skipped", series_id, result.created, result.skipped);
+
+    Ok(result)
+}
+
+/// Edita "esta ocurrencia", "esta y las siguientes" o "toda la serie".
+/// El cambio de hora/fecha de la cita elegida se aplica como desplazamiento a las demás;
+/// solo se mueven ocurrencias agendadas o confirmadas. Todo o nada: si alguna choca, no se guarda ninguna.
+#[tauri::command]
+pub async fn update_appointment_series(
+    db_pool: State<'_, DbPool>,
+    appointment_id: i64,
+    scope: String,
+    appointment: Appointment,
+) -> Result<i64, String> {
+    validate_series_scope(&scope)?;
+    validate_appointment_fields(&appointment)?;
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let anchor: Option<(Option<i64>, String)> = sqlx::query_as(
+        "SELECT series_id, starts_at FROM appointments WHERE id = ?1"
+    )
+    .bind(appointment_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    let (series_id, anchor_starts_at) = anchor.ok_or_else(|| format!("Appointment {} not found", appointment_id))?;
+
+    let Some(series_id) = series_id.filter(|_| scope != "this") else {
+        apply_appointment_update(&mut tx, appointment_id, &appointment, StatusChange::default()).await?;
+        tx.commit().await.map_err(|e| e.to_string())?;
+        return Ok(1);
+    };
+
+    // El desplazamiento se mide en hora local de pared; la duración, en tiempo real
+    let tz = load_clinic_timezone(&mut tx).await?;
+    let appointment = apply_appointment_type(&mut tx, &appointment, tz).await?;
+    let appointment = normalize_appointment_times(&appointment, tz)?;
+    let anchor_start = appointment_local_time(&anchor_starts_at, tz).ok_or("Invalid stored starts_at")?;
+    let new_start = appointment_local_time(&appointment.starts_at, tz).ok_or("Invalid starts_at")?;
+    let shift = new_start - anchor_start;
+    let duration = parse_appointment_instant(&appointment.ends_at, tz)? - parse_appointment_instant(&appointment.starts_at, tz)?;
+
+    let mut targets = sqlx::query(
+        "SELECT id, starts_at, status, confirmed_at, reminder_1d_sent_at
+         FROM appointments
+         WHERE series_id = ?1
+           AND (id = ?2 OR status IN ('scheduled', 'confirmed'))
+           AND (?3 = 'all' OR starts_at >= ?4)
+         ORDER BY starts_at ASC"
+    )
+    .bind(series_id)
+    .bind(appointment_id)
+    .bind(&scope)
+    .bind(&anchor_starts_at)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    // Al mover hacia adelante se empieza por la última para no chocar con la siguiente aún sin mover
+    if shift > chrono::Duration::zero() {
+        targets.reverse();
+    }
+
+    let mut errors = Vec::new();
+    for row in &targets {
+        let id: i64 = row.get("id");
+        let starts_at: String = row.get("starts_at");
+        let Some(current_start) = appointment_local_time(&starts_at, tz) else {
+            continue;
+        };
+        let moved_start = current_start + shift;
+
+        let mut occurrence = appointment.clone();
+        match series_occurrence_times(moved_start, duration, tz) {
+            Ok((starts_at, ends_at)) => {
+                occurrence.starts_at = starts_at;
+                occurrence.ends_at = ends_at;
+            }
+            Err(e) => {
+                errors.push(format!("{}: {}", format_display_date(moved_start.date()), e));
+                continue;
+            }
+        }
+        if id != appointment_id {
+            occurrence.status = row.get("status");
+            occurrence.confirmed_at = row.get("confirmed_at");
+            occurrence.reminder_1d_sent_at = row.get("reminder_1d_sent_at");
+        }
+
+        if let Err(e) = apply_appointment_update(&mut tx, id, &occurrence, StatusChange::default()).await {
+            errors.push(format!("{}: {}", format_display_date(moved_start.date()), e));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(targets.len() as i64)
+}
+
+/// Cancela "esta ocurrencia", "esta y las siguientes" o "toda la serie" (solo citas activas).
+/// El motivo y quién canceló quedan en el historial de cada ocurrencia.
+#[tauri::command]
+pub async fn cancel_appointment_series(
+    db_pool: State<'_, DbPool>,
+    appointment_id: i64,
+    scope: String,
+    reason: Option<String>,
+    changed_by: Option<String>,
+) -> Result<i64, String> {
+    validate_series_scope(&scope)?;
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let anchor: Option<(Option<i64>, String)> = sqlx::query_as(
+        "SELECT series_id, starts_at FROM appointments WHERE id = ?1"
+    )
+    .bind(appointment_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    let (series_id, anchor_starts_at) = anchor.ok_or_else(|| format!("Appointment {} not found", appointment_id))?;
+
+    let targets: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, status
+         FROM appointments
+         WHERE status IN ('scheduled', 'confirmed')
+           AND (
+             id = ?1
+             OR (?2 IS NOT NULL AND ?3 != 'this' AND series_id = ?2
+                 AND (?3 = 'all' OR starts_at >= ?4))
+           )
+         ORDER BY starts_at ASC"
+    )
+    .bind(appointment_id)
+    .bind(series_id)
+    .bind(&scope)
+    .bind(&anchor_starts_at)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to cancel appointments: {}", e))?;
+
+    let change = StatusChange { reason: reason.as_deref(), changed_by: changed_by.as_deref() };
+    for (id, status) in &targets {
+        change_appointment_status(&mut tx, *id, status, "cancelled", change).await?;
+    }
+
+    // Solo el turno liberado más próximo con candidatos se ofrece a la lista de espera:
+    // ofrecer cada ocurrencia mandaría un mensaje por semana al mismo paciente
+    for (id, _) in &targets {
+        if !offer_freed_slot(&mut tx, *id, None, true).await?.is_empty() {
+            break;
+        }
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(targets.len() as i64)
+}
+
+// ============================================================================
+// WAITLIST MODULE (lista de espera y ofertas de turnos liberados)
+// ============================================================================
+
+/// Texto de respaldo si el usuario eliminó todas las plantillas de disponibilidad
+const DEFAULT_AVAILABILITY_TEMPLATE: &str =
+    "Hola {nombre}. Se liberó un turno para {procedimiento} el {fecha} a las {hora}. ¿Te gustaría tomarlo?";
+
+const DEFAULT_WAITLIST_OFFERS_PER_SLOT: i64 = 3;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaitlistEntry {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub patient_name: Option<String>,        // Solo lectura
+    pub procedure: String,
+    pub provider_id: Option<i64>,            // None = cualquier profesional
+    #[serde(default)]
+    pub preferred_weekdays: Vec<i64>,        // 0 = domingo … 6 = sábado; vacío = cualquier día
+    pub preferred_start_time: Option<String>, // "HH:MM"
+    pub preferred_end_time: Option<String>,
+    pub urgency: Option<String>,             // 'normal' | 'high' | 'urgent'
+    pub status: Option<String>,              // 'waiting' | 'offered' | 'booked' | 'removed'
+    pub notes: Option<String>,
+    pub last_offered_at: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitlistOffer {
+    pub entry_id: i64,
+    pub patient_id: i64,
+    pub patient_name: String,
+    pub score: i64,
+    pub message_id: Option<i64>,             // None en dry_run
+}
+
+fn urgency_weight(urgency: &str) -> i64 {
+    match urgency {
+        "urgent" => 3,
+        "high" => 2,
+        _ => 1,
+    }
+}
+
+/// Ofrece el turno de una cita (cancelada) a los mejores candidatos de la lista de espera.
+/// Filtros: profesional, días y franja horaria preferidos. Orden: urgencia, mismo
+/// procedimiento y antigüedad en la lista. Con `enqueue` se crean los mensajes 'availability'.
+async fn offer_freed_slot(
+    conn: &mut sqlx::SqliteConnection,
+    appointment_id: i64,
+    limit: Option<i64>,
+    enqueue: bool,
+) -> Result<Vec<WaitlistOffer>, String> {
+    use chrono::Datelike;
+
+    let slot = sqlx::query(
+        "SELECT patient_id, starts_at, ends_at, procedure, provider_id FROM appointments WHERE id = ?1"
+    )
+    .bind(appointment_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Appointment {} not found", appointment_id))?;
+
+    let slot_patient_id: i64 = slot.get("patient_id");
+    let slot_starts_at: String = slot.get("starts_at");
+    let slot_ends_at: String = slot.get("ends_at");
+    let slot_procedure: String = slot.get("procedure");
+    let slot_provider_id: Option<i64> = slot.get("provider_id");
+
+    // Preferencias de día/franja se comparan en hora local de la clínica
+    let tz = load_clinic_timezone(conn).await?;
+    let (Some(starts), Some(ends)) = (appointment_local_time(&slot_starts_at, tz), appointment_local_time(&slot_ends_at, tz)) else {
+        return Ok(Vec::new());
+    };
+    if starts <= tz.now_local() {
+        return Ok(Vec::new()); // Turno ya pasado: nada que ofrecer
+    }
+
+    let limit = match limit {
+        Some(n) => n.max(1),
+        None => load_limit_setting(conn, "waitlistOffersPerSlot")
+            .await?
+            .unwrap_or(DEFAULT_WAITLIST_OFFERS_PER_SLOT),
+    };
+
+    let rows = sqlx::query(
+        "SELECT w.id, w.patient_id, p.full_name, w.procedure, w.provider_id, w.preferred_weekdays,
+                w.preferred_start_time, w.preferred_end_time, w.urgency, w.created_at
+         FROM waitlist_entries w
+         JOIN patients p ON p.id = w.patient_id
+         WHERE w.status IN ('waiting', 'offered')
+           AND w.patient_id != ?1
+           AND NOT EXISTS (
+             SELECT 1 FROM waitlist_offers o WHERE o.entry_id = w.id AND o.appointment_id = ?2
+           )
+         ORDER BY w.created_at ASC, w.id ASC"
+    )
+    .bind(slot_patient_id)
+    .bind(appointment_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to load waitlist: {}", e))?;
+
+    let weekday = starts.weekday().num_days_from_sunday() as i64;
+    let mut candidates: Vec<WaitlistOffer> = Vec::new();
+    let mut seen_patients = std::collections::HashSet::new();
+
+    for row in rows {
+        let provider_id: Option<i64> = row.get("provider_id");
+        if provider_id.is_some() && slot_provider_id.is_some() && provider_id != slot_provider_id {
+            continue;
+        }
+
+        let weekdays: String = row.get("preferred_weekdays");
+        let weekdays: Vec<i64> = weekdays.split(',').filter_map(|d| d.trim().parse().ok()).collect();
+        if !weekdays.is_empty() && !weekdays.contains(&weekday) {
+            continue;
+        }
+
+        let window_start = row.get::<Option<String>, _>("preferred_start_time").as_deref().and_then(parse_schedule_time);
+        let window_end = row.get::<Option<String>, _>("preferred_end_time").as_deref().and_then(parse_schedule_time);
+        if window_start.is_some_and(|t| starts.time() < t) || window_end.is_some_and(|t| ends.time() > t) {
+            continue;
+        }
+
+        // Un paciente con varias entradas recibe una sola oferta (la mejor, por orden de llegada)
+        let patient_id: i64 = row.get("patient_id");
+        if !seen_patients.insert(patient_id) {
+            continue;
+        }
+
+        let procedure: String = row.get("procedure");
+        let created_at: String = row.get("created_at");
+        // created_at es datetime('now') de SQLite, es decir UTC
+        let days_waiting = chrono::NaiveDateTime::parse_from_str(&created_at, "%Y-%m-%d %H:%M:%S")
+            .map(|created| (chrono::Utc::now().naive_utc() - created).num_days().clamp(0, 60))
+            .unwrap_or(0);
+        let procedure_match = procedure.trim().eq_ignore_ascii_case(slot_procedure.trim());
+
+        let score = urgency_weight(&row.get::<String, _>("urgency")) * 100
+            + if procedure_match { 20 } else { 0 }
+            + days_waiting;
+
+        candidates.push(WaitlistOffer {
+            entry_id: row.get("id"),
+            patient_id,
+            patient_name: row.get("full_name"),
+            score,
+            message_id: None,
+        });
+    }
+
+    // Orden estable: a igual puntaje, el que llegó primero
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.score));
+    candidates.truncate(limit as usize);
+
+    if !enqueue || candidates.is_empty() {
+        return Ok(candidates);
+    }
+
+    let template_body = load_preferred_template_body(conn, "availability")
+        .await?
+        .unwrap_or_else(|| DEFAULT_AVAILABILITY_TEMPLATE.to_string());
+    let mut context = default_template_context(conn).await?;
+    context.insert("procedimiento".to_string(), slot_procedure.clone());
+    context.insert("fecha".to_string(), format_display_date(starts.date()));
+    context.insert("hora".to_string(), starts.format("%H:%M").to_string());
+
+    for offer in candidates.iter_mut() {
+        context.insert("nombre".to_string(), offer.patient_name.clone());
+        let message_text = render_template_body("availability", &template_body, &context).text;
+
+        let message_id = sqlx::query(
+            "INSERT INTO message_queue (patient_id, appointment_id, type, message_text, status)
+             VALUES (?1, NULL, 'availability', ?2, 'pending')"
+        )
+        .bind(offer.patient_id)
+        .bind(&message_text)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to create availability message: {}", e))?
+        .last_insert_rowid();
+
+        sqlx::query(
+            "INSERT INTO waitlist_offers (entry_id, appointment_id, slot_starts_at, slot_ends_at, score, message_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(offer.entry_id)
+        .bind(appointment_id)
+        .bind(&slot_starts_at)
+        .bind(&slot_ends_at)
+        .bind(offer.score)
+        .bind(message_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to log waitlist offer: {}", e))?;
+
+        sqlx::query(
+            "UPDATE waitlist_entries SET status = 'offered', last_offered_at = datetime('now') WHERE id = ?1"
+        )
+        .bind(offer.entry_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        offer.message_id = Some(message_id);
+    }
+
+    println!("📣 Offered freed slot of appointment {} to {} waitlisted patients", appointment_id, candidates.len());
+
+    Ok(candidates)
+}
+
+#[tauri::command]
+pub async fn get_waitlist(
+    db_pool: State<'_, DbPool>,
+    include_closed: Option<bool>,
+) -> Result<Vec<WaitlistEntry>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT w.id, w.patient_id, p.full_name, w.procedure, w.provider_id, w.preferred_weekdays,
+                w.preferred_start_time, w.preferred_end_time, w.urgency, w.status, w.notes,
+                w.last_offered_at, w.created_at, w.updated_at
+         FROM waitlist_entries w
+         JOIN patients p ON p.id = w.patient_id
+         WHERE w.status IN ('waiting', 'offered') OR ?1 = 1
+         ORDER BY CASE w.urgency WHEN 'urgent' THEN 0 WHEN 'high' THEN 1 ELSE 2 END, w.created_at ASC"
+    )
+    .bind(include_closed.unwrap_or(false) as i64)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| WaitlistEntry {
+            id: row.get("id"),
+            patient_id: row.get("patient_id"),
+            patient_name: row.get("full_name"),
+            procedure: row.get("procedure"),
+            provider_id: row.get("provider_id"),
+            preferred_weekdays: row
+                .get::<String, _>("preferred_weekdays")
+                .split(',')
+                .filter_map(|d| d.trim().parse().ok())
+                .collect(),
+            preferred_start_time: row.get("preferred_start_time"),
+            preferred_end_time: row.get("preferred_end_time"),
+            urgency: row.get("urgency"),
+            status: row.get("status"),
+            notes: row.get("notes"),
+            last_offered_at: row.get("last_offered_at"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn save_waitlist_entry(
+    db_pool: State<'_, DbPool>,
+    entry: WaitlistEntry,
+) -> Result<i64, String> {
+    let procedure = entry.procedure.trim();
+    if procedure.is_empty() {
+        return Err("Procedure is required".to_string());
+    }
+    let urgency = entry.urgency.as_deref().unwrap_or("normal");
+    if !matches!(urgency, "normal" | "high" | "urgent") {
+        return Err(format!("Invalid urgency: {}", urgency));
+    }
+    let status = entry.status.as_deref().unwrap_or("waiting");
+    if !matches!(status, "waiting" | "offered" | "booked" | "removed") {
+        return Err(format!("Invalid status: {}", status));
+    }
+    if let Some(day) = entry.preferred_weekdays.iter().find(|d| !(0..=6).contains(*d)) {
+        return Err(format!("Invalid weekday: {}", day));
+    }
+    for time in [&entry.preferred_start_time, &entry.preferred_end_time].into_iter().flatten() {
+        chrono::NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("Invalid time: {}", time))?;
+    }
+
+    let mut weekdays = entry.preferred_weekdays.clone();
+    weekdays.sort_unstable();
+    weekdays.dedup();
+    let weekdays = weekdays.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(",");
+    let provider_id = entry.provider_id.filter(|&i| i > 0);
+
+    let pool = db_pool.0.lock().await;
+
+    if let Some(id) = entry.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE waitlist_entries
+             SET patient_id = ?1, procedure = ?2, provider_id = ?3, preferred_weekdays = ?4,
+                 preferred_start_time = ?5, preferred_end_time = ?6, urgency = ?7, status = ?8, notes = ?9
+             WHERE id = ?10"
+        )
+        .bind(entry.patient_id)
+        .bind(procedure)
+        .bind(provider_id)
+        .bind(&weekdays)
+        .bind(&entry.preferred_start_time)
+        .bind(&entry.preferred_end_time)
+        .bind(urgency)
+        .bind(status)
+        .bind(&entry.notes)
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update waitlist entry: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Waitlist entry {} not found", id));
+        }
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO waitlist_entries (patient_id, procedure, provider_id, preferred_weekdays,
+                                           preferred_start_time, preferred_end_time, urgency, status, notes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+        )
+        .bind(entry.patient_id)
+        .bind(procedure)
+        .bind(provider_id)
+        .bind(&weekdays)
+        .bind(&entry.preferred_start_time)
+        .bind(&entry.preferred_end_time)
+        .bind(urgency)
+        .bind(status)
+        .bind(&entry.notes)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create waitlist entry: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+/// Soft delete: se conserva el historial de ofertas
+#[tauri::command]
+pub async fn delete_waitlist_entry(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("UPDATE waitlist_entries SET status = 'removed' WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Ofrece manualmente el turno de una cita a la lista de espera (con `dry_run` solo muestra el ranking)
+#[tauri::command]
+pub async fn offer_slot_to_waitlist(
+    db_pool: State<'_, DbPool>,
+    appointment_id: i64,
+    limit: Option<i64>,
+    dry_run: Option<bool>,
+) -> Result<Vec<WaitlistOffer>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let offers = offer_freed_slot(&mut tx, appointment_id, limit, !dry_run.unwrap_or(false)).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(offers)
+}
+
+// ============================================================================
+// APPOINTMENT STATUS MODULE (transiciones, historial e inasistencias)
+// ============================================================================
+
+const APPOINTMENT_STATUSES: &[&str] = &["scheduled", "confirmed", "cancelled", "no_show", "completed"];
+
+/// Horas de anticipación bajo las cuales una cancelación cuenta como tardía (setting 'lateCancellationHours')
+const DEFAULT_LATE_CANCELLATION_HOURS: i64 = 24;
+/// Inasistencias en la ventana que marcan a un paciente como habitual (setting 'habitualNoShowCount')
+const DEFAULT_HABITUAL_NO_SHOWS: i64 = 2;
+const ATTENDANCE_WINDOW_DAYS: i64 = 365;
+
+/// Motivo y autor de un cambio de estado (ambos opcionales)
+#[derive(Debug, Clone, Copy, Default)]
+struct StatusChange<'a> {
+    reason: Option<&'a str>,
+    changed_by: Option<&'a str>,
+}
+
+/// Estados a los que puede pasar una cita. cancelled y completed son finales;
+/// no_show solo se corrige a completed (el paciente llegó tarde y fue atendido).
+fn allowed_status_transitions(from: &str) -> &'static [&'static str] {
+    match from {
+        "scheduled" => &["confirmed", "cancelled", "no_show", "completed"],
+        "confirmed" => &["scheduled", "cancelled", "no_show", "completed"],
+        "no_show" => &["completed"],
+        _ => &[],
+    }
+}
+
+fn ensure_status_transition(from: &str, to: &str) -> Result<(), String> {
+    if from == to || allowed_status_transitions(from).contains(&to) {
+        return Ok(());
+    }
+    Err(format!("Invalid status change: {} → {}", from, to))
+}
+
+async fn record_status_change(
+    conn: &mut sqlx::SqliteConnection,
+    appointment_id: i64,
+    from_status: Option<&str>,
+    to_status: &str,
+    reason: Option<&str>,
+    changed_by: Option<&str>,
+) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO appointment_status_history (appointment_id, from_status, to_status, reason, changed_by)
+         VALUES (?1, ?2, ?3, ?4, ?5)"
+    )
+    .bind(appointment_id)
+    .bind(from_status)
+    .bind(to_status)
+    .bind(reason.map(str::trim).filter(|r| !r.is_empty()))
+    .bind(changed_by.map(str::trim).filter(|c| !c.is_empty()))
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to record status change: {}", e))?;
+
+    Ok(())
+}
+
+/// Cambia solo el estado de una cita (sin tocar horario ni recursos) y lo registra en el historial
+async fn change_appointment_status(
+    conn: &mut sqlx::SqliteConnection,
+    appointment_id: i64,
+    from_status: &str,
+    to_status: &str,
+    change: StatusChange<'_>,
+) -> Result<(), String> {
+    ensure_status_transition(from_status, to_status)?;
+    if from_status == to_status {
+        return Ok(());
+    }
+
+    sqlx::query(
+        "UPDATE appointments
+         SET status = ?1,
+             confirmed_at = CASE WHEN ?1 = 'confirmed' THEN COALESCE(confirmed_at, datetime('now')) ELSE confirmed_at END
+         WHERE id = ?2"
+    )
+    .bind(to_status)
+    .bind(appointment_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to update appointment status: {}", e))?;
+
+    record_status_change(conn, appointment_id, Some(from_status), to_status, change.reason, change.changed_by).await
+}
+
+#[derive(Debug, Serialize)]
+pub struct AppointmentStatusEvent {
+    pub id: i64,
+    pub appointment_id: i64,
+    pub from_status: Option<String>,     // None = creación de la cita
+    pub to_status: String,
+    pub reason: Option<String>,
+    pub changed_by: Option<String>,
+    pub changed_at: String,              // UTC canónico
+}
+
+#[derive(Debug, Serialize)]
+pub struct PatientAttendance {
+    pub patient_id: i64,
+    pub patient_name: String,
+    pub total: i64,                      // Citas resueltas: completadas + inasistencias + canceladas
+    pub completed: i64,
+    pub no_shows: i64,
+    pub cancellations: i64,
+    pub late_cancellations: i64,         // Canceladas con menos de N horas de anticipación
+    pub no_show_rate: f64,               // 0–1 sobre el total
+    pub late_cancellation_rate: f64,
+    pub habitual_no_show: bool,
+}
+
+/// Cambia el estado de una cita validando la transición (p. ej. cancelar con motivo,
+/// marcar inasistencia). Si se libera un turno activo, se ofrece a la lista de espera.
+#[tauri::command]
+pub async fn set_appointment_status(
+    db_pool: State<'_, DbPool>,
+    appointment_id: i64,
+    status: String,
+    reason: Option<String>,
+    changed_by: Option<String>,
+) -> Result<(), String> {
+    if !APPOINTMENT_STATUSES.contains(&status.as_str()) {
+        return Err(format!("Invalid status: {}", status));
+    }
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let previous_status: String = sqlx::query_scalar("SELECT status FROM appointments WHERE id = ?1")
+        .bind(appointment_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Appointment {} not found", appointment_id))?;
+
+    let change = StatusChange { reason: reason.as_deref(), changed_by: changed_by.as_deref() };
+    change_appointment_status(&mut tx, appointment_id, &previous_status, &status, change).await?;
+
+    if matches!(previous_status.as_str(), "scheduled" | "confirmed") && status == "cancelled" {
+        offer_freed_slot(&mut tx, appointment_id, None, true).await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_appointment_status_history(
+    db_pool: State<'_, DbPool>,
+    appointment_id: i64,
+) -> Result<Vec<AppointmentStatusEvent>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT id, appointment_id, from_status, to_status, reason, changed_by, changed_at
+         FROM appointment_status_history
+         WHERE appointment_id = ?1
+         ORDER BY changed_at ASC, id ASC"
+    )
+    .bind(appointment_id)
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| format!("Failed to fetch status history: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| AppointmentStatusEvent {
+            id: row.get("id"),
+            appointment_id: row.get("appointment_id"),
+            from_status: row.get("from_status"),
+            to_status: row.get("to_status"),
+            reason: row.get("reason"),
+            changed_by: row.get("changed_by"),
+            changed_at: row.get("changed_at"),
+        })
+        .collect())
+}
+
+/// Asistencia por paciente para citas con inicio en [from, to) (UTC canónico; `to` None = sin límite).
+/// Una cancelación es tardía si se registró con menos de 'lateCancellationHours' de anticipación.
+async fn load_patient_attendance(
+    conn: &mut sqlx::SqliteConnection,
+    from: &str,
+    to: Option<&str>,
+    patient_id: Option<i64>,
+) -> Result<Vec<PatientAttendance>, String> {
+    let late_hours = load_limit_setting(conn, "lateCancellationHours")
+        .await?
+        .unwrap_or(DEFAULT_LATE_CANCELLATION_HOURS);
+    let habitual_threshold = load_limit_setting(conn, "habitualNoShowCount")
+        .await?
+        .unwrap_or(DEFAULT_HABITUAL_NO_SHOWS);
+
+    let rows = sqlx::query(
+        "SELECT a.patient_id, p.full_name,
+                SUM(a.status = 'completed') AS completed,
+                SUM(a.status = 'no_show') AS no_shows,
+                SUM(a.status = 'cancelled') AS cancellations,
+                SUM(a.status = 'cancelled' AND EXISTS (
+                      SELECT 1 FROM appointment_status_history h
+                      WHERE h.appointment_id = a.id
+                        AND h.to_status = 'cancelled'
+                        AND h.from_status IS NOT NULL
+                        AND (julianday(a.starts_at) - julianday(h.changed_at)) * 24 < ?4
+                    )) AS late_cancellations
+         FROM appointments a
+         JOIN patients p ON p.id = a.patient_id
+         WHERE a.status IN ('completed', 'no_show', 'cancelled')
+           AND a.starts_at >= ?1
+           AND (?2 IS NULL OR a.starts_at < ?2)
+           AND (?3 IS NULL OR a.patient_id = ?3)
+         GROUP BY a.patient_id, p.full_name"
+    )
+    .bind(from)
+    .bind(to)
+    .bind(patient_id)
+    .bind(late_hours)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to compute attendance: {}", e))?;
+
+    let rate = |count: i64, total: i64| {
+        if total > 0 {
+            (count as f64 / total as f64 * 100.0).round() / 100.0
+        } else {
+            0.0
+        }
+    };
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let completed: i64 = row.get("completed");
+            let no_shows: i64 = row.get("no_shows");
+            let cancellations: i64 = row.get("cancellations");
+            let late_cancellations: i64 = row.get("late_cancellations");
+            let total = completed + no_shows + cancellations;
+
+            PatientAttendance {
+                patient_id: row.get("patient_id"),
+                patient_name: row.get("full_name"),
+                total,
+                completed,
+                no_shows,
+                cancellations,
+                late_cancellations,
+                no_show_rate: rate(no_shows, total),
+                late_cancellation_rate: rate(late_cancellations, total),
+                habitual_no_show: no_shows >= habitual_threshold,
+            }
+        })
+        .collect())
+}
+
+/// Reporte de inasistencias y cancelaciones tardías por paciente (fechas locales, ambas inclusive)
+#[tauri::command]
+pub async fn get_attendance_report(
+    db_pool: State<'_, DbPool>,
+    start_date: String,
+    end_date: String,
+    only_habitual: Option<bool>,
+) -> Result<Vec<PatientAttendance>, String> {
+    let parse_date = |value: &str| {
+        chrono::NaiveDate::parse_from_str(value.get(..10).unwrap_or(value), "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date: '{}'", value))
+    };
+    let start = parse_date(&start_date)?;
+    let end = parse_date(&end_date)?;
+
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let tz = load_clinic_timezone(&mut conn).await?;
+
+    let from = format_canonical_utc(tz.day_start_utc(start));
+    let to = format_canonical_utc(tz.day_start_utc(end + chrono::Duration::days(1)));
+
+    let mut report = load_patient_attendance(&mut conn, &from, Some(&to), None).await?;
+    if only_habitual.unwrap_or(false) {
+        report.retain(|p| p.habitual_no_show);
+    }
+
+    report.sort_by(|a, b| {
+        b.no_show_rate
+            .partial_cmp(&a.no_show_rate)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.no_shows.cmp(&a.no_shows))
+            .then(b.late_cancellations.cmp(&a.late_cancellations))
+    });
+    Ok(report)
+}
+
+/// Asistencia del último año de un paciente, para advertir al agendar si es inasistente habitual
+#[tauri::command]
+pub async fn get_patient_attendance(
+    db_pool: State<'_, DbPool>,
+    patient_id: i64,
+) -> Result<PatientAttendance, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let from = format_canonical_utc(chrono::Utc::now() - chrono::Duration::days(ATTENDANCE_WINDOW_DAYS));
+    let attendance = load_patient_attendance(&mut conn, &from, None, Some(patient_id)).await?;
+
+    if let Some(attendance) = attendance.into_iter().next() {
+        return Ok(attendance);
+    }
+
+    let patient_name: String = sqlx::query_scalar("SELECT full_name FROM patients WHERE id = ?1")
+        .bind(patient_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Patient {} not found", patient_id))?;
+
+    Ok(PatientAttendance {
+        patient_id,
+        patient_name,
+        total: 0,
+        completed: 0,
+        no_shows: 0,
+        cancellations: 0,
+        late_cancellations: 0,
+        no_show_rate: 0.0,
+        late_cancellation_rate: 0.0,
+        habitual_no_show: false,
+    })
+}
+
+// ============================================================================
+// APPOINTMENT RECONCILIATION MODULE (sesiones ↔ citas)
+// ============================================================================
+
+/// Días hacia atrás que revisa la conciliación diaria por defecto
+const DEFAULT_RECONCILIATION_DAYS: i64 = 7;
+
+#[derive(Debug, Serialize)]
+pub struct UnattendedAppointment {
+    pub appointment_id: i64,
+    pub patient_id: i64,
+    pub patient_name: String,
+    pub phone: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub procedure: String,
+    pub status: String,                       // 'scheduled' | 'confirmed'
+    pub provider_id: Option<i64>,
+    pub unlinked_session_id: Option<i64>,     // Sesión del mismo día sin cita: probablemente se atendió
+}
+
+/// Valida que la cita pueda vincularse a la sesión (mismo paciente, sin otra sesión, no cancelada)
+/// y la marca como completada.
+async fn attend_appointment(
+    conn: &mut sqlx::SqliteConnection,
+    appointment_id: i64,
+    patient_id: i64,
+    session_id: Option<i64>,
+) -> Result<(), String> {
+    let (appointment_patient_id, status): (i64, String) = sqlx::query_as(
+        "SELECT patient_id, status FROM appointments WHERE id = ?1"
+    )
+    .bind(appointment_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Appointment {} not found", appointment_id))?;
+
+    if appointment_patient_id != patient_id {
+        return Err(format!("Appointment {} does not belong to the patient", appointment_id));
+    }
+    if status == "cancelled" {
+        return Err(format!("Appointment {} was cancelled", appointment_id));
+    }
+
+    let linked_session: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM sessions WHERE appointment_id = ?1 AND (?2 IS NULL OR id != ?2) LIMIT 1"
+    )
+    .bind(appointment_id)
+    .bind(session_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    if let Some(linked_session) = linked_session {
+        return Err(format!("Appointment {} is already linked to session {}", appointment_id, linked_session));
+    }
+
+    let change = StatusChange { reason: Some("Sesión registrada"), changed_by: None };
+    change_appointment_status(conn, appointment_id, &status, "completed", change).await
+}
+
+/// Conciliación diaria: citas ya terminadas que siguen agendadas/confirmadas y no tienen sesión.
+/// El personal las vincula a la sesión correspondiente o las marca como inasistencia.
+#[tauri::command]
+pub async fn list_unattended_appointments(
+    db_pool: State<'_, DbPool>,
+    days_back: Option<i64>,
+) -> Result<Vec<UnattendedAppointment>, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let tz = load_clinic_timezone(&mut conn).await?;
+
+    let now = chrono::Utc::now();
+    let today = tz.to_local(now).date();
+    let days_back = days_back.filter(|&d| d > 0).unwrap_or(DEFAULT_RECONCILIATION_DAYS);
+    let from = format_canonical_utc(tz.day_start_utc(today - chrono::Duration::days(days_back)));
+
+    let rows = sqlx::query(
+        "SELECT a.id, a.patient_id, p.full_name, p.phone, a.starts_at, a.ends_at, a.procedure, a.status, a.provider_id
+         FROM appointments a
+         JOIN patients p ON p.id = a.patient_id
+         WHERE a.status IN ('scheduled', 'confirmed')
+           AND a.starts_at >= ?1
+           AND a.ends_at <= ?2
+           AND NOT EXISTS (SELECT 1 FROM sessions s WHERE s.appointment_id = a.id)
+         ORDER BY a.starts_at ASC"
+    )
+    .bind(&from)
+    .bind(format_canonical_utc(now))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to list unattended appointments: {}", e))?;
+
+    let mut unattended = Vec::with_capacity(rows.len());
+    for row in rows {
+        let patient_id: i64 = row.get("patient_id");
+        let starts_at: String = row.get("starts_at");
+
+        // Las sesiones guardan la fecha local de la clínica
+        let local_date = appointment_local_time(&starts_at, tz).map(|t| t.format("%Y-%m-%d").to_string());
+        let unlinked_session_id: Option<i64> = match local_date {
+            Some(date) => sqlx::query_scalar(
+                "SELECT id FROM sessions
+                 WHERE patient_id = ?1 AND substr(date, 1, 10) = ?2 AND is_saved = 1 AND appointment_id IS NULL
+                 ORDER BY id DESC
+                 LIMIT 1"
+            )
+            .bind(patient_id)
+            .bind(date)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?,
+            None => None,
+        };
+
+        unattended.push(UnattendedAppointment {
+            appointment_id: row.get("id"),
+            patient_id,
+            patient_name: row.get("full_name"),
+            phone: row.get("phone"),
+            starts_at,
+            ends_at: row.get("ends_at"),
+            procedure: row.get("procedure"),
+            status: row.get("status"),
+            provider_id: row.get("provider_id"),
+            unlinked_session_id,
+        });
+    }
+
+    Ok(unattended)
+}
+
+/// Vincula una sesión ya guardada con su cita (p. ej. desde la conciliación) y completa la cita.
+/// Es un dato administrativo: se permite también en sesiones firmadas.
+#[tauri::command]
+pub async fn link_session_to_appointment(
+    db_pool: State<'_, DbPool>,
+    session_id: i64,
+    appointment_id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let (patient_id, current): (i64, Option<i64>) = sqlx::query_as(
+        "SELECT patient_id, appointment_id FROM sessions WHERE id = ?1"
+    )
+    .bind(session_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Session {} not found", session_id))?;
+
+    if current.is_some_and(|id| id != appointment_id) {
+        return Err(format!("Session {} is already linked to another appointment", session_id));
+    }
+
+    attend_appointment(&mut tx, appointment_id, patient_id, Some(session_id)).await?;
+
+    sqlx::query("UPDATE sessions SET appointment_id = ?1 WHERE id = ?2")
+        .bind(appointment_id)
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to link session: {}", e))?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// ============================================================================
+// ICALENDAR MODULE (exportar / importar agenda en .ics, RFC 5545)
+// ============================================================================
+
+const ICS_PRODID: &str = "-//Oklus//Agenda//ES";
+const ICS_DATETIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Debug, Serialize)]
+pub struct IcsFile {
+    pub filename: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IcsImportEvent {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub starts_at: Option<String>,       // UTC canónico
+    pub ends_at: Option<String>,
+    pub patient_id: Option<i64>,
+    pub outcome: String,                 // 'imported' | 'duplicate' | 'conflict' | 'skipped' | 'invalid'
+    pub appointment_id: Option<i64>,     // Cita creada o duplicada
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct IcsImportResult {
+    pub imported: i64,
+    pub duplicates: i64,
+    pub conflicts: i64,
+    pub skipped: i64,
+    pub invalid: i64,
+    pub events: Vec<IcsImportEvent>,
+}
+
+/// Evento a escribir en un VCALENDAR
+struct IcsEvent {
+    uid: String,
+    summary: String,
+    description: Option<String>,
+    location: Option<String>,
+    starts_at: chrono::DateTime<chrono::Utc>,
+    ends_at: chrono::DateTime<chrono::Utc>,
+    status: &'static str,
+    last_modified: Option<chrono::DateTime<chrono::Utc>>,
+    alarm_before: Option<&'static str>,  // Duración ISO, p. ej. "-P1D"
+}
+
+/// Evento leído de un .ics (solo las propiedades que usamos)
+#[derive(Debug, Default)]
+struct ParsedIcsEvent {
+    uid: Option<String>,
+    summary: String,
+    description: Option<String>,
+    status: Option<String>,
+    dtstart: Option<(String, Option<String>, bool)>, // (valor, TZID, VALUE=DATE)
+    dtend: Option<(String, Option<String>, bool)>,
+    duration: Option<String>,
+    attendees: Vec<String>,                          // CN de cada ATTENDEE
+    recurring: bool,
+}
+
+fn appointment_ics_uid(appointment_id: i64) -> String {
+    format!("appointment-{}@oklus", appointment_id)
+}
+
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn ics_unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Corta líneas a 75 octetos (sin partir caracteres UTF-8); la continuación empieza con un espacio
+fn ics_fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+fn render_ics_calendar(calendar_name: &str, timezone: Option<&str>, events: &[IcsEvent]) -> String {
+    let stamp = chrono::Utc::now().format(ICS_DATETIME_FORMAT).to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", ICS_PRODID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", ics_escape(calendar_name)),
+    ];
+    if let Some(timezone) = timezone {
+        lines.push(format!("X-WR-TIMEZONE:{}", timezone));
+    }
+
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART:{}", event.starts_at.format(ICS_DATETIME_FORMAT)));
+        lines.push(format!("DTEND:{}", event.ends_at.format(ICS_DATETIME_FORMAT)));
+        lines.push(format!("SUMMARY:{}", ics_escape(&event.summary)));
+        if let Some(description) = event.description.as_deref().filter(|d| !d.is_empty()) {
+            lines.push(format!("DESCRIPTION:{}", ics_escape(description)));
+        }
+        if let Some(location) = event.location.as_deref().filter(|l| !l.is_empty()) {
+            lines.push(format!("LOCATION:{}", ics_escape(location)));
+        }
+        lines.push(format!("STATUS:{}", event.status));
+        if let Some(modified) = event.last_modified {
+            lines.push(format!("LAST-MODIFIED:{}", modified.format(ICS_DATETIME_FORMAT)));
+        }
+        if let Some(trigger) = event.alarm_before {
+            lines.push("BEGIN:VALARM".to_string());
+            lines.push("ACTION:DISPLAY".to_string());
+            lines.push(format!("DESCRIPTION:{}", ics_escape(&event.summary)));
+            lines.push(format!("TRIGGER:{}", trigger));
+            lines.push("END:VALARM".to_string());
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut content = lines.iter().map(|line| ics_fold(line)).collect::<Vec<_>>().join("\r\n");
+    content.push_str("\r\n");
+    content
+}
+
+fn ics_status(appointment_status: &str) -> &'static str {
+    match appointment_status {
+        "confirmed" | "completed" => "CONFIRMED",
+        "cancelled" | "no_show" => "CANCELLED",
+        _ => "TENTATIVE",
+    }
+}
+
+/// Deshace el plegado de líneas (CRLF o LF seguido de espacio/tab)
+fn ics_unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in content.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ if raw.is_empty() => {}
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// Línea de contenido: "DTSTART;TZID=America/New_York:20250115T100000"
+struct IcsProperty {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl IcsProperty {
+    fn param(&self, key: &str) -> Option<String> {
+        self.params.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+    }
+
+    /// (valor, TZID, VALUE=DATE) de DTSTART / DTEND
+    fn date_value(&self) -> (String, Option<String>, bool) {
+        let is_date = self.param("VALUE").is_some_and(|v| v.eq_ignore_ascii_case("DATE"));
+        (self.value.trim().to_string(), self.param("TZID"), is_date)
+    }
+}
+
+fn split_ics_property(line: &str) -> Option<IcsProperty> {
+    let mut in_quotes = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            in_quotes = !in_quotes;
+            None
+        }
+        ':' if !in_quotes => Some(i),
+        _ => None,
+    })?;
+
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_uppercase();
+    let params = parts
+        .filter_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            Some((key.trim().to_uppercase(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect();
+
+    Some(IcsProperty { name, params, value: value.to_string() })
+}
+
+fn parse_ics_events(content: &str) -> Vec<ParsedIcsEvent> {
+    let mut events = Vec::new();
+    let mut current: Option<ParsedIcsEvent> = None;
+    let mut nested = 0; // VALARM u otros componentes dentro del evento
+
+    for line in ics_unfold(content) {
+        let Some(property) = split_ics_property(&line) else {
+            continue;
+        };
+        let value = &property.value;
+
+        match (property.name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => current = Some(ParsedIcsEvent::default()),
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => events.extend(current.take()),
+            (_, Some(_)) if nested > 0 => {}
+            ("UID", Some(event)) => event.uid = Some(value.trim().to_string()).filter(|u| !u.is_empty()),
+            ("SUMMARY", Some(event)) => event.summary = ics_unescape(value).trim().to_string(),
+            ("DESCRIPTION", Some(event)) => event.description = Some(ics_unescape(value)).filter(|d| !d.trim().is_empty()),
+            ("STATUS", Some(event)) => event.status = Some(value.trim().to_uppercase()),
+            ("DTSTART", Some(event)) => event.dtstart = Some(property.date_value()),
+            ("DTEND", Some(event)) => event.dtend = Some(property.date_value()),
+            ("DURATION", Some(event)) => event.duration = Some(value.trim().to_string()),
+            ("ATTENDEE", Some(event)) => event.attendees.extend(property.param("CN").filter(|cn| !cn.trim().is_empty())),
+            ("RRULE", Some(event)) | ("RDATE", Some(event)) => event.recurring = true,
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Fecha/hora de un .ics: UTC ("...Z"), con TZID, o flotante (= hora local de la clínica).
+/// Un TZID desconocido (p. ej. nombres de Windows) se interpreta como la zona de la clínica.
+fn parse_ics_datetime(
+    (value, tzid, is_date): &(String, Option<String>, bool),
+    clinic_tz: ClinicTimezone,
+) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    if *is_date || value.len() == 8 {
+        return Err("All-day events are not appointments".to_string());
+    }
+    if let Some(utc) = value.strip_suffix(['Z', 'z']) {
+        return chrono::NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .map(|naive| naive.and_utc())
+            .map_err(|_| format!("Invalid date-time: '{}'", value));
+    }
+
+    let local = chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .map_err(|_| format!("Invalid date-time: '{}'", value))?;
+    let tz = tzid
+        .as_deref()
+        .and_then(|name| ClinicTimezone::parse(name).ok())
+        .unwrap_or(clinic_tz);
+    tz.to_utc(local)
+}
+
+/// DURATION de RFC 5545: [+]P[nW][nD][T[nH][nM][nS]]
+fn parse_ics_duration(value: &str) -> Option<chrono::Duration> {
+    let value = value.trim().trim_start_matches('+');
+    let body = value.strip_prefix('P')?;
+    let mut total = chrono::Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+
+    for c in body.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => in_time = true,
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                // try_*: un DURATION fuera de rango (P99999999999999W) es inválido, no un panic
+                let part = match (unit, in_time) {
+                    ('W', false) => chrono::Duration::try_weeks(n),
+                    ('D', false) => chrono::Duration::try_days(n),
+                    ('H', true) => chrono::Duration::try_hours(n),
+                    ('M', true) => chrono::Duration::try_minutes(n),
+                    ('S', true) => chrono::Duration::try_seconds(n),
+                    _ => None,
+                }?;
+                total = total.checked_add(&part)?;
+            }
+        }
+    }
+
+    (number.is_empty() && total > chrono::Duration::zero()).then_some(total)
+}
+
+/// Busca el paciente del evento: nombre exacto de un ATTENDEE o nombre contenido en el SUMMARY
+/// (el más largo gana, para no confundir "Ana Paz" con "Ana Paz Ruiz").
+fn match_ics_patient<'a>(event: &ParsedIcsEvent, patients: &'a [(i64, String)]) -> Option<&'a (i64, String)> {
+    let by_attendee = event.attendees.iter().find_map(|cn| {
+        patients
+            .iter()
+            .find(|(_, name)| name.trim().eq_ignore_ascii_case(cn.trim()))
+    });
+    if by_attendee.is_some() {
+        return by_attendee;
+    }
+
+    let summary = event.summary.to_lowercase();
+    patients
+        .iter()
+        .filter(|(_, name)| name.trim().chars().count() >= 3 && summary.contains(&name.trim().to_lowercase()))
+        .max_by_key(|(_, name)| name.trim().len())
+}
+
+/// Procedimiento a partir del SUMMARY sin el nombre del paciente ("Limpieza - Ana Paz" → "Limpieza")
+/// Rango en bytes de `haystack` donde aparece `needle` sin distinguir mayúsculas.
+/// Compara carácter a carácter sobre el texto original: to_lowercase puede cambiar
+/// la longitud en bytes ('İ'), así que no sirven los índices de una copia en minúsculas.
+fn find_case_insensitive(haystack: &str, needle: &str) -> Option<std::ops::Range<usize>> {
+    let needle: Vec<char> = needle.chars().flat_map(char::to_lowercase).collect();
+    if needle.is_empty() {
+        return None;
+    }
+
+    haystack.char_indices().find_map(|(start, _)| {
+        let mut expected = needle.iter();
+        for (offset, c) in haystack[start..].char_indices() {
+            if !c.to_lowercase().all(|lower| expected.next() == Some(&lower)) {
+                return None;
+            }
+            if expected.len() == 0 {
+                return Some(start..start + offset + c.len_utf8());
+            }
+        }
+        None
+    })
+}
+
+fn ics_procedure(summary: &str, patient_name: Option<&str>) -> String {
+    let mut procedure = summary.to_string();
+    if let Some(name) = patient_name.map(str::trim).filter(|n| !n.is_empty()) {
+        if let Some(range) = find_case_insensitive(&procedure, name) {
+            procedure.replace_range(range, "");
+        }
+    }
+    let procedure = procedure.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—' | ':' | '|' | ',' | '/'));
+    if procedure.is_empty() {
+        "Cita".to_string()
+    } else {
+        procedure.to_string()
+    }
+}
+
+/// Nombre de la clínica y dirección (perfil del doctor), para títulos y LOCATION
+async fn load_clinic_identity(conn: &mut sqlx::SqliteConnection) -> Result<(String, Option<String>), String> {
+    let profile: Option<(String, Option<String>)> = sqlx::query_as(
+        "SELECT clinic_name, location FROM doctor_profile ORDER BY id LIMIT 1"
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(match profile {
+        Some((name, location)) => (
+            Some(name).filter(|n| !n.trim().is_empty()).unwrap_or_else(|| "Oklus".to_string()),
+            location.filter(|l| !l.trim().is_empty()),
+        ),
+        None => ("Oklus".to_string(), None),
+    })
+}
+
+/// Iniciales de un nombre ("Juan Pérez" → "J.P.")
+fn name_initials(name: &str) -> String {
+    name.split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .flat_map(|c| c.to_uppercase().chain(std::iter::once('.')))
+        .collect()
+}
+
+/// VCALENDAR de la agenda en [range_start, range_end) (UTC canónico), sin citas canceladas.
+/// Con `patient_details` incluye paciente, teléfono y notas (exportación del profesional);
+/// sin ellos cada evento lleva solo hora, estado, procedimiento e iniciales (feed por HTTP).
+async fn build_agenda_ics(
+    conn: &mut sqlx::SqliteConnection,
+    tz: ClinicTimezone,
+    range_start: &str,
+    range_end: &str,
+    provider_id: Option<i64>,
+    patient_details: bool,
+) -> Result<String, String> {
+    let appointments = query_appointments(conn, range_start, range_end, provider_id, None).await?;
+    let (clinic_name, clinic_location) = load_clinic_identity(conn).await?;
+
+    let providers: HashMap<i64, String> = sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM providers")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    let resources: HashMap<i64, String> = sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM resources")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    let mut patients: HashMap<i64, (String, String)> = HashMap::new();
+
+    let mut events = Vec::with_capacity(appointments.len());
+    for appointment in appointments.into_iter().filter(|a| a.status != "cancelled") {
+        let (Some(id), Ok(starts_at), Ok(ends_at)) = (
+            appointment.id,
+            parse_appointment_instant(&appointment.starts_at, tz),
+            parse_appointment_instant(&appointment.ends_at, tz),
+        ) else {
+            continue;
+        };
+
+        if let std::collections::hash_map::Entry::Vacant(entry) = patients.entry(appointment.patient_id) {
+            let patient: (String, String) = sqlx::query_as("SELECT full_name, phone FROM patients WHERE id = ?1")
+                .bind(appointment.patient_id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?
+                .unwrap_or_default();
+            entry.insert(patient);
+        }
+        let (patient_name, phone) = &patients[&appointment.patient_id];
+        let last_modified = appointment
+            .updated_at
+            .as_deref()
+            .and_then(|u| chrono::NaiveDateTime::parse_from_str(u, "%Y-%m-%d %H:%M:%S").ok())
+            .map(|naive| naive.and_utc());
+
+        if !patient_details {
+            let initials = name_initials(patient_name);
+            events.push(IcsEvent {
+                uid: appointment_ics_uid(id),
+                summary: if initials.is_empty() {
+                    appointment.procedure.clone()
+                } else {
+                    format!("{} ({})", appointment.procedure, initials)
+                },
+                description: None,
+                location: None,
+                starts_at,
+                ends_at,
+                status: ics_status(&appointment.status),
+                last_modified,
+                alarm_before: None,
+            });
+            continue;
+        }
+
+        let mut description = vec![format!("Paciente: {}", patient_name)];
+        if !phone.trim().is_empty() {
+            description.push(format!("Teléfono: {}", phone.trim()));
+        }
+        if let Some(provider_name) = appointment.provider_id.and_then(|pid| providers.get(&pid)) {
+            description.push(format!("Profesional: {}", provider_name));
+        }
+        if let Some(notes) = appointment.notes.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+            description.push(format!("Notas: {}", notes));
+        }
+
+        let resource_names: Vec<&str> = appointment
+            .resource_ids
+            .iter()
+            .flatten()
+            .filter_map(|rid| resources.get(rid).map(String::as_str))
+            .collect();
+
+        events.push(IcsEvent {
+            uid: appointment_ics_uid(id),
+            summary: format!("{} - {}", appointment.procedure, patient_name),
+            description: Some(description.join("\n")),
+            location: Some(resource_names.join(", ")).filter(|l| !l.is_empty()).or_else(|| clinic_location.clone()),
+            starts_at,
+            ends_at,
+            status: ics_status(&appointment.status),
+            last_modified,
+            alarm_before: None,
+        });
+    }
+
+    let timezone = match tz {
+        ClinicTimezone::Named(_) => Some(tz.name()),
+        ClinicTimezone::System => None,
+    };
+    Ok(render_ics_calendar(&format!("Agenda {}", clinic_name), timezone.as_deref(), &events))
+}
+
+/// Agenda en formato .ics para importar en el calendario del teléfono
+#[tauri::command]
+pub async fn export_appointments_ics(
+    db_pool: State<'_, DbPool>,
+    range_start: String,
+    range_end: String,
+    provider_id: Option<i64>,
+) -> Result<String, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let tz = load_clinic_timezone(&mut conn).await?;
+    let range_start = canonical_appointment_time(&range_start, tz)?;
+    let range_end = canonical_appointment_time(&range_end, tz)?;
+
+    build_agenda_ics(&mut conn, tz, &range_start, &range_end, provider_id.filter(|&i| i > 0), true).await
+}
+
+/// .ics de una cita para adjuntar al mensaje del paciente: sin notas ni datos de otros pacientes,
+/// con aviso un día antes.
+#[tauri::command]
+pub async fn get_appointment_ics(
+    db_pool: State<'_, DbPool>,
+    appointment_id: i64,
+) -> Result<IcsFile, String> {
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let row = sqlx::query(
+        "SELECT a.starts_at, a.ends_at, a.procedure, a.status, a.updated_at, pr.name AS provider_name
+         FROM appointments a
+         LEFT JOIN providers pr ON pr.id = a.provider_id
+         WHERE a.id = ?1"
+    )
+    .bind(appointment_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Appointment {} not found", appointment_id))?;
+
+    let tz = load_clinic_timezone(&mut conn).await?;
+    let (clinic_name, clinic_location) = load_clinic_identity(&mut conn).await?;
+
+    let starts_at = parse_appointment_instant(&row.get::<String, _>("starts_at"), tz)?;
+    let ends_at = parse_appointment_instant(&row.get::<String, _>("ends_at"), tz)?;
+    let procedure: String = row.get("procedure");
+    let provider_name: Option<String> = row.get("provider_name");
+    let updated_at: Option<String> = row.get("updated_at");
+
+    let event = IcsEvent {
+        uid: appointment_ics_uid(appointment_id),
+        summary: format!("{} - {}", procedure, clinic_name),
+        description: provider_name.map(|name| format!("Profesional: {}", name)),
+        location: clinic_location,
+        starts_at,
+        ends_at,
+        status: ics_status(&row.get::<String, _>("status")),
+        last_modified: updated_at
+            .and_then(|u| chrono::NaiveDateTime::parse_from_str(&u, "%Y-%m-%d %H:%M:%S").ok())
+            .map(|naive| naive.and_utc()),
+        alarm_before: Some("-P1D"),
+    };
+
+    Ok(IcsFile {
+        filename: format!("cita-{}.ics", tz.to_local(starts_at).format("%Y%m%d-%H%M")),
+        content: render_ics_calendar(&clinic_name, None, &[event]),
+    })
+}
+
+/// Importa citas desde un .ics de otra agenda. El paciente se reconoce por el nombre (ATTENDEE o
+/// SUMMARY); si no, se usa `default_patient_id`. Los duplicados (mismo UID, citas exportadas por
+/// Oklus o misma hora y paciente) no se importan y los choques se informan por evento.
+/// Con `dry_run` solo se devuelve el informe.
+#[tauri::command]
+pub async fn import_appointments_ics(
+    db_pool: State<'_, DbPool>,
+    content: String,
+    default_patient_id: Option<i64>,
+    provider_id: Option<i64>,
+    dry_run: Option<bool>,
+) -> Result<IcsImportResult, String> {
+    let events = parse_ics_events(&content);
+    if events.is_empty() {
+        return Err("No events found in the .ics file".to_string());
+    }
+
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let tz = load_clinic_timezone(&mut tx).await?;
+    let patients: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, full_name FROM patients WHERE COALESCE(status, 'active') = 'active'"
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    let default_patient = match default_patient_id.filter(|&i| i > 0) {
+        Some(id) => Some(
+            patients
+                .iter()
+                .find(|(pid, _)| *pid == id)
+                .cloned()
+                .ok_or_else(|| format!("Patient {} not found", id))?,
+        ),
+        None => None,
+    };
+
+    let mut result = IcsImportResult::default();
+
+    for event in events {
+        let mut report = IcsImportEvent {
+            uid: event.uid.clone(),
+            summary: event.summary.clone(),
+            starts_at: None,
+            ends_at: None,
+            patient_id: None,
+            outcome: "invalid".to_string(),
+            appointment_id: None,
+            message: None,
+        };
+
+        let outcome = import_ics_event(&mut tx, &event, tz, &patients, default_patient.as_ref(), provider_id, &mut report).await;
+        match outcome {
+            Ok(outcome) => report.outcome = outcome.to_string(),
+            Err(e) => {
+                report.outcome = if e.contains("overlaps") { "conflict" } else { "invalid" }.to_string();
+                report.message = Some(e);
+            }
+        }
+
+        match report.outcome.as_str() {
+            "imported" => result.imported += 1,
+            "duplicate" => result.duplicates += 1,
+            "conflict" => result.conflicts += 1,
+            "skipped" => result.skipped += 1,
+            _ => result.invalid += 1,
+        }
+        result.events.push(report);
+    }
+
+    if dry_run.unwrap_or(false) {
+        return Ok(result);
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!(
+        "📅 ICS import: {} imported, {} duplicates, {} conflicts, {} invalid",
+        result.imported, result.duplicates, result.conflicts, result.invalid
+    );
+
+    Ok(result)
+}
+
+/// Importa un evento; completa el informe y devuelve el resultado ('imported', 'duplicate', 'skipped')
+async fn import_ics_event(
+    conn: &mut sqlx::SqliteConnection,
+    event: &ParsedIcsEvent,
+    tz: ClinicTimezone,
+    patients: &[(i64, String)],
+    default_patient: Option<&(i64, String)>,
+    provider_id: Option<i64>,
+    report: &mut IcsImportEvent,
+) -> Result<&'static str, String> {
+    if event.status.as_deref() == Some("CANCELLED") {
+        report.message = Some("Cancelled event".to_string());
+        return Ok("skipped");
+    }
+    if event.recurring {
+        return Err("Recurring events are not supported; export the occurrences individually".to_string());
+    }
+
+    let dtstart = event.dtstart.as_ref().ok_or("Event without DTSTART")?;
+    let starts_at = parse_ics_datetime(dtstart, tz)?;
+    let ends_at = match (&event.dtend, event.duration.as_deref()) {
+        (Some(dtend), _) => parse_ics_datetime(dtend, tz)?,
+        (None, Some(duration)) => parse_ics_duration(duration)
+            .and_then(|duration| starts_at.checked_add_signed(duration))
+            .ok_or_else(|| format!("Invalid DURATION: '{}'", duration))?,
+        (None, None) => return Err("Event without DTEND or DURATION".to_string()),
+    };
+    report.starts_at = Some(format_canonical_utc(starts_at));
+    report.ends_at = Some(format_canonical_utc(ends_at));
+
+    let (patient_id, patient_name) = match_ics_patient(event, patients)
+        .or(default_patient)
+        .cloned()
+        .ok_or("Patient not found (no matching name and no default patient)")?;
+    report.patient_id = Some(patient_id);
+
+    // Duplicados: citas exportadas por nosotros, UID ya importado o misma hora y paciente
+    let own_id = event
+        .uid
+        .as_deref()
+        .and_then(|uid| uid.strip_prefix("appointment-")?.strip_suffix("@oklus")?.parse::<i64>().ok());
+    let duplicate: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM appointments
+         WHERE id = ?1
+            OR (?2 IS NOT NULL AND external_uid = ?2)
+            OR (patient_id = ?3 AND starts_at = ?4 AND ends_at = ?5 AND status != 'cancelled')
+         LIMIT 1"
+    )
+    .bind(own_id)
+    .bind(&event.uid)
+    .bind(patient_id)
+    .bind(&report.starts_at)
+    .bind(&report.ends_at)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    if let Some(existing) = duplicate {
+        report.appointment_id = Some(existing);
+        return Ok("duplicate");
+    }
+
+    let appointment = Appointment {
+        id: None,
+        patient_id,
+        starts_at: format_canonical_utc(starts_at),
+        ends_at: format_canonical_utc(ends_at),
+        procedure: ics_procedure(&event.summary, Some(&patient_name)),
+        notes: event.description.clone(),
+        status: if event.status.as_deref() == Some("CONFIRMED") { "confirmed" } else { "scheduled" }.to_string(),
+        provider_id: provider_id.filter(|&i| i > 0),
+        resource_ids: None,
+        series_id: None,
+        appointment_type_id: None,
+        confirmed_at: None,
+        reminder_1d_sent_at: None,
+        created_at: None,
+        updated_at: None,
+    };
+    let appointment_id = insert_appointment(conn, &appointment).await?;
+
+    if let Some(uid) = &event.uid {
+        sqlx::query("UPDATE appointments SET external_uid = ?1 WHERE id = ?2")
+            .bind(uid)
+            .bind(appointment_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    report.appointment_id = Some(appointment_id);
+    Ok("imported")
+}
+
+// ============================================================================
+// ICS FEED SERVER MODULE (suscripción de calendario en la red local)
+// ============================================================================
+
+const DEFAULT_ICS_FEED_PORT: u16 = 8787;
+const ICS_FEED_PATH: &str = "/calendar.ics";
+const ICS_FEED_DAYS_AHEAD: i64 = 90;
+const ICS_FEED_MAX_REQUEST_BYTES: usize = 8 * 1024;
+const ICS_FEED_READ_TIMEOUT_SECS: u64 = 5;
+/// Conexiones atendidas a la vez; las demás esperan en la cola del sistema operativo
+const ICS_FEED_MAX_CONNECTIONS: usize = 8;
+
+/// Copia en memoria del estado y el token del feed. Se valida antes de tomar la base,
+/// así un pedido sin token válido nunca bloquea la app.
+#[derive(Debug, Clone)]
+struct IcsFeedAccess {
+    enabled: bool,
+    token: Option<String>,
+}
+
+/// Servidor del feed en ejecución (estado de Tauri en crate::IcsFeedServer).
+/// Al soltarlo se detiene el servidor.
+pub struct IcsFeedHandle {
+    port: u16,
+    access: std::sync::Arc<std::sync::RwLock<IcsFeedAccess>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl IcsFeedHandle {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Actualiza la copia en memoria tras guardar los settings del feed
+    fn set_access(&self, settings: &IcsFeedSettings) {
+        if let Ok(mut access) = self.access.write() {
+            *access = IcsFeedAccess { enabled: settings.enabled, token: settings.token.clone() };
+        }
+    }
+}
+
+impl Drop for IcsFeedHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+        println!("🛑 ICS feed stopped (port {})", self.port);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IcsFeedStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub port: u16,
+    pub token: Option<String>,
+    pub url: Option<String>,         // http://<ip-lan>:<puerto>/calendar.ics?token=...
+    pub webcal_url: Option<String>,  // Mismo feed con esquema webcal:// (abre la app de calendario)
+}
+
+/// Settings del feed: icsFeedEnabled ("1"/"0"), icsFeedPort, icsFeedToken (categoría 'calendar')
+struct IcsFeedSettings {
+    enabled: bool,
+    port: u16,
+    token: Option<String>,
+}
+
+async fn load_ics_feed_settings(conn: &mut sqlx::SqliteConnection) -> Result<IcsFeedSettings, String> {
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT key, value FROM user_settings WHERE key IN ('icsFeedEnabled', 'icsFeedPort', 'icsFeedToken')"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut settings = IcsFeedSettings { enabled: false, port: DEFAULT_ICS_FEED_PORT, token: None };
+    for (key, value) in rows {
+        let value = value.trim();
+        match key.as_str() {
+            "icsFeedEnabled" => settings.enabled = value == "1",
+            "icsFeedPort" => settings.port = value.parse().ok().filter(|&p| p > 0).unwrap_or(DEFAULT_ICS_FEED_PORT),
+            "icsFeedToken" => settings.token = Some(value.to_string()).filter(|t| !t.is_empty()),
+            _ => {}
+        }
+    }
+    Ok(settings)
+}
+
+async fn save_ics_feed_setting(conn: &mut sqlx::SqliteConnection, key: &str, value: &str) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO user_settings (key, value, category)
+         VALUES (?1, ?2, 'calendar')
+         ON CONFLICT(key) DO UPDATE SET value = ?2, category = 'calendar', updated_at = datetime('now')"
+    )
+    .bind(key)
+    .bind(value)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Token de 32 caracteres hex (16 bytes del generador aleatorio del sistema operativo)
+fn generate_ics_feed_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Failed to generate feed token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Comparación en tiempo constante (no revela cuántos caracteres coinciden)
+fn ics_feed_token_matches(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected.bytes().zip(provided.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// IP de este equipo en la LAN (la de la interfaz con ruta por defecto).
+/// Un socket UDP "conectado" no envía paquetes; solo elige la interfaz.
+fn local_lan_ip() -> Option<std::net::IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip()).filter(|ip| !ip.is_unspecified())
+}
+
+/// Dirección donde escucha el feed: la IP de la LAN, o solo este equipo si no hay red
+fn ics_feed_host() -> std::net::IpAddr {
+    local_lan_ip().unwrap_or(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST))
+}
+
+fn ics_feed_status(settings: &IcsFeedSettings, running_port: Option<u16>) -> IcsFeedStatus {
+    let port = running_port.unwrap_or(settings.port);
+    let host = ics_feed_host();
+    let url = settings
+        .token
+        .as_ref()
+        .map(|token| format!("http://{}:{}{}?token={}", host, port, ICS_FEED_PATH, token));
+
+    IcsFeedStatus {
+        enabled: settings.enabled,
+        running: running_port.is_some(),
+        port,
+        token: settings.token.clone(),
+        webcal_url: url.as_ref().map(|u| u.replacen("http://", "webcal://", 1)),
+        url,
+    }
+}
+
+/// Abre el puerto solo en la interfaz de la LAN (no en todas) y atiende cada conexión
+/// en su propia tarea, hasta ICS_FEED_MAX_CONNECTIONS a la vez.
+/// Con port = 0 el sistema asigna uno libre (ver IcsFeedHandle::port).
+async fn start_ics_feed_server(
+    pool: std::sync::Arc<tokio::sync::Mutex<sqlx::SqlitePool>>,
+    port: u16,
+    settings: &IcsFeedSettings,
+) -> Result<IcsFeedHandle, String> {
+    let listener = tokio::net::TcpListener::bind((ics_feed_host(), port))
+        .await
+        .map_err(|e| format!("Failed to open port {} for the calendar feed: {}", port, e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+    let access = std::sync::Arc::new(std::sync::RwLock::new(IcsFeedAccess {
+        enabled: settings.enabled,
+        token: settings.token.clone(),
+    }));
+    let connections = std::sync::Arc::new(tokio::sync::Semaphore::new(ICS_FEED_MAX_CONNECTIONS));
+
+    let server_access = access.clone();
+    let task = tokio::spawn(async move {
+        loop {
+            // Sin cupo no se aceptan más conexiones hasta que termine alguna
+            let Ok(permit) = connections.clone().acquire_owned().await else {
+                break;
+            };
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    println!("⚠️ ICS feed accept failed: {}", e);
+                    continue;
+                }
+            };
+            let pool = pool.clone();
+            let access = server_access.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_ics_feed_connection(stream, &pool, &access).await {
+                    println!("⚠️ ICS feed request from {} failed: {}", peer, e);
+                }
+                drop(permit);
+            });
+        }
+    });
+
+    println!("📅 ICS feed listening on port {}", port);
+    Ok(IcsFeedHandle { port, access, task })
+}
+
+/// Arranque de la app: levanta el feed si quedó habilitado. Un puerto ocupado
+/// no impide abrir la app; el estado queda como "no iniciado".
+pub async fn autostart_ics_feed(pool: std::sync::Arc<tokio::sync::Mutex<sqlx::SqlitePool>>) -> Option<IcsFeedHandle> {
+    let settings = {
+        let guard = pool.lock().await;
+        let mut conn = guard.acquire().await.ok()?;
+        load_ics_feed_settings(&mut conn).await.ok()?
+    };
+    if !settings.enabled || settings.token.is_none() {
+        return None;
+    }
+
+    start_ics_feed_server(pool, settings.port, &settings)
+        .await
+        .map_err(|e| println!("⚠️ {}", e))
+        .ok()
+}
+
+struct IcsFeedResponse {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl IcsFeedResponse {
+    fn text(status: &'static str) -> Self {
+        IcsFeedResponse { status, content_type: "text/plain; charset=utf-8", body: format!("{}\n", status) }
+    }
+}
+
+async fn serve_ics_feed_connection(
+    mut stream: tokio::net::TcpStream,
+    pool: &tokio::sync::Mutex<sqlx::SqlitePool>,
+    access: &std::sync::RwLock<IcsFeedAccess>,
+) -> Result<(), String> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Solo interesa la cabecera: GET/HEAD no llevan cuerpo
+    let mut head = Vec::with_capacity(1024);
+    let read_head = async {
+        let mut chunk = [0u8; 1024];
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < ICS_FEED_MAX_REQUEST_BYTES {
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&chunk[..read]);
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(ICS_FEED_READ_TIMEOUT_SECS), read_head)
+        .await
+        .map_err(|_| "timed out reading request".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let head = String::from_utf8_lossy(&head);
+    let request_line = head.lines().next().unwrap_or_default();
+    let method = request_line.split_whitespace().next().unwrap_or_default();
+    let response = ics_feed_response(pool, access, request_line).await;
+
+    let mut message = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if response.status.starts_with("405") {
+        message.push_str("Allow: GET, HEAD\r\n");
+    }
+    message.push_str("\r\n");
+    if method != "HEAD" {
+        message.push_str(&response.body);
+    }
+
+    stream.write_all(message.as_bytes()).await.map_err(|e| e.to_string())?;
+    stream.shutdown().await.map_err(|e| e.to_string())
+}
+
+/// GET /calendar.ics?token=...&provider=ID → agenda desde hoy hasta ICS_FEED_DAYS_AHEAD días.
+/// El token y el estado vienen de la copia en memoria del servidor, que los comandos
+/// actualizan al guardarlos: regenerarlo o deshabilitar el feed corta las suscripciones al instante.
+async fn ics_feed_response(
+    pool: &tokio::sync::Mutex<sqlx::SqlitePool>,
+    access: &std::sync::RwLock<IcsFeedAccess>,
+    request_line: &str,
+) -> IcsFeedResponse {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return IcsFeedResponse::text("400 Bad Request");
+    };
+    if method != "GET" && method != "HEAD" {
+        return IcsFeedResponse::text("405 Method Not Allowed");
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != ICS_FEED_PATH {
+        return IcsFeedResponse::text("404 Not Found");
+    }
+
+    let mut token = String::new();
+    let mut provider_id = None;
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        let value = urlencoding::decode(value).map(|v| v.into_owned()).unwrap_or_default();
+        match key {
+            "token" => token = value,
+            "provider" => provider_id = value.parse::<i64>().ok().filter(|&id| id > 0),
+            _ => {}
+        }
+    }
+
+    let access = match access.read() {
+        Ok(access) => access.clone(),
+        Err(_) => return IcsFeedResponse::text("500 Internal Server Error"),
+    };
+    let Some(expected) = access.token.filter(|_| access.enabled) else {
+        return IcsFeedResponse::text("404 Not Found");
+    };
+    if !ics_feed_token_matches(&expected, &token) {
+        return IcsFeedResponse::text("401 Unauthorized");
+    }
+
+    let guard = pool.lock().await;
+    let result = async {
+        let mut conn = guard.acquire().await.map_err(|e| e.to_string())?;
+        let tz = load_clinic_timezone(&mut conn).await?;
+        let today = tz.now_local().date();
+        let range_start = format_canonical_utc(tz.day_start_utc(today));
+        let range_end = format_canonical_utc(tz.day_start_utc(today + chrono::Duration::days(ICS_FEED_DAYS_AHEAD)));
+        // Viaja por HTTP sin cifrar: sin nombres, teléfonos ni notas
+        let calendar = build_agenda_ics(&mut conn, tz, &range_start, &range_end, provider_id, false).await?;
+
+        Ok::<_, String>(IcsFeedResponse { status: "200 OK", content_type: "text/calendar; charset=utf-8", body: calendar })
+    }
+    .await;
+
+    result.unwrap_or_else(|e| {
+        println!("❌ ICS feed: {}", e);
+        IcsFeedResponse::text("500 Internal Server Error")
+    })
+}
+
+#[tauri::command]
+pub async fn get_ics_feed_status(
+    db_pool: State<'_, DbPool>,
+    feed_server: State<'_, crate::IcsFeedServer>,
+) -> Result<IcsFeedStatus, String> {
+    let server = feed_server.0.lock().await;
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let settings = load_ics_feed_settings(&mut conn).await?;
+    Ok(ics_feed_status(&settings, server.as_ref().map(IcsFeedHandle::port)))
+}
+
+/// Habilita el feed (genera el token la primera vez) y levanta el servidor.
+/// Sin puerto usa el guardado (o DEFAULT_ICS_FEED_PORT).
+#[tauri::command]
+pub async fn enable_ics_feed(
+    db_pool: State<'_, DbPool>,
+    feed_server: State<'_, crate::IcsFeedServer>,
+    port: Option<u16>,
+) -> Result<IcsFeedStatus, String> {
+    let mut server = feed_server.0.lock().await;
+
+    let mut settings = {
+        let pool = db_pool.0.lock().await;
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+        load_ics_feed_settings(&mut conn).await?
+    };
+    let port = port.filter(|&p| p > 0).unwrap_or(settings.port);
+
+    let generated_token = match settings.token {
+        Some(_) => None,
+        None => Some(generate_ics_feed_token()?),
+    };
+    if let Some(token) = &generated_token {
+        settings.token = Some(token.clone());
+    }
+    settings.enabled = true;
+    settings.port = port;
+
+    // Soltar el servidor anterior libera el puerto antes de volver a abrirlo
+    if server.as_ref().is_some_and(|running| running.port != port) {
+        *server = None;
+    }
+    match server.as_ref() {
+        Some(running) => running.set_access(&settings),
+        None => *server = Some(start_ics_feed_server(db_pool.0.clone(), port, &settings).await?),
+    }
+
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    if let Some(token) = &generated_token {
+        save_ics_feed_setting(&mut conn, "icsFeedToken", token).await?;
+    }
+    save_ics_feed_setting(&mut conn, "icsFeedEnabled", "1").await?;
+    save_ics_feed_setting(&mut conn, "icsFeedPort", &port.to_string()).await?;
+
+    Ok(ics_feed_status(&settings, server.as_ref().map(IcsFeedHandle::port)))
+}
+
+#[tauri::command]
+pub async fn disable_ics_feed(
+    db_pool: State<'_, DbPool>,
+    feed_server: State<'_, crate::IcsFeedServer>,
+) -> Result<IcsFeedStatus, String> {
+    let mut server = feed_server.0.lock().await;
+    *server = None;
+
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    save_ics_feed_setting(&mut conn, "icsFeedEnabled", "0").await?;
+
+    let settings = load_ics_feed_settings(&mut conn).await?;
+    Ok(ics_feed_status(&settings, None))
+}
+
+/// Nuevo token: las suscripciones existentes dejan de funcionar (p. ej. si el enlace se filtró)
+#[tauri::command]
+pub async fn regenerate_ics_feed_token(
+    db_pool: State<'_, DbPool>,
+    feed_server: State<'_, crate::IcsFeedServer>,
+) -> Result<IcsFeedStatus, String> {
+    let server = feed_server.0.lock().await;
+    let pool = db_pool.0.lock().await;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    save_ics_feed_setting(&mut conn, "icsFeedToken", &generate_ics_feed_token()?).await?;
+
+    let settings = load_ics_feed_settings(&mut conn).await?;
+    if let Some(running) = server.as_ref() {
+        running.set_access(&settings);
+    }
+    Ok(ics_feed_status(&settings, server.as_ref().map(IcsFeedHandle::port)))
+}
+
+// ============================================================================
+// REMINDER RULES MODULE (recordatorios programados en segundo plano)
+// ============================================================================
+
+const REMINDER_TEMPLATE_KINDS: &[&str] = &["reminder", "reminder_1d"];
+const MAX_REMINDER_OFFSET_MINUTES: i64 = 30 * 24 * 60;
+const REMINDER_SCHEDULER_INTERVAL_SECS: u64 = 5 * 60;
+const DEFAULT_REMINDER_QUIET_START: &str = "21:00";
+const DEFAULT_REMINDER_QUIET_END: &str = "08:00";
+/// Reglas desde esta antelación salen el día hábil anterior si caen en días cerrados
+const REMINDER_WORKING_DAY_MIN_OFFSET: i64 = 24 * 60;
+/// Máximo de días cerrados seguidos que se adelantan (vacaciones largas)
+const REMINDER_MAX_CLOSED_DAYS: i64 = 14;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReminderRule {
+    pub id: Option<i64>,
+    pub name: String,
+    pub offset_minutes: i64,             // Antelación: 4320 = 3 días, 1440 = 24 h, 120 = 2 h
+    pub template_kind: Option<String>,   // 'reminder' (default) | 'reminder_1d'
+    pub template_id: Option<i64>,        // None = plantilla preferida de template_kind
+    pub active: Option<bool>,
+    pub template_title: Option<String>,  // Solo lectura (JOIN)
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReminderRunResult {
+    pub queued: i64,        // Mensajes creados en message_queue
+    pub skipped: i64,       // Reglas vencidas reemplazadas por un recordatorio más cercano
+    pub quiet_hours: bool,  // Pasada omitida por horario de silencio
+}
+
+/// Horario de silencio en hora local de la clínica; puede cruzar la medianoche (21:00–08:00).
+/// Inicio igual a fin = sin horario de silencio.
+async fn load_reminder_quiet_hours(
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<Option<(chrono::NaiveTime, chrono::NaiveTime)>, String> {
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT key, value FROM user_settings WHERE key IN ('reminderQuietStart', 'reminderQuietEnd')"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let setting = |key: &str, default: &str| {
+        let value = rows.iter().find(|(k, _)| k == key).map_or(default, |(_, v)| v.trim());
+        chrono::NaiveTime::parse_from_str(value, "%H:%M").unwrap_or_else(|_| {
+            println!("⚠️ Invalid {} '{}', using {}", key, value, default);
+            chrono::NaiveTime::parse_from_str(default, "%H:%M").unwrap()
+        })
+    };
+    let start = setting("reminderQuietStart", DEFAULT_REMINDER_QUIET_START);
+    let end = setting("reminderQuietEnd", DEFAULT_REMINDER_QUIET_END);
+
+    Ok(Some((start, end)).filter(|(start, end)| start != end))
+}
+
+fn is_quiet_time(time: chrono::NaiveTime, (start, end): (chrono::NaiveTime, chrono::NaiveTime)) -> bool {
+    if start < end {
+        time >= start && time < end
+    } else {
+        time >= start || time < end
+    }
+}
+
+/// "hoy", "mañana" o "el 15/01/2025" (para la variable {cuando})
+fn reminder_relative_day(date: chrono::NaiveDate, today: chrono::NaiveDate) -> String {
+    match (date - today).num_days() {
+        0 => "hoy".to_string(),
+        1 => "mañana".to_string(),
+        _ => format!("el {}", format_display_date(date)),
+    }
+}
+
+/// Plantilla de una regla: la elegida (si sigue activa) o la preferida de su tipo.
+/// Para 'reminder_1d' sin plantillas se usa DEFAULT_REMINDER_1D_TEMPLATE.
+async fn load_reminder_rule_template(
+    conn: &mut sqlx::SqliteConnection,
+    template_kind: &str,
+    template_id: Option<i64>,
+) -> Result<Option<(String, String)>, String> {
+    if let Some(template_id) = template_id {
+        let template: Option<(String, String)> = sqlx::query_as(
+            "SELECT kind, body FROM text_templates WHERE id = ?1 AND active = 1"
+        )
+        .bind(template_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+        if template.is_some() {
+            return Ok(template);
+        }
+    }
+
+    let body = load_preferred_template_body(conn, template_kind)
+        .await?
+        .or_else(|| (template_kind == "reminder_1d").then(|| DEFAULT_REMINDER_1D_TEMPLATE.to_string()));
+    Ok(body.map(|body| (template_kind.to_string(), body)))
+}
+
+/// Fin del adelanto por días cerrados: si mañana y los días siguientes no tienen horario
+/// (fin de semana, feriados, cierres), hoy es el último día hábil antes de ellos y los
+/// recordatorios de un día o más que caerían en esos días salen hoy. El viernes cubre así
+/// el fin de semana y el lunes. Devuelve el inicio del próximo día hábil, o None si mañana abre.
+async fn reminder_working_day_cutoff(
+    conn: &mut sqlx::SqliteConnection,
+    tz: ClinicTimezone,
+    today: chrono::NaiveDate,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, String> {
+    let schedule = load_work_schedule(conn, None).await?;
+    // Sin horario semanal no se sabe qué días cierra la clínica
+    if !schedule.is_configured() {
+        return Ok(None);
+    }
+
+    let mut next_open = today + chrono::Duration::days(1);
+    while schedule.open_intervals(next_open, None).is_empty()
+        && next_open - today <= chrono::Duration::days(REMINDER_MAX_CLOSED_DAYS)
+    {
+        next_open += chrono::Duration::days(1);
+    }
+
+    Ok((next_open - today > chrono::Duration::days(1)).then(|| tz.day_start_utc(next_open)))
+}
+
+/// Una pasada del programador: para cada cita activa que empieza dentro de la mayor
+/// antelación configurada, envía la regla vencida más cercana al inicio que aún no se
+/// registró; las vencidas más lejanas se marcan 'skipped' (citas agendadas con poca
+/// anticipación no reciben el de 3 días y el de 24 h juntos). Las reglas de un día o más
+/// que caerían en días cerrados vencen el día hábil anterior (reminder_working_day_cutoff).
+/// Con `respect_quiet_hours`, durante el horario de silencio no se crea nada: lo pendiente
+/// sale en la primera pasada posterior.
+async fn run_reminder_rules(
+    conn: &mut sqlx::SqliteConnection,
+    now: chrono::DateTime<chrono::Utc>,
+    respect_quiet_hours: bool,
+) -> Result<ReminderRunResult, String> {
+    let mut result = ReminderRunResult::default();
+
+    let tz = load_clinic_timezone(conn).await?;
+    let local_now = tz.to_local(now);
+    if respect_quiet_hours
+        && load_reminder_quiet_hours(conn).await?.is_some_and(|quiet| is_quiet_time(local_now.time(), quiet))
+    {
+        result.quiet_hours = true;
+        return Ok(result);
+    }
+
+    // (id, offset_minutes, template_kind, template_id), de la más cercana a la más lejana
+    let rules: Vec<(i64, i64, String, Option<i64>)> = sqlx::query_as(
+        "SELECT id, offset_minutes, template_kind, template_id
+         FROM reminder_rules
+         WHERE active = 1
+         ORDER BY offset_minutes ASC, id ASC"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    let Some(max_offset) = rules.iter().map(|(_, offset, _, _)| *offset).max() else {
+        return Ok(result);
+    };
+    let working_day_cutoff = reminder_working_day_cutoff(conn, tz, local_now.date())
+        .await?
+        .filter(|cutoff| *cutoff > now)
+        .unwrap_or(now);
+
+    let rows = sqlx::query(
+        "SELECT a.id AS appointment_id, a.patient_id, a.starts_at, a.procedure, p.full_name,
+                t.preparation_notes,
+                (SELECT GROUP_CONCAT(rule_id) FROM reminder_log WHERE appointment_id = a.id) AS logged_rules
+         FROM appointments a
+         JOIN patients p ON a.patient_id = p.id
+         LEFT JOIN appointment_types t ON t.id = a.appointment_type_id
+         WHERE a.starts_at > ?1 AND a.starts_at <= ?2
+           AND a.status IN ('scheduled', 'confirmed')
+         ORDER BY a.starts_at ASC"
+    )
+    .bind(format_canonical_utc(now))
+    .bind(format_canonical_utc(working_day_cutoff + chrono::Duration::minutes(max_offset)))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to fetch appointments for reminders: {}", e))?;
+
+    let base_context = default_template_context(conn).await?;
+    let mut templates: HashMap<i64, Option<(String, String)>> = HashMap::new();
+
+    for row in rows {
+        let appointment_id: i64 = row.get("appointment_id");
+        let starts_at: String = row.get("starts_at");
+        let Ok(start) = parse_appointment_instant(&starts_at, tz) else {
+            continue;
+        };
+        let logged = parse_resource_ids(row.get("logged_rules"));
+
+        // Reglas cuyo momento ya llegó (o cae en días cerrados); la primera es la más
+        // cercana al inicio de la cita
+        let due: Vec<&(i64, i64, String, Option<i64>)> = rules
+            .iter()
+            .filter(|(_, offset, _, _)| {
+                let send_at = start - chrono::Duration::minutes(*offset);
+                send_at <= now || (*offset >= REMINDER_WORKING_DAY_MIN_OFFSET && send_at < working_day_cutoff)
+            })
+            .collect();
+        let Some(&&(rule_id, _, ref template_kind, template_id)) = due.first() else {
+            continue;
+        };
+
+        for (stale_rule, _, _, _) in due.iter().skip(1).filter(|(id, _, _, _)| !logged.contains(id)) {
+            sqlx::query("INSERT INTO reminder_log (rule_id, appointment_id, status) VALUES (?1, ?2, 'skipped')")
+                .bind(stale_rule)
+                .bind(appointment_id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+            result.skipped += 1;
+        }
+        if logged.contains(&rule_id) {
+            continue;
+        }
+
+        if let std::collections::hash_map::Entry::Vacant(entry) = templates.entry(rule_id) {
+            let template = load_reminder_rule_template(conn, template_kind, template_id).await?;
+            if template.is_none() {
+                println!("⚠️ Reminder rule {} has no active '{}' template; skipping", rule_id, template_kind);
+            }
+            entry.insert(template);
+        }
+        let Some((kind, body)) = &templates[&rule_id] else {
+            continue;
+        };
+
+        // Display in clinic-local time
+        let local_start = tz.to_local(start);
+        let preparation_notes: Option<String> = row.get("preparation_notes");
+        let mut context = base_context.clone();
+        context.insert("nombre".to_string(), row.get("full_name"));
+        context.insert("procedimiento".to_string(), row.get("procedure"));
+        context.insert("hora".to_string(), local_start.format("%H:%M").to_string());
+        context.insert("fecha".to_string(), format_display_date(local_start.date()));
+        context.insert("cuando".to_string(), reminder_relative_day(local_start.date(), local_now.date()));
+        context.insert("preparacion".to_string(), preparation_notes.clone().unwrap_or_default());
+        let mut message_text = render_template_body(kind, body, &context).text;
+
+        // Templates written before appointment types have no {preparacion}: append the instructions
+        if let Some(notes) = preparation_notes.filter(|_| !body.contains("{preparacion}")) {
+            message_text = format!("{}\n\n{}", message_text.trim_end(), notes);
+        }
+
+        let patient_id: i64 = row.get("patient_id");
+        let message_id = sqlx::query(
+            "INSERT INTO message_queue (patient_id, appointment_id, type, message_text, status)
+             VALUES (?1, ?2, ?3, ?4, 'pending')"
+        )
+        .bind(patient_id)
+        .bind(appointment_id)
+        .bind(kind)
+        .bind(&message_text)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to create reminder message: {}", e))?
+        .last_insert_rowid();
+
+        sqlx::query(
+            "INSERT INTO reminder_log (rule_id, appointment_id, message_id, status) VALUES (?1, ?2, ?3, 'queued')"
+        )
+        .bind(rule_id)
+        .bind(appointment_id)
+        .bind(message_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        // Legacy column, still shown by the agenda
+        if kind == "reminder_1d" {
+            sqlx::query("UPDATE appointments SET reminder_1d_sent_at = datetime('now') WHERE id = ?1")
+                .bind(appointment_id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| format!("Failed to update reminder timestamp: {}", e))?;
+        }
+
+        result.queued += 1;
+    }
+
+    Ok(result)
+}
+
+/// Tareas diarias del programador, a lo sumo una vez por día local de la clínica
+/// (setting dailyJobsLastRun): mensajes de recall de los pacientes vencidos y cuotas
+/// de ortodoncia vencidas.
+async fn run_daily_jobs(conn: &mut sqlx::SqliteConnection) -> Result<bool, String> {
+    let today = load_clinic_timezone(conn).await?.now_local().date().format("%Y-%m-%d").to_string();
+    let last_run: Option<String> = sqlx::query_scalar("SELECT value FROM user_settings WHERE key = 'dailyJobsLastRun'")
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    if last_run.as_deref() == Some(today.as_str()) {
+        return Ok(false);
+    }
+
+    let recalls = queue_recall_messages(conn, 0).await?;
+    if recalls > 0 {
+        println!("🔁 Generated {} recall messages", recalls);
+    }
+    let charges = create_due_ortho_charges(conn, None).await?;
+    if charges > 0 {
+        println!("🦷 Generated {} ortho charges", charges);
+    }
+
+    sqlx::query(
+        "INSERT INTO user_settings (key, value, category)
+         VALUES ('dailyJobsLastRun', ?1, 'scheduler')
+         ON CONFLICT(key) DO UPDATE SET value = ?1, updated_at = datetime('now')"
+    )
+    .bind(&today)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Programador en segundo plano: una pasada al iniciar la app y luego cada
+/// REMINDER_SCHEDULER_INTERVAL_SECS. Corre además las tareas diarias (run_daily_jobs).
+/// Los recordatorios se pausan con el setting reminderSchedulerEnabled = "0".
+pub fn spawn_reminder_scheduler(
+    pool: std::sync::Arc<tokio::sync::Mutex<sqlx::SqlitePool>>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(REMINDER_SCHEDULER_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+
+            let guard = pool.lock().await;
+            let daily = async {
+                let mut tx = guard.begin().await.map_err(|e| e.to_string())?;
+                run_daily_jobs(&mut tx).await?;
+                tx.commit().await.map_err(|e| e.to_string())
+            }
+            .await;
+            if let Err(e) = daily {
+                println!("❌ Daily jobs: {}", e);
+            }
+
+            let run = async {
+                let mut tx = guard.begin().await.map_err(|e| e.to_string())?;
+                let enabled: Option<String> = sqlx::query_scalar(
+                    "SELECT value FROM user_settings WHERE key = 'reminderSchedulerEnabled'"
+                )
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+                if enabled.as_deref().map(str::trim) == Some("0") {
+                    return Ok(None);
+                }
+
+                let result = run_reminder_rules(&mut tx, chrono::Utc::now(), true).await?;
+                tx.commit().await.map_err(|e| e.to_string())?;
+                Ok::<_, String>(Some(result))
+            }
+            .await;
+            drop(guard);
+
+            match run {
+                Ok(Some(result)) if result.queued > 0 => println!("🔔 Queued {} reminder messages", result.queued),
+                Err(e) => println!("❌ Reminder scheduler: {}", e),
+                _ => {}
+            }
+        }
+    })
+}
+
+/// Pasada manual del programador (botón "generar recordatorios"); devuelve los mensajes creados.
+/// El usuario la pide explícitamente: no respeta el horario de silencio.
+#[tauri::command]
+pub async fn generate_1d_reminders(
+    db_pool: State<'_, DbPool>,
+) -> Result<i64, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let result = run_reminder_rules(&mut tx, chrono::Utc::now(), false).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    println!("✅ Generated {} reminder messages", result.queued);
+
+    Ok(result.queued)
+}
+
+/// Una pasada como la del programador en segundo plano (con horario de silencio:
+/// `quiet_hours` indica si se omitió)
+#[tauri::command]
+pub async fn run_reminder_scheduler(
+    db_pool: State<'_, DbPool>,
+) -> Result<ReminderRunResult, String> {
+    let pool = db_pool.0.lock().await;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let result = run_reminder_rules(&mut tx, chrono::Utc::now(), true).await?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn get_reminder_rules(
+    db_pool: State<'_, DbPool>,
+) -> Result<Vec<ReminderRule>, String> {
+    let pool = db_pool.0.lock().await;
+
+    let rows = sqlx::query(
+        "SELECT r.id, r.name, r.offset_minutes, r.template_kind, r.template_id, r.active,
+                t.title AS template_title, r.created_at, r.updated_at
+         FROM reminder_rules r
+         LEFT JOIN text_templates t ON t.id = r.template_id
+         ORDER BY r.active DESC, r.offset_minutes DESC"
+    )
+    .fetch_all(&*pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .iter()
+        .map(|row| ReminderRule {
+            id: Some(row.get("id")),
+            name: row.get("name"),
+            offset_minutes: row.get("offset_minutes"),
+            template_kind: Some(row.get("template_kind")),
+            template_id: row.get("template_id"),
+            active: Some(row.get::<i64, _>("active") != 0),
+            template_title: row.get("template_title"),
+            created_at: Some(row.get("created_at")),
+            updated_at: Some(row.get("updated_at")),
+        })
+        .collect())
+}
+
+/// Guarda una regla. Con plantilla elegida, el tipo de plantilla de la regla es el de esa plantilla.
+#[tauri::command]
+pub async fn save_reminder_rule(
+    db_pool: State<'_, DbPool>,
+    rule: ReminderRule,
+) -> Result<i64, String> {
+    if rule.name.trim().is_empty() {
+        return Err("Reminder rule name is required".to_string());
+    }
+    if !(5..=MAX_REMINDER_OFFSET_MINUTES).contains(&rule.offset_minutes) {
+        return Err(format!("Reminder offset must be between 5 and {} minutes", MAX_REMINDER_OFFSET_MINUTES));
+    }
+
+    let pool = db_pool.0.lock().await;
+    let template_id = rule.template_id.filter(|&i| i > 0);
+
+    let template_kind = match template_id {
+        Some(template_id) => sqlx::query_scalar::<_, String>("SELECT kind FROM text_templates WHERE id = ?1")
+            .bind(template_id)
+            .fetch_optional(&*pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Template {} not found", template_id))?,
+        None => rule.template_kind.clone().unwrap_or_else(|| "reminder".to_string()),
+    };
+    if !REMINDER_TEMPLATE_KINDS.contains(&template_kind.as_str()) {
+        return Err(format!("Reminder templates must be of kind reminder or reminder_1d (got {})", template_kind));
+    }
+
+    if let Some(id) = rule.id.filter(|&i| i > 0) {
+        let result = sqlx::query(
+            "UPDATE reminder_rules
+             SET name = ?1, offset_minutes = ?2, template_kind = ?3, template_id = ?4, active = ?5
+             WHERE id = ?6"
+        )
+        .bind(rule.name.trim())
+        .bind(rule.offset_minutes)
+        .bind(&template_kind)
+        .bind(template_id)
+        .bind(rule.active.unwrap_or(true) as i64)
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to update reminder rule: {}", e))?;
+
+        if result.rows_affected() == 0 {
+            return Err(format!("Reminder rule {} not found", id));
+        }
+        Ok(id)
+    } else {
+        let result = sqlx::query(
+            "INSERT INTO reminder_rules (name, offset_minutes, template_kind, template_id, active)
+             VALUES (?1, ?2, ?3, ?4, ?5)"
+        )
+        .bind(rule.name.trim())
+        .bind(rule.offset_minutes)
+        .bind(&template_kind)
+        .bind(template_id)
+        .bind(rule.active.unwrap_or(true) as i64)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to create reminder rule: {}", e))?;
+
+        Ok(result.last_insert_rowid())
+    }
+}
+
+#[tauri::command]
+pub async fn delete_reminder_rule(
+    db_pool: State<'_, DbPool>,
+    id: i64,
+) -> Result<(), String> {
+    let pool = db_pool.0.lock().await;
+
+    sqlx::query("DELETE FROM reminder_rules WHERE id = ?1")
+        .bind(id)
+        .execute(&*pool)
+        .await
+        .map_err(|e| format!("Failed to delete reminder rule: {}", e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn tz(name: &str) -> ClinicTimezone {
+        ClinicTimezone::parse(name).unwrap()
+    }
+
+    fn local(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn appointment(starts_at: &str, ends_at: &str) -> Appointment {
+        Appointment {
+            id: None,
+            patient_id: 1,
+            starts_at: starts_at.to_string(),
+            ends_at: ends_at.to_string(),
+            procedure: "Control".to_string(),
+            notes: None,
+            status: "scheduled".to_string(),
+            provider_id: None,
+            resource_ids: None,
+            series_id: None,
+            appointment_type_id: None,
+            confirmed_at: None,
+            reminder_1d_sent_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn unknown_timezone_is_rejected() {
+        assert!(ClinicTimezone::parse("Mars/Olympus").is_err());
+        assert_eq!(tz(" America/Montevideo ").name(), "America/Montevideo");
+    }
+
+    #[test]
+    fn offsets_and_local_times_resolve_to_the_same_instant() {
+        let montevideo = tz("America/Montevideo");
+        let expected = "2025-01-15T13:00:00Z";
+        for value in ["2025-01-15T10:00:00-03:00", "2025-01-15T13:00:00Z", "2025-01-15T10:00:00", "2025-01-15 10:00"] {
+            assert_eq!(canonical_appointment_time(value, montevideo).unwrap(), expected, "{}", value);
+        }
+        assert!(canonical_appointment_time("15/01/2025 10:00", montevideo).is_err());
+    }
+
+    #[test]
+    fn spring_forward_gap_is_an_error() {
+        let new_york = tz("America/New_York");
+        assert!(new_york.to_utc(local("2024-03-10 02:30")).is_err());
+        assert_eq!(format_canonical_utc(new_york.to_utc(local("2024-03-10 03:00")).unwrap()), "2024-03-10T07:00:00Z");
+        assert!(canonical_appointment_time("2024-03-10T02:30:00", new_york).is_err());
+        // With an explicit offset the instant is exact, no gap involved
+        assert_eq!(canonical_appointment_time("2024-03-10T02:30:00-05:00", new_york).unwrap(), "2024-03-10T07:30:00Z");
+    }
+
+    #[test]
+    fn fall_back_ambiguity_takes_the_first_occurrence() {
+        let new_york = tz("America/New_York");
+        assert_eq!(format_canonical_utc(new_york.to_utc(local("2024-11-03 01:30")).unwrap()), "2024-11-03T05:30:00Z");
+        // The second 01:30 (EST) is still reachable with an explicit offset
+        let second = parse_appointment_instant("2024-11-03T01:30:00-05:00", new_york).unwrap();
+        assert_eq!(new_york.to_local(second), local("2024-11-03 01:30"));
+    }
+
+    #[test]
+    fn duration_across_fall_back_is_real_time() {
+        let new_york = tz("America/New_York");
+        let appointment = normalize_appointment_times(&appointment("2024-11-03T00:30:00", "2024-11-03T02:30:00"), new_york).unwrap();
+        let starts = parse_appointment_instant(&appointment.starts_at, new_york).unwrap();
+        let ends = parse_appointment_instant(&appointment.ends_at, new_york).unwrap();
+        assert_eq!((ends - starts).num_hours(), 3);
+    }
+
+    #[test]
+    fn day_start_skips_missing_midnight() {
+        // Chile springs forward at 00:00: 2024-09-08 starts at 01:00 local (-03:00)
+        let santiago = tz("America/Santiago");
+        let start = santiago.day_start_utc(NaiveDate::from_ymd_opt(2024, 9, 8).unwrap());
+        assert_eq!(format_canonical_utc(start), "2024-09-08T04:00:00Z");
+        assert_eq!(canonical_appointment_time("2024-09-08", santiago).unwrap(), "2024-09-08T04:00:00Z");
+        // The previous day has a normal midnight (-04:00)
+        let previous = santiago.day_start_utc(NaiveDate::from_ymd_opt(2024, 9, 7).unwrap());
+        assert_eq!(format_canonical_utc(previous), "2024-09-07T04:00:00Z");
+    }
+
+    #[test]
+    fn late_evening_local_time_is_next_utc_day() {
+        let montevideo = tz("America/Montevideo");
+        let canonical = canonical_appointment_time("2025-01-15T22:30:00", montevideo).unwrap();
+        assert_eq!(canonical, "2025-01-16T01:30:00Z");
+        assert_eq!(appointment_local_time(&canonical, montevideo).unwrap().date(), NaiveDate::from_ymd_opt(2025, 1, 15).unwrap());
+    }
+
+    #[test]
+    fn end_must_be_after_start_chronologically() {
+        let montevideo = tz("America/Montevideo");
+        // Crossing midnight is fine when the end is on the next day
+        assert!(normalize_appointment_times(&appointment("2025-01-15T23:30:00", "2025-01-16T00:30:00"), montevideo).is_ok());
+        assert!(normalize_appointment_times(&appointment("2025-01-15T23:30:00", "2025-01-15T00:30:00"), montevideo).is_err());
+        assert!(normalize_appointment_times(&appointment("2025-01-15T10:00:00", "2025-01-15T10:00:00"), montevideo).is_err());
+        // Lexically "10:00:00-03:00" > "12:30:00Z", but it is 13:00Z and therefore after the start
+        assert!(normalize_appointment_times(&appointment("2025-01-15T12:30:00Z", "2025-01-15T10:00:00-03:00"), montevideo).is_ok());
+    }
+
+    #[test]
+    fn canonical_strings_sort_chronologically() {
+        let montevideo = tz("America/Montevideo");
+        let mut values: Vec<String> = ["2025-01-15T23:00:00", "2025-01-16T01:00:00Z", "2025-01-15T21:30:00-03:00"]
+            .iter()
+            .map(|value| canonical_appointment_time(value, montevideo).unwrap())
+            .collect();
+        values.sort();
+        assert_eq!(values, vec!["2025-01-16T00:30:00Z", "2025-01-16T01:00:00Z", "2025-01-16T02:00:00Z"]);
+        assert!(values.iter().all(|value| value.len() == 20 && value.ends_with('Z')));
+    }
+
+    #[test]
+    fn ics_procedure_strips_name_whose_lowercase_changes_length() {
+        // 'İ' is 2 bytes but lowercases to 3 ("i̇"): offsets of a lowercased copy would panic
+        assert_eq!(ics_procedure("İsmail Yılmaz - Limpieza", Some("İSMAIL YıLMAZ")), "Limpieza");
+        assert_eq!(ics_procedure("Control - İrem", Some("irem")), "Control - İrem");
+        assert_eq!(ics_procedure("Endodoncia: ANA pérez", Some("Ana Pérez")), "Endodoncia");
+        assert_eq!(ics_procedure("Ana Pérez", Some("Ana Pérez")), "Cita");
+    }
+}
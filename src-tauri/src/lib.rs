@@ -12,6 +12,206 @@ pub use commands::*;
 // Estado compartido para el pool de base de datos
 pub struct DbPool(pub Arc<Mutex<SqlitePool>>);
 
+// Servidor del feed .ics en la LAN (None = detenido)
+#[derive(Default)]
+pub struct IcsFeedServer(pub Mutex<Option<commands::IcsFeedHandle>>);
+
+// Migración incremental: columnas nuevas en tablas existentes + SQL idempotente.
+// Las columnas se agregan ANTES del SQL para que índices/triggers puedan usarlas.
+pub struct Migration {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, &'static str, &'static str)], // (tabla, columna, definición)
+    pub sql: &'static str,
+}
+
+// Migraciones posteriores al esquema unificado (se ejecutan en orden en cada arranque)
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "002_treatment_plans",
+        columns: &[("session_items", "treatment_plan_item_id", "INTEGER")],
+        sql: include_str!("../migrations/002_treatment_plans.sql"),
+    },
+    Migration {
+        name: "003_tooth_events",
+        columns: &[],
+        sql: include_str!("../migrations/003_tooth_events.sql"),
+    },
+    Migration {
+        name: "004_periodontal_charts",
+        columns: &[],
+        sql: include_str!("../migrations/004_periodontal_charts.sql"),
+    },
+    Migration {
+        name: "005_prescriptions",
+        columns: &[],
+        sql: include_str!("../migrations/005_prescriptions.sql"),
+    },
+    Migration {
+        name: "006_lab_orders",
+        columns: &[("session_items", "lab_order_id", "INTEGER")],
+        sql: include_str!("../migrations/006_lab_orders.sql"),
+    },
+    Migration {
+        name: "007_session_signoff",
+        columns: &[
+            ("sessions", "signed_at", "TEXT"),
+            ("sessions", "signed_by", "TEXT"),
+            ("sessions", "content_hash", "TEXT"),
+        ],
+        sql: include_str!("../migrations/007_session_signoff.sql"),
+    },
+    Migration {
+        name: "008_clinical_codes",
+        columns: &[
+            ("diagnosis_options", "cie10_code", "TEXT"),
+            ("procedure_templates", "procedure_code", "TEXT"),
+            ("session_items", "procedure_code", "TEXT"),
+        ],
+        sql: include_str!("../migrations/008_clinical_codes.sql"),
+    },
+    Migration {
+        name: "009_reminder_templates",
+        columns: &[],
+        sql: include_str!("../migrations/009_reminder_templates.sql"),
+    },
+    Migration {
+        name: "010_recall_rules",
+        columns: &[],
+        sql: include_str!("../migrations/010_recall_rules.sql"),
+    },
+    Migration {
+        name: "011_ortho_cases",
+        columns: &[("attachments", "ortho_case_id", "INTEGER")],
+        sql: include_str!("../migrations/011_ortho_cases.sql"),
+    },
+    Migration {
+        name: "012_soap_notes",
+        columns: &[
+            ("sessions", "soap_subjective", "TEXT"),
+            ("sessions", "soap_objective", "TEXT"),
+            ("sessions", "soap_assessment", "TEXT"),
+            ("sessions", "soap_plan", "TEXT"),
+        ],
+        sql: include_str!("../migrations/012_soap_notes.sql"),
+    },
+    Migration {
+        name: "013_providers",
+        columns: &[
+            ("sessions", "provider_id", "INTEGER"),
+            ("session_items", "provider_id", "INTEGER"),
+            ("appointments", "provider_id", "INTEGER"),
+        ],
+        sql: include_str!("../migrations/013_providers.sql"),
+    },
+    Migration {
+        name: "014_resources",
+        columns: &[],
+        sql: include_str!("../migrations/014_resources.sql"),
+    },
+    Migration {
+        name: "015_schedule",
+        columns: &[],
+        sql: include_str!("../migrations/015_schedule.sql"),
+    },
+    Migration {
+        name: "016_appointment_series",
+        columns: &[("appointments", "series_id", "INTEGER")],
+        sql: include_str!("../migrations/016_appointment_series.sql"),
+    },
+    Migration {
+        name: "017_waitlist",
+        columns: &[],
+        sql: include_str!("../migrations/017_waitlist.sql"),
+    },
+    Migration {
+        name: "018_appointment_status_history",
+        columns: &[],
+        sql: include_str!("../migrations/018_appointment_status_history.sql"),
+    },
+    Migration {
+        name: "019_session_appointments",
+        columns: &[("sessions", "appointment_id", "INTEGER")],
+        sql: include_str!("../migrations/019_session_appointments.sql"),
+    },
+    Migration {
+        name: "020_ics_import",
+        columns: &[("appointments", "external_uid", "TEXT")],
+        sql: include_str!("../migrations/020_ics_import.sql"),
+    },
+    Migration {
+        name: "021_appointment_types",
+        columns: &[("appointments", "appointment_type_id", "INTEGER")],
+        sql: include_str!("../migrations/021_appointment_types.sql"),
+    },
+    Migration {
+        name: "022_reminder_rules",
+        columns: &[],
+        sql: include_str!("../migrations/022_reminder_rules.sql"),
+    },
+];
+
+/// Agrega una columna si la tabla existe y aún no la tiene (ALTER TABLE no es idempotente en SQLite)
+async fn ensure_column(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let table_exists: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1"
+    )
+    .bind(table)
+    .fetch_one(pool)
+    .await?;
+
+    if table_exists == 0 {
+        return Ok(());
+    }
+
+    let column_exists: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2"
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(pool)
+    .await?;
+
+    if column_exists == 0 {
+        sqlx::raw_sql(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Ejecuta el esquema unificado y luego todas las migraciones incrementales
+pub async fn run_migrations(pool: &SqlitePool) -> Result<(), String> {
+    let unified_schema = include_str!("../migrations/001_unified_schema.sql");
+    sqlx::raw_sql(unified_schema)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("001_unified_schema: {}", e))?;
+
+    for migration in MIGRATIONS {
+        for (table, column, definition) in migration.columns {
+            ensure_column(pool, table, column, definition)
+                .await
+                .map_err(|e| format!("{}: {}", migration.name, e))?;
+        }
+
+        sqlx::raw_sql(migration.sql)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("{}: {}", migration.name, e))?;
+    }
+
+    // Data migration: appointment times in canonical UTC (no-op once converted)
+    normalize_appointment_timestamps(pool).await?;
+
+    Ok(())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -50,15 +250,20 @@ pub fn run() {
                     .await
                     .expect("Failed to create database pool");
 
-                // Ejecutar migración unificada (incluye todos los PRAGMAs y esquemas)
-                let unified_schema = include_str!("../migrations/001_unified_schema.sql");
-                sqlx::raw_sql(unified_schema)
-                    .execute(&pool)
+                // Ejecutar migración unificada (PRAGMAs y esquemas) + migraciones incrementales
+                run_migrations(&pool)
                     .await
-                    .expect("Failed to run unified schema migration");
+                    .expect("Failed to run database migrations");
 
                 // Crear el DbPool y agregarlo al state de Tauri
                 let db_pool = DbPool(Arc::new(Mutex::new(pool)));
+
+                // Feed .ics opt-in: solo se levanta si el usuario lo habilitó
+                let feed_server = commands::autostart_ics_feed(db_pool.0.clone()).await;
+                app.manage(IcsFeedServer(Mutex::new(feed_server)));
+
+                // Recordatorios programados (reglas de reminder_rules) en segundo plano
+                commands::spawn_reminder_scheduler(db_pool.0.clone());
                 app.manage(db_pool);
 
                 println!("Database initialized successfully");
@@ -159,6 +364,128 @@ pub fn run() {
             get_consents_by_patient,
             create_informed_consent,
             get_consent_by_id,
+            // Treatment Plans commands
+            get_treatment_plans_by_patient,
+            get_treatment_plan,
+            save_treatment_plan,
+            accept_treatment_plan,
+            update_treatment_plan_status,
+            delete_treatment_plan,
+            convert_plan_items_to_session_items,
+            // Odontogram commands
+            get_odontogram,
+            migrate_tooth_dx_json,
+            // Tooth history commands
+            get_tooth_history,
+            rebuild_tooth_history,
+            diff_odontogram,
+            // Periodontal chart commands
+            get_perio_chart,
+            get_perio_charts_by_patient,
+            save_perio_chart,
+            delete_perio_chart,
+            compare_perio_charts,
+            // Prescription commands
+            get_drug_catalog,
+            save_drug,
+            delete_drug,
+            check_prescription_allergies,
+            create_prescription,
+            get_prescriptions_by_patient,
+            get_prescription,
+            void_prescription,
+            generate_prescription_pdf,
+            // Lab order commands
+            get_lab_orders_by_patient,
+            get_lab_order,
+            save_lab_order,
+            update_lab_order_status,
+            delete_lab_order,
+            list_overdue_lab_orders,
+            list_lab_order_agenda_alerts,
+            get_procedure_profitability,
+            // Session sign-off commands
+            sign_session,
+            verify_session_signature,
+            add_session_addendum,
+            get_session_addenda,
+            // Clinical code commands
+            search_clinical_codes,
+            import_clinical_codes,
+            // Template engine commands
+            get_template_variables,
+            render_template,
+            // Recall commands
+            get_recall_rules,
+            save_recall_rule,
+            delete_recall_rule,
+            list_due_recalls,
+            generate_recall_messages,
+            // Ortho case commands
+            get_ortho_cases_by_patient,
+            save_ortho_case,
+            delete_ortho_case,
+            save_ortho_archwire_entry,
+            get_ortho_archwire_log,
+            delete_ortho_archwire_entry,
+            link_ortho_photo,
+            generate_ortho_charges,
+            get_ortho_progress_report,
+            // SOAP note commands
+            prefill_soap_note,
+            // Provider commands
+            get_providers,
+            save_provider,
+            delete_provider,
+            get_provider_production_report,
+            // Resource commands
+            get_resources,
+            save_resource,
+            delete_resource,
+            // Appointment type commands
+            get_appointment_types,
+            save_appointment_type,
+            delete_appointment_type,
+            // Schedule commands
+            get_schedule,
+            save_schedule,
+            get_schedule_closures,
+            save_schedule_closure,
+            delete_schedule_closure,
+            // Recurring appointment commands
+            create_appointment_series,
+            update_appointment_series,
+            cancel_appointment_series,
+            // Waitlist commands
+            get_waitlist,
+            save_waitlist_entry,
+            delete_waitlist_entry,
+            offer_slot_to_waitlist,
+            // Timezone commands
+            get_clinic_timezone,
+            set_clinic_timezone,
+            // Appointment status commands
+            set_appointment_status,
+            get_appointment_status_history,
+            get_attendance_report,
+            get_patient_attendance,
+            // Appointment reconciliation commands
+            list_unattended_appointments,
+            link_session_to_appointment,
+            // iCalendar commands
+            export_appointments_ics,
+            get_appointment_ics,
+            import_appointments_ics,
+            // ICS feed commands
+            get_ics_feed_status,
+            enable_ics_feed,
+            disable_ics_feed,
+            regenerate_ics_feed_token,
+            // Reminder rule commands
+            get_reminder_rules,
+            save_reminder_rule,
+            delete_reminder_rule,
+            run_reminder_scheduler,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
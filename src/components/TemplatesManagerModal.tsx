@@ -24,16 +24,6 @@ const TEMPLATE_CATEGORIES: Record<TemplateKind, string> = {
   payment_notes: "Notas de Pago",
 };
 
-// Variables disponibles para cada categoría (español, una sola llave)
-const TEMPLATE_VARIABLES: Record<TemplateKind, string[]> = {
-  whatsapp_message: ["{nombre}", "{saldo}", "{dias}"],
-  diagnosis: ["{nombre}", "{edad}", "{pieza}", "{fecha}", "{doctor}"],
-  clinical_notes: ["{nombre}", "{edad}", "{fecha}", "{doctor}"],
-  reason_detail: ["{nombre}", "{edad}", "{fecha}", "{doctor}"],
-  procedure_notes: ["{nombre}", "{procedimiento}", "{pieza}", "{fecha}", "{doctor}"],
-  payment_notes: ["{monto}", "{nombre}", "{fecha}"],
-};
-
 export function TemplatesManagerModal({
   open,
   onOpenChange,
@@ -47,6 +37,8 @@ export function TemplatesManagerModal({
   const [isCreating, setIsCreating] = useState(false);
   const [newTemplateTitle, setNewTemplateTitle] = useState("");
   const [newTemplateBody, setNewTemplateBody] = useState("");
+  // Variables disponibles de la categoría (registro del backend, español, una sola llave)
+  const [variables, setVariables] = useState<string[]>([]);
 
   // Actualizar categoría cuando cambie defaultCategory
   useEffect(() => {
@@ -58,6 +50,10 @@ export function TemplatesManagerModal({
   useEffect(() => {
     if (open) {
       loadTemplates();
+      tauriSqliteRepository
+        .getTemplateVariables(selectedCategory)
+        .then((vars) => setVariables(vars.map((v) => `{${v}}`)))
+        .catch(() => setVariables([]));
     }
   }, [open, selectedCategory]);
 
@@ -166,7 +162,7 @@ export function TemplatesManagerModal({
               Variables disponibles:
             </p>
             <div className="flex flex-wrap gap-1.5">
-              {TEMPLATE_VARIABLES[selectedCategory].map((variable) => (
+              {variables.map((variable) => (
                 <code
                   key={variable}
                   className="px-2 py-1 bg-[hsl(var(--background))] border border-[hsl(var(--border))] rounded text-xs font-mono"
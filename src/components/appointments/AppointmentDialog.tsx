@@ -9,7 +9,11 @@ import {
   SelectItem,
   SelectTrigger,
 } from "../ui/Select";
-import type { Appointment, DoctorProfile } from "../../lib/types";
+import type {
+  Appointment,
+  DoctorProfile,
+  PatientAttendance,
+} from "../../lib/types";
 import { tauriSqliteRepository } from "../../lib/storage/TauriSqliteRepository";
 import { Alert } from "../ui/Alert";
 import { Loader2 } from "lucide-react";
@@ -44,6 +48,7 @@ export const AppointmentDialog = ({
   const [procedure, setProcedure] = useState("");
   const [notes, setNotes] = useState("");
   const [status, setStatus] = useState<Appointment["status"]>("scheduled");
+  const [attendance, setAttendance] = useState<PatientAttendance | null>(null);
 
   // For appointment picker
   const [bookedSlots, setBookedSlots] = useState<
@@ -120,6 +125,15 @@ export const AppointmentDialog = ({
 
       fetchBookedSlots();
 
+      // Warn when booking a habitual no-show patient
+      setAttendance(null);
+      if (!appointment) {
+        tauriSqliteRepository
+          .getPatientAttendance(patientId)
+          .then(setAttendance)
+          .catch((err) => console.error("Error loading attendance:", err));
+      }
+
       // Initialize form fields
       if (appointment) {
         // Editing existing appointment - hide picker
@@ -150,7 +164,7 @@ export const AppointmentDialog = ({
       }
       setError(null);
     }
-  }, [open, appointment]);
+  }, [open, appointment, patientId]);
 
   // Handle slot selection from picker
   const handleSlotSelect = (slotDate: Date) => {
@@ -176,14 +190,23 @@ export const AppointmentDialog = ({
         startsAt.getTime() + parseInt(durationMinutes) * 60000,
       );
 
-      const appointmentData: Appointment = {
-        patient_id: patientId,
-        starts_at: startsAt.toISOString(),
-        ends_at: endsAt.toISOString(),
-        procedure: "Cita", // Default procedure name
-        notes: notes.trim() || undefined,
-        status: "scheduled", // Always start as scheduled
-      };
+      // Editing only changes time and notes: status, confirmation and the rest are kept
+      const appointmentData: Appointment = appointment
+        ? {
+            ...appointment,
+            starts_at: startsAt.toISOString(),
+            ends_at: endsAt.toISOString(),
+            notes: notes.trim() || undefined,
+            status,
+          }
+        : {
+            patient_id: patientId,
+            starts_at: startsAt.toISOString(),
+            ends_at: endsAt.toISOString(),
+            procedure: "Cita", // Default procedure name
+            notes: notes.trim() || undefined,
+            status: "scheduled", // Always start as scheduled
+          };
 
       if (appointment?.id) {
         await tauriSqliteRepository.updateAppointment(
@@ -236,6 +259,17 @@ export const AppointmentDialog = ({
           </Alert>
         )}
 
+        {attendance?.habitual_no_show && (
+          <Alert variant="warning">
+            <p className="text-sm">
+              Paciente con inasistencias frecuentes: faltó a {attendance.no_shows}{" "}
+              de {attendance.total} citas del último año (
+              {Math.round(attendance.no_show_rate * 100)}%). Considera
+              confirmar la cita con anticipación.
+            </p>
+          </Alert>
+        )}
+
         {/* Show appointment picker for new appointments */}
         {showPicker && !appointment && (
           <div>
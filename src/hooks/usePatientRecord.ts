@@ -1,5 +1,5 @@
 // src/hooks/usePatientRecord.ts
-import { useCallback, useMemo, useState, useEffect } from "react";
+import { useCallback, useMemo, useRef, useState, useEffect } from "react";
 import type {
   AttachmentFile,
   Patient,
@@ -11,6 +11,12 @@ import type {
 } from "../lib/types";
 import { getRepository } from "../lib/storage/TauriSqliteRepository";
 import { saveAttachmentFile } from "../lib/files/attachments";
+import {
+  parseOdontogramDetail,
+  parseToothDx,
+  serializeToothDx,
+  type OdontogramV1,
+} from "../lib/print-utils";
 import { useToast } from "./useToast";
 
 // Initial states
@@ -106,6 +112,8 @@ export function usePatientRecord() {
   const [originalManualDiagnosis, setOriginalManualDiagnosis] = useState("");
   const [originalSessionOdontograms, setOriginalSessionOdontograms] = useState<Map<number, ToothDx>>(new Map());
   const [originalDraftSessions, setOriginalDraftSessions] = useState<Map<number, string>>(new Map());
+  // Versioned odontogram per session (surfaces/notes the flat ToothDx cannot hold)
+  const odontogramDetails = useRef<Map<number, OdontogramV1 | null>>(new Map());
 
   // Computed diagnosis from teeth (based on active session's odontogram)
   const diagnosisFromTeeth = useMemo(() => {
@@ -282,6 +290,11 @@ export function usePatientRecord() {
       for (const [tooth, diagnoses] of Object.entries(latestOdontogram)) {
         nextOdontogram[tooth] = [...diagnoses];
       }
+      // Copy surfaces/notes too, so the copied conditions keep them when saved
+      odontogramDetails.current.set(
+        newSessionId,
+        latestSavedId ? odontogramDetails.current.get(latestSavedId) ?? null : null,
+      );
     }
 
     const newSession: SessionWithItems = {
@@ -399,8 +412,9 @@ export function usePatientRecord() {
 
         // NOTE: sessionPayload is legacy - we now save per-session odontogram
         // Keep this for backward compatibility with saveVisitWithSessions
-        const toothDxJson = activeSessionId && sessionOdontograms.get(activeSessionId)
-          ? JSON.stringify(sessionOdontograms.get(activeSessionId))
+        const activeToothDx = activeSessionId ? sessionOdontograms.get(activeSessionId) : undefined;
+        const toothDxJson = activeSessionId && activeToothDx
+          ? serializeToothDx(activeToothDx, odontogramDetails.current.get(activeSessionId))
           : undefined;
 
         const sessionPayload: Session = {
@@ -483,7 +497,7 @@ export function usePatientRecord() {
             const sessionId = s.session.id!;
             const sessionOdonto = sessionOdontograms.get(sessionId);
             const toothDxJsonForSession = sessionOdonto && Object.keys(sessionOdonto).length > 0
-              ? JSON.stringify(sessionOdonto)
+              ? serializeToothDx(sessionOdonto, odontogramDetails.current.get(sessionId))
               : undefined;
 
             return {
@@ -579,6 +593,10 @@ export function usePatientRecord() {
           "La historia clinica se ha guardado correctamente",
         );
 
+        // Saved drafts now share session_id; keep the active one's detail for the next save
+        const savedDetail = parseOdontogramDetail(toothDxJson);
+        if (savedDetail) odontogramDetails.current.set(session_id, savedDetail);
+
         // Snapshot saved odontograms
         setOriginalSessionOdontograms(new Map(sessionOdontograms));
         setOriginalManualDiagnosis(manualDiagnosis);
@@ -702,7 +720,7 @@ export function usePatientRecord() {
       if (hasOdontogramChanges && patientId != null && activeSessionId) {
         const activeOdonto = sessionOdontograms.get(activeSessionId);
         const toothDxJson = activeOdonto && Object.keys(activeOdonto).length > 0
-          ? JSON.stringify(activeOdonto)
+          ? serializeToothDx(activeOdonto, odontogramDetails.current.get(activeSessionId))
           : null;
         const activeSession = sessions.find(
           (s) => s.session.id === activeSessionId,
@@ -718,6 +736,7 @@ export function usePatientRecord() {
           fullDiagnosis || null
         );
 
+        odontogramDetails.current.set(session_id, parseOdontogramDetail(toothDxJson));
         setSession((prev) => ({ ...prev, id: session_id }));
         setActiveSessionId(session_id);
 
@@ -874,15 +893,16 @@ export function usePatientRecord() {
 
       // NEW: Load odontograms for ALL sessions into Map
       const odontogramMap = new Map<number, ToothDx>();
+      odontogramDetails.current = new Map();
       allSess.forEach((s) => {
+        if (s.session.id) {
+          odontogramDetails.current.set(
+            s.session.id,
+            parseOdontogramDetail(s.session.tooth_dx_json),
+          );
+        }
         if (s.session.id && s.session.tooth_dx_json) {
-          try {
-            const parsed = JSON.parse(s.session.tooth_dx_json) as ToothDx;
-            odontogramMap.set(s.session.id, parsed);
-          } catch (e) {
-            console.warn(`Failed to parse tooth_dx_json for session ${s.session.id}:`, e);
-            odontogramMap.set(s.session.id, {});
-          }
+          odontogramMap.set(s.session.id, parseToothDx(s.session.tooth_dx_json));
         } else if (s.session.id) {
           odontogramMap.set(s.session.id, {});
         }
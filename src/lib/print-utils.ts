@@ -62,19 +62,83 @@ export function truncateText(text: string, maxLength: number): string {
   return text.substring(0, maxLength) + "...";
 }
 
+/**
+ * Versioned odontogram stored by the backend (schema_version >= 1)
+ */
+export type OdontogramV1 = {
+  schema_version: number;
+  teeth: Record<string, { conditions: OdontogramCondition[] }>;
+};
+
+type OdontogramCondition = {
+  diagnosis: string;
+  surfaces?: string[];
+  notes?: string;
+};
+
+const ODONTOGRAM_SCHEMA_VERSION = 1;
+
 /**
  * Parse ToothDx JSON string safely
+ * Accepts both the legacy shape ({"11": ["Caries"]}) and the versioned one
  */
-export function parseToothDx(toothDxJson?: string): ToothDx {
+export function parseToothDx(toothDxJson?: string | null): ToothDx {
   if (!toothDxJson) return {};
   try {
-    return JSON.parse(toothDxJson) as ToothDx;
+    const parsed = JSON.parse(toothDxJson);
+    if (parsed && typeof parsed === "object" && "schema_version" in parsed) {
+      const teeth = (parsed as OdontogramV1).teeth ?? {};
+      return Object.fromEntries(
+        Object.entries(teeth).map(([tooth, state]) => [
+          tooth,
+          (state.conditions ?? []).map((c) => c.diagnosis),
+        ]),
+      );
+    }
+    return parsed as ToothDx;
   } catch (error) {
     console.error("Error parsing tooth_dx_json:", error);
     return {};
   }
 }
 
+/**
+ * Versioned odontogram with surfaces and notes (null for empty or legacy JSON)
+ */
+export function parseOdontogramDetail(toothDxJson?: string | null): OdontogramV1 | null {
+  if (!toothDxJson) return null;
+  try {
+    const parsed = JSON.parse(toothDxJson);
+    return parsed && typeof parsed === "object" && "schema_version" in parsed
+      ? (parsed as OdontogramV1)
+      : null;
+  } catch {
+    return null;
+  }
+}
+
+/**
+ * Serialize the flat ToothDx edited in the UI as a versioned odontogram.
+ * Conditions that still exist on the same tooth keep the surfaces and notes from `detail`;
+ * new ones are whole-tooth conditions.
+ */
+export function serializeToothDx(toothDx: ToothDx, detail?: OdontogramV1 | null): string {
+  const teeth: OdontogramV1["teeth"] = {};
+
+  for (const [tooth, diagnoses] of Object.entries(toothDx)) {
+    const previous = [...(detail?.teeth?.[tooth]?.conditions ?? [])];
+    const conditions = diagnoses.map((diagnosis) => {
+      const index = previous.findIndex((c) => c.diagnosis === diagnosis);
+      if (index < 0) return { diagnosis };
+      const [{ surfaces, notes }] = previous.splice(index, 1);
+      return { diagnosis, ...(surfaces?.length ? { surfaces } : {}), ...(notes ? { notes } : {}) };
+    });
+    if (conditions.length > 0) teeth[tooth] = { conditions };
+  }
+
+  return JSON.stringify({ schema_version: ODONTOGRAM_SCHEMA_VERSION, teeth });
+}
+
 /**
  * Get tooth classification (permanent/deciduous)
  */
@@ -155,6 +155,7 @@ export class TauriSqliteRepository {
       type RustSessionRow = {
         visit: Session;
         items: import("../types").SessionItem[];
+        addenda?: import("../types").SessionAddendum[];
       };
       const rustData = await invoke<RustSessionRow[]>("get_sessions_by_visit", {
         visitId: sessionId,
@@ -164,6 +165,7 @@ export class TauriSqliteRepository {
       return rustData.map((row) => ({
         session: row.visit,
         items: row.items,
+        addenda: row.addenda ?? [],
       }));
     } catch (error) {
       console.error("Error en getSessionsWithItems:", error);
@@ -799,6 +801,7 @@ export class TauriSqliteRepository {
       type RustSessionRow = {
         visit: Session;
         items: import("../types").SessionItem[];
+        addenda?: import("../types").SessionAddendum[];
       };
       const rustData = await invoke<RustSessionRow[]>(
         "get_sessions_by_patient",
@@ -959,6 +962,16 @@ export class TauriSqliteRepository {
     }
   }
 
+  /** Variables aceptadas por el motor de plantillas para un tipo (sin llaves) */
+  async getTemplateVariables(kind: string): Promise<string[]> {
+    try {
+      return await invoke<string[]>("get_template_variables", { kind });
+    } catch (error) {
+      console.error("Error getting template variables:", error);
+      throw error;
+    }
+  }
+
   async updateTextTemplate(
     id: number,
     body: string,
@@ -1072,6 +1085,20 @@ export class TauriSqliteRepository {
     }
   }
 
+  async getPatientAttendance(
+    patientId: number
+  ): Promise<import("../types").PatientAttendance> {
+    try {
+      return await invoke<import("../types").PatientAttendance>(
+        "get_patient_attendance",
+        { patientId }
+      );
+    } catch (error) {
+      console.error("Error getting patient attendance:", error);
+      throw error;
+    }
+  }
+
   async generateAvailableSlots(options: {
     days?: number;
     slotMinutes?: number;
@@ -65,18 +65,34 @@ export type Session = {
 
   // Administrative
   signer?: string;
+  provider_id?: number; // NEW: Attending provider (resolved from signer if omitted)
+  appointment_id?: number | null; // NEW: Appointment attended in this session (marked completed on save)
   clinical_notes?: string; // RENAMED: from observations
+  soap?: SoapNote; // NEW: Structured note; clinical_notes gets the flattened text on save
   is_saved?: boolean;
 
   // Metadata
   created_at?: string;
   updated_at?: string;
+
+  // Sign-off (read-only, set by sign_session)
+  signed_at?: string | null;
+  signed_by?: string | null;
+  content_hash?: string | null;
 };
 
 // DEPRECATED: Use Session instead
 /** @deprecated Use Session type instead */
 export type Visit = Session;
 
+// SOAP note (Subjetivo, Objetivo, Evaluación, Plan)
+export type SoapNote = {
+  subjective?: string;
+  objective?: string;
+  assessment?: string;
+  plan?: string;
+};
+
 export type SessionItem = {
   id?: number;
   session_id?: number;
@@ -89,6 +105,10 @@ export type SessionItem = {
   procedure_notes?: string; // NEW: Notes specific to this procedure
   procedure_template_id?: number; // Optional: for audit trail
   sort_order?: number;
+  treatment_plan_item_id?: number; // NEW: Planned item performed in this session
+  lab_order_id?: number; // NEW: Lab work (crown, bridge...) for this item
+  procedure_code?: string; // NEW: CDT code (defaults to the template's code)
+  provider_id?: number; // NEW: Performing provider (defaults to the session's)
   created_at?: string;
 };
 
@@ -127,6 +147,19 @@ export type ProcedureTemplate = {
   name: string;
   default_price: number; // REAL ahora (soporta decimales)
   active?: boolean;
+  procedure_code?: string; // CDT; omit = keep, "" = remove
+  created_at?: string;
+  updated_at?: string;
+};
+
+export type Provider = {
+  id?: number;
+  name: string;
+  license_number?: string;
+  specialty?: string;
+  color?: string;
+  commission_rate?: number; // % of collections (0-100)
+  active?: boolean;
   created_at?: string;
   updated_at?: string;
 };
@@ -145,10 +178,20 @@ export type DiagnosisOption = {
   color: "success" | "info" | "warning" | "danger" | "default";
   active?: boolean;
   sort_order?: number;
+  cie10_code?: string; // CIE-10; omit = keep, "" = remove
   created_at?: string;
   updated_at?: string;
 };
 
+export type ClinicalCode = {
+  id?: number;
+  system: "CIE10" | "CDT";
+  code: string;
+  description: string;
+  category?: string;
+  active?: boolean;
+};
+
 export type ReasonType = {
   id?: number;
   name: string;
@@ -189,6 +232,14 @@ export type TextTemplate = {
   updated_at?: string;
 };
 
+// Resultado de render_template (variables sin valor quedan como {variable})
+export type RenderedTemplate = {
+  template_id?: number;
+  kind: string;
+  text: string;
+  missing_variables: string[];
+};
+
 // -------- TELEMETRY & OBSERVABILITY --------
 
 export type TelemetryEvent = {
@@ -251,6 +302,18 @@ export type ToothDx = Record<string, string[]>;
 export type SessionWithItems = {
   session: Session;
   items: SessionItem[];
+  addenda?: SessionAddendum[]; // Read-only: appended after sign-off
+};
+
+/**
+ * SessionAddendum: Append-only note on a signed session
+ */
+export type SessionAddendum = {
+  id?: number;
+  session_id: number;
+  author: string;
+  body: string;
+  created_at?: string;
 };
 
 // DEPRECATED: Use SessionWithItems instead
@@ -334,13 +397,17 @@ export type PatientListItem = {
 export type Appointment = {
   id?: number;
   patient_id: number;
-  starts_at: string;  // ISO 8601 datetime string (e.g. "2025-01-15T10:00:00-03:00")
-  ends_at: string;    // ISO 8601 datetime string
-  procedure: string;  // What service is scheduled
+  starts_at: string;  // Stored as canonical UTC ("2025-01-15T13:00:00Z"); input may carry an offset or be clinic-local
+  ends_at: string;    // Same format as starts_at (empty = start + appointment type duration)
+  procedure: string;  // What service is scheduled (empty = appointment type name)
   notes?: string;     // Optional appointment notes
   status: "scheduled" | "confirmed" | "cancelled" | "no_show" | "completed";
+  provider_id?: number | null; // NEW: Overlaps are checked per provider (omitted on update = keep, 0 = clear)
+  resource_ids?: number[] | null; // NEW: Chairs/rooms/equipment used (overlaps checked per resource; omitted on update = keep)
+  series_id?: number | null; // NEW: Recurring series this occurrence belongs to
+  appointment_type_id?: number | null; // NEW: Catalog type (default duration, required resources, preparation notes; omitted on update = keep, 0 = clear)
   confirmed_at?: string;       // When patient confirmed
-  reminder_1d_sent_at?: string; // When 1-day reminder was created
+  reminder_1d_sent_at?: string; // When the 24h reminder was created (per-rule tracking: reminder rules)
   created_at?: string;
   updated_at?: string;
 };
@@ -352,6 +419,213 @@ export type Appointment = {
 export type AvailableSlot = {
   starts_at: string;  // ISO 8601 datetime
   ends_at: string;    // ISO 8601 datetime
+  resource_id?: number | null;    // Chair/room the slot belongs to (null = no resources configured)
+  resource_name?: string | null;
+};
+
+/**
+ * Resource: Chair, room or equipment that appointments can be booked on
+ */
+export type Resource = {
+  id?: number;
+  name: string;
+  kind: "chair" | "room" | "equipment";
+  color?: string | null;
+  active?: boolean;
+  sort_order?: number;
+  created_at?: string;
+  updated_at?: string;
+};
+
+/**
+ * AppointmentType: Catalog entry that fills in duration, resources and reminder instructions
+ */
+export type AppointmentType = {
+  id?: number;
+  name: string;
+  duration_minutes: number;          // Default length (ends_at) and slot size
+  color?: string | null;
+  preparation_notes?: string | null; // Added to the appointment reminder ({preparacion})
+  resource_ids?: number[];           // Required resources, added to the appointment's own
+  procedure_template_ids?: number[]; // Linked procedure templates
+  active?: boolean;
+  sort_order?: number;
+  created_at?: string;
+  updated_at?: string;
+};
+
+/**
+ * Recurring appointment series (RRULE subset: FREQ=WEEKLY|MONTHLY, INTERVAL, BYDAY, BYMONTHDAY, COUNT, UNTIL)
+ * Returned by create_appointment_series with one entry per occurrence
+ */
+export type SeriesOccurrence = {
+  starts_at: string;
+  ends_at: string;
+  appointment_id: number | null; // null = not created (see error)
+  error: string | null;          // Overlap, outside working hours, ...
+};
+
+export type AppointmentSeriesResult = {
+  series_id: number | null;      // null on dry run or when nothing was created
+  created: number;
+  skipped: number;
+  occurrences: SeriesOccurrence[];
+};
+
+/** Scope for editing/cancelling a recurring appointment */
+export type SeriesEditScope = "this" | "following" | "all";
+
+/**
+ * WaitlistEntry: Patient waiting for a slot. Freed (cancelled) slots are offered
+ * to the best-ranked entries via an 'availability' message in the queue
+ */
+export type WaitlistEntry = {
+  id?: number;
+  patient_id: number;
+  patient_name?: string;          // Read-only
+  procedure: string;
+  provider_id?: number | null;    // null = any provider
+  preferred_weekdays?: number[];  // 0 = Sunday … 6 = Saturday; empty = any day
+  preferred_start_time?: string | null; // "HH:MM"
+  preferred_end_time?: string | null;
+  urgency?: "normal" | "high" | "urgent";
+  status?: "waiting" | "offered" | "booked" | "removed";
+  notes?: string | null;
+  last_offered_at?: string | null;
+  created_at?: string;
+  updated_at?: string;
+};
+
+export type WaitlistOffer = {
+  entry_id: number;
+  patient_id: number;
+  patient_name: string;
+  score: number;
+  message_id: number | null; // null on dry run
+};
+
+/**
+ * AppointmentStatusEvent: One entry of an appointment's status history
+ */
+export type AppointmentStatusEvent = {
+  id: number;
+  appointment_id: number;
+  from_status: Appointment["status"] | null; // null = appointment created
+  to_status: Appointment["status"];
+  reason: string | null;     // e.g. cancellation reason
+  changed_by: string | null; // "patient" | "clinic" | staff name
+  changed_at: string;        // Canonical UTC
+};
+
+/**
+ * PatientAttendance: No-show and late-cancellation stats for a patient
+ */
+export type PatientAttendance = {
+  patient_id: number;
+  patient_name: string;
+  total: number;               // completed + no_shows + cancellations
+  completed: number;
+  no_shows: number;
+  cancellations: number;
+  late_cancellations: number;  // Cancelled with less than lateCancellationHours notice
+  no_show_rate: number;        // 0–1
+  late_cancellation_rate: number;
+  habitual_no_show: boolean;   // Warn when booking
+};
+
+/**
+ * UnattendedAppointment: Past appointment still scheduled/confirmed with no session (daily reconciliation)
+ */
+export type UnattendedAppointment = {
+  appointment_id: number;
+  patient_id: number;
+  patient_name: string;
+  phone: string;
+  starts_at: string;
+  ends_at: string;
+  procedure: string;
+  status: "scheduled" | "confirmed";
+  provider_id: number | null;
+  unlinked_session_id: number | null; // Same-day session without appointment: link it instead of marking no-show
+};
+
+/**
+ * IcsFile: iCalendar file to save or attach (per-appointment invite)
+ */
+export type IcsFile = {
+  filename: string; // e.g. "cita-20250115-1000.ics"
+  content: string;  // RFC 5545 text (CRLF line endings)
+};
+
+/**
+ * IcsImportResult: Per-event report of an .ics import (or dry run)
+ */
+export type IcsImportEvent = {
+  uid: string | null;
+  summary: string;
+  starts_at: string | null; // Canonical UTC
+  ends_at: string | null;
+  patient_id: number | null;
+  outcome: "imported" | "duplicate" | "conflict" | "skipped" | "invalid";
+  appointment_id: number | null; // Created or already existing appointment
+  message: string | null;        // Why it was not imported
+};
+
+export type IcsImportResult = {
+  imported: number;
+  duplicates: number;
+  conflicts: number;
+  skipped: number;
+  invalid: number;
+  events: IcsImportEvent[];
+};
+
+/**
+ * IcsFeedStatus: LAN calendar subscription (read-only, token-protected .ics feed)
+ */
+export type IcsFeedStatus = {
+  enabled: boolean;
+  running: boolean;          // false while enabled = port could not be opened
+  port: number;
+  token: string | null;
+  url: string | null;        // http://<lan-ip>:<port>/calendar.ics?token=… (append &provider=ID to filter)
+  webcal_url: string | null; // Same feed with webcal:// (opens the calendar app)
+};
+
+/**
+ * ClinicTimezoneInfo: Timezone used to interpret clinic-local appointment times
+ */
+export type ClinicTimezoneInfo = {
+  timezone: string;    // IANA name (e.g. "America/Montevideo") or "system"
+  utc_offset: string;  // Current offset, e.g. "-03:00"
+  configured: boolean; // false = falls back to the system timezone
+};
+
+/**
+ * ScheduleRange: Weekly working hours or break (clinic-wide or per provider override)
+ */
+export type ScheduleRange = {
+  id?: number;
+  provider_id?: number | null; // null = clinic schedule
+  weekday: number;             // 0 = Sunday … 6 = Saturday (same as Date.getDay())
+  start_time: string;          // "HH:MM"
+  end_time: string;            // "HH:MM"
+  kind: "work" | "break";
+};
+
+/**
+ * ScheduleClosure: Holiday or one-off closure (whole day or a time range)
+ */
+export type ScheduleClosure = {
+  id?: number;
+  provider_id?: number | null; // null = whole clinic
+  start_date: string;          // "YYYY-MM-DD"
+  end_date?: string | null;    // inclusive
+  start_time?: string | null;  // null = all day
+  end_time?: string | null;
+  kind: "holiday" | "closure";
+  repeats_yearly?: boolean;
+  reason?: string | null;
 };
 
 /**
@@ -362,13 +636,38 @@ export type MessageQueueItem = {
   id?: number;
   patient_id: number;
   appointment_id?: number;  // Optional link to appointment
-  type: "reminder_1d" | "availability" | "custom";
+  type: "reminder_1d" | "reminder" | "recall" | "availability" | "custom";
   message_text: string;     // Pre-generated message ready to send
   status: "pending" | "sent" | "skipped";
   sent_at?: string;
   created_at?: string;
 };
 
+/**
+ * ReminderRule: Reminder sent a fixed time before each appointment by the background scheduler
+ * Quiet hours (clinic-local "HH:MM"): settings reminderQuietStart / reminderQuietEnd (default 21:00–08:00)
+ */
+export type ReminderRule = {
+  id?: number;
+  name: string;
+  offset_minutes: number;                       // 4320 = 3 days, 1440 = 24h, 120 = 2h
+  template_kind?: "reminder" | "reminder_1d";   // Preferred template of this kind when template_id is null
+  template_id?: number | null;
+  active?: boolean;
+  template_title?: string | null;               // Read-only
+  created_at?: string;
+  updated_at?: string;
+};
+
+/**
+ * ReminderRunResult: Outcome of one scheduler pass (run_reminder_scheduler)
+ */
+export type ReminderRunResult = {
+  queued: number;       // Messages added to message_queue
+  skipped: number;      // Due rules superseded by a closer reminder
+  quiet_hours: boolean; // Pass skipped because of quiet hours
+};
+
 /**
  * InformedConsent: Consentimiento informado digital con firma
  * Sistema de protección legal para procedimientos odontológicos